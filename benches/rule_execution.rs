@@ -4,7 +4,8 @@
 //! Run with: cargo bench
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
-use fraud_rule_engine::{RuleEngine, Transaction, UserProfile, Value};
+use fraud_rule_engine::runtime::ExecutionContext;
+use fraud_rule_engine::{EngineConfig, RuleEngine, Transaction, UserProfile, Value};
 
 fn benchmark_single_rule(c: &mut Criterion) {
     let dsl = r#"
@@ -30,6 +31,44 @@ fn benchmark_single_rule(c: &mut Criterion) {
     });
 }
 
+fn benchmark_context_reuse(c: &mut Criterion) {
+    let dsl = r#"
+        rule "simple" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.8);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let transaction = Transaction::new().with_field("amount", Value::Float(5000.0));
+    let profile = UserProfile::new();
+    let mut group = c.benchmark_group("single_rule_context");
+    
+    group.bench_function("fresh", |b| {
+        b.iter(|| {
+            engine.execute(
+                black_box(transaction.clone()),
+                black_box(profile.clone()),
+            )
+        })
+    });
+    
+    let mut scratch = ExecutionContext::new(Transaction::new(), UserProfile::new());
+    group.bench_function("reset", |b| {
+        b.iter(|| {
+            engine.execute_with_context(
+                &mut scratch,
+                black_box(transaction.clone()),
+                black_box(profile.clone()),
+            )
+        })
+    });
+    
+    group.finish();
+}
+
 fn benchmark_10_rules(c: &mut Criterion) {
     let mut dsl = String::new();
     for i in 0..10 {
@@ -118,6 +157,16 @@ fn benchmark_500_rules(c: &mut Criterion) {
             )
         })
     });
+    
+    let engine = engine.with_config(EngineConfig::production());
+    c.bench_function("500_rules_no_metadata", |b| {
+        b.iter(|| {
+            engine.execute(
+                black_box(transaction.clone()),
+                black_box(profile.clone()),
+            )
+        })
+    });
 }
 
 fn benchmark_complex_rules(c: &mut Criterion) {
@@ -241,6 +290,111 @@ fn benchmark_profile_mutations(c: &mut Criterion) {
     });
 }
 
+fn benchmark_literal_arithmetic(c: &mut Criterion) {
+    let mut dsl = String::new();
+    for i in 0..100 {
+        dsl.push_str(&format!(
+            r#"
+            rule "rule_{}" {{
+                priority: {},
+                if (txn.amount > {} * 10 + 60 * 60 * 24 / 1000) {{
+                    profile.limit = 24 * 60 * 60 * 1000;
+                }}
+            }}
+            "#,
+            i, 1000 - i, i
+        ));
+    }
+    
+    let engine = RuleEngine::from_dsl(&dsl).unwrap();
+    let transaction = Transaction::new().with_field("amount", Value::Float(5000.0));
+    let profile = UserProfile::new();
+    
+    c.bench_function("100_rules_literal_arithmetic", |b| {
+        b.iter(|| {
+            engine.execute(
+                black_box(transaction.clone()),
+                black_box(profile.clone()),
+            )
+        })
+    });
+}
+
+fn benchmark_list_membership(c: &mut Criterion) {
+    let deny: Vec<String> = (0..5000).map(|i| format!("\"merchant_{}\"", i)).collect();
+    let mut group = c.benchmark_group("membership_5000");
+    
+    // `includes` always scans the array; `in` on a large constant list is
+    // a hash set lookup
+    for (name, condition) in [
+        ("linear_scan", "DENY.includes(txn.merchant)"),
+        ("hash_set", "txn.merchant in DENY"),
+    ] {
+        let dsl = format!(
+            r#"
+            const DENY = [{}];
+            rule "deny_list" {{
+                priority: 100,
+                if ({}) {{
+                    setDecision("BLOCK");
+                }}
+            }}
+            "#,
+            deny.join(", "),
+            condition
+        );
+        
+        let engine = RuleEngine::from_dsl(&dsl).unwrap();
+        // The last entry is the scan's worst case
+        let transaction = Transaction::new().with_field("merchant", Value::from("merchant_4999"));
+        let profile = UserProfile::new();
+        
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                engine.execute(
+                    black_box(transaction.clone()),
+                    black_box(profile.clone()),
+                )
+            })
+        });
+    }
+    
+    group.finish();
+}
+
+fn benchmark_string_literals(c: &mut Criterion) {
+    // Every condition compares against a string literal that never matches
+    let rules: Vec<String> = (0..20)
+        .map(|i| {
+            format!(
+                r#"
+                rule "merchant_{i}" {{
+                    priority: {i},
+                    if (txn.merchant == "blocked_merchant_name_{i}" || txn.channel != "card_present") {{
+                        createComment("merchant matched blocked entry {i}");
+                    }}
+                }}
+                "#
+            )
+        })
+        .collect();
+    let engine = RuleEngine::from_dsl(&rules.join("\n")).unwrap();
+    
+    let transaction = Transaction::new()
+        .with_field("merchant", Value::from("corner_store"))
+        .with_field("channel", Value::from("card_present"));
+    let profile = UserProfile::new();
+    
+    c.bench_function("string_literals_20_rules", |b| {
+        b.iter(|| {
+            engine.execute(
+                black_box(transaction.clone()),
+                black_box(profile.clone()),
+            )
+        })
+    });
+}
+
 fn benchmark_compilation(c: &mut Criterion) {
     let dsl = r#"
         rule "test" {
@@ -260,6 +414,51 @@ fn benchmark_compilation(c: &mut Criterion) {
     });
 }
 
+fn benchmark_batch_execution(c: &mut Criterion) {
+    let mut dsl = String::new();
+    for i in 0..100 {
+        dsl.push_str(&format!(
+            r#"
+            rule "rule_{}" {{
+                priority: {},
+                if (txn.amount > {}) {{
+                    profile.counter = profile.counter + 1;
+                }}
+            }}
+            "#,
+            i, 1000 - i, i * 50
+        ));
+    }
+    
+    let engine = RuleEngine::from_dsl(&dsl).unwrap();
+    let inputs: Vec<_> = (0..100)
+        .map(|i| {
+            (
+                Transaction::new().with_field("amount", Value::Float(i as f64 * 50.0)),
+                UserProfile::new().with_field("counter", Value::Int(0)),
+            )
+        })
+        .collect();
+    
+    let mut group = c.benchmark_group("batch_100_txns");
+    
+    group.bench_function("execute_loop", |b| {
+        b.iter(|| {
+            black_box(&inputs)
+                .iter()
+                .cloned()
+                .map(|(txn, profile)| engine.execute(txn, profile))
+                .collect::<Vec<_>>()
+        })
+    });
+    
+    group.bench_function("execute_batch", |b| {
+        b.iter(|| engine.execute_batch(black_box(&inputs).clone()))
+    });
+    
+    group.finish();
+}
+
 fn benchmark_by_rule_count(c: &mut Criterion) {
     let mut group = c.benchmark_group("rule_count_scaling");
     
@@ -303,12 +502,17 @@ fn benchmark_by_rule_count(c: &mut Criterion) {
 criterion_group!(
     benches,
     benchmark_single_rule,
+    benchmark_context_reuse,
     benchmark_10_rules,
     benchmark_100_rules,
     benchmark_500_rules,
     benchmark_complex_rules,
     benchmark_profile_mutations,
+    benchmark_literal_arithmetic,
+    benchmark_list_membership,
+    benchmark_string_literals,
     benchmark_compilation,
+    benchmark_batch_execution,
     benchmark_by_rule_count,
 );
 
@@ -8,6 +8,9 @@
 use crate::Value;
 use ahash::HashMap;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
 
 /// Actions emitted by rules during execution
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -43,7 +46,7 @@ pub enum Action {
     
     /// Set transaction decision
     SetDecision {
-        decision: String, // ALLOW, BLOCK, REVIEW
+        decision: Decision,
     },
     
     /// Custom action with arbitrary parameters
@@ -103,11 +106,153 @@ impl Action {
     }
     
     /// Set decision action
-    pub fn set_decision(decision: impl Into<String>) -> Self {
-        Action::SetDecision {
-            decision: decision.into(),
+    pub fn set_decision(decision: Decision) -> Self {
+        Action::SetDecision { decision }
+    }
+}
+
+/// Custom actions rules may call, with the names of their parameters
+///
+/// A call to a registered name such as `setRiskBand("GOLD")` compiles to an
+/// [`Action::Custom`] whose `params` are keyed by the declared names
+/// (`{"band": "GOLD"}`) rather than `arg0`, `arg1`, ...
+#[derive(Debug, Clone, Default)]
+pub struct ActionRegistry {
+    schemas: HashMap<String, Vec<String>>,
+}
+
+impl ActionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Register an action and its parameter names, in call order
+    pub fn register_action(mut self, name: impl Into<String>, param_names: &[&str]) -> Self {
+        let params = param_names.iter().map(|p| p.to_string()).collect();
+        self.schemas.insert(name.into(), params);
+        self
+    }
+    
+    /// Parameter names of a registered action
+    pub fn params(&self, name: &str) -> Option<&[String]> {
+        self.schemas.get(name).map(Vec::as_slice)
+    }
+}
+
+/// Remove exact-duplicate actions, keeping the first occurrence of each
+pub fn dedup_actions(actions: &mut Vec<Action>) {
+    let mut unique: Vec<Action> = Vec::with_capacity(actions.len());
+    for action in actions.drain(..) {
+        if !unique.contains(&action) {
+            unique.push(action);
         }
     }
+    *actions = unique;
+}
+
+/// Drop every `SetFraudScore` except the last one, since only the final
+/// score matters downstream
+pub fn keep_last_fraud_score(actions: &mut Vec<Action>) {
+    if let Some(last) = actions.iter().rposition(|a| matches!(a, Action::SetFraudScore { .. })) {
+        let mut index = 0;
+        actions.retain(|a| {
+            let keep = index == last || !matches!(a, Action::SetFraudScore { .. });
+            index += 1;
+            keep
+        });
+    }
+}
+
+/// How the `SetFraudScore` actions of one execution are combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FraudScorePolicy {
+    /// Return every `SetFraudScore` and let the caller decide
+    #[default]
+    KeepAll,
+    
+    /// Keep only the last score set
+    Last,
+    
+    /// Keep the highest score set
+    Max,
+    
+    /// Add up all scores set, clamped to 1.0
+    Sum,
+}
+
+/// Collapse all `SetFraudScore` actions into one according to `policy`
+///
+/// The combined score takes the place of the last `SetFraudScore`, so other
+/// actions keep their order. `KeepAll` leaves the actions untouched.
+pub fn apply_fraud_score_policy(actions: &mut Vec<Action>, policy: FraudScorePolicy) {
+    let mut scores = actions.iter().filter_map(|a| match a {
+        Action::SetFraudScore { score } => Some(*score),
+        _ => None,
+    });
+    
+    let combined = match policy {
+        FraudScorePolicy::KeepAll => return,
+        FraudScorePolicy::Last => scores.next_back(),
+        FraudScorePolicy::Max => scores.reduce(f64::max),
+        FraudScorePolicy::Sum => scores.reduce(|a, b| a + b).map(|sum| sum.min(1.0)),
+    };
+    let Some(combined) = combined else {
+        return;
+    };
+    
+    keep_last_fraud_score(actions);
+    if let Some(Action::SetFraudScore { score }) = actions
+        .iter_mut()
+        .rfind(|a| matches!(a, Action::SetFraudScore { .. }))
+    {
+        *score = combined;
+    }
+}
+
+/// Transaction decision set by `setDecision`
+///
+/// Serialized as the uppercase name (`"ALLOW"`, `"BLOCK"`, `"REVIEW"`), the
+/// same strings the DSL uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Decision {
+    Allow,
+    Block,
+    Review,
+}
+
+/// Error for a decision name that is not ALLOW, BLOCK or REVIEW
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("unknown decision: {0}")]
+pub struct UnknownDecision(pub String);
+
+impl Decision {
+    /// The decision's name as written in the DSL
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Allow => "ALLOW",
+            Decision::Block => "BLOCK",
+            Decision::Review => "REVIEW",
+        }
+    }
+}
+
+impl FromStr for Decision {
+    type Err = UnknownDecision;
+    
+    /// Parse a decision name, ignoring ASCII case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Decision::Allow, Decision::Block, Decision::Review]
+            .into_iter()
+            .find(|d| d.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownDecision(s.to_string()))
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[cfg(test)]
@@ -131,4 +276,99 @@ mod tests {
             _ => panic!("Wrong action type"),
         }
     }
+
+    #[test]
+    fn test_dedup_actions_keeps_first_seen_order() {
+        let mut actions = vec![
+            Action::set_fraud_score(0.9),
+            Action::create_case("HIGH", "a"),
+            Action::set_fraud_score(0.9),
+            Action::create_case("HIGH", "b"),
+            Action::create_case("HIGH", "a"),
+        ];
+
+        dedup_actions(&mut actions);
+
+        assert_eq!(
+            actions,
+            vec![
+                Action::set_fraud_score(0.9),
+                Action::create_case("HIGH", "a"),
+                Action::create_case("HIGH", "b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_last_fraud_score() {
+        let mut actions = vec![
+            Action::set_fraud_score(0.2),
+            Action::create_case("HIGH", "a"),
+            Action::set_fraud_score(0.9),
+            Action::create_comment("note"),
+        ];
+
+        keep_last_fraud_score(&mut actions);
+
+        assert_eq!(
+            actions,
+            vec![
+                Action::create_case("HIGH", "a"),
+                Action::set_fraud_score(0.9),
+                Action::create_comment("note"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fraud_score_policies() {
+        let actions = vec![
+            Action::set_fraud_score(0.3),
+            Action::create_case("HIGH", "a"),
+            Action::set_fraud_score(0.9),
+            Action::set_fraud_score(0.5),
+            Action::create_comment("note"),
+        ];
+        let collapse = |policy| {
+            let mut actions = actions.clone();
+            apply_fraud_score_policy(&mut actions, policy);
+            actions
+        };
+        let with_score = |score| {
+            vec![
+                Action::create_case("HIGH", "a"),
+                Action::set_fraud_score(score),
+                Action::create_comment("note"),
+            ]
+        };
+
+        assert_eq!(collapse(FraudScorePolicy::KeepAll), actions);
+        assert_eq!(collapse(FraudScorePolicy::Last), with_score(0.5));
+        assert_eq!(collapse(FraudScorePolicy::Max), with_score(0.9));
+        assert_eq!(collapse(FraudScorePolicy::Sum), with_score(1.0));
+
+        let mut no_scores = vec![Action::create_comment("note")];
+        apply_fraud_score_policy(&mut no_scores, FraudScorePolicy::Sum);
+        assert_eq!(no_scores, vec![Action::create_comment("note")]);
+    }
+
+    #[test]
+    fn test_decision_parsing() {
+        assert_eq!("ALLOW".parse(), Ok(Decision::Allow));
+        assert_eq!("block".parse(), Ok(Decision::Block));
+        assert_eq!("Review".parse(), Ok(Decision::Review));
+        assert_eq!(
+            "BLOCKK".parse::<Decision>(),
+            Err(UnknownDecision("BLOCKK".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_decision_serializes_as_uppercase_string() {
+        let json = serde_json::to_string(&Action::set_decision(Decision::Block)).unwrap();
+        assert_eq!(json, r#"{"type":"set_decision","decision":"BLOCK"}"#);
+
+        let action: Action = serde_json::from_str(&json).unwrap();
+        assert_eq!(action, Action::set_decision(Decision::Block));
+    }
 }
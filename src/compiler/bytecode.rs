@@ -1,6 +1,7 @@
 // src/compiler/bytecode.rs
 //! Bytecode instructions for the rule engine VM
 
+use crate::compiler::symbols::SymbolId;
 use crate::Value;
 use serde::{Deserialize, Serialize};
 
@@ -8,17 +9,21 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Instruction {
     // Stack operations
-    Push(Value),
+    Push(Value), // scalars; heap-backed literals go through the constant pool
+    LoadConst(usize), // index into the constant pool
     Pop,
     Dup,
     
-    // Variable access
-    LoadProfileField(String),
-    StoreProfileField(String),
-    LoadTxnField(String),
-    StoreTxnField(String),
+    // Variable access; fields are addressed by interned name
+    LoadProfileField(SymbolId),
+    StoreProfileField(SymbolId),
+    LoadTxnField(SymbolId),
+    StoreTxnField(SymbolId),
     LoadLocal(String),
     StoreLocal(String),
+    DeclareLocal(String), // pops the value into a local of the innermost scope, shadowing any outer one
+    EnterScope, // start a block that declares locals
+    ExitScope, // drop the innermost scope's locals, restoring what they shadowed
     
     // Arithmetic operations
     Add,
@@ -26,8 +31,16 @@ pub enum Instruction {
     Mul,
     Div,
     Mod,
+    Pow,
     Neg,
     
+    // Bitwise operations (Int only)
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    
     // Comparison operations
     Eq,
     Ne,
@@ -35,6 +48,8 @@ pub enum Instruction {
     Gte,
     Lt,
     Lte,
+    EqConst(usize), // pops a value, compares it with a constant pool entry
+    NeConst(usize),
     
     // Logical operations
     And,
@@ -44,19 +59,38 @@ pub enum Instruction {
     // Control flow
     Jump(usize),
     JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    JumpIfNotNull(usize), // pops the value, jumps if it is not null
+    JumpIfUnknown(usize), // under SQL null semantics pops a Null condition and jumps; otherwise does nothing
+    ShortCircuitAnd(usize), // `&&`: pops and jumps if the left operand is false, else leaves it for `And`
+    ShortCircuitOr(usize), // `||`: pops and jumps if the left operand is true, else leaves it for `Or`
+    LoopGuard(usize), // pops the iteration count, jumps to target past the limit
     Return,
+    Assert(String), // pops the condition; if false records an error naming this source and stops the rule
     
     // Function and action calls
     CallGlobal(String, usize), // function name, arg count
+    CallBuiltin(BuiltinFn, usize), // builtin function, arg count
     CallAction(ActionType, usize), // action type, arg count
     
     // Array/Object operations
     ArrayAccess,
-    ArrayContains,
+    ArrayContains, // pops needle, then haystack (array element or substring)
+    ArrayLength, // length of an array, 0 for anything else
     ObjectGet(String),
+    BuildObject(usize), // entry count; pops key/value pairs
+    BuildArray(usize), // element count; pops the elements
+    SetContains(usize), // index into the set table; pops the needle
     
     // Method calls
     MethodCall(String, usize), // method name, arg count
+    ArrayPush(bool),
+    MatchRegex(usize),         // index into the regex table; Bool for strings, else Null           // append to the array below the item; true if a max length is on top
+    
+    // Tracing
+    TraceCondition(usize), // index into the rule's conditions; peeks the result
+    MemoCondition(usize, usize), // memo table entry, instructions to skip to the condition's TraceCondition on a cache hit
+    MarkFired,             // a top-level `if` condition held
 }
 
 /// Action types that can be called from rules
@@ -67,13 +101,91 @@ pub enum ActionType {
     SendAuthAdvise,
     SetFraudScore,
     SetDecision,
-    Custom(String),
+    Custom(String, Vec<String>), // action name, parameter names
+}
+
+impl ActionType {
+    /// Action name as called in the DSL
+    pub fn name(&self) -> &str {
+        match self {
+            ActionType::CreateCase => "createCase",
+            ActionType::CreateComment => "createComment",
+            ActionType::SendAuthAdvise => "sendAuthAdvise",
+            ActionType::SetFraudScore => "setFraudScore",
+            ActionType::SetDecision => "setDecision",
+            ActionType::Custom(name, _) => name,
+        }
+    }
+}
+
+/// Built-in functions dispatched directly by the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuiltinFn {
+    Abs,
+    Min,
+    Max,
+    Clamp,
+    Floor,
+    Ceil,
+    Round,
+    Sqrt,
+    Decimal,
+    Now,
+    HoursBetween,
+    ParseDate,
+    ToInt,
+    ToFloat,
+    ToString,
+    ToBool,
+    // Only resolvable with the `crypto` feature; the variants always exist
+    // so bytecode has the same layout either way
+    Sha256,
+    Md5,
+}
+
+impl BuiltinFn {
+    /// Look up a builtin by its DSL name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(BuiltinFn::Abs),
+            "min" => Some(BuiltinFn::Min),
+            "max" => Some(BuiltinFn::Max),
+            "clamp" => Some(BuiltinFn::Clamp),
+            "floor" => Some(BuiltinFn::Floor),
+            "ceil" => Some(BuiltinFn::Ceil),
+            "round" => Some(BuiltinFn::Round),
+            "sqrt" => Some(BuiltinFn::Sqrt),
+            "decimal" => Some(BuiltinFn::Decimal),
+            "now" => Some(BuiltinFn::Now),
+            "hoursBetween" => Some(BuiltinFn::HoursBetween),
+            "parseDate" => Some(BuiltinFn::ParseDate),
+            "toInt" => Some(BuiltinFn::ToInt),
+            "toFloat" => Some(BuiltinFn::ToFloat),
+            "toString" => Some(BuiltinFn::ToString),
+            "toBool" => Some(BuiltinFn::ToBool),
+            #[cfg(feature = "crypto")]
+            "sha256" => Some(BuiltinFn::Sha256),
+            #[cfg(feature = "crypto")]
+            "md5" => Some(BuiltinFn::Md5),
+            _ => None,
+        }
+    }
 }
 
 impl Instruction {
     /// Returns true if this instruction is a jump target
     pub fn is_jump(&self) -> bool {
-        matches!(self, Instruction::Jump(_) | Instruction::JumpIfFalse(_))
+        matches!(
+            self,
+            Instruction::Jump(_)
+                | Instruction::JumpIfFalse(_)
+                | Instruction::JumpIfTrue(_)
+                | Instruction::JumpIfNotNull(_)
+                | Instruction::JumpIfUnknown(_)
+                | Instruction::ShortCircuitAnd(_)
+                | Instruction::ShortCircuitOr(_)
+                | Instruction::LoopGuard(_)
+        )
     }
 }
 
@@ -1,53 +1,263 @@
 // src/compiler/compiler.rs
 //! Compiler that converts AST to bytecode
 
-use crate::compiler::bytecode::{ActionType, Instruction};
+use crate::actions::ActionRegistry;
+use crate::compiler::bytecode::{ActionType, BuiltinFn, Instruction};
+use crate::compiler::memo::{FieldRead, MemoTable};
+use crate::compiler::optimizer;
+use crate::compiler::pool::ConstantPool;
+use crate::compiler::regexes::RegexTable;
+use crate::compiler::schema::Schema;
+use crate::compiler::sets::{SetTable, MIN_SET_LEN};
+use crate::compiler::symbols::{SymbolId, SymbolTable};
 use crate::parser::ast::*;
-use crate::{CompiledFunction, CompiledRule, CompilationError, Value};
+use crate::runtime::{ExecutionContext, ValueType, VM};
+use crate::{CompiledFunction, CompiledRule, CompilationError, Transaction, UserProfile, Value};
+use ahash::{HashMap, HashSet};
+use std::sync::Arc;
 
-pub struct Compiler {
+/// Compile-time definitions shared by every rule and function of a program
+#[derive(Debug, Default)]
+pub struct CompileEnv {
+    /// Values of `const` declarations, inlined wherever they are referenced
+    pub constants: HashMap<String, Value>,
+    
+    /// Field names interned by every compiled rule and function
+    pub symbols: SymbolTable,
+    
+    /// Regex patterns used by `matches` in every rule and function
+    pub regexes: RegexTable,
+    
+    /// Membership sets for `in` over large constant lists
+    pub sets: SetTable,
+    
+    /// String, array, object and bytes literals of every rule and function
+    pub pool: ConstantPool,
+    
+    /// Fields read by each memoizable top-level condition
+    pub memo: MemoTable,
+    
+    /// Custom actions calls may resolve to, ahead of global functions
+    pub actions: ActionRegistry,
+    
+    /// Declared field types that comparisons are checked against
+    pub schema: Schema,
+}
+
+impl CompileEnv {
+    /// Evaluate `const` declarations in order
+    ///
+    /// A constant may use literals, operators, pure builtins and constants
+    /// declared before it; a forward or circular reference is an error.
+    pub fn from_constants(constants: &[ConstNode]) -> Result<Self, CompilationError> {
+        let mut env = Self::default();
+        
+        for constant in constants {
+            if env.constants.contains_key(&constant.name) {
+                return Err(CompilationError::CompileError(format!(
+                    "constant '{}' is declared more than once",
+                    constant.name
+                )));
+            }
+            
+            env.check_constant_expr(&constant.name, &constant.value)?;
+            
+            let mut compiler = Compiler::new(&mut env);
+            compiler.compile_expression(&constant.value)?;
+            let bytecode = compiler.resolve_labels();
+            
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            ctx.sets = Arc::new(env.sets.clone());
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            if let Some(error) = ctx.errors.pop() {
+                return Err(CompilationError::CompileError(format!(
+                    "constant '{}': {}",
+                    constant.name, error
+                )));
+            }
+            
+            let value = ctx.pop().unwrap_or(Value::Null);
+            env.constants.insert(constant.name.clone(), value);
+        }
+        
+        Ok(env)
+    }
+    
+    /// Reject anything in a constant's expression that is not known at
+    /// compile time
+    fn check_constant_expr(&self, name: &str, expr: &Expression) -> Result<(), CompilationError> {
+        let error = |what: String| {
+            Err(CompilationError::CompileError(format!("constant '{}' {}", name, what)))
+        };
+        
+        match expr {
+            Expression::Literal(_) => Ok(()),
+            Expression::Variable(var) if self.constants.contains_key(var) => Ok(()),
+            Expression::Variable(var) => {
+                error(format!("refers to '{}', which is not a constant declared before it", var))
+            }
+            Expression::FieldAccess { object, field } => {
+                error(format!("cannot read runtime field '{}.{}'", object, field))
+            }
+            Expression::FunctionCall { name: func, args } => match BuiltinFn::from_name(func) {
+                Some(builtin) if builtin != BuiltinFn::Now => args
+                    .iter()
+                    .try_for_each(|arg| self.check_constant_expr(name, arg)),
+                _ => error(format!("cannot call '{}' at compile time", func)),
+            },
+            Expression::Binary { left, right, .. } => {
+                self.check_constant_expr(name, left)?;
+                self.check_constant_expr(name, right)
+            }
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                self.check_constant_expr(name, condition)?;
+                self.check_constant_expr(name, then_expr)?;
+                self.check_constant_expr(name, else_expr)
+            }
+            Expression::Unary { operand, .. } => self.check_constant_expr(name, operand),
+            Expression::ObjectField { object, .. } => self.check_constant_expr(name, object),
+            Expression::ArrayAccess { array, index } => {
+                self.check_constant_expr(name, array)?;
+                self.check_constant_expr(name, index)
+            }
+            Expression::MethodCall { object, args, .. } => {
+                self.check_constant_expr(name, object)?;
+                args.iter().try_for_each(|arg| self.check_constant_expr(name, arg))
+            }
+            Expression::ObjectLiteral(entries) => entries
+                .iter()
+                .try_for_each(|(_, value)| self.check_constant_expr(name, value)),
+            Expression::ArrayLiteral(elements) => elements
+                .iter()
+                .try_for_each(|element| self.check_constant_expr(name, element)),
+            Expression::Lambda { .. } => error(format!("cannot use '{}' at compile time", expr)),
+        }
+    }
+}
+
+pub struct Compiler<'a> {
     instructions: Vec<Instruction>,
     label_counter: usize,
     labels: Vec<(usize, usize)>, // (label_id, instruction_index)
+    conditions: Vec<String>, // source of traced top-level `if` conditions
+    locals: HashSet<String>, // local variables assigned so far, incl. params
+    loops: Vec<(usize, usize, usize)>, // (continue, break) labels and scope depth of enclosing loops
+    scope_depth: usize, // blocks entered with `EnterScope` and not yet exited
+    in_function: bool, // whether `return` may carry a value
+    statement_lines: Vec<usize>, // source line of each statement, in pre-order
+    statements_seen: usize,
+    line_map: Vec<(usize, usize)>, // (first instruction, source line) per statement
+    env: &'a mut CompileEnv,
 }
 
-impl Compiler {
-    fn new() -> Self {
+impl<'a> Compiler<'a> {
+    fn new(env: &'a mut CompileEnv) -> Self {
         Self {
             instructions: Vec::new(),
             label_counter: 0,
             labels: Vec::new(),
+            conditions: Vec::new(),
+            locals: HashSet::default(),
+            loops: Vec::new(),
+            scope_depth: 0,
+            in_function: false,
+            statement_lines: Vec::new(),
+            statements_seen: 0,
+            line_map: Vec::new(),
+            env,
         }
     }
     
-    pub fn compile_rule(rule: &RuleNode) -> Result<CompiledRule, CompilationError> {
-        let mut compiler = Compiler::new();
+    pub fn compile_rule(rule: &RuleNode, env: &mut CompileEnv) -> Result<CompiledRule, CompilationError> {
+        let mut compiler = Compiler::new(env);
+        compiler.statement_lines = rule.lines.clone();
         
-        // Compile all statements in the rule body
+        // Compile all statements in the rule body; top-level `if` conditions
+        // are traced so `RuleEngine::explain` can report them
         for stmt in &rule.body {
-            compiler.compile_statement(stmt)?;
+            match stmt {
+                Statement::IfStatement {
+                    condition,
+                    then_block,
+                    else_block,
+                } => {
+                    compiler.mark_statement();
+                    compiler.compile_if(condition, then_block, else_block.as_deref(), true)?
+                }
+                _ => compiler.compile_statement(stmt)?,
+            }
         }
         
-        // Resolve jump labels
-        let bytecode = compiler.resolve_labels();
+        let conditions = std::mem::take(&mut compiler.conditions);
+        let line_map = std::mem::take(&mut compiler.line_map);
+        
+        // Resolve jump labels, fold literal operations, then pool what is left
+        let (bytecode, folded_positions) = optimizer::fold_constants_with_positions(compiler.resolve_labels());
+        let (mut bytecode, positions) = optimizer::pool_constants(bytecode, &mut env.pool);
+        Self::set_memo_skips(&mut bytecode);
+        
+        let gate = match &rule.enabled_when {
+            Some(condition) => Self::compile_gate(condition, env)?,
+            None => Vec::new(),
+        };
+        let lines = line_map
+            .into_iter()
+            .map(|(pc, line)| (positions[folded_positions[pc]], line))
+            .collect();
         
         Ok(CompiledRule {
             id: rule.id.clone(),
             priority: rule.priority,
             enabled: rule.enabled,
+            gate,
             bytecode,
+            conditions,
+            group: None,
+            tags: rule.tags.clone(),
+            lines,
         })
     }
     
-    pub fn compile_function(func: &FunctionNode) -> Result<CompiledFunction, CompilationError> {
-        let mut compiler = Compiler::new();
+    /// Bytecode leaving the value of a rule's `enabled:` condition on the
+    /// stack
+    fn compile_gate(condition: &Expression, env: &mut CompileEnv) -> Result<Vec<Instruction>, CompilationError> {
+        let mut compiler = Compiler::new(env);
+        compiler.compile_expression(condition)?;
+        let bytecode = optimizer::fold_constants(compiler.resolve_labels());
+        Ok(optimizer::pool_constants(bytecode, &mut env.pool).0)
+    }
+    
+    pub fn compile_function(func: &FunctionNode, env: &mut CompileEnv) -> Result<CompiledFunction, CompilationError> {
+        let mut compiler = Compiler::new(env);
+        compiler.locals.extend(func.params.iter().cloned());
+        compiler.in_function = true;
+        // `CallGlobal` runs the body in a scope holding the parameters, so
+        // its locals never overwrite the caller's
+        compiler.scope_depth = 1;
+        
+        // Names the body assigns without `let` belong to the call too:
+        // declare them in the function scope up front, since a declaration
+        // at the first assignment could land in a nested block's scope
+        let mut assigned = Vec::new();
+        Self::assigned_locals(&func.body, &mut assigned);
+        for name in assigned {
+            if compiler.locals.insert(name.clone()) {
+                compiler.emit(Instruction::Push(Value::Null));
+                compiler.emit(Instruction::DeclareLocal(name));
+            }
+        }
         
         // Compile function body
         for stmt in &func.body {
             compiler.compile_statement(stmt)?;
         }
         
-        let bytecode = compiler.resolve_labels();
+        // Falling off the end returns null
+        compiler.emit(Instruction::Push(Value::Null));
+        compiler.emit(Instruction::Return);
+        
+        let bytecode = optimizer::fold_constants(compiler.resolve_labels());
+        let (bytecode, _) = optimizer::pool_constants(bytecode, &mut env.pool);
         
         Ok(CompiledFunction {
             name: func.name.clone(),
@@ -56,60 +266,175 @@ impl Compiler {
         })
     }
     
+    /// Local variables assigned anywhere in `block` other than by `let`, in
+    /// order of first assignment
+    fn assigned_locals(block: &[Statement], names: &mut Vec<String>) {
+        for stmt in block {
+            let target = match stmt {
+                Statement::Assignment { target, .. } | Statement::CompoundAssignment { target, .. } => Some(target),
+                Statement::Expression(Expression::MethodCall { object, method, .. }) if method == "push" => {
+                    match &**object {
+                        Expression::Variable(name) => Some(name),
+                        _ => None,
+                    }
+                }
+                Statement::IfStatement { then_block, else_block, .. } => {
+                    Self::assigned_locals(then_block, names);
+                    if let Some(else_block) = else_block {
+                        Self::assigned_locals(else_block, names);
+                    }
+                    None
+                }
+                Statement::ForEach { body, .. } | Statement::WhileLoop { body, .. } | Statement::Block(body) => {
+                    Self::assigned_locals(body, names);
+                    None
+                }
+                _ => None,
+            };
+            if let Some(name) = target.filter(|name| !name.contains('.') && !names.contains(name)) {
+                names.push(name.clone());
+            }
+        }
+    }
+    
     fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompilationError> {
+        self.mark_statement();
+        
         match stmt {
             Statement::IfStatement {
                 condition,
                 then_block,
                 else_block,
             } => {
-                // Compile condition
-                self.compile_expression(condition)?;
+                self.compile_if(condition, then_block, else_block.as_deref(), false)?;
+            }
+            
+            Statement::ForEach { var, iterable, body } => {
+                // Hidden locals hold the array and index; `$` keeps them out
+                // of reach of DSL identifiers and the label id keeps nested
+                // loops apart
+                let loop_id = self.new_label();
+                let iter_var = format!("$iter{}", loop_id);
+                let index_var = format!("$index{}", loop_id);
                 
-                // Create labels
-                let else_label = self.new_label();
+                // The loop variable and hidden locals live in the loop's
+                // scope: an outer local of the same name is untouched and the
+                // array is dropped after the loop. Each is declared once and
+                // then updated with plain stores.
+                self.emit(Instruction::EnterScope);
+                self.scope_depth += 1;
+                self.compile_expression(iterable)?;
+                self.emit(Instruction::DeclareLocal(iter_var.clone()));
+                self.emit(Instruction::Push(Value::Int(0)));
+                self.emit(Instruction::DeclareLocal(index_var.clone()));
+                self.emit(Instruction::Push(Value::Null));
+                self.emit(Instruction::DeclareLocal(var.clone()));
+                
+                let start_label = self.new_label();
+                let next_label = self.new_label();
                 let end_label = self.new_label();
                 
-                // Jump to else if condition is false
-                self.emit_jump_if_false(else_label);
+                // Bounds check: index < length (non-arrays have length 0)
+                self.place_label(start_label);
+                self.emit(Instruction::LoadLocal(index_var.clone()));
+                self.emit(Instruction::LoadLocal(iter_var.clone()));
+                self.emit(Instruction::ArrayLength);
+                self.emit(Instruction::Lt);
+                self.emit_jump_if_false(end_label);
                 
-                // Compile then block
-                for stmt in then_block {
-                    self.compile_statement(stmt)?;
-                }
+                // Bind the current element to the loop variable
+                self.emit(Instruction::LoadLocal(iter_var));
+                self.emit(Instruction::LoadLocal(index_var.clone()));
+                self.emit(Instruction::ArrayAccess);
+                self.emit(Instruction::StoreLocal(var.clone()));
+                let newly_bound = self.locals.insert(var.clone());
                 
-                // Jump to end
-                self.emit_jump(end_label);
+                self.compile_loop_body(body, next_label, end_label)?;
                 
-                // Else block
-                self.place_label(else_label);
-                if let Some(else_stmts) = else_block {
-                    for stmt in else_stmts {
-                        self.compile_statement(stmt)?;
-                    }
+                // Advance the index and jump back to the bounds check
+                self.place_label(next_label);
+                self.emit(Instruction::LoadLocal(index_var.clone()));
+                self.emit(Instruction::Push(Value::Int(1)));
+                self.emit(Instruction::Add);
+                self.emit(Instruction::StoreLocal(index_var));
+                self.emit_jump(start_label);
+                
+                self.place_label(end_label);
+                self.emit(Instruction::ExitScope);
+                self.scope_depth -= 1;
+                if newly_bound {
+                    self.locals.remove(var);
                 }
+            }
+            
+            Statement::WhileLoop { condition, body } => {
+                let loop_id = self.new_label();
+                let count_var = format!("$count{}", loop_id);
+                
+                // The iteration count lives in the loop's scope, so a
+                // recursive call running the same loop cannot reset it
+                self.emit(Instruction::EnterScope);
+                self.scope_depth += 1;
+                self.emit(Instruction::Push(Value::Int(0)));
+                self.emit(Instruction::DeclareLocal(count_var.clone()));
+                
+                let start_label = self.new_label();
+                let end_label = self.new_label();
                 
-                // End label
+                self.place_label(start_label);
+                self.compile_expression(condition)?;
+                self.emit_jump_if_unknown(end_label);
+                self.emit_jump_if_false(end_label);
+                
+                // Count the iteration and let the VM enforce its limit
+                self.emit(Instruction::LoadLocal(count_var.clone()));
+                self.emit(Instruction::Push(Value::Int(1)));
+                self.emit(Instruction::Add);
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::StoreLocal(count_var));
+                self.emit(Instruction::LoopGuard(end_label));
+                
+                self.compile_loop_body(body, start_label, end_label)?;
+                
+                self.emit_jump(start_label);
                 self.place_label(end_label);
+                self.emit(Instruction::ExitScope);
+                self.scope_depth -= 1;
+            }
+            
+            Statement::Block(block) => {
+                self.compile_block(block)?;
+            }
+            
+            Statement::Let { name, value } => {
+                self.check_assignable(name)?;
+                self.compile_expression(value)?;
+                self.locals.insert(name.clone());
+                // Inside a block or function the local shadows any outer one
+                // until it ends; a rule's own body has no scope to leave
+                self.emit(if self.scope_depth > 0 {
+                    Instruction::DeclareLocal(name.clone())
+                } else {
+                    Instruction::StoreLocal(name.clone())
+                });
             }
             
             Statement::Assignment { target, value } => {
+                self.check_assignable(target)?;
+                
                 // Compile value expression
                 self.compile_expression(value)?;
+                self.emit_store(target);
+            }
+            
+            Statement::CompoundAssignment { target, op, value } => {
+                self.check_assignable(target)?;
                 
-                // Determine storage location
-                if target.starts_with("profile.") {
-                    let field = target.strip_prefix("profile.").unwrap();
-                    self.emit(Instruction::StoreProfileField(field.to_string()));
-                } else if target.starts_with("txn.") || target.starts_with("transaction.") {
-                    let field = target
-                        .strip_prefix("txn.")
-                        .or_else(|| target.strip_prefix("transaction."))
-                        .unwrap();
-                    self.emit(Instruction::StoreTxnField(field.to_string()));
-                } else {
-                    self.emit(Instruction::StoreLocal(target.clone()));
-                }
+                // Desugar `target op= value` into `target = target op value`
+                self.emit_load(target);
+                self.compile_expression(value)?;
+                self.emit(Self::binary_instruction(op));
+                self.emit_store(target);
             }
             
             Statement::ActionCall { action, args } => {
@@ -125,16 +450,66 @@ impl Compiler {
                     "sendAuthAdvise" => ActionType::SendAuthAdvise,
                     "setFraudScore" => ActionType::SetFraudScore,
                     "setDecision" => ActionType::SetDecision,
-                    _ => ActionType::Custom(action.clone()),
+                    _ => ActionType::Custom(action.clone(), Vec::new()),
                 };
                 
                 self.emit(Instruction::CallAction(action_type, args.len()));
             }
             
-            Statement::Return => {
+            Statement::Expression(Expression::FunctionCall { name, args }) if name == "suppress" => {
+                self.compile_suppress(args)?;
+            }
+            
+            Statement::Expression(Expression::FunctionCall { name, args })
+                if self.env.actions.params(name).is_some() =>
+            {
+                self.compile_custom_action(name, args)?;
+            }
+            
+            Statement::Return(value) => {
+                // A function's return value is left on top of its stack frame
+                match value {
+                    Some(value) if self.in_function => self.compile_expression(value)?,
+                    Some(_) => {
+                        return Err(CompilationError::CompileError(
+                            "return with a value is only allowed in functions".to_string(),
+                        ));
+                    }
+                    None if self.in_function => self.emit(Instruction::Push(Value::Null)),
+                    None => {}
+                }
                 self.emit(Instruction::Return);
             }
             
+            Statement::Require(condition) => {
+                self.compile_expression(condition)?;
+                self.emit(Instruction::Assert(condition.to_string()));
+            }
+            
+            Statement::Break | Statement::Continue => {
+                let Some(&(continue_label, break_label, loop_depth)) = self.loops.last() else {
+                    let keyword = if *stmt == Statement::Break { "break" } else { "continue" };
+                    return Err(CompilationError::CompileError(format!(
+                        "'{}' outside of a loop",
+                        keyword
+                    )));
+                };
+                
+                // Leave the blocks entered inside the loop before jumping out
+                for _ in loop_depth..self.scope_depth {
+                    self.emit(Instruction::ExitScope);
+                }
+                if *stmt == Statement::Break {
+                    self.emit_jump(break_label);
+                } else {
+                    self.emit_jump(continue_label);
+                }
+            }
+            
+            Statement::Expression(Expression::MethodCall { object, method, args }) if method == "push" => {
+                self.compile_push(object, args)?;
+            }
+            
             Statement::Expression(expr) => {
                 self.compile_expression(expr)?;
                 self.emit(Instruction::Pop); // Discard result
@@ -144,29 +519,148 @@ impl Compiler {
         Ok(())
     }
     
+    /// Compile an if/else; with `trace`, the condition's source is recorded
+    /// and a `TraceCondition` reports its result at runtime
+    fn compile_if(
+        &mut self,
+        condition: &Expression,
+        then_block: &[Statement],
+        else_block: Option<&[Statement]>,
+        top_level: bool,
+    ) -> Result<(), CompilationError> {
+        // A pure condition can be answered from the cache when enabled
+        if top_level {
+            if let Some(reads) = self.memo_reads(condition).filter(|reads| !reads.is_empty()) {
+                let entry = self.env.memo.add(reads);
+                // The skip is set once the rule's bytecode is final
+                self.emit(Instruction::MemoCondition(entry, 0));
+            }
+        }
+        
+        // Compile condition
+        self.compile_expression(condition)?;
+        
+        if top_level {
+            self.emit(Instruction::TraceCondition(self.conditions.len()));
+            self.conditions.push(condition.to_string());
+        }
+        
+        // Create labels
+        let else_label = self.new_label();
+        let end_label = self.new_label();
+        
+        // Skip both branches if the condition is unknown, and jump to else
+        // if it is false
+        self.emit_jump_if_unknown(end_label);
+        self.emit_jump_if_false(else_label);
+        
+        // A top-level condition holding means the rule fired
+        if top_level {
+            self.emit(Instruction::MarkFired);
+        }
+        
+        // Compile then block
+        self.compile_block(then_block)?;
+        
+        // Jump to end
+        self.emit_jump(end_label);
+        
+        // Else block
+        self.place_label(else_label);
+        if let Some(else_stmts) = else_block {
+            self.compile_block(else_stmts)?;
+        }
+        
+        // End label
+        self.place_label(end_label);
+        
+        Ok(())
+    }
+    
     fn compile_expression(&mut self, expr: &Expression) -> Result<(), CompilationError> {
         match expr {
-            Expression::Binary { left, op, right } => {
+            Expression::Binary { left, op: op @ (BinaryOp::In | BinaryOp::NotIn), right } => {
+                if let Some(index) = self.constant_set(right) {
+                    // A large constant list is a hash lookup, not a scan
+                    self.compile_expression(left)?;
+                    self.emit(Instruction::SetContains(index));
+                } else {
+                    // ArrayContains expects the haystack (array or string)
+                    // below the needle on the stack
+                    self.compile_expression(right)?;
+                    self.compile_expression(left)?;
+                    self.emit(Instruction::ArrayContains);
+                }
+                if *op == BinaryOp::NotIn {
+                    self.emit(Instruction::Not);
+                }
+            }
+            
+            Expression::Binary { left, op: op @ (BinaryOp::And | BinaryOp::Or), right } => {
+                // Short-circuit: skip the right operand once the left decides
+                // the result, otherwise combine the two, which lets an unknown
+                // left operand be settled by the right one
+                let short_label = self.new_label();
+                let end_label = self.new_label();
+                let is_and = *op == BinaryOp::And;
+                
                 self.compile_expression(left)?;
+                self.emit(if is_and {
+                    Instruction::ShortCircuitAnd(short_label)
+                } else {
+                    Instruction::ShortCircuitOr(short_label)
+                });
                 self.compile_expression(right)?;
+                self.emit(Self::binary_instruction(op));
+                self.emit_jump(end_label);
+                self.place_label(short_label);
+                self.emit(Instruction::Push(Value::Bool(!is_and)));
+                self.place_label(end_label);
+            }
+            
+            Expression::Binary { left, op: BinaryOp::Coalesce, right } => {
+                // The right operand is only evaluated when the left is null
+                let end_label = self.new_label();
                 
-                let instruction = match op {
-                    BinaryOp::Add => Instruction::Add,
-                    BinaryOp::Sub => Instruction::Sub,
-                    BinaryOp::Mul => Instruction::Mul,
-                    BinaryOp::Div => Instruction::Div,
-                    BinaryOp::Mod => Instruction::Mod,
-                    BinaryOp::Eq => Instruction::Eq,
-                    BinaryOp::Ne => Instruction::Ne,
-                    BinaryOp::Gt => Instruction::Gt,
-                    BinaryOp::Gte => Instruction::Gte,
-                    BinaryOp::Lt => Instruction::Lt,
-                    BinaryOp::Lte => Instruction::Lte,
-                    BinaryOp::And => Instruction::And,
-                    BinaryOp::Or => Instruction::Or,
-                };
+                self.compile_expression(left)?;
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::JumpIfNotNull(end_label));
+                self.emit(Instruction::Pop);
+                self.compile_expression(right)?;
+                self.place_label(end_label);
+            }
+            
+            Expression::Binary { left, op, right } => {
+                if matches!(
+                    op,
+                    BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Gt | BinaryOp::Gte | BinaryOp::Lt | BinaryOp::Lte
+                ) {
+                    self.check_comparison(left, right)?;
+                }
                 
-                self.emit(instruction);
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                self.emit(Self::binary_instruction(op));
+            }
+            
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                // Only the selected branch is evaluated; an unknown condition
+                // selects neither and yields Null
+                let else_label = self.new_label();
+                let unknown_label = self.new_label();
+                let end_label = self.new_label();
+                
+                self.compile_expression(condition)?;
+                self.emit_jump_if_unknown(unknown_label);
+                self.emit_jump_if_false(else_label);
+                self.compile_expression(then_expr)?;
+                self.emit_jump(end_label);
+                self.place_label(else_label);
+                self.compile_expression(else_expr)?;
+                self.emit_jump(end_label);
+                self.place_label(unknown_label);
+                self.emit(Instruction::Push(Value::Null));
+                self.place_label(end_label);
             }
             
             Expression::Unary { op, operand } => {
@@ -181,10 +675,17 @@ impl Compiler {
             Expression::FieldAccess { object, field } => {
                 match object.as_str() {
                     "profile" => {
-                        self.emit(Instruction::LoadProfileField(field.clone()));
+                        let id = self.env.symbols.intern(field);
+                        self.emit(Instruction::LoadProfileField(id));
                     }
                     "txn" | "transaction" => {
-                        self.emit(Instruction::LoadTxnField(field.clone()));
+                        let id = self.env.symbols.intern(field);
+                        self.emit(Instruction::LoadTxnField(id));
+                    }
+                    // Field of an object held in a local, e.g. a loop variable
+                    _ if self.locals.contains(object) => {
+                        self.emit(Instruction::LoadLocal(object.clone()));
+                        self.emit(Instruction::ObjectGet(field.clone()));
                     }
                     _ => {
                         return Err(CompilationError::UnknownField(format!(
@@ -195,19 +696,55 @@ impl Compiler {
                 }
             }
             
+            Expression::ObjectField { object, field } => {
+                // ObjectGet yields Null for non-objects, so a missing
+                // intermediate object makes the whole chain Null
+                self.compile_expression(object)?;
+                self.emit(Instruction::ObjectGet(field.clone()));
+            }
+            
             Expression::ArrayAccess { array, index } => {
                 self.compile_expression(array)?;
                 self.compile_expression(index)?;
                 self.emit(Instruction::ArrayAccess);
             }
             
+            Expression::FunctionCall { name, args } if name == "matches" => {
+                self.compile_matches(args)?;
+            }
+            
+            Expression::FunctionCall { name, args } if name == "suppressed" => {
+                self.compile_suppressed(args)?;
+            }
+            
             Expression::FunctionCall { name, args } => {
                 // Compile arguments
                 for arg in args {
                     self.compile_expression(arg)?;
                 }
                 
-                self.emit(Instruction::CallGlobal(name.clone(), args.len()));
+                // Builtins take precedence over user functions of the same name
+                match BuiltinFn::from_name(name) {
+                    Some(builtin) => self.emit(Instruction::CallBuiltin(builtin, args.len())),
+                    None => self.emit(Instruction::CallGlobal(name.clone(), args.len())),
+                }
+            }
+            
+            Expression::MethodCall { method, .. } if method == "push" => {
+                return Err(CompilationError::CompileError(
+                    "push() modifies an array in place and can only be used as a statement".to_string(),
+                ));
+            }
+            
+            Expression::MethodCall { object, method, args } if method == "any" || method == "all" => {
+                self.compile_quantifier(object, method == "all", args)?;
+            }
+            
+            Expression::Lambda { .. } => {
+                return Err(CompilationError::CompileError(format!(
+                    "'{}' can only be the argument of any() or all()",
+                    expr
+                )));
             }
             
             Expression::MethodCall {
@@ -223,27 +760,567 @@ impl Compiler {
                     self.compile_expression(arg)?;
                 }
                 
-                // Special handling for common array methods
-                if method == "includes" || method == "contains" {
+                // Membership and substring checks share ArrayContains
+                if (method == "includes" || method == "contains") && args.len() == 1 {
                     self.emit(Instruction::ArrayContains);
                 } else {
                     self.emit(Instruction::MethodCall(method.clone(), args.len()));
                 }
             }
             
+            Expression::ObjectLiteral(entries) => {
+                for (key, value) in entries {
+                    self.emit(Instruction::Push(Value::String(key.clone())));
+                    self.compile_expression(value)?;
+                }
+                self.emit(Instruction::BuildObject(entries.len()));
+            }
+            
+            Expression::ArrayLiteral(elements) => match Self::literal_values(elements) {
+                // An all-literal array is built once, at compile time
+                Some(values) => self.emit(Instruction::Push(Value::Array(values))),
+                None => {
+                    for element in elements {
+                        self.compile_expression(element)?;
+                    }
+                    self.emit(Instruction::BuildArray(elements.len()));
+                }
+            },
+            
             Expression::Literal(lit) => {
                 let value: Value = lit.clone().into();
                 self.emit(Instruction::Push(value));
             }
             
             Expression::Variable(name) => {
-                self.emit(Instruction::LoadLocal(name.clone()));
+                // Constants are inlined unless shadowed by a parameter or
+                // loop variable
+                match self.env.constants.get(name) {
+                    Some(value) if !self.locals.contains(name) => {
+                        self.emit(Instruction::Push(value.clone()));
+                    }
+                    // A function sees only its own locals, never the caller's
+                    None if self.in_function && !self.locals.contains(name) => {
+                        self.emit(Instruction::Push(Value::Null));
+                    }
+                    _ => self.emit(Instruction::LoadLocal(name.clone())),
+                }
             }
         }
         
         Ok(())
     }
     
+    /// Compile a call to a registered custom action
+    fn compile_custom_action(&mut self, name: &str, args: &[Expression]) -> Result<(), CompilationError> {
+        let params = self.env.actions.params(name).unwrap_or_default().to_vec();
+        if params.len() != args.len() {
+            return Err(CompilationError::CompileError(format!(
+                "action '{}' expects {} argument(s), got {}",
+                name,
+                params.len(),
+                args.len()
+            )));
+        }
+        
+        for arg in args {
+            self.compile_expression(arg)?;
+        }
+        self.emit(Instruction::CallAction(ActionType::Custom(name.to_string(), params), args.len()));
+        
+        Ok(())
+    }
+    
+    /// Compile `matches(value, pattern)`, compiling the pattern now so an
+    /// invalid one is a compile error rather than a runtime failure
+    fn compile_matches(&mut self, args: &[Expression]) -> Result<(), CompilationError> {
+        let [value, pattern] = args else {
+            return Err(CompilationError::CompileError(format!(
+                "matches() takes a value and a pattern, got {} argument(s)",
+                args.len()
+            )));
+        };
+        
+        let pattern = self.string_constant(pattern).ok_or_else(|| Self::non_literal_pattern(pattern))?;
+        let index = self.env.regexes.intern(&pattern).map_err(CompilationError::CompileError)?;
+        
+        self.compile_expression(value)?;
+        self.emit(Instruction::MatchRegex(index));
+        
+        Ok(())
+    }
+    
+    fn non_literal_pattern(pattern: &Expression) -> CompilationError {
+        CompilationError::CompileError(format!(
+            "matches() pattern must be a string literal or string constant, got '{}'",
+            pattern
+        ))
+    }
+    
+    /// The values of an array literal whose elements are all literals
+    fn literal_values(elements: &[Expression]) -> Option<Vec<Value>> {
+        elements
+            .iter()
+            .map(|element| match element {
+                Expression::Literal(lit) => Some(lit.clone().into()),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    /// The set-table index to test `x in expr` against, when `expr` is an
+    /// all-literal array or (unshadowed) array constant of at least
+    /// [`MIN_SET_LEN`] scalars
+    fn constant_set(&mut self, expr: &Expression) -> Option<usize> {
+        let values = match expr {
+            Expression::ArrayLiteral(elements) => Self::literal_values(elements)?,
+            Expression::Variable(name) if !self.locals.contains(name) => match self.env.constants.get(name) {
+                Some(Value::Array(values)) => values.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        
+        if values.len() < MIN_SET_LEN {
+            return None;
+        }
+        self.env.sets.intern(&values)
+    }
+    
+    /// Fields a top-level condition reads, if its result depends on nothing
+    /// else; see [`MemoTable`]
+    fn memo_reads(&mut self, condition: &Expression) -> Option<Vec<FieldRead>> {
+        let mut reads = Vec::new();
+        self.collect_reads(condition, &mut Vec::new(), &mut reads).then_some(reads)
+    }
+    
+    /// Add the fields `expr` reads to `reads`, returning false if its result
+    /// depends on anything else; `params` are the enclosing lambda parameters
+    fn collect_reads(&mut self, expr: &Expression, params: &mut Vec<String>, reads: &mut Vec<FieldRead>) -> bool {
+        match expr {
+            Expression::Literal(_) => true,
+            Expression::FieldAccess { object, field } => {
+                let read = match object.as_str() {
+                    "profile" => FieldRead::Profile(self.env.symbols.intern(field)),
+                    "txn" | "transaction" => FieldRead::Txn(self.env.symbols.intern(field)),
+                    _ => return params.contains(object),
+                };
+                if !reads.contains(&read) {
+                    reads.push(read);
+                }
+                true
+            }
+            Expression::Variable(name) => {
+                params.contains(name) || (!self.locals.contains(name) && self.env.constants.contains_key(name))
+            }
+            Expression::FunctionCall { name, args } => {
+                let pure = name == "matches"
+                    || BuiltinFn::from_name(name).is_some_and(|builtin| builtin != BuiltinFn::Now);
+                pure && args.iter().all(|arg| self.collect_reads(arg, params, reads))
+            }
+            Expression::Lambda { param, body } => {
+                params.push(param.clone());
+                let pure = self.collect_reads(body, params, reads);
+                params.pop();
+                pure
+            }
+            Expression::Binary { left, right, .. } => {
+                self.collect_reads(left, params, reads) && self.collect_reads(right, params, reads)
+            }
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                self.collect_reads(condition, params, reads)
+                    && self.collect_reads(then_expr, params, reads)
+                    && self.collect_reads(else_expr, params, reads)
+            }
+            Expression::Unary { operand, .. } => self.collect_reads(operand, params, reads),
+            Expression::ObjectField { object, .. } => self.collect_reads(object, params, reads),
+            Expression::ArrayAccess { array, index } => {
+                self.collect_reads(array, params, reads) && self.collect_reads(index, params, reads)
+            }
+            Expression::MethodCall { object, args, .. } => {
+                self.collect_reads(object, params, reads) && args.iter().all(|arg| self.collect_reads(arg, params, reads))
+            }
+            Expression::ObjectLiteral(entries) => entries.iter().all(|(_, value)| self.collect_reads(value, params, reads)),
+            Expression::ArrayLiteral(elements) => elements.iter().all(|element| self.collect_reads(element, params, reads)),
+        }
+    }
+    
+    /// Point each `MemoCondition` at the `TraceCondition` ending its
+    /// condition, where a cached result is pushed
+    fn set_memo_skips(bytecode: &mut [Instruction]) {
+        for start in 0..bytecode.len() {
+            let Instruction::MemoCondition(entry, _) = bytecode[start] else {
+                continue;
+            };
+            if let Some(offset) = bytecode[start..].iter().position(|i| matches!(i, Instruction::TraceCondition(_))) {
+                bytecode[start] = Instruction::MemoCondition(entry, offset);
+            }
+        }
+    }
+    
+    /// The string a literal or (unshadowed) string constant stands for
+    fn string_constant(&self, expr: &Expression) -> Option<String> {
+        match expr {
+            Expression::Literal(Literal::String(s)) => Some(s.clone()),
+            Expression::Variable(name) if !self.locals.contains(name) => match self.env.constants.get(name) {
+                Some(Value::String(s)) => Some(s.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    
+    /// Compile `suppress(key)`: record the current time in the profile, as
+    /// `profile._suppress_<key> = now()`
+    fn compile_suppress(&mut self, args: &[Expression]) -> Result<(), CompilationError> {
+        let [key] = args else {
+            return Err(CompilationError::CompileError(format!(
+                "suppress() takes a key, got {} argument(s)",
+                args.len()
+            )));
+        };
+        
+        let field = self.suppression_field("suppress", key)?;
+        self.emit(Instruction::CallBuiltin(BuiltinFn::Now, 0));
+        self.emit(Instruction::StoreProfileField(field));
+        
+        Ok(())
+    }
+    
+    /// Compile `suppressed(key, window_hours)`: whether `suppress(key)` ran
+    /// less than `window_hours` ago, i.e.
+    /// `hoursBetween(profile._suppress_<key>, now()) < window_hours`, which
+    /// is false when the key was never suppressed
+    fn compile_suppressed(&mut self, args: &[Expression]) -> Result<(), CompilationError> {
+        let [key, window] = args else {
+            return Err(CompilationError::CompileError(format!(
+                "suppressed() takes a key and a window in hours, got {} argument(s)",
+                args.len()
+            )));
+        };
+        
+        let field = self.suppression_field("suppressed", key)?;
+        self.emit(Instruction::LoadProfileField(field));
+        self.emit(Instruction::CallBuiltin(BuiltinFn::Now, 0));
+        self.emit(Instruction::CallBuiltin(BuiltinFn::HoursBetween, 2));
+        self.compile_expression(window)?;
+        self.emit(Instruction::Lt);
+        
+        Ok(())
+    }
+    
+    /// Profile field holding the time of the last `suppress(key)`
+    ///
+    /// The key must be known at compile time so the field can be interned.
+    fn suppression_field(&mut self, function: &str, key: &Expression) -> Result<SymbolId, CompilationError> {
+        let name = self.string_constant(key).ok_or_else(|| {
+            CompilationError::CompileError(format!(
+                "{}() key must be a string literal or string constant, got '{}'",
+                function, key
+            ))
+        })?;
+        Ok(self.env.symbols.intern(&format!("_suppress_{}", name)))
+    }
+    
+    /// Compile `array.any(x => predicate)` or `array.all(x => predicate)`
+    ///
+    /// Loops like `for`, binding each element to the lambda's parameter, and
+    /// stops at the first element that decides the result. A non-array has no
+    /// elements, so `any` is false and `all` is true.
+    fn compile_quantifier(
+        &mut self,
+        array: &Expression,
+        is_all: bool,
+        args: &[Expression],
+    ) -> Result<(), CompilationError> {
+        let [Expression::Lambda { param, body }] = args else {
+            return Err(CompilationError::CompileError(format!(
+                "{}() takes a lambda such as `x => x > 0`",
+                if is_all { "all" } else { "any" }
+            )));
+        };
+        
+        let loop_id = self.new_label();
+        let iter_var = format!("$iter{}", loop_id);
+        let index_var = format!("$index{}", loop_id);
+        
+        // The parameter and the hidden iteration locals live in their own
+        // scope, so an outer local of the same name is untouched and the
+        // array is dropped once the quantifier is decided. Each is declared
+        // once; the loop then updates it with plain stores.
+        self.emit(Instruction::EnterScope);
+        self.compile_expression(array)?;
+        self.emit(Instruction::DeclareLocal(iter_var.clone()));
+        self.emit(Instruction::Push(Value::Int(0)));
+        self.emit(Instruction::DeclareLocal(index_var.clone()));
+        self.emit(Instruction::Push(Value::Null));
+        self.emit(Instruction::DeclareLocal(param.clone()));
+        
+        let start_label = self.new_label();
+        let exhausted_label = self.new_label();
+        let decided_label = self.new_label();
+        let end_label = self.new_label();
+        
+        self.place_label(start_label);
+        self.emit(Instruction::LoadLocal(index_var.clone()));
+        self.emit(Instruction::LoadLocal(iter_var.clone()));
+        self.emit(Instruction::ArrayLength);
+        self.emit(Instruction::Lt);
+        self.emit_jump_if_false(exhausted_label);
+        
+        self.emit(Instruction::LoadLocal(iter_var));
+        self.emit(Instruction::LoadLocal(index_var.clone()));
+        self.emit(Instruction::ArrayAccess);
+        self.emit(Instruction::StoreLocal(param.clone()));
+        
+        // The parameter shadows a constant of the same name only inside the
+        // predicate
+        let newly_bound = self.locals.insert(param.clone());
+        self.compile_expression(body)?;
+        if newly_bound {
+            self.locals.remove(param);
+        }
+        
+        // `any` is decided by a true element, `all` by a false one
+        if is_all {
+            self.emit_jump_if_false(decided_label);
+        } else {
+            self.emit_jump_if_true(decided_label);
+        }
+        
+        self.emit(Instruction::LoadLocal(index_var.clone()));
+        self.emit(Instruction::Push(Value::Int(1)));
+        self.emit(Instruction::Add);
+        self.emit(Instruction::StoreLocal(index_var));
+        self.emit_jump(start_label);
+        
+        self.place_label(exhausted_label);
+        self.emit(Instruction::Push(Value::Bool(is_all)));
+        self.emit_jump(end_label);
+        self.place_label(decided_label);
+        self.emit(Instruction::Push(Value::Bool(!is_all)));
+        self.place_label(end_label);
+        self.emit(Instruction::ExitScope);
+        
+        Ok(())
+    }
+    
+    /// Compile `target.push(item)` or `target.push(item, max_len)`
+    ///
+    /// Appending writes the whole array back to the target, so the target
+    /// must be assignable: a profile/txn field or a local variable.
+    fn compile_push(&mut self, object: &Expression, args: &[Expression]) -> Result<(), CompilationError> {
+        let target = match object {
+            Expression::FieldAccess { object, field }
+                if matches!(object.as_str(), "profile" | "txn" | "transaction") =>
+            {
+                format!("{}.{}", object, field)
+            }
+            Expression::Variable(name) => name.clone(),
+            other => {
+                return Err(CompilationError::CompileError(format!(
+                    "push() needs a profile/txn field or a local variable, got '{}'",
+                    other
+                )));
+            }
+        };
+        if !(1..=2).contains(&args.len()) {
+            return Err(CompilationError::CompileError(format!(
+                "push() takes an item and an optional max length, got {} argument(s)",
+                args.len()
+            )));
+        }
+        self.check_assignable(&target)?;
+        
+        self.emit_load(&target);
+        for arg in args {
+            self.compile_expression(arg)?;
+        }
+        self.emit(Instruction::ArrayPush(args.len() == 2));
+        self.emit_store(&target);
+        
+        Ok(())
+    }
+    
+    /// Compile a loop body where `continue` jumps to `continue_label` and
+    /// `break` to `break_label`
+    fn compile_loop_body(
+        &mut self,
+        body: &[Statement],
+        continue_label: usize,
+        break_label: usize,
+    ) -> Result<(), CompilationError> {
+        self.loops.push((continue_label, break_label, self.scope_depth));
+        let result = self.compile_block(body);
+        self.loops.pop();
+        result
+    }
+    
+    /// Compile the statements of a nested block
+    ///
+    /// A block that declares locals with `let` is wrapped in
+    /// `EnterScope`/`ExitScope`, so they go out of scope at its end and
+    /// outer locals they shadowed come back. Blocks without one cost
+    /// nothing extra.
+    fn compile_block(&mut self, block: &[Statement]) -> Result<(), CompilationError> {
+        let declared: Vec<&String> = block
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Let { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect();
+        if declared.is_empty() {
+            return block.iter().try_for_each(|stmt| self.compile_statement(stmt));
+        }
+        
+        // Names only this block binds stop shadowing constants after it
+        let unbound: Vec<&String> = declared.into_iter().filter(|name| !self.locals.contains(*name)).collect();
+        self.emit(Instruction::EnterScope);
+        self.scope_depth += 1;
+        let result = block.iter().try_for_each(|stmt| self.compile_statement(stmt));
+        self.scope_depth -= 1;
+        self.emit(Instruction::ExitScope);
+        for name in unbound {
+            self.locals.remove(name);
+        }
+        result
+    }
+    
+    /// Reject a comparison between a schema field and an operand whose type
+    /// is known to be incompatible, e.g. `txn.amount > "1000"`
+    ///
+    /// Only literals, constants and schema fields have a known type, and at
+    /// least one side must be a schema field, so rules compiled without a
+    /// schema are never affected.
+    fn check_comparison(&self, left: &Expression, right: &Expression) -> Result<(), CompilationError> {
+        let (Some((left_type, left_declared)), Some((right_type, right_declared))) =
+            (self.static_type(left), self.static_type(right))
+        else {
+            return Ok(());
+        };
+        if !(left_declared || right_declared) || left_type.is_comparable_with(right_type) {
+            return Ok(());
+        }
+        
+        // The declared field sets the expectation
+        let ((expected, expected_expr), (actual, actual_expr)) = if left_declared {
+            ((left_type, left), (right_type, right))
+        } else {
+            ((right_type, right), (left_type, left))
+        };
+        Err(CompilationError::TypeMismatch {
+            expected: format!("{} ({})", expected, expected_expr),
+            actual: format!("{} ({})", actual, actual_expr),
+        })
+    }
+    
+    /// Type of an expression when known at compile time, and whether it
+    /// comes from the schema
+    fn static_type(&self, expr: &Expression) -> Option<(ValueType, bool)> {
+        match expr {
+            Expression::Literal(Literal::Null) => None,
+            Expression::Literal(literal) => Some((Value::from(literal.clone()).value_type(), false)),
+            Expression::Variable(name) if !self.locals.contains(name) => {
+                match self.env.constants.get(name)? {
+                    Value::Null => None,
+                    value => Some((value.value_type(), false)),
+                }
+            }
+            Expression::FieldAccess { object, field } => {
+                self.env.schema.get(object, field).map(|value_type| (value_type, true))
+            }
+            _ => None,
+        }
+    }
+    
+    /// Constants cannot be reassigned
+    fn check_assignable(&self, target: &str) -> Result<(), CompilationError> {
+        if self.env.constants.contains_key(target) {
+            return Err(CompilationError::CompileError(format!(
+                "cannot assign to constant '{}'",
+                target
+            )));
+        }
+        Ok(())
+    }
+    
+    /// Instruction for a binary operator whose operands are already on the stack
+    fn binary_instruction(op: &BinaryOp) -> Instruction {
+        match op {
+            BinaryOp::Add => Instruction::Add,
+            BinaryOp::Sub => Instruction::Sub,
+            BinaryOp::Mul => Instruction::Mul,
+            BinaryOp::Div => Instruction::Div,
+            BinaryOp::Mod => Instruction::Mod,
+            BinaryOp::Pow => Instruction::Pow,
+            BinaryOp::Eq => Instruction::Eq,
+            BinaryOp::Ne => Instruction::Ne,
+            BinaryOp::Gt => Instruction::Gt,
+            BinaryOp::Gte => Instruction::Gte,
+            BinaryOp::Lt => Instruction::Lt,
+            BinaryOp::Lte => Instruction::Lte,
+            BinaryOp::In => Instruction::ArrayContains,
+            BinaryOp::NotIn => unreachable!("`not in` is compiled as ArrayContains + Not"),
+            BinaryOp::And => Instruction::And,
+            BinaryOp::Or => Instruction::Or,
+            BinaryOp::Coalesce => unreachable!("`??` is compiled as Dup + JumpIfNotNull"),
+            BinaryOp::BitAnd => Instruction::BitAnd,
+            BinaryOp::BitOr => Instruction::BitOr,
+            BinaryOp::BitXor => Instruction::BitXor,
+            BinaryOp::Shl => Instruction::Shl,
+            BinaryOp::Shr => Instruction::Shr,
+        }
+    }
+    
+    /// Load an assignment target (profile.x, txn.x or a local) onto the stack
+    fn emit_load(&mut self, target: &str) {
+        if let Some(field) = target.strip_prefix("profile.") {
+            let id = self.env.symbols.intern(field);
+            self.emit(Instruction::LoadProfileField(id));
+        } else if let Some(field) = target
+            .strip_prefix("txn.")
+            .or_else(|| target.strip_prefix("transaction."))
+        {
+            let id = self.env.symbols.intern(field);
+            self.emit(Instruction::LoadTxnField(id));
+        } else {
+            self.emit(Instruction::LoadLocal(target.to_string()));
+        }
+    }
+    
+    /// Store the top of the stack into an assignment target
+    fn emit_store(&mut self, target: &str) {
+        if let Some(field) = target.strip_prefix("profile.") {
+            let id = self.env.symbols.intern(field);
+            self.emit(Instruction::StoreProfileField(id));
+        } else if let Some(field) = target
+            .strip_prefix("txn.")
+            .or_else(|| target.strip_prefix("transaction."))
+        {
+            let id = self.env.symbols.intern(field);
+            self.emit(Instruction::StoreTxnField(id));
+        } else {
+            self.locals.insert(target.to_string());
+            self.emit(Instruction::StoreLocal(target.to_string()));
+        }
+    }
+    
+    /// Note that the next statement starts at the next instruction
+    ///
+    /// Statements are visited in the same pre-order the parser recorded their
+    /// lines in. A statement that emitted nothing gives way to the next one.
+    fn mark_statement(&mut self) {
+        if let Some(&line) = self.statement_lines.get(self.statements_seen) {
+            let pc = self.instructions.len();
+            if self.line_map.last().is_some_and(|&(start, _)| start == pc) {
+                self.line_map.pop();
+            }
+            self.line_map.push((pc, line));
+        }
+        self.statements_seen += 1;
+    }
+    
     fn emit(&mut self, instruction: Instruction) {
         self.instructions.push(instruction);
     }
@@ -253,7 +1330,59 @@ impl Compiler {
     }
     
     fn emit_jump_if_false(&mut self, label: usize) {
-        self.emit(Instruction::JumpIfFalse(label));
+        self.emit_conditional_jump(label, false);
+    }
+    
+    fn emit_jump_if_true(&mut self, label: usize) {
+        self.emit_conditional_jump(label, true);
+    }
+    
+    /// Emit a jump taken, under SQL null semantics, when the condition on
+    /// the stack is unknown
+    ///
+    /// Negating an unknown leaves it unknown, so a trailing `Not` moves after
+    /// this jump, where the conditional jump that follows can fold it.
+    fn emit_jump_if_unknown(&mut self, label: usize) {
+        let negated = self.take_trailing_not();
+        self.emit(Instruction::JumpIfUnknown(label));
+        if negated {
+            self.emit(Instruction::Not);
+        }
+    }
+    
+    /// Emit a jump taken when the value on the stack is `when`
+    ///
+    /// A `Not` just before the jump is folded into the opposite jump, so
+    /// `if (!cond)` costs one instruction instead of two. That only holds
+    /// once a `JumpIfUnknown` has taken unknowns off the stack: under SQL
+    /// null semantics `!` keeps an unknown unknown, which the plain and the
+    /// opposite jump read differently.
+    fn emit_conditional_jump(&mut self, label: usize, when: bool) {
+        let checked = self
+            .instructions
+            .len()
+            .checked_sub(2)
+            .is_some_and(|index| matches!(self.instructions[index], Instruction::JumpIfUnknown(_)));
+        let negated = checked && self.take_trailing_not();
+        self.emit(if when != negated {
+            Instruction::JumpIfTrue(label)
+        } else {
+            Instruction::JumpIfFalse(label)
+        });
+    }
+    
+    /// Remove the last instruction if it is a `Not`, returning whether it was
+    ///
+    /// A `Not` that a label points at stays, since a jump landing there
+    /// expects the value un-negated.
+    fn take_trailing_not(&mut self) -> bool {
+        let position = self.instructions.len();
+        let negated = self.instructions.last() == Some(&Instruction::Not)
+            && self.labels.last().is_none_or(|&(_, labelled)| labelled < position);
+        if negated {
+            self.instructions.pop();
+        }
+        negated
     }
     
     fn new_label(&mut self) -> usize {
@@ -271,7 +1400,14 @@ impl Compiler {
         // Replace label IDs with actual instruction indices
         for instruction in &mut self.instructions {
             match instruction {
-                Instruction::Jump(label) | Instruction::JumpIfFalse(label) => {
+                Instruction::Jump(label)
+                | Instruction::JumpIfFalse(label)
+                | Instruction::JumpIfTrue(label)
+                | Instruction::JumpIfNotNull(label)
+                | Instruction::JumpIfUnknown(label)
+                | Instruction::ShortCircuitAnd(label)
+                | Instruction::ShortCircuitOr(label)
+                | Instruction::LoopGuard(label) => {
                     if let Some((_, pos)) = self.labels.iter().find(|(l, _)| l == label) {
                         *label = *pos;
                     }
@@ -294,32 +1430,396 @@ mod tests {
             id: "test".to_string(),
             priority: 100,
             enabled: true,
+            enabled_when: None,
+            tags: Vec::new(),
             body: vec![Statement::Assignment {
                 target: "profile.count".to_string(),
                 value: Expression::Literal(Literal::Int(42)),
             }],
+            lines: Vec::new(),
         };
         
-        let compiled = Compiler::compile_rule(&rule).unwrap();
+        let compiled = Compiler::compile_rule(&rule, &mut CompileEnv::default()).unwrap();
         assert!(!compiled.bytecode.is_empty());
     }
 
+    #[test]
+    fn test_compile_compound_assignment() {
+        let rule = RuleNode {
+            id: "test".to_string(),
+            priority: 100,
+            enabled: true,
+            enabled_when: None,
+            tags: Vec::new(),
+            body: vec![Statement::CompoundAssignment {
+                target: "profile.count".to_string(),
+                op: BinaryOp::Add,
+                value: Expression::Literal(Literal::Int(1)),
+            }],
+            lines: Vec::new(),
+        };
+        
+        let mut env = CompileEnv::default();
+        let compiled = Compiler::compile_rule(&rule, &mut env).unwrap();
+        let count = env.symbols.intern("count");
+        
+        assert_eq!(env.symbols.len(), 1);
+        assert_eq!(
+            compiled.bytecode,
+            vec![
+                Instruction::LoadProfileField(count),
+                Instruction::Push(Value::Int(1)),
+                Instruction::Add,
+                Instruction::StoreProfileField(count),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_map_follows_folding() {
+        let program = crate::parser::parse(
+            "rule \"t\" {\n  if (true) {\n    profile.x = 1 + 2 * 3;\n    profile.y = txn.a / 0;\n  }\n}",
+        )
+        .unwrap();
+        
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut CompileEnv::default()).unwrap();
+        
+        // `1 + 2 * 3` folds to one Push, so `profile.y` starts two
+        // instructions after `profile.x`
+        assert_eq!(compiled.lines, vec![(0, 2), (5, 3), (7, 4)]);
+        assert_eq!(compiled.line_at(6), Some(3));
+        assert_eq!(compiled.line_at(9), Some(4));
+    }
+
+    #[test]
+    fn test_negated_condition_folds_into_jump() {
+        let program = crate::parser::parse(
+            r#"rule "t" { if (true) { if (!txn.blocked) { profile.x = 1; } while (!(txn.n > 3)) { txn.n += 1; } } }"#,
+        )
+        .unwrap();
+        
+        let mut env = CompileEnv::default();
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut env).unwrap();
+        let blocked = env.symbols.intern("blocked");
+        
+        assert!(!compiled.bytecode.contains(&Instruction::Not));
+        assert_eq!(
+            compiled.bytecode[5..8],
+            [Instruction::LoadTxnField(blocked), Instruction::JumpIfUnknown(11), Instruction::JumpIfTrue(11)]
+        );
+        
+        // A jump landing between `Not` and the branch keeps the `Not`:
+        // the ternary's then-value is tested un-negated
+        let program = crate::parser::parse(
+            r#"rule "t" { if (true) { if (txn.c ? txn.x : !txn.y) { profile.x = 1; } } }"#,
+        )
+        .unwrap();
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut CompileEnv::default()).unwrap();
+        
+        assert!(compiled.bytecode.contains(&Instruction::Not));
+        assert!(!compiled.bytecode.iter().any(|i| matches!(i, Instruction::JumpIfTrue(_))));
+    }
+
+    #[test]
+    fn test_dangling_else_binds_to_inner_if() {
+        // Braces are mandatory, so the `else` can only belong to `if (txn.b)`
+        let program = crate::parser::parse(
+            r#"rule "t" { if (txn.a) { if (txn.b) { createComment("then"); } else { createComment("else"); } } }"#,
+        )
+        .unwrap();
+        
+        let mut env = CompileEnv::default();
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut env).unwrap();
+        let (a, b) = (env.symbols.intern("a"), env.symbols.intern("b"));
+        
+        assert_eq!(
+            compiled.bytecode,
+            vec![
+                Instruction::MemoCondition(0, 2), // only the top-level condition is memoizable
+                Instruction::LoadTxnField(a),
+                Instruction::TraceCondition(0),
+                Instruction::JumpIfUnknown(15),
+                Instruction::JumpIfFalse(15), // outer if: no else, skip everything
+                Instruction::MarkFired,
+                Instruction::LoadTxnField(b),
+                Instruction::JumpIfUnknown(14),
+                Instruction::JumpIfFalse(12), // inner if: to its else block
+                Instruction::LoadConst(0), // "then"
+                Instruction::CallAction(ActionType::CreateComment, 1),
+                Instruction::Jump(14),
+                Instruction::LoadConst(1), // "else"
+                Instruction::CallAction(ActionType::CreateComment, 1),
+                Instruction::Jump(15), // end of the outer then block
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_short_circuit_and() {
+        let rule = RuleNode {
+            id: "test".to_string(),
+            priority: 100,
+            enabled: true,
+            enabled_when: None,
+            tags: Vec::new(),
+            body: vec![Statement::Assignment {
+                target: "result".to_string(),
+                value: Expression::Binary {
+                    left: Box::new(Expression::Literal(Literal::Bool(false))),
+                    op: BinaryOp::And,
+                    right: Box::new(Expression::FunctionCall {
+                        name: "expensive".to_string(),
+                        args: vec![],
+                    }),
+                },
+            }],
+            lines: Vec::new(),
+        };
+        
+        let compiled = Compiler::compile_rule(&rule, &mut CompileEnv::default()).unwrap();
+        
+        assert_eq!(
+            compiled.bytecode,
+            vec![
+                Instruction::Push(Value::Bool(false)),
+                Instruction::ShortCircuitAnd(5),
+                Instruction::CallGlobal("expensive".to_string(), 0),
+                Instruction::And,
+                Instruction::Jump(6),
+                Instruction::Push(Value::Bool(false)),
+                Instruction::StoreLocal("result".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_if_statement() {
         let rule = RuleNode {
             id: "test".to_string(),
             priority: 100,
             enabled: true,
+            enabled_when: None,
+            tags: Vec::new(),
             body: vec![Statement::IfStatement {
                 condition: Expression::Literal(Literal::Bool(true)),
-                then_block: vec![Statement::Return],
+                then_block: vec![Statement::Return(None)],
                 else_block: None,
             }],
+            lines: Vec::new(),
         };
         
-        let compiled = Compiler::compile_rule(&rule).unwrap();
+        let compiled = Compiler::compile_rule(&rule, &mut CompileEnv::default()).unwrap();
         
         // Should have: Push(true), JumpIfFalse, Return, label
         assert!(compiled.bytecode.len() >= 2);
     }
+
+    #[test]
+    fn test_top_level_conditions_are_traced() {
+        let nested = Statement::IfStatement {
+            condition: Expression::Variable("inner".to_string()),
+            then_block: vec![Statement::Return(None)],
+            else_block: None,
+        };
+        let rule = RuleNode {
+            id: "test".to_string(),
+            priority: 100,
+            enabled: true,
+            enabled_when: None,
+            tags: Vec::new(),
+            body: vec![Statement::IfStatement {
+                condition: Expression::Binary {
+                    left: Box::new(Expression::FieldAccess {
+                        object: "txn".to_string(),
+                        field: "amount".to_string(),
+                    }),
+                    op: BinaryOp::Gt,
+                    right: Box::new(Expression::Literal(Literal::Int(1000))),
+                },
+                then_block: vec![nested],
+                else_block: None,
+            }],
+            lines: Vec::new(),
+        };
+        
+        let compiled = Compiler::compile_rule(&rule, &mut CompileEnv::default()).unwrap();
+        let traced = compiled
+            .bytecode
+            .iter()
+            .filter(|i| matches!(i, Instruction::TraceCondition(_)))
+            .count();
+        
+        assert_eq!(compiled.conditions, vec!["txn.amount > 1000".to_string()]);
+        assert_eq!(traced, 1);
+    }
+
+    #[test]
+    fn test_literal_operands_are_folded() {
+        // profile.limit = 100 * 10 + 24;
+        let rule = RuleNode {
+            id: "test".to_string(),
+            priority: 100,
+            enabled: true,
+            enabled_when: None,
+            tags: Vec::new(),
+            body: vec![Statement::Assignment {
+                target: "profile.limit".to_string(),
+                value: Expression::Binary {
+                    left: Box::new(Expression::Binary {
+                        left: Box::new(Expression::Literal(Literal::Int(100))),
+                        op: BinaryOp::Mul,
+                        right: Box::new(Expression::Literal(Literal::Int(10))),
+                    }),
+                    op: BinaryOp::Add,
+                    right: Box::new(Expression::Literal(Literal::Int(24))),
+                },
+            }],
+            lines: Vec::new(),
+        };
+        
+        let mut env = CompileEnv::default();
+        let compiled = Compiler::compile_rule(&rule, &mut env).unwrap();
+        
+        assert_eq!(
+            compiled.bytecode,
+            vec![
+                Instruction::Push(Value::Int(1024)),
+                Instruction::StoreProfileField(env.symbols.intern("limit")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negative_literal_is_a_single_push() {
+        let program = crate::parser::parse(
+            r#"rule "t" { if (txn.country == "US") { profile.delta = -5; } }"#,
+        )
+        .unwrap();
+        
+        let mut env = CompileEnv::default();
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut env).unwrap();
+        
+        assert!(!compiled.bytecode.contains(&Instruction::Neg));
+        let pushes: Vec<_> = compiled.bytecode.iter().filter(|i| **i == Instruction::Push(Value::Int(-5))).collect();
+        assert_eq!(pushes.len(), 1);
+        
+        // The literal's type is visible to schema checks
+        let program = crate::parser::parse(r#"rule "t" { if (txn.country == -5) { } }"#).unwrap();
+        let mut env = CompileEnv {
+            schema: Schema::new().with_field("txn.country", ValueType::String),
+            ..CompileEnv::default()
+        };
+        let err = Compiler::compile_rule(&program.rules[0], &mut env).unwrap_err();
+        assert!(matches!(err, CompilationError::TypeMismatch { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_large_constant_lists_use_sets() {
+        let list = (0..MIN_SET_LEN).map(|i| format!("\"c{}\"", i)).collect::<Vec<_>>().join(", ");
+        let source = format!(
+            r#"
+            const DENY = [{list}];
+            rule "t" {{
+                if (txn.a in [{list}] || txn.b not in DENY || txn.c in ["x", "y"]) {{ }}
+                for (DENY in txn.lists) {{ if (txn.d in DENY) {{ }} }}
+            }}
+        "#
+        );
+        let program = crate::parser::parse(&source).unwrap();
+        
+        let mut env = CompileEnv::from_constants(&program.constants).unwrap();
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut env).unwrap();
+        
+        // The literal and the constant hold the same values, so share a set
+        let set_lookups = compiled.bytecode.iter().filter(|i| **i == Instruction::SetContains(0)).count();
+        assert_eq!(set_lookups, 2);
+        assert_eq!(env.sets.len(), 1);
+        
+        // A short list, and a loop variable shadowing the constant, are scanned
+        let scans = compiled.bytecode.iter().filter(|i| **i == Instruction::ArrayContains).count();
+        assert_eq!(scans, 2);
+        let short_list = env.pool.intern(Value::from(vec!["x", "y"]));
+        assert!(compiled.bytecode.contains(&Instruction::LoadConst(short_list)));
+        assert!(!compiled.bytecode.iter().any(|i| matches!(i, Instruction::BuildArray(_))));
+    }
+
+    #[test]
+    fn test_memoizable_conditions() {
+        let program = crate::parser::parse(
+            r#"
+            const LIMIT = 500;
+            function risky(x) { return x > 1; }
+            rule "t" {
+                if (txn.amount > LIMIT && profile.country != txn.country) { }
+                if (txn.items.any(i => i.amount > txn.amount)) { }
+                if (risky(txn.amount)) { }
+                if (hoursBetween(now(), profile.last_seen) < 1) { }
+                if (LIMIT > 100) { }
+                for (item in txn.items) { if (item.amount > 1) { } }
+            }
+        "#,
+        )
+        .unwrap();
+        
+        let mut env = CompileEnv::from_constants(&program.constants).unwrap();
+        let compiled = Compiler::compile_rule(&program.rules[0], &mut env).unwrap();
+        let field = |name: &str| env.symbols.get(name).unwrap();
+        
+        // Only the first two conditions depend on nothing but fields; a
+        // function call, `now()`, a constant condition and a nested `if`
+        // are evaluated every time
+        assert_eq!(env.memo.len(), 2);
+        assert_eq!(
+            env.memo.reads(0),
+            Some(&[FieldRead::Txn(field("amount")), FieldRead::Profile(field("country")), FieldRead::Txn(field("country"))][..])
+        );
+        assert_eq!(env.memo.reads(1), Some(&[FieldRead::Txn(field("items")), FieldRead::Txn(field("amount"))][..]));
+        
+        // Each skip lands on the condition's TraceCondition
+        for (pc, instruction) in compiled.bytecode.iter().enumerate() {
+            if let Instruction::MemoCondition(entry, skip) = instruction {
+                assert_eq!(compiled.bytecode[pc + skip], Instruction::TraceCondition(*entry));
+            }
+        }
+    }
+    
+    #[test]
+    fn test_schema_checks_comparisons() {
+        let field = |object: &str, field: &str| Expression::FieldAccess {
+            object: object.to_string(),
+            field: field.to_string(),
+        };
+        let compare = |left, right| Expression::Binary {
+            left: Box::new(left),
+            op: BinaryOp::Lt,
+            right: Box::new(right),
+        };
+        
+        let mut env = CompileEnv {
+            schema: Schema::new()
+                .with_field("txn.amount", ValueType::Float)
+                .with_field("profile.country", ValueType::String),
+            ..CompileEnv::default()
+        };
+        env.constants.insert("LIMIT".to_string(), Value::String("high".to_string()));
+        let mut compiler = Compiler::new(&mut env);
+        
+        let err = compiler
+            .compile_expression(&compare(Expression::Variable("LIMIT".to_string()), field("transaction", "amount")))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch: expected float (transaction.amount), got string (LIMIT)"
+        );
+        
+        let err = compiler
+            .compile_expression(&compare(field("profile", "country"), field("txn", "amount")))
+            .unwrap_err();
+        assert!(matches!(err, CompilationError::TypeMismatch { expected, .. } if expected == "string (profile.country)"));
+        
+        // Numeric types mix, and undeclared fields and null are not checked
+        compiler.compile_expression(&compare(field("txn", "amount"), Expression::Literal(Literal::Int(5)))).unwrap();
+        compiler.compile_expression(&compare(field("txn", "other"), Expression::Literal(Literal::Bool(true)))).unwrap();
+        compiler.compile_expression(&compare(field("txn", "amount"), Expression::Literal(Literal::Null))).unwrap();
+    }
 }
@@ -0,0 +1,97 @@
+// src/compiler/limits.rs
+//! Size limits that keep hostile or runaway rule sources from exhausting
+//! the compiler
+
+use crate::parser::Program;
+use crate::CompilationError;
+
+/// Limits on the size of a program and the bytecode it compiles to
+///
+/// Exceeding any of them is a [`CompilationError::CompileError`]. The
+/// defaults are far above what hand-written rules need; lower them when
+/// hosting rules written by untrusted tenants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileLimits {
+    /// Rules in the program, grouped rules included
+    pub max_rules: usize,
+    /// Statements in one rule, nested ones included
+    pub max_statements_per_rule: usize,
+    /// How deeply expressions (parentheses, operators) and blocks may nest;
+    /// also bounds the parser's recursion
+    pub max_expression_depth: usize,
+    /// Bytecode instructions across all rules and functions
+    pub max_instructions: usize,
+}
+
+impl Default for CompileLimits {
+    fn default() -> Self {
+        Self {
+            max_rules: 10_000,
+            max_statements_per_rule: 10_000,
+            max_expression_depth: 64,
+            max_instructions: 1_000_000,
+        }
+    }
+}
+
+impl CompileLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_rules(mut self, max: usize) -> Self {
+        self.max_rules = max;
+        self
+    }
+
+    pub fn with_max_statements_per_rule(mut self, max: usize) -> Self {
+        self.max_statements_per_rule = max;
+        self
+    }
+
+    pub fn with_max_expression_depth(mut self, max: usize) -> Self {
+        self.max_expression_depth = max;
+        self
+    }
+
+    pub fn with_max_instructions(mut self, max: usize) -> Self {
+        self.max_instructions = max;
+        self
+    }
+
+    /// Check the rule count and the statements in each rule
+    pub fn check_program(&self, program: &Program) -> Result<(), CompilationError> {
+        let rules = program.rules.iter().chain(program.groups.iter().flat_map(|g| &g.rules));
+        self.check_rule_count(rules.clone().count())?;
+
+        for rule in rules {
+            // The parser records one line per statement, nested ones included
+            Self::check(
+                &format!("statements in rule '{}'", rule.id),
+                rule.lines.len(),
+                self.max_statements_per_rule,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn check_rule_count(&self, count: usize) -> Result<(), CompilationError> {
+        Self::check("rules", count, self.max_rules)
+    }
+
+    /// Check the total number of compiled instructions
+    pub fn check_instructions(&self, count: usize) -> Result<(), CompilationError> {
+        Self::check("bytecode instructions", count, self.max_instructions)
+    }
+
+    fn check(what: &str, count: usize, max: usize) -> Result<(), CompilationError> {
+        if count > max {
+            return Err(CompilationError::CompileError(format!(
+                "too many {}: {} (the limit is {})",
+                what, count, max
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+// src/compiler/memo.rs
+//! Read sets of top-level conditions whose results can be cached
+
+use crate::compiler::symbols::SymbolId;
+use serde::{Deserialize, Serialize};
+
+/// A field read by a memoizable condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldRead {
+    Profile(SymbolId),
+    Txn(SymbolId),
+}
+
+/// Fields read by each memoizable condition, referenced by `MemoCondition`
+/// instructions by index
+///
+/// A rule's top-level `if` condition is memoizable when its result depends
+/// only on the profile and transaction fields it reads: it calls no global
+/// or host function and no `now()`, and uses no local variable. With
+/// `EngineConfig::memoize_conditions` the VM caches its result, keyed by
+/// the values of those fields. Serialized as the plain list of read sets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MemoTable {
+    conditions: Vec<Vec<FieldRead>>,
+}
+
+impl MemoTable {
+    /// Add a condition's read set, returning its index
+    pub fn add(&mut self, reads: Vec<FieldRead>) -> usize {
+        self.conditions.push(reads);
+        self.conditions.len() - 1
+    }
+
+    /// Fields read by the condition at `index`
+    #[inline]
+    pub fn reads(&self, index: usize) -> Option<&[FieldRead]> {
+        self.conditions.get(index).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.conditions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+}
@@ -2,31 +2,278 @@
 //! Compiler that converts AST to bytecode
 
 pub mod bytecode;
+#[allow(clippy::module_inception)]
 pub mod compiler;
+pub mod limits;
+pub mod memo;
+pub mod optimizer;
+pub mod pool;
+pub mod regexes;
+pub mod report;
+pub mod schema;
+pub mod sets;
+pub mod symbols;
+pub mod warnings;
 
+use crate::actions::ActionRegistry;
+use crate::compiler::bytecode::Instruction;
+use crate::compiler::limits::CompileLimits;
+use crate::compiler::memo::{FieldRead, MemoTable};
+use crate::compiler::pool::ConstantPool;
+use crate::compiler::regexes::RegexTable;
+use crate::compiler::schema::Schema;
+use crate::compiler::sets::SetTable;
+use crate::compiler::symbols::SymbolTable;
+use crate::compiler::warnings::CompileWarning;
 use crate::parser::Program;
-use crate::{CompiledFunction, CompiledRule, CompilationError};
-use ahash::HashMap;
+use crate::runtime::HostFunctions;
+use crate::{CompiledFunction, CompiledRule, CompilationError, RuleGroup};
+use ahash::{HashMap, HashSet};
+
+/// Compiled rules (in execution order), global functions by name, and the
+/// field names, regex patterns, membership sets, pooled literals and
+/// memoizable conditions their bytecode refers to
+pub type CompiledProgram = (
+    Vec<CompiledRule>,
+    HashMap<String, CompiledFunction>,
+    SymbolTable,
+    RegexTable,
+    SetTable,
+    ConstantPool,
+    MemoTable,
+);
 
 /// Compile a parsed program into bytecode
-pub fn compile(program: Program) -> Result<(Vec<CompiledRule>, HashMap<String, CompiledFunction>), CompilationError> {
+///
+/// Statement calls to a name in `actions` compile to custom actions, calls
+/// to a name in `host_functions` are accepted as host function calls, and
+/// comparisons involving a field declared in `schema` are type checked. A
+/// program over the rule, statement or instruction counts in `limits` is
+/// rejected.
+pub fn compile(
+    program: Program,
+    actions: &ActionRegistry,
+    host_functions: &HostFunctions,
+    schema: &Schema,
+    limits: &CompileLimits,
+) -> Result<CompiledProgram, CompilationError> {
+    compile_with_warnings(program, actions, host_functions, schema, limits).map(|(compiled, _)| compiled)
+}
+
+/// Compile a parsed program like [`compile`], also returning warnings about
+/// code that compiles but never runs, such as statements after a `return`
+pub fn compile_with_warnings(
+    program: Program,
+    actions: &ActionRegistry,
+    host_functions: &HostFunctions,
+    schema: &Schema,
+    limits: &CompileLimits,
+) -> Result<(CompiledProgram, Vec<CompileWarning>), CompilationError> {
+    limits.check_program(&program)?;
+    
+    // Results, timings and toggles all address rules by id
+    let mut rule_ids = HashSet::default();
+    for rule in program.rules.iter().chain(program.groups.iter().flat_map(|g| &g.rules)) {
+        if !rule_ids.insert(rule.id.as_str()) {
+            return Err(CompilationError::CompileError(format!("duplicate rule '{}'", rule.id)));
+        }
+    }
+    
+    let warnings = program
+        .functions
+        .iter()
+        .flat_map(warnings::check_function)
+        .chain(
+            program
+                .rules
+                .iter()
+                .chain(program.groups.iter().flat_map(|g| &g.rules))
+                .flat_map(warnings::check_rule),
+        )
+        .collect();
+    
     let mut rules = Vec::new();
     let mut functions = HashMap::default();
     
+    // Constants are resolved first so rules and functions can inline them
+    let mut env = compiler::CompileEnv::from_constants(&program.constants)?;
+    env.actions = actions.clone();
+    env.schema = schema.clone();
+    
     // Compile global functions
     for func in program.functions {
-        let compiled = compiler::Compiler::compile_function(&func)?;
+        let compiled = compiler::Compiler::compile_function(&func, &mut env)?;
         functions.insert(compiled.name.clone(), compiled);
     }
     
-    // Compile rules (sorted by priority, descending)
-    let mut rule_nodes = program.rules;
-    rule_nodes.sort_by(|a, b| b.priority.cmp(&a.priority));
+    // Compile rules, then sort them by priority (descending) with each
+    // group's rules kept together
+    for rule in &program.rules {
+        rules.push(compiler::Compiler::compile_rule(rule, &mut env)?);
+    }
     
-    for rule in rule_nodes {
-        let compiled = compiler::Compiler::compile_rule(&rule)?;
-        rules.push(compiled);
+    let mut group_names = HashSet::default();
+    for group in &program.groups {
+        if !group_names.insert(group.name.as_str()) {
+            return Err(CompilationError::CompileError(format!(
+                "duplicate group '{}'",
+                group.name
+            )));
+        }
+        
+        let info = RuleGroup {
+            name: group.name.clone(),
+            priority: group.priority,
+        };
+        for rule in &group.rules {
+            let mut compiled = compiler::Compiler::compile_rule(rule, &mut env)?;
+            compiled.group = Some(info.clone());
+            rules.push(compiled);
+        }
     }
     
-    Ok((rules, functions))
+    CompiledRule::sort_for_execution(&mut rules);
+    
+    let instructions = rules.iter().map(|r| r.bytecode.len() + r.gate.len()).sum::<usize>()
+        + functions.values().map(|f| f.bytecode.len()).sum::<usize>();
+    limits.check_instructions(instructions)?;
+    
+    validate_semantics(&rules, &functions, host_functions)?;
+    
+    Ok(((rules, functions, env.symbols, env.regexes, env.sets, env.pool, env.memo), warnings))
+}
+
+/// Check that every table index in loaded rules and functions refers to an
+/// existing entry, and that no skip distance or operand count is larger than
+/// the bytecode it is in
+///
+/// Compiled bytecode always satisfies both, but a payload passed to
+/// [`RuleEngine::from_bytecode`](crate::RuleEngine::from_bytecode) or
+/// [`RuleEngine::from_json`](crate::RuleEngine::from_json) may have been
+/// corrupted or edited; rejecting it here keeps a bad field id from reaching
+/// the VM, and a huge count from overflowing the program counter or sizing
+/// an allocation.
+pub fn validate_indexes(
+    rules: &[CompiledRule],
+    functions: &HashMap<String, CompiledFunction>,
+    symbols: &SymbolTable,
+    regexes: &RegexTable,
+    sets: &SetTable,
+    pool: &ConstantPool,
+    memo: &MemoTable,
+) -> Result<(), CompilationError> {
+    let bodies = rules
+        .iter()
+        .flat_map(|r| [&r.bytecode, &r.gate].map(|bytecode| (format!("rule '{}'", r.id), bytecode)))
+        .chain(functions.values().map(|f| (format!("function '{}'", f.name), &f.bytecode)));
+    
+    let out_of_range = |what: &str, index: usize, len: usize, owner: &str| {
+        CompilationError::CompileError(format!(
+            "{} index {} is out of range for {} entries (in {})",
+            what, index, len, owner
+        ))
+    };
+    for (owner, bytecode) in bodies {
+        for instruction in bytecode {
+            let count = match *instruction {
+                Instruction::MemoCondition(_, skip) => Some(("memo skip", skip)),
+                Instruction::BuildArray(len) => Some(("array length", len)),
+                Instruction::BuildObject(entries) => Some(("object entry count", entries)),
+                Instruction::CallGlobal(_, args)
+                | Instruction::CallBuiltin(_, args)
+                | Instruction::CallAction(_, args)
+                | Instruction::MethodCall(_, args) => Some(("argument count", args)),
+                _ => None,
+            };
+            if let Some((what, count)) = count.filter(|&(_, count)| count > bytecode.len()) {
+                return Err(CompilationError::CompileError(format!(
+                    "{} {} exceeds the {} instructions of {}",
+                    what,
+                    count,
+                    bytecode.len(),
+                    owner
+                )));
+            }
+            
+            let (what, index, len) = match *instruction {
+                Instruction::LoadProfileField(id)
+                | Instruction::StoreProfileField(id)
+                | Instruction::LoadTxnField(id)
+                | Instruction::StoreTxnField(id) => ("field symbol", id as usize, symbols.len()),
+                Instruction::LoadConst(index) | Instruction::EqConst(index) | Instruction::NeConst(index) => {
+                    ("constant", index, pool.len())
+                }
+                Instruction::SetContains(index) => ("set", index, sets.len()),
+                Instruction::MatchRegex(index) => ("regex", index, regexes.len()),
+                Instruction::MemoCondition(entry, _) => ("memo", entry, memo.len()),
+                _ => continue,
+            };
+            if index >= len {
+                return Err(out_of_range(what, index, len, &owner));
+            }
+        }
+    }
+    
+    for entry in 0..memo.len() {
+        for read in memo.reads(entry).unwrap_or_default() {
+            let (FieldRead::Profile(id) | FieldRead::Txn(id)) = *read;
+            if id as usize >= symbols.len() {
+                return Err(out_of_range("field symbol", id as usize, symbols.len(), &format!("memo entry {}", entry)));
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Check that every function call in the compiled rules and functions has a
+/// target and passes as many arguments as the target has parameters
+///
+/// Any name that is not an action or builtin compiles to a global function
+/// call, so a typo like `calculateRsik(profile)` or `createcase(...)` would
+/// otherwise silently do nothing at runtime, and a missing argument would
+/// silently be Null. Registered custom actions compile to action calls, not
+/// function calls, so they are not checked here. Host functions take any
+/// number of arguments, so only their names are checked.
+pub fn validate_semantics(
+    rules: &[CompiledRule],
+    functions: &HashMap<String, CompiledFunction>,
+    host_functions: &HostFunctions,
+) -> Result<(), CompilationError> {
+    let bodies = rules
+        .iter()
+        .flat_map(|r| [&r.bytecode, &r.gate].map(|bytecode| (format!("rule '{}'", r.id), bytecode)))
+        .chain(functions.values().map(|f| (format!("function '{}'", f.name), &f.bytecode)));
+    
+    let mut problems = Vec::new();
+    for (owner, bytecode) in bodies {
+        for instruction in bytecode {
+            let Instruction::CallGlobal(name, arg_count) = instruction else {
+                continue;
+            };
+            
+            let problem = match functions.get(name) {
+                None if host_functions.contains_key(name) => continue,
+                None => format!("unknown function or action: {} (in {})", name, owner),
+                Some(func) if func.params.len() != *arg_count => format!(
+                    "function '{}' expects {} argument(s), got {} (in {})",
+                    name,
+                    func.params.len(),
+                    arg_count,
+                    owner
+                ),
+                Some(_) => continue,
+            };
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+    }
+    
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        problems.sort();
+        Err(CompilationError::CompileError(problems.join("; ")))
+    }
 }
@@ -0,0 +1,354 @@
+// src/compiler/optimizer.rs
+//! Bytecode optimizations applied after label resolution
+
+use crate::compiler::bytecode::Instruction;
+use crate::compiler::pool::ConstantPool;
+use crate::runtime::{ExecutionContext, VM};
+use crate::{NullSemantics, OverflowPolicy, Transaction, UserProfile};
+use ahash::{HashMap, HashSet};
+
+/// Fold operations on literal operands into a single `Push`
+///
+/// `Push(2) Push(3) Mul` becomes `Push(6)`, and folding repeats on the
+/// result, so `1 + 2 * 3` compiles to one instruction. An operand is only
+/// folded away when no jump lands on it or on the operation. Operations
+/// that would record a runtime error (e.g. division by zero) are left for
+/// the VM so the error is still reported. Jump targets are remapped to the
+/// shortened bytecode.
+pub fn fold_constants(bytecode: Vec<Instruction>) -> Vec<Instruction> {
+    fold_constants_with_positions(bytecode).0
+}
+
+/// [`fold_constants`], also returning the new index of each original
+/// instruction (plus one past the end) so side tables can be remapped
+pub fn fold_constants_with_positions(bytecode: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+    let targets: HashSet<usize> = bytecode.iter().filter_map(jump_target).collect();
+
+    let mut folded: Vec<Instruction> = Vec::with_capacity(bytecode.len());
+    // Whether a jump lands on the folded instruction at the same index
+    let mut landing: Vec<bool> = Vec::with_capacity(bytecode.len());
+    // New index of each original instruction, plus one past the end
+    let mut positions = Vec::with_capacity(bytecode.len() + 1);
+
+    for (index, instruction) in bytecode.into_iter().enumerate() {
+        let is_target = targets.contains(&index);
+        let arity = operand_count(&instruction);
+
+        if let Some(arity) = arity.filter(|_| !is_target) {
+            if let Some(value) = try_fold(&folded, &landing, arity, &instruction) {
+                let start = folded.len() - arity;
+                folded.truncate(start);
+                let first_is_target = landing[start];
+                landing.truncate(start);
+
+                positions.push(start);
+                folded.push(value);
+                landing.push(first_is_target);
+                continue;
+            }
+        }
+
+        positions.push(folded.len());
+        folded.push(instruction);
+        landing.push(is_target);
+    }
+    positions.push(folded.len());
+    remap_jumps(&mut folded, &positions);
+
+    (folded, positions)
+}
+
+/// Move heap-backed literals into `pool`, run after folding
+///
+/// A `Push` of a string, array, object or bytes becomes a `LoadConst`, and
+/// a `LoadConst` directly followed by `==` or `!=` fuses into `EqConst` /
+/// `NeConst`, which compare against the pooled value without cloning it.
+/// The comparison is only fused away when no jump lands on it. Returns the
+/// new index of each instruction like [`fold_constants_with_positions`].
+pub fn pool_constants(bytecode: Vec<Instruction>, pool: &mut ConstantPool) -> (Vec<Instruction>, Vec<usize>) {
+    let targets: HashSet<usize> = bytecode.iter().filter_map(jump_target).collect();
+
+    let mut pooled: Vec<Instruction> = Vec::with_capacity(bytecode.len());
+    let mut positions = Vec::with_capacity(bytecode.len() + 1);
+
+    for (index, instruction) in bytecode.into_iter().enumerate() {
+        if let Some(Instruction::LoadConst(constant)) = pooled.last() {
+            let fused = match instruction {
+                Instruction::Eq => Some(Instruction::EqConst(*constant)),
+                Instruction::Ne => Some(Instruction::NeConst(*constant)),
+                _ => None,
+            };
+            if let Some(fused) = fused.filter(|_| !targets.contains(&index)) {
+                positions.push(pooled.len() - 1);
+                *pooled.last_mut().unwrap() = fused;
+                continue;
+            }
+        }
+
+        positions.push(pooled.len());
+        pooled.push(match instruction {
+            Instruction::Push(value) if ConstantPool::is_pooled(&value) => Instruction::LoadConst(pool.intern(value)),
+            other => other,
+        });
+    }
+    positions.push(pooled.len());
+    remap_jumps(&mut pooled, &positions);
+
+    (pooled, positions)
+}
+
+/// Point jumps at the new index of their original target
+fn remap_jumps(bytecode: &mut [Instruction], positions: &[usize]) {
+    for instruction in bytecode {
+        match instruction {
+            Instruction::Jump(target)
+            | Instruction::JumpIfFalse(target)
+            | Instruction::JumpIfTrue(target)
+            | Instruction::JumpIfNotNull(target)
+            | Instruction::JumpIfUnknown(target)
+            | Instruction::ShortCircuitAnd(target)
+            | Instruction::ShortCircuitOr(target)
+            | Instruction::LoopGuard(target) => {
+                if let Some(&position) = positions.get(*target) {
+                    *target = position;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn jump_target(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::JumpIfFalse(target)
+        | Instruction::JumpIfTrue(target)
+        | Instruction::JumpIfNotNull(target)
+        | Instruction::JumpIfUnknown(target)
+        | Instruction::ShortCircuitAnd(target)
+        | Instruction::ShortCircuitOr(target)
+        | Instruction::LoopGuard(target) => Some(*target),
+        _ => None,
+    }
+}
+
+/// Number of stack operands a foldable operation consumes
+fn operand_count(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Add
+        | Instruction::Sub
+        | Instruction::Mul
+        | Instruction::Div
+        | Instruction::Mod
+        | Instruction::Pow
+        | Instruction::Eq
+        | Instruction::Ne
+        | Instruction::Gt
+        | Instruction::Gte
+        | Instruction::Lt
+        | Instruction::Lte
+        | Instruction::And
+        | Instruction::Or
+        | Instruction::BitAnd
+        | Instruction::BitOr
+        | Instruction::BitXor
+        | Instruction::Shl
+        | Instruction::Shr => Some(2),
+        Instruction::Neg | Instruction::Not => Some(1),
+        _ => None,
+    }
+}
+
+/// Evaluate `op` on the trailing literal operands, if they are all plain
+/// `Push`es and only the first is a jump target
+fn try_fold(
+    folded: &[Instruction],
+    landing: &[bool],
+    arity: usize,
+    op: &Instruction,
+) -> Option<Instruction> {
+    let start = folded.len().checked_sub(arity)?;
+    let operands = &folded[start..];
+    if !operands.iter().all(|i| matches!(i, Instruction::Push(_))) {
+        return None;
+    }
+    if landing[start + 1..].iter().any(|&is_target| is_target) {
+        return None;
+    }
+
+    // Run the snippet on the VM so folding can never disagree with it
+    let mut snippet = operands.to_vec();
+    snippet.push(op.clone());
+    // Null semantics are also a runtime setting, so the snippet must give
+    // the same result under both
+    let mut results = [NullSemantics::Rust, NullSemantics::Sql].map(|null_semantics| {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        // Overflow is left to the engine's runtime policy, so it blocks folding
+        ctx.numeric_overflow = OverflowPolicy::Error;
+        ctx.null_semantics = null_semantics;
+        VM::execute(&snippet, &mut ctx, &HashMap::default(), &HashMap::default());
+        
+        if !ctx.errors.is_empty() || ctx.stack.len() != 1 {
+            return None;
+        }
+        ctx.pop()
+    });
+    
+    if results[0] != results[1] {
+        return None;
+    }
+    results[0].take().map(Instruction::Push)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+
+    #[test]
+    fn test_folds_literal_arithmetic() {
+        let bytecode = vec![
+            Instruction::Push(Value::Int(1)),
+            Instruction::Push(Value::Int(2)),
+            Instruction::Push(Value::Int(3)),
+            Instruction::Mul,
+            Instruction::Add,
+            Instruction::Push(Value::Int(7)),
+            Instruction::Gt,
+            Instruction::Not,
+        ];
+
+        assert_eq!(fold_constants(bytecode), vec![Instruction::Push(Value::Bool(true))]);
+    }
+
+    #[test]
+    fn test_keeps_operations_on_non_literals() {
+        let bytecode = vec![
+            Instruction::LoadLocal("i".to_string()),
+            Instruction::Push(Value::Int(10)),
+            Instruction::Mul,
+        ];
+
+        assert_eq!(fold_constants(bytecode.clone()), bytecode);
+    }
+
+    #[test]
+    fn test_keeps_runtime_errors() {
+        let bytecode = vec![
+            Instruction::Push(Value::Int(1)),
+            Instruction::Push(Value::Int(0)),
+            Instruction::Div,
+        ];
+
+        assert_eq!(fold_constants(bytecode.clone()), bytecode);
+    }
+
+    #[test]
+    fn test_keeps_null_semantics_dependent_results() {
+        // `null > 5` is false or unknown depending on the engine's setting
+        let bytecode = vec![
+            Instruction::Push(Value::Null),
+            Instruction::Push(Value::Int(5)),
+            Instruction::Gt,
+        ];
+        assert_eq!(fold_constants(bytecode.clone()), bytecode);
+
+        // `null == null` holds either way
+        let bytecode = vec![Instruction::Push(Value::Null), Instruction::Push(Value::Null), Instruction::Eq];
+        assert_eq!(fold_constants(bytecode), vec![Instruction::Push(Value::Bool(true))]);
+    }
+
+    #[test]
+    fn test_does_not_fold_across_jump_targets() {
+        // A jump landing on the second operand or on `Add` itself means the
+        // operands are not always pushed together, so nothing is folded
+        let bytecode = vec![
+            Instruction::Push(Value::Int(1)),
+            Instruction::JumpIfTrue(3),
+            Instruction::Push(Value::Int(2)),
+            Instruction::Push(Value::Int(3)),
+            Instruction::Add,
+        ];
+        assert_eq!(fold_constants(bytecode.clone()), bytecode);
+
+        let bytecode = vec![
+            Instruction::Push(Value::Bool(true)),
+            Instruction::JumpIfTrue(4),
+            Instruction::Push(Value::Int(2)),
+            Instruction::Push(Value::Int(3)),
+            Instruction::Add,
+        ];
+        assert_eq!(fold_constants(bytecode.clone()), bytecode);
+    }
+
+    #[test]
+    fn test_remaps_jump_targets() {
+        let bytecode = vec![
+            Instruction::Push(Value::Int(2)),
+            Instruction::Push(Value::Int(3)),
+            Instruction::Lt,
+            Instruction::JumpIfFalse(5),
+            Instruction::Push(Value::Int(1)),
+            Instruction::Push(Value::Int(4)),
+            Instruction::Neg,
+            Instruction::Jump(0),
+        ];
+
+        assert_eq!(
+            fold_constants(bytecode),
+            vec![
+                Instruction::Push(Value::Bool(true)),
+                Instruction::JumpIfFalse(3),
+                Instruction::Push(Value::Int(1)),
+                Instruction::Push(Value::Int(-4)),
+                Instruction::Jump(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pools_heap_literals() {
+        let bytecode = vec![
+            Instruction::LoadLocal("c".to_string()),
+            Instruction::Push(Value::from("US")),
+            Instruction::Eq,
+            Instruction::JumpIfFalse(7),
+            Instruction::LoadLocal("c".to_string()),
+            Instruction::Push(Value::from("US")),
+            Instruction::Ne,
+            Instruction::Push(Value::from("GB")),
+            Instruction::Push(Value::Int(1)),
+            Instruction::Jump(3),
+        ];
+
+        let mut pool = ConstantPool::default();
+        let (pooled, positions) = pool_constants(bytecode, &mut pool);
+        assert_eq!(
+            pooled,
+            vec![
+                Instruction::LoadLocal("c".to_string()),
+                Instruction::EqConst(0),
+                Instruction::JumpIfFalse(5),
+                Instruction::LoadLocal("c".to_string()),
+                Instruction::NeConst(0),
+                Instruction::LoadConst(1),
+                Instruction::Push(Value::Int(1)),
+                Instruction::Jump(2),
+            ]
+        );
+        assert_eq!(positions, vec![0, 1, 1, 2, 3, 4, 4, 5, 6, 7, 8]);
+        assert_eq!(pool.len(), 2);
+
+        // A comparison that a jump lands on needs both operands on the stack
+        let bytecode = vec![
+            Instruction::Push(Value::from("US")),
+            Instruction::Eq,
+            Instruction::JumpIfTrue(1),
+        ];
+        let (pooled, _) = pool_constants(bytecode, &mut pool);
+        assert_eq!(
+            pooled,
+            vec![Instruction::LoadConst(0), Instruction::Eq, Instruction::JumpIfTrue(1)]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+// src/compiler/pool.rs
+//! Constant pool for literals that own heap memory
+
+use crate::runtime::value::HashKey;
+use crate::Value;
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Literal values referenced by `LoadConst`, `EqConst` and `NeConst`
+/// instructions, by index
+///
+/// Strings, arrays, objects and bytes move here from `Push` once a rule is
+/// optimized, and equal literals share one entry across every rule and
+/// function. Scalars stay inline in `Push`, where copying them is as cheap
+/// as an index lookup. Serialized as the plain list of values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Value>", into = "Vec<Value>")]
+pub struct ConstantPool {
+    values: Vec<Value>,
+    /// Index of each hashable value, so interning is not a linear scan
+    indexes: HashMap<HashKey, usize>,
+}
+
+impl ConstantPool {
+    /// Whether `value` belongs in the pool rather than inline in `Push`
+    pub fn is_pooled(value: &Value) -> bool {
+        matches!(value, Value::String(_) | Value::Array(_) | Value::Object(_) | Value::Bytes(_))
+    }
+
+    /// Get the index of `value`, adding it if it is new
+    pub fn intern(&mut self, value: Value) -> usize {
+        let key = HashKey::new(value.clone());
+        let existing = match &key {
+            Some(key) => self.indexes.get(key).copied(),
+            // Arrays and objects cannot be hashed; they are rare enough to scan
+            None => self.values.iter().position(|v| *v == value),
+        };
+        if let Some(index) = existing {
+            return index;
+        }
+
+        let index = self.values.len();
+        if let Some(key) = key {
+            self.indexes.insert(key, index);
+        }
+        self.values.push(value);
+        index
+    }
+
+    /// The value at `index`
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.values.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl From<Vec<Value>> for ConstantPool {
+    fn from(values: Vec<Value>) -> Self {
+        let indexes = values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| Some((HashKey::new(value.clone())?, index)))
+            .collect();
+        Self { values, indexes }
+    }
+}
+
+impl From<ConstantPool> for Vec<Value> {
+    fn from(pool: ConstantPool) -> Self {
+        pool.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_equal_values() {
+        let mut pool = ConstantPool::default();
+        let us = pool.intern(Value::from("US"));
+        let list = pool.intern(Value::from(vec!["a", "b"]));
+
+        assert_eq!(pool.intern(Value::from("US")), us);
+        assert_eq!(pool.intern(Value::from(vec!["a", "b"])), list);
+        assert_eq!(pool.intern(Value::from("GB")), 2);
+        assert_eq!(pool.get(us), Some(&Value::from("US")));
+        assert_eq!(pool.get(3), None);
+        assert_eq!(pool.len(), 3);
+
+        assert!(ConstantPool::is_pooled(&Value::from("US")));
+        assert!(!ConstantPool::is_pooled(&Value::Int(1)));
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut pool = ConstantPool::default();
+        pool.intern(Value::from("RU"));
+        pool.intern(Value::Bytes(vec![1, 2]));
+
+        let mut restored: ConstantPool = serde_json::from_str(&serde_json::to_string(&pool).unwrap()).unwrap();
+        assert_eq!(restored.get(1), Some(&Value::Bytes(vec![1, 2])));
+        // Interning after a round trip still finds existing entries
+        assert_eq!(restored.intern(Value::from("RU")), 0);
+        assert_eq!(restored.len(), 2);
+    }
+}
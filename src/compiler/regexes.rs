@@ -0,0 +1,121 @@
+// src/compiler/regexes.rs
+//! Regex patterns used by `matches`, compiled once at compile time
+
+use serde::{Deserialize, Serialize};
+
+/// Patterns referenced by `MatchRegex` instructions, by index
+///
+/// Each pattern is compiled when the rule using it is compiled (or when the
+/// table is deserialized), so the VM never compiles a regex on the hot path
+/// and an invalid pattern is a compile error. Serialized as the plain list of
+/// patterns. Without the `regex` feature the table only carries patterns and
+/// nothing matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct RegexTable {
+    patterns: Vec<String>,
+    #[cfg(feature = "regex")]
+    compiled: Vec<regex::Regex>,
+}
+
+impl RegexTable {
+    /// Get the index of a pattern, compiling and adding it if it is new
+    pub fn intern(&mut self, pattern: &str) -> Result<usize, String> {
+        if let Some(index) = self.patterns.iter().position(|p| p == pattern) {
+            return Ok(index);
+        }
+
+        #[cfg(feature = "regex")]
+        {
+            let regex = regex::Regex::new(pattern)
+                .map_err(|e| format!("invalid regex pattern '{}': {}", pattern, e))?;
+            self.compiled.push(regex);
+            self.patterns.push(pattern.to_string());
+            Ok(self.patterns.len() - 1)
+        }
+
+        #[cfg(not(feature = "regex"))]
+        Err("matches() requires the `regex` feature".to_string())
+    }
+
+    /// Whether `text` matches the pattern at `index`; None without the
+    /// `regex` feature
+    #[inline]
+    pub fn is_match(&self, index: usize, text: &str) -> Option<bool> {
+        #[cfg(feature = "regex")]
+        return self.compiled.get(index).map(|regex| regex.is_match(text));
+
+        #[cfg(not(feature = "regex"))]
+        {
+            let _ = (index, text);
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+impl TryFrom<Vec<String>> for RegexTable {
+    type Error = String;
+
+    fn try_from(patterns: Vec<String>) -> Result<Self, Self::Error> {
+        #[cfg(feature = "regex")]
+        {
+            let mut table = Self::default();
+            for pattern in &patterns {
+                table.intern(pattern)?;
+            }
+            Ok(table)
+        }
+
+        #[cfg(not(feature = "regex"))]
+        Ok(Self { patterns })
+    }
+}
+
+impl From<RegexTable> for Vec<String> {
+    fn from(table: RegexTable) -> Self {
+        table.patterns
+    }
+}
+
+#[cfg(all(test, feature = "regex"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_compiles_once() {
+        let mut table = RegexTable::default();
+        let email = table.intern(r"^[^@]+@example\.com$").unwrap();
+
+        assert_eq!(table.intern(r"^[^@]+@example\.com$").unwrap(), email);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.is_match(email, "ann@example.com"), Some(true));
+        assert_eq!(table.is_match(email, "ann@example.org"), Some(false));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let err = RegexTable::default().intern("(unclosed").unwrap_err();
+        assert!(err.starts_with("invalid regex pattern '(unclosed'"), "{}", err);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut table = RegexTable::default();
+        table.intern("^4[0-9]{5}$").unwrap();
+
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(json, r#"["^4[0-9]{5}$"]"#);
+
+        let restored: RegexTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.is_match(0, "411111"), Some(true));
+        assert!(serde_json::from_str::<RegexTable>(r#"["("]"#).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+// src/compiler/report.rs
+//! Summary of a compiled program, for reviewing a deploy
+
+use crate::compiler::bytecode::Instruction;
+use crate::compiler::symbols::SymbolTable;
+use crate::compiler::warnings::CompileWarning;
+use crate::{CompiledFunction, CompiledRule};
+use ahash::HashMap;
+use std::collections::BTreeSet;
+
+/// What a program compiled to, from
+/// [`RuleEngine::from_dsl_with_report`](crate::RuleEngine::from_dsl_with_report)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompileReport {
+    /// Bytecode length of each rule, in execution order
+    pub rule_sizes: Vec<(String, usize)>,
+    
+    /// Instructions across all rules and functions
+    pub total_instructions: usize,
+    
+    /// Fields read or written, as `profile.x` or `txn.x`, sorted
+    pub fields: Vec<String>,
+    
+    /// Global and host functions called, sorted; builtins are not listed
+    pub functions: Vec<String>,
+    
+    /// Actions emitted, sorted
+    pub actions: Vec<String>,
+    
+    /// Code that compiles but never runs
+    pub warnings: Vec<CompileWarning>,
+}
+
+impl CompileReport {
+    /// Summarize compiled rules and functions
+    ///
+    /// Built from the optimized bytecode, so a call folded away at compile
+    /// time is not listed.
+    pub fn new(
+        rules: &[CompiledRule],
+        functions: &HashMap<String, CompiledFunction>,
+        symbols: &SymbolTable,
+        warnings: Vec<CompileWarning>,
+    ) -> Self {
+        let mut fields = BTreeSet::new();
+        let mut called = BTreeSet::new();
+        let mut actions = BTreeSet::new();
+        
+        let bytecode = rules.iter().map(|r| &r.bytecode).chain(functions.values().map(|f| &f.bytecode));
+        let mut total_instructions = 0;
+        for instructions in bytecode {
+            total_instructions += instructions.len();
+            for instruction in instructions {
+                match instruction {
+                    Instruction::LoadProfileField(id) | Instruction::StoreProfileField(id) => {
+                        fields.insert(format!("profile.{}", symbols.name(*id).unwrap_or_default()));
+                    }
+                    Instruction::LoadTxnField(id) | Instruction::StoreTxnField(id) => {
+                        fields.insert(format!("txn.{}", symbols.name(*id).unwrap_or_default()));
+                    }
+                    Instruction::CallGlobal(name, _) => {
+                        called.insert(name.clone());
+                    }
+                    Instruction::CallAction(action, _) => {
+                        actions.insert(action.name().to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        Self {
+            rule_sizes: rules.iter().map(|r| (r.id.clone(), r.bytecode.len())).collect(),
+            total_instructions,
+            fields: fields.into_iter().collect(),
+            functions: called.into_iter().collect(),
+            actions: actions.into_iter().collect(),
+            warnings,
+        }
+    }
+}
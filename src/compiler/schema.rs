@@ -0,0 +1,45 @@
+// src/compiler/schema.rs
+//! Declared types of profile/transaction fields, checked at compile time
+
+use crate::runtime::value::ValueType;
+use ahash::HashMap;
+
+/// Expected type of each input field, keyed as written in rules
+/// (`txn.amount`, `profile.risk_score`)
+///
+/// When compiling with a schema, a comparison between a declared field and
+/// an operand of an incompatible type, such as `txn.amount > "1000"`, is a
+/// [`TypeMismatch`](crate::CompilationError::TypeMismatch). Int, Float and
+/// Decimal are compatible with each other; fields not in the schema are not
+/// checked.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    fields: HashMap<String, ValueType>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare the type of a field; `transaction.x` is the same as `txn.x`
+    pub fn with_field(mut self, field: impl Into<String>, value_type: ValueType) -> Self {
+        let field = field.into();
+        let field = match field.strip_prefix("transaction.") {
+            Some(name) => format!("txn.{}", name),
+            None => field,
+        };
+        self.fields.insert(field, value_type);
+        self
+    }
+
+    /// Declared type of a `txn` or `profile` field
+    pub fn get(&self, object: &str, field: &str) -> Option<ValueType> {
+        let object = if object == "transaction" { "txn" } else { object };
+        self.fields.get(&format!("{}.{}", object, field)).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
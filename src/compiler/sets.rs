@@ -0,0 +1,123 @@
+// src/compiler/sets.rs
+//! Hash sets behind `x in [...]` over large constant lists
+
+use crate::runtime::value::HashKey;
+use crate::Value;
+use ahash::HashSet;
+use serde::{Deserialize, Serialize};
+
+/// Constant arrays shorter than this stay a linear `ArrayContains` scan,
+/// which beats hashing for a handful of elements
+pub const MIN_SET_LEN: usize = 16;
+
+/// Membership sets referenced by `SetContains` instructions, by index
+///
+/// `x in DENY_LIST` against a constant array of [`MIN_SET_LEN`] or more
+/// scalars looks `x` up in a set built at compile time instead of scanning
+/// the array. Membership is `==` on [`Value`], exactly as the scan would
+/// find it. Serialized as the plain list of each set's elements.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Vec<Value>>", into = "Vec<Vec<Value>>")]
+pub struct SetTable {
+    sets: Vec<HashSet<HashKey>>,
+}
+
+impl SetTable {
+    /// Get the index of a set holding `values`, adding it if it is new;
+    /// None if some value cannot be hashed (arrays, objects, NaN)
+    pub fn intern(&mut self, values: &[Value]) -> Option<usize> {
+        // As keys, NaNs would match each other, but `==` never matches NaN
+        if values.iter().any(|v| matches!(v, Value::Float(f) if f.is_nan())) {
+            return None;
+        }
+
+        let set: HashSet<HashKey> = values.iter().cloned().map(HashKey::new).collect::<Option<_>>()?;
+        if let Some(index) = self.sets.iter().position(|existing| *existing == set) {
+            return Some(index);
+        }
+
+        self.sets.push(set);
+        Some(self.sets.len() - 1)
+    }
+
+    /// Whether the set at `index` holds `value`
+    #[inline]
+    pub fn contains(&self, index: usize, value: Value) -> bool {
+        match (self.sets.get(index), HashKey::new(value)) {
+            (Some(set), Some(key)) => set.contains(&key),
+            _ => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+}
+
+impl From<Vec<Vec<Value>>> for SetTable {
+    fn from(sets: Vec<Vec<Value>>) -> Self {
+        Self {
+            sets: sets
+                .into_iter()
+                .map(|values| values.into_iter().filter_map(HashKey::new).collect())
+                .collect(),
+        }
+    }
+}
+
+impl From<SetTable> for Vec<Vec<Value>> {
+    fn from(table: SetTable) -> Self {
+        table
+            .sets
+            .into_iter()
+            .map(|set| set.into_iter().map(HashKey::into_value).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_matches_array_equality() {
+        let values = vec![Value::Int(1), Value::Float(-0.0), Value::from("US"), Value::Null];
+        let mut table = SetTable::default();
+        let index = table.intern(&values).unwrap();
+
+        for value in [Value::Int(1), Value::Float(0.0), Value::from("US"), Value::Null] {
+            assert_eq!(table.contains(index, value.clone()), values.contains(&value), "{}", value);
+        }
+        // Like `==`, an Int does not match a Float of the same value
+        for value in [Value::Float(1.0), Value::from("us"), Value::Float(f64::NAN), Value::Int(2)] {
+            assert!(!table.contains(index, value.clone()), "{}", value);
+        }
+        assert!(!table.contains(index + 1, Value::Int(1)));
+    }
+
+    #[test]
+    fn test_intern_reuses_equal_sets() {
+        let mut table = SetTable::default();
+        let first = table.intern(&[Value::Int(1), Value::Int(2)]).unwrap();
+
+        assert_eq!(table.intern(&[Value::Int(2), Value::Int(1), Value::Int(2)]), Some(first));
+        assert_eq!(table.intern(&[Value::Int(3)]), Some(first + 1));
+        assert_eq!(table.intern(&[Value::Int(1), Value::from(vec![1])]), None);
+        assert_eq!(table.intern(&[Value::Float(f64::NAN)]), None);
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut table = SetTable::default();
+        table.intern(&[Value::from("RU"), Value::from("KP")]).unwrap();
+
+        let restored: SetTable = serde_json::from_str(&serde_json::to_string(&table).unwrap()).unwrap();
+        assert!(restored.contains(0, Value::from("KP")));
+        assert!(!restored.contains(0, Value::from("US")));
+    }
+}
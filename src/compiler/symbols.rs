@@ -0,0 +1,102 @@
+// src/compiler/symbols.rs
+//! Interned profile/transaction field names
+
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Index of a field name in a [`SymbolTable`]
+pub type SymbolId = u32;
+
+/// Field names referenced by compiled bytecode, interned at compile time
+///
+/// Field instructions carry a [`SymbolId`] instead of the name, so the VM
+/// addresses fields by index and never clones or hashes a name on the hot
+/// path. Serialized as the plain list of names.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<String>", into = "Vec<String>")]
+pub struct SymbolTable {
+    names: Vec<String>,
+    ids: HashMap<String, SymbolId>,
+}
+
+impl SymbolTable {
+    /// Get the id of a name, adding it if it is new
+    pub fn intern(&mut self, name: &str) -> SymbolId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+
+        let id = self.names.len() as SymbolId;
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Name of an interned symbol; None if the id was not produced by this
+    /// table
+    #[inline]
+    pub fn name(&self, id: SymbolId) -> Option<&str> {
+        self.names.get(id as usize).map(String::as_str)
+    }
+
+    /// Id of a name, if it has been interned
+    pub fn get(&self, name: &str) -> Option<SymbolId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl From<Vec<String>> for SymbolTable {
+    fn from(names: Vec<String>) -> Self {
+        let ids = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id as SymbolId))
+            .collect();
+        Self { names, ids }
+    }
+}
+
+impl From<SymbolTable> for Vec<String> {
+    fn from(table: SymbolTable) -> Self {
+        table.names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_reuses_ids() {
+        let mut table = SymbolTable::default();
+        let amount = table.intern("amount");
+        let country = table.intern("country");
+
+        assert_eq!(table.intern("amount"), amount);
+        assert_ne!(amount, country);
+        assert_eq!(table.name(country), Some("country"));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut table = SymbolTable::default();
+        table.intern("amount");
+        table.intern("country");
+
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(json, r#"["amount","country"]"#);
+
+        let mut restored: SymbolTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, table);
+        assert_eq!(restored.intern("country"), 1);
+    }
+}
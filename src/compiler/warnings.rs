@@ -0,0 +1,158 @@
+// src/compiler/warnings.rs
+//! Static checks for code that compiles but cannot be what the author meant
+
+use crate::parser::ast::{FunctionNode, RuleNode, Statement};
+use std::fmt;
+
+/// A problem found while compiling that does not stop compilation
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileWarning {
+    /// Where the warning applies, e.g. `rule 'velocity'` or `function 'score'`
+    pub owner: String,
+    /// Source line of the offending statement; None in functions, which
+    /// carry no line information
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}, line {}: {}", self.owner, line, self.message),
+            None => write!(f, "{}: {}", self.owner, self.message),
+        }
+    }
+}
+
+/// Warnings for a rule's body
+pub fn check_rule(rule: &RuleNode) -> Vec<CompileWarning> {
+    check_body(format!("rule '{}'", rule.id), &rule.lines, &rule.body)
+}
+
+/// Warnings for a global function's body
+pub fn check_function(func: &FunctionNode) -> Vec<CompileWarning> {
+    check_body(format!("function '{}'", func.name), &[], &func.body)
+}
+
+fn check_body(owner: String, lines: &[usize], body: &[Statement]) -> Vec<CompileWarning> {
+    let mut walker = Walker {
+        owner,
+        lines,
+        statements_seen: 0,
+        warnings: Vec::new(),
+    };
+    walker.block(body);
+    walker.warnings
+}
+
+/// Pre-order walk over a body's statements, matching the order the parser
+/// recorded their lines in
+struct Walker<'a> {
+    owner: String,
+    lines: &'a [usize],
+    statements_seen: usize,
+    warnings: Vec<CompileWarning>,
+}
+
+impl Walker<'_> {
+    /// Walk a block, returning whether it always returns
+    ///
+    /// Only the first statement after the return is reported, once per block.
+    fn block(&mut self, block: &[Statement]) -> bool {
+        let mut returns = false;
+        let mut reported = false;
+
+        for stmt in block {
+            if returns && !reported {
+                self.warnings.push(CompileWarning {
+                    owner: self.owner.clone(),
+                    line: self.lines.get(self.statements_seen).copied(),
+                    message: "unreachable statement after return".to_string(),
+                });
+                reported = true;
+            }
+            returns |= self.statement(stmt);
+        }
+
+        returns
+    }
+
+    /// Walk a statement, returning whether it always returns
+    fn statement(&mut self, stmt: &Statement) -> bool {
+        self.statements_seen += 1;
+
+        match stmt {
+            Statement::Return(_) => true,
+            Statement::Block(block) => self.block(block),
+            Statement::IfStatement { then_block, else_block, .. } => {
+                let then_returns = self.block(then_block);
+                let else_returns = else_block.as_ref().is_some_and(|block| self.block(block));
+                then_returns && else_returns
+            }
+            // A loop body may run zero times
+            Statement::ForEach { body, .. } | Statement::WhileLoop { body, .. } => {
+                self.block(body);
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_statement_after_top_level_return() {
+        let program = parse(
+            r#"
+            rule "r" {
+                if (txn.amount > 10) {
+                    return;
+                    profile.flagged = true;
+                }
+                profile.seen = true;
+            }
+        "#,
+        )
+        .unwrap();
+
+        let warnings = check_rule(&program.rules[0]);
+        assert_eq!(
+            warnings,
+            vec![CompileWarning {
+                owner: "rule 'r'".to_string(),
+                line: Some(5),
+                message: "unreachable statement after return".to_string(),
+            }]
+        );
+        assert_eq!(warnings[0].to_string(), "rule 'r', line 5: unreachable statement after return");
+    }
+
+    #[test]
+    fn test_if_else_that_always_returns() {
+        let program = parse(
+            r#"
+            function f(x) {
+                if (x > 1) { return 1; } else { return 2; }
+                return 3;
+            }
+            function g(x) {
+                if (x > 1) { return 1; }
+                for (v in x) { return v; }
+                return 3;
+            }
+        "#,
+        )
+        .unwrap();
+
+        let warnings = check_function(&program.functions[0]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].to_string(), "function 'f': unreachable statement after return");
+
+        // An if without else, or a loop, may fall through
+        assert!(check_function(&program.functions[1]).is_empty());
+    }
+}
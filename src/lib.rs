@@ -39,19 +39,34 @@ pub mod runtime;
 
 
 use ahash::HashMap;
+use compiler::memo::MemoTable;
+use compiler::pool::ConstantPool;
+use compiler::regexes::RegexTable;
+use compiler::sets::SetTable;
+use compiler::symbols::SymbolTable;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use thiserror::Error;
 
-pub use actions::Action;
+pub use actions::{Action, ActionRegistry, Decision, FraudScorePolicy};
 pub use compiler::bytecode::Instruction;
-pub use runtime::value::Value;
+pub use compiler::limits::CompileLimits;
+pub use compiler::report::CompileReport;
+pub use compiler::schema::Schema;
+pub use compiler::warnings::CompileWarning;
+pub use runtime::value::{HashKey, Value, ValueType};
+pub use runtime::{HostFn, HostFunctions};
 
 /// Errors that can occur during compilation
 #[derive(Error, Debug)]
 pub enum CompilationError {
-    #[error("Parse error: {0}")]
-    ParseError(String),
+    #[error("Parse error at {line}:{column}: {message}")]
+    ParseError {
+        message: String,
+        line: usize,
+        column: usize,
+    },
     
     #[error("Compilation error: {0}")]
     CompileError(String),
@@ -59,12 +74,15 @@ pub enum CompilationError {
     #[error("Unknown field: {0}")]
     UnknownField(String),
     
+    #[error("Unknown rule: {0}")]
+    UnknownRule(String),
+    
     #[error("Type mismatch: expected {expected}, got {actual}")]
     TypeMismatch { expected: String, actual: String },
 }
 
 /// Errors during rule execution
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, PartialEq, Serialize)]
 pub enum ExecutionError {
     #[error("Runtime error: {0}")]
     RuntimeError(String),
@@ -76,11 +94,206 @@ pub enum ExecutionError {
     InvalidOperation,
 }
 
+/// Rules, functions (sorted by name), field names, regex patterns,
+/// membership sets, pooled literals and memoizable conditions, as
+/// serialized by [`RuleEngine::to_bytecode`] and [`RuleEngine::to_json`]
+type ExportData = (
+    Vec<CompiledRule>,
+    Vec<CompiledFunction>,
+    SymbolTable,
+    RegexTable,
+    SetTable,
+    ConstantPool,
+    MemoTable,
+);
+
+/// Magic bytes at the start of serialized bytecode
+const BYTECODE_MAGIC: &[u8; 4] = b"FRBC";
+
+/// Format version of serialized bytecode
+///
+/// Bump whenever `Instruction` (or anything else in the serialized rules and
+/// functions) changes shape, so `from_bytecode` rejects payloads written by
+/// an incompatible build instead of mis-executing them.
+pub const BYTECODE_VERSION: u16 = 23;
+
+/// A process-wide unique value for `RuleEngine::generation`
+fn next_generation() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
 /// Main rule engine instance
 #[derive(Clone)]
 pub struct RuleEngine {
     compiled_rules: Arc<Vec<CompiledRule>>,
+    /// Live enabled flag per rule, parallel to `compiled_rules`
+    ///
+    /// Toggling goes through atomics rather than a lock so `execute` never
+    /// blocks; the cost is that a toggle is shared by every clone of the
+    /// engine, and a rule already running when it is disabled finishes.
+    rule_enabled: Arc<Vec<AtomicBool>>,
+    /// Usage counters per rule, parallel to `compiled_rules` and shared by
+    /// clones like the enabled flags; only updated with
+    /// `EngineConfig::collect_rule_stats`
+    rule_stats: Arc<[RuleCounters]>,
     global_functions: Arc<HashMap<String, CompiledFunction>>,
+    /// Field names referenced by the rules' and functions' bytecode
+    symbols: Arc<SymbolTable>,
+    /// Regex patterns used by `matches`, compiled once
+    regexes: Arc<RegexTable>,
+    /// Membership sets for `in` over large constant lists
+    sets: Arc<SetTable>,
+    /// String, array, object and bytes literals, shared by every rule
+    pool: Arc<ConstantPool>,
+    /// Fields read by conditions whose results can be cached
+    memo: Arc<MemoTable>,
+    /// Identifies the compiled rules and the settings that affect condition
+    /// results, so a context's condition cache is never reused across them
+    generation: u64,
+    /// Custom actions known to `add_rule`
+    actions: Arc<ActionRegistry>,
+    /// Field types `add_rule` checks comparisons against
+    schema: Arc<Schema>,
+    /// Size limits `add_rule` enforces
+    limits: CompileLimits,
+    /// Priority `add_rule` gives rules that omit one
+    default_priority: i32,
+    /// Functions supplied by the host, called when no DSL function matches
+    host_functions: Arc<HostFunctions>,
+    config: EngineConfig,
+}
+
+/// Execution settings for a rule engine
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Maximum iterations a single `while` loop may run before it is
+    /// stopped and an error is recorded
+    pub max_loop_iterations: usize,
+    
+    /// Maximum depth of nested global function calls; deeper calls are
+    /// refused and an error is recorded
+    pub max_call_depth: usize,
+    
+    /// Record an error whenever a rule reads a profile or transaction field
+    /// that is absent (the read still yields Null). Useful for catching typos
+    /// while authoring rules.
+    pub strict_fields: bool,
+    
+    /// Remove exact-duplicate actions (first occurrence wins) after all
+    /// rules have run
+    pub dedup_actions: bool,
+    
+    /// How multiple `SetFraudScore` actions are combined into one
+    pub fraud_score_policy: FraudScorePolicy,
+    
+    /// Record executed/skipped rule ids and per-rule timings in
+    /// `ExecutionMetadata`. Turning this off leaves those empty and saves a
+    /// clock read, two id clones and a map insert per rule; totals, flags
+    /// and errors are still filled in.
+    pub collect_metadata: bool,
+    
+    /// Whether every rule runs or execution stops at the first that fires
+    pub mode: ExecutionMode,
+    
+    /// What integer `+`, `-`, `*`, `/` and negation do when the result does
+    /// not fit in an i64
+    pub numeric_overflow: OverflowPolicy,
+    
+    /// Whether a comparison with Null is false or unknown
+    pub null_semantics: NullSemantics,
+    
+    /// Cache the results of pure top-level `if` conditions in the execution
+    /// context, keyed by the values of the fields they read. Pays off when
+    /// one context sees repeated inputs: `execute_batch`, `execute_stream`
+    /// and `execute_with_context`.
+    pub memoize_conditions: bool,
+    
+    /// Count executions and fires per rule across calls, for
+    /// `RuleEngine::get_rule_stats`. Costs a few atomic adds per rule and a
+    /// clock read per fire.
+    pub collect_rule_stats: bool,
+}
+
+/// Which rules an execution runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// Run every enabled rule in priority order (unless one `return`s)
+    #[default]
+    RunAll,
+    
+    /// Stop after the first rule that fires, i.e. the highest-priority rule
+    /// with a top-level `if` whose condition held. That rule's body still
+    /// runs to completion; rules without a top-level `if` never fire.
+    FirstMatch,
+}
+
+/// What integer arithmetic does when its result does not fit in an i64
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wrap around in two's complement, e.g. `i64::MAX + 1` is `i64::MIN`
+    #[default]
+    Wrap,
+    
+    /// Clamp to `i64::MAX` or `i64::MIN`
+    Saturate,
+    
+    /// Record an `ExecutionError`; the result is Null
+    Error,
+}
+
+/// How conditions treat Null, such as a missing field
+///
+/// With `profile.missing` absent:
+///
+/// | Rule snippet                               | `Rust`      | `Sql`          |
+/// |--------------------------------------------|-------------|----------------|
+/// | `profile.missing > 5`                      | `false`     | `null`         |
+/// | `!(profile.missing > 5)`                   | `true`      | `null`         |
+/// | `if (profile.missing > 5) {..} else {..}`  | runs `else` | runs neither   |
+/// | `profile.missing == null`                  | `true`      | `true`         |
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullSemantics {
+    /// Null is falsy: ordering comparisons with Null are false, and a
+    /// condition is either true or false
+    #[default]
+    Rust,
+    
+    /// Three-valued logic: `<`, `<=`, `>` and `>=` with a Null operand are
+    /// unknown (Null), `!` keeps an unknown unknown, and `&&`/`||` are
+    /// unknown unless the other operand decides them. An `if` whose condition
+    /// is unknown runs neither branch, a ternary on it yields Null, and a
+    /// `while` on it stops. `==` and `!=` still compare Null as a value, so
+    /// `x == null` remains the null test (SQL's `IS NULL`).
+    Sql,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            max_loop_iterations: runtime::context::DEFAULT_MAX_LOOP_ITERATIONS,
+            max_call_depth: runtime::context::DEFAULT_MAX_CALL_DEPTH,
+            strict_fields: false,
+            dedup_actions: false,
+            fraud_score_policy: FraudScorePolicy::KeepAll,
+            collect_metadata: true,
+            mode: ExecutionMode::RunAll,
+            numeric_overflow: OverflowPolicy::Wrap,
+            null_semantics: NullSemantics::Rust,
+            memoize_conditions: false,
+            collect_rule_stats: false,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Defaults for serving traffic: per-rule metadata is not collected
+    pub fn production() -> Self {
+        Self {
+            collect_metadata: false,
+            ..Self::default()
+        }
+    }
 }
 
 /// A compiled rule ready for execution
@@ -89,7 +302,58 @@ pub struct CompiledRule {
     pub id: String,
     pub priority: i32,
     pub enabled: bool,
+    /// Bytecode of an `enabled: <expression>` header, leaving whether the
+    /// rule runs on the stack; empty for a plain `true`/`false`
+    #[serde(default)]
+    pub gate: Vec<Instruction>,
     pub bytecode: Vec<Instruction>,
+    /// Source of each top-level `if` condition, indexed by `TraceCondition`
+    #[serde(default)]
+    pub conditions: Vec<String>,
+    /// The `group` the rule was declared in, if any
+    #[serde(default)]
+    pub group: Option<RuleGroup>,
+    /// Labels from the rule's `tags` field
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `(first instruction, source line)` of each statement, by instruction;
+    /// kept beside the bytecode so the VM never touches it
+    #[serde(default)]
+    pub lines: Vec<(usize, usize)>,
+}
+
+impl CompiledRule {
+    /// Source line of the statement containing the instruction at `pc`
+    pub fn line_at(&self, pc: usize) -> Option<usize> {
+        let index = self.lines.partition_point(|&(start, _)| start <= pc);
+        index.checked_sub(1).map(|i| self.lines[i].1)
+    }
+    
+    /// Execution order key: priority descending, with the rules of a group
+    /// kept together at the group's priority
+    fn run_order(&self) -> (std::cmp::Reverse<i32>, Option<&str>, std::cmp::Reverse<i32>) {
+        match &self.group {
+            Some(group) => (
+                std::cmp::Reverse(group.priority),
+                Some(&group.name),
+                std::cmp::Reverse(self.priority),
+            ),
+            None => (std::cmp::Reverse(self.priority), None, std::cmp::Reverse(0)),
+        }
+    }
+
+    /// Sort rules into execution order; the sort is stable, so rules with
+    /// equal priority keep their declaration order
+    pub(crate) fn sort_for_execution(rules: &mut [CompiledRule]) {
+        rules.sort_by(|a, b| a.run_order().cmp(&b.run_order()));
+    }
+}
+
+/// A named group of rules sharing a priority and a `return` scope
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleGroup {
+    pub name: String,
+    pub priority: i32,
 }
 
 /// A compiled global function
@@ -100,6 +364,47 @@ pub struct CompiledFunction {
     pub bytecode: Vec<Instruction>,
 }
 
+/// Bulk insertion and typed getters shared by [`Transaction`] and
+/// [`UserProfile`]
+///
+/// Numeric getters accept any number and convert it like `Value::as_int` and
+/// `Value::as_float` (so `get_int` truncates a Float); `get_string` and
+/// `get_bool` only accept their own type. A missing field or a value of any
+/// other type is None.
+macro_rules! field_accessors {
+    ($type:ty) => {
+        impl $type {
+            /// Insert every `(key, value)` pair, replacing existing fields
+            pub fn with_fields<K: Into<String>>(mut self, fields: impl IntoIterator<Item = (K, Value)>) -> Self {
+                self.fields.extend(fields.into_iter().map(|(key, value)| (key.into(), value)));
+                self
+            }
+
+            pub fn get_int(&self, key: &str) -> Option<i64> {
+                self.fields.get(key).filter(|v| v.is_numeric()).map(Value::as_int)
+            }
+
+            pub fn get_float(&self, key: &str) -> Option<f64> {
+                self.fields.get(key).filter(|v| v.is_numeric()).map(Value::as_float)
+            }
+
+            pub fn get_string(&self, key: &str) -> Option<&str> {
+                match self.fields.get(key) {
+                    Some(Value::String(s)) => Some(s),
+                    _ => None,
+                }
+            }
+
+            pub fn get_bool(&self, key: &str) -> Option<bool> {
+                match self.fields.get(key) {
+                    Some(Value::Bool(b)) => Some(*b),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
 /// Transaction data (immutable input)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
@@ -118,6 +423,26 @@ impl Transaction {
         self.fields.insert(key.into(), value);
         self
     }
+
+    /// Build a transaction from a plain JSON object, e.g. a request payload
+    ///
+    /// Top-level keys become fields. Nested objects and arrays keep their
+    /// shape as [`Value::Object`] and [`Value::Array`], so rules can read
+    /// `txn.card.bin`. A key missing at any depth reads as null.
+    ///
+    /// ```
+    /// use fraud_rule_engine::{Transaction, Value};
+    ///
+    /// let txn = Transaction::from_json(r#"{"amount": 120, "card": {"bin": "411111"}}"#).unwrap();
+    /// assert_eq!(txn.fields.get("amount"), Some(&Value::Int(120)));
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let fields = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(json)?
+            .into_iter()
+            .map(|(key, value)| (key, Value::from(value)))
+            .collect();
+        Ok(Self { fields })
+    }
 }
 
 impl Default for Transaction {
@@ -126,6 +451,8 @@ impl Default for Transaction {
     }
 }
 
+field_accessors!(Transaction);
+
 /// User profile data (can be mutated by rules)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserProfile {
@@ -152,8 +479,13 @@ impl Default for UserProfile {
     }
 }
 
+field_accessors!(UserProfile);
+
 /// Result of rule execution
-#[derive(Debug, Clone)]
+///
+/// Serializes as one JSON object for logging, with durations in
+/// microseconds (`total_duration_us`, `rule_timings_us`).
+#[derive(Debug, Clone, Serialize)]
 pub struct ExecutionResult {
     /// Modified profile (with mutations applied)
     pub profile: UserProfile,
@@ -169,7 +501,7 @@ pub struct ExecutionResult {
 }
 
 /// Metadata about rule execution
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ExecutionMetadata {
     /// Rules that were executed
     pub executed_rules: Vec<String>,
@@ -178,13 +510,310 @@ pub struct ExecutionMetadata {
     pub skipped_rules: Vec<String>,
     
     /// Execution time per rule
+    #[serde(rename = "rule_timings_us", serialize_with = "duration_micros::serialize_map")]
     pub rule_timings: HashMap<String, std::time::Duration>,
     
     /// Total execution time
+    #[serde(rename = "total_duration_us", serialize_with = "duration_micros::serialize")]
     pub total_duration: std::time::Duration,
     
-    /// Whether execution was short-circuited via return
+    /// Whether execution was short-circuited via return (including a
+    /// `return` that only ended its group)
     pub short_circuited: bool,
+    
+    /// Whether execution stopped because the instruction budget ran out
+    pub budget_exceeded: bool,
+    
+    /// Runtime errors recorded while executing rules
+    pub errors: Vec<ExecutionError>,
+    
+    /// Where each of `errors` was recorded, index for index
+    pub error_locations: Vec<ErrorLocation>,
+    
+    /// Whether this result came from [`RuleEngine::dry_run`]; its profile
+    /// and transaction must not be persisted
+    pub dry_run: bool,
+    
+    /// Profile fields the rules changed, one entry per field; only recorded
+    /// by [`RuleEngine::dry_run`]
+    pub profile_changes: Vec<FieldChange>,
+}
+
+/// A profile field changed during a dry run, or that two engines left
+/// with different values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    /// Value before execution (or from the first engine); Null if the field
+    /// was absent
+    pub old: Value,
+    pub new: Value,
+}
+
+/// How two engines' results for the same input differ, from
+/// [`RuleEngine::diff_execute`]
+///
+/// Actions are compared as a multiset: order does not matter, but an action
+/// emitted twice by one engine and once by the other is reported once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExecutionDiff {
+    /// Actions only the engine `diff_execute` was called on emitted
+    pub only_in_self: Vec<Action>,
+    /// Actions only the other engine emitted
+    pub only_in_other: Vec<Action>,
+    /// Profile fields that ended up different, by field name; `old` is this
+    /// engine's value and `new` the other's
+    pub profile_changes: Vec<FieldChange>,
+}
+
+impl ExecutionDiff {
+    /// Compare two results of the same input
+    pub fn between(ours: &ExecutionResult, theirs: &ExecutionResult) -> Self {
+        // Action is not Eq (scores are floats), so each action is matched
+        // off against an equal one instead of hashed
+        let mut unmatched: Vec<Option<&Action>> = theirs.actions.iter().map(Some).collect();
+        let mut only_in_self = Vec::new();
+        for action in &ours.actions {
+            match unmatched.iter_mut().find(|other| **other == Some(action)) {
+                Some(other) => *other = None,
+                None => only_in_self.push(action.clone()),
+            }
+        }
+        let only_in_other = unmatched.into_iter().flatten().cloned().collect();
+        
+        let fields: std::collections::BTreeSet<&String> =
+            ours.profile.fields.keys().chain(theirs.profile.fields.keys()).collect();
+        let profile_changes = fields
+            .into_iter()
+            .filter_map(|field| {
+                let old = ours.profile.fields.get(field).cloned().unwrap_or(Value::Null);
+                let new = theirs.profile.fields.get(field).cloned().unwrap_or(Value::Null);
+                (old != new).then(|| FieldChange { field: field.clone(), old, new })
+            })
+            .collect();
+        
+        Self { only_in_self, only_in_other, profile_changes }
+    }
+    
+    /// Whether both results had the same actions and profile
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.profile_changes.is_empty()
+    }
+}
+
+/// The rule and source line a runtime error came from
+///
+/// An error inside a global function is located at the rule statement that
+/// called it. The line is None for rules loaded without line information.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorLocation {
+    pub rule_id: String,
+    pub line: Option<usize>,
+}
+
+impl std::fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "rule '{}', line {}", self.rule_id, line),
+            None => write!(f, "rule '{}'", self.rule_id),
+        }
+    }
+}
+
+/// Serde for `ExecutionMetadata` timings: whole microseconds, saturating at
+/// `u64::MAX`
+mod duration_micros {
+    use serde::ser::SerializeMap;
+    use serde::Serializer;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn micros(duration: &Duration) -> u64 {
+        u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(micros(duration))
+    }
+
+    pub fn serialize_map<S: Serializer, H>(
+        timings: &HashMap<String, Duration, H>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(timings.len()))?;
+        for (rule_id, duration) in timings {
+            map.serialize_entry(rule_id, &micros(duration))?;
+        }
+        map.end()
+    }
+}
+
+/// Result of [`RuleEngine::explain`]: the normal execution result plus a
+/// record of why each rule did what it did
+#[derive(Debug, Clone)]
+pub struct ExplainResult {
+    /// Per-rule explanations, in execution order
+    pub rules: Vec<RuleExplanation>,
+    
+    /// The same result [`RuleEngine::execute`] would have produced
+    pub result: ExecutionResult,
+}
+
+/// Explanation of a single executed rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleExplanation {
+    pub rule_id: String,
+    
+    /// Top-level `if` conditions that were evaluated, in order
+    pub conditions: Vec<ConditionTrace>,
+    
+    /// Profile and transaction fields the rule read (`profile.x`, `txn.x`)
+    /// with the value seen on first read
+    pub fields_read: Vec<(String, Value)>,
+    
+    /// Actions emitted by this rule
+    pub actions: Vec<Action>,
+}
+
+/// Result of [`RuleEngine::execute_debug`]: the normal execution result
+/// plus every instruction the VM ran
+#[derive(Debug, Clone)]
+pub struct DebugResult {
+    /// Instructions in execution order, across all rules
+    pub steps: Vec<TraceStep>,
+    
+    /// The same result [`RuleEngine::execute`] would have produced
+    pub result: ExecutionResult,
+}
+
+/// An instruction about to be executed and the operand stack it saw
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep {
+    pub rule_id: String,
+    pub instruction: Instruction,
+    /// Operand stack before the instruction ran, bottom first
+    pub stack: Vec<Value>,
+}
+
+/// A traced condition and what it evaluated to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionTrace {
+    /// Condition rendered back to DSL source
+    pub condition: String,
+    pub result: bool,
+}
+
+/// Options for compiling DSL source into a [`RuleEngine`]
+///
+/// Start one with [`RuleEngine::builder`]. The settings combine freely, e.g.
+/// a schema with limits and a default priority; the `RuleEngine::from_dsl_with_*`
+/// constructors are shorthands for a builder with a single setting. The
+/// schema, limits, custom actions and default priority also apply to rules
+/// added later with [`RuleEngine::add_rule`].
+///
+/// # Example
+///
+/// ```rust
+/// use fraud_rule_engine::{CompileLimits, RuleEngine, Schema, ValueType};
+///
+/// let engine = RuleEngine::builder()
+///     .with_schema(Schema::new().with_field("txn.amount", ValueType::Float))
+///     .with_limits(CompileLimits::new().with_max_rules(100))
+///     .with_default_priority(1)
+///     .compile(r#"rule "big" { if (txn.amount > 1000) { setFraudScore(0.5); } }"#)
+///     .unwrap();
+///
+/// assert_eq!(engine.get_rule("big").unwrap().priority, 1);
+/// ```
+#[derive(Clone)]
+pub struct RuleEngineBuilder {
+    actions: ActionRegistry,
+    host_functions: HostFunctions,
+    schema: Schema,
+    limits: CompileLimits,
+    default_priority: i32,
+}
+
+impl Default for RuleEngineBuilder {
+    fn default() -> Self {
+        Self {
+            actions: ActionRegistry::default(),
+            host_functions: HostFunctions::default(),
+            schema: Schema::default(),
+            limits: CompileLimits::default(),
+            default_priority: parser::parser::DEFAULT_PRIORITY,
+        }
+    }
+}
+
+impl RuleEngineBuilder {
+    /// Let rules call registered custom actions
+    pub fn with_actions(mut self, actions: ActionRegistry) -> Self {
+        self.actions = actions;
+        self
+    }
+    
+    /// Let rules call host functions; see [`HostFn`]
+    pub fn with_host_functions(mut self, host_functions: HostFunctions) -> Self {
+        self.host_functions = host_functions;
+        self
+    }
+    
+    /// Type check comparisons against a schema of input field types
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+    
+    /// Limit the size of the rules, for hosting rules from untrusted sources
+    pub fn with_limits(mut self, limits: CompileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    
+    /// Give rules that omit `priority` this priority instead of 100
+    pub fn with_default_priority(mut self, default_priority: i32) -> Self {
+        self.default_priority = default_priority;
+        self
+    }
+    
+    /// Compile DSL source into a rule engine
+    pub fn compile(self, dsl_source: &str) -> Result<RuleEngine, CompilationError> {
+        self.compile_with_report(dsl_source).map(|(engine, _)| engine)
+    }
+    
+    /// Compile DSL source like [`RuleEngineBuilder::compile`], also returning
+    /// a [`CompileReport`] of what the rules compiled to
+    pub fn compile_with_report(self, dsl_source: &str) -> Result<(RuleEngine, CompileReport), CompilationError> {
+        let ast = parser::parse_with_options(dsl_source, &self.limits, self.default_priority)?;
+        self.build(ast)
+    }
+    
+    /// Compile several named sources into one rule engine, as
+    /// [`RuleEngine::from_sources`] does
+    pub fn compile_sources(self, sources: &[(&str, &str)]) -> Result<RuleEngine, CompilationError> {
+        let ast = parser::parse_sources_with_options(sources, &self.limits, self.default_priority)?;
+        self.build(ast).map(|(engine, _)| engine)
+    }
+    
+    fn build(self, ast: parser::Program) -> Result<(RuleEngine, CompileReport), CompilationError> {
+        let ((rules, functions, symbols, regexes, sets, pool, memo), warnings) = compiler::compile_with_warnings(
+            ast,
+            &self.actions,
+            &self.host_functions,
+            &self.schema,
+            &self.limits,
+        )?;
+        
+        let report = CompileReport::new(&rules, &functions, &symbols, warnings);
+        let mut engine = RuleEngine::from_parts(rules, functions, symbols, regexes, sets, pool, memo)
+            .with_host_functions(self.host_functions);
+        engine.actions = Arc::new(self.actions);
+        engine.schema = Arc::new(self.schema);
+        engine.limits = self.limits;
+        engine.default_priority = self.default_priority;
+        Ok((engine, report))
+    }
 }
 
 impl RuleEngine {
@@ -207,124 +836,1014 @@ impl RuleEngine {
     /// let engine = RuleEngine::from_dsl(dsl).unwrap();
     /// ```
     pub fn from_dsl(dsl_source: &str) -> Result<Self, CompilationError> {
-        let ast = parser::parse(dsl_source)?;
-        let (rules, functions) = compiler::compile(ast)?;
-        
-        Ok(Self {
-            compiled_rules: Arc::new(rules),
-            global_functions: Arc::new(functions),
-        })
+        Self::builder().compile(dsl_source)
     }
     
-    /// Load from pre-compiled bytecode (for hot reload)
-    pub fn from_bytecode(data: &[u8]) -> Result<Self, CompilationError> {
-        let (rules, functions): (Vec<CompiledRule>, Vec<CompiledFunction>) = 
-            bincode::deserialize(data)
-                .map_err(|e| CompilationError::CompileError(e.to_string()))?;
-        
-        let mut func_map = HashMap::default();
-        for func in functions {
-            func_map.insert(func.name.clone(), func);
-        }
-        
-        Ok(Self {
-            compiled_rules: Arc::new(rules),
-            global_functions: Arc::new(func_map),
-        })
+    /// Start a [`RuleEngineBuilder`] for compiling rules with a combination
+    /// of custom actions, host functions, a schema, limits and a default
+    /// priority
+    pub fn builder() -> RuleEngineBuilder {
+        RuleEngineBuilder::default()
     }
     
-    /// Serialize to bytecode for storage/hot reload
-    pub fn to_bytecode(&self) -> Result<Vec<u8>, CompilationError> {
-        let functions: Vec<_> = self.global_functions.values().cloned().collect();
-        let data = (self.compiled_rules.as_ref(), functions);
-        
-        bincode::serialize(&data)
-            .map_err(|e| CompilationError::CompileError(e.to_string()))
+    /// Create a rule engine whose rules may call registered custom actions
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::{Action, ActionRegistry, RuleEngine, Transaction, UserProfile};
+    ///
+    /// let actions = ActionRegistry::new().register_action("setRiskBand", &["band"]);
+    /// let engine = RuleEngine::from_dsl_with_actions(
+    ///     r#"rule "gold" { priority: 1, if (true) { setRiskBand("GOLD"); } }"#,
+    ///     actions,
+    /// )
+    /// .unwrap();
+    ///
+    /// let result = engine.execute(Transaction::new(), UserProfile::new());
+    /// assert!(matches!(&result.actions[0], Action::Custom { params, .. } if params.contains_key("band")));
+    /// ```
+    pub fn from_dsl_with_actions(
+        dsl_source: &str,
+        actions: ActionRegistry,
+    ) -> Result<Self, CompilationError> {
+        Self::builder().with_actions(actions).compile(dsl_source)
     }
     
-    /// Execute rules against transaction and profile
+    /// Create a rule engine whose rules may call host functions
     ///
-    /// This is the HOT PATH - optimized for minimal latency.
+    /// A call to a name that is not a DSL function runs the host function of
+    /// that name with the call's arguments and evaluates to its result. Host
+    /// functions run inline on every execution, so they must be fast and
+    /// pure; see [`HostFn`].
     ///
     /// # Example
     ///
     /// ```rust
-    /// use fraud_rule_engine::{RuleEngine, Transaction, UserProfile, Value};
-    /// use ahash::HashMap;
+    /// use fraud_rule_engine::{HostFn, HostFunctions, RuleEngine, Transaction, UserProfile, Value};
+    /// use std::sync::Arc;
     ///
-    /// let engine = RuleEngine::from_dsl("rule \"test\" { priority: 1, if (true) {} }").unwrap();
-    /// let txn = Transaction::new();
-    /// let profile = UserProfile::new();
+    /// let mut host_functions = HostFunctions::default();
+    /// let lookup_bin: HostFn = Arc::new(|args| match args.first() {
+    ///     Some(Value::String(bin)) if bin.starts_with("4") => Value::from("VISA"),
+    ///     _ => Value::Null,
+    /// });
+    /// host_functions.insert("lookupBin".to_string(), lookup_bin);
     ///
-    /// let result = engine.execute(txn, profile);
+    /// let engine = RuleEngine::from_dsl_with_host_functions(
+    ///     r#"rule "visa" { priority: 1, if (lookupBin(txn.bin) == "VISA") { setFraudScore(0.1); } }"#,
+    ///     host_functions,
+    /// )
+    /// .unwrap();
+    ///
+    /// let txn = Transaction::new().with_field("bin", Value::from("411111"));
+    /// assert_eq!(engine.execute(txn, UserProfile::new()).actions.len(), 1);
     /// ```
-    pub fn execute(
-        &self,
-        transaction: Transaction,
-        profile: UserProfile,
-    ) -> ExecutionResult {
-        let start = std::time::Instant::now();
-        
-        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
-        
-        // Execute each enabled rule in priority order
-        for rule in self.compiled_rules.iter() {
-            if !rule.enabled {
-                ctx.metadata.skipped_rules.push(rule.id.clone());
-                continue;
-            }
-            
-            let rule_start = std::time::Instant::now();
-            
-            // Execute rule bytecode
-            runtime::vm::VM::execute(&rule.bytecode, &mut ctx, &self.global_functions);
-            
-            ctx.metadata.executed_rules.push(rule.id.clone());
-            ctx.metadata.rule_timings.insert(
-                rule.id.clone(),
-                rule_start.elapsed(),
-            );
-            
-            // Check for short-circuit
-            if ctx.should_return {
-                ctx.metadata.short_circuited = true;
-                break;
-            }
-        }
-        
-        ctx.metadata.total_duration = start.elapsed();
-        
-        ExecutionResult {
-            profile: ctx.profile,
-            transaction: ctx.transaction,
-            actions: ctx.actions,
-            metadata: ctx.metadata,
-        }
+    pub fn from_dsl_with_host_functions(
+        dsl_source: &str,
+        host_functions: HostFunctions,
+    ) -> Result<Self, CompilationError> {
+        Self::builder().with_host_functions(host_functions).compile(dsl_source)
     }
     
-    /// Validate DSL syntax without compiling
-    pub fn validate_dsl(dsl_source: &str) -> Result<(), CompilationError> {
-        parser::parse(dsl_source)?;
-        Ok(())
+    /// Create a rule engine from several named sources, e.g. one per file
+    ///
+    /// Functions and constants defined in any source can be used by rules in
+    /// all of them. Defining the same rule id, function, constant or group in
+    /// two sources is an error naming both; see [`parser::parse_sources`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::RuleEngine;
+    ///
+    /// let engine = RuleEngine::from_sources(&[
+    ///     ("functions.rules", "function isLarge(amount) { return amount > 1000; }"),
+    ///     ("velocity.rules", r#"rule "large" { priority: 1, if (isLarge(txn.amount)) { setFraudScore(0.5); } }"#),
+    /// ])
+    /// .unwrap();
+    /// ```
+    pub fn from_sources(sources: &[(&str, &str)]) -> Result<Self, CompilationError> {
+        Self::builder().compile_sources(sources)
     }
     
-    /// Get metadata about loaded rules
-    pub fn get_rules_metadata(&self) -> Vec<RuleMetadata> {
-        self.compiled_rules
-            .iter()
-            .map(|r| RuleMetadata {
-                id: r.id.clone(),
-                priority: r.priority,
-                enabled: r.enabled,
-            })
-            .collect()
+    /// Create a rule engine whose comparisons are type checked against a
+    /// schema of input field types
+    ///
+    /// Comparing a declared field with a literal, constant or other declared
+    /// field of an incompatible type is a [`CompilationError::TypeMismatch`].
+    /// Rules added later with [`RuleEngine::add_rule`] are checked too.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::{CompilationError, RuleEngine, Schema, ValueType};
+    ///
+    /// let schema = Schema::new().with_field("txn.amount", ValueType::Float);
+    /// let err = RuleEngine::from_dsl_with_schema(
+    ///     r#"rule "big" { priority: 1, if (txn.amount > "1000") { setFraudScore(0.5); } }"#,
+    ///     schema,
+    /// )
+    /// .err()
+    /// .unwrap();
+    ///
+    /// assert!(matches!(err, CompilationError::TypeMismatch { .. }));
+    /// ```
+    pub fn from_dsl_with_schema(dsl_source: &str, schema: Schema) -> Result<Self, CompilationError> {
+        Self::builder().with_schema(schema).compile(dsl_source)
     }
     
-    /// Get list of global functions
-    pub fn get_functions(&self) -> Vec<String> {
-        self.global_functions.keys().cloned().collect()
-    }
-}
+    /// Create a rule engine along with a [`CompileReport`] of what the rules
+    /// compiled to: bytecode sizes, the fields, functions and actions they
+    /// use, and any warnings
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::RuleEngine;
+    ///
+    /// let (engine, report) = RuleEngine::from_dsl_with_report(
+    ///     r#"rule "big" { priority: 1, if (txn.amount > 1000) { setFraudScore(0.5); } }"#,
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(report.fields, ["txn.amount"]);
+    /// assert_eq!(report.actions, ["setFraudScore"]);
+    /// ```
+    pub fn from_dsl_with_report(dsl_source: &str) -> Result<(Self, CompileReport), CompilationError> {
+        Self::builder().compile_with_report(dsl_source)
+    }
+    
+    /// Create a rule engine with limits on the size of the rules, for
+    /// hosting rules from untrusted sources
+    ///
+    /// Other constructors apply [`CompileLimits::default`]. Rules
+    /// added later with [`RuleEngine::add_rule`] are held to the same limits.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::{CompileLimits, RuleEngine};
+    ///
+    /// let limits = CompileLimits::new().with_max_rules(100).with_max_expression_depth(16);
+    /// let nested = format!(r#"rule "deep" {{ priority: 1, if ({}1{}) {{}} }}"#, "(".repeat(20), ")".repeat(20));
+    ///
+    /// assert!(RuleEngine::from_dsl_with_limits(&nested, limits).is_err());
+    /// ```
+    pub fn from_dsl_with_limits(dsl_source: &str, limits: CompileLimits) -> Result<Self, CompilationError> {
+        Self::builder().with_limits(limits).compile(dsl_source)
+    }
+    
+    /// Create a rule engine where rules that omit `priority` get
+    /// `default_priority` instead of 100
+    ///
+    /// Rules added later with [`RuleEngine::add_rule`] get the same default.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::RuleEngine;
+    ///
+    /// let engine = RuleEngine::from_dsl_with_default_priority(
+    ///     r#"
+    ///     rule "unranked" { if (true) {} }
+    ///     rule "urgent" { priority: 10, if (true) {} }
+    ///     "#,
+    ///     1,
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(engine.get_rule("unranked").unwrap().priority, 1);
+    /// assert_eq!(engine.get_rules_metadata()[0].id, "urgent");
+    /// ```
+    pub fn from_dsl_with_default_priority(dsl_source: &str, default_priority: i32) -> Result<Self, CompilationError> {
+        Self::builder().with_default_priority(default_priority).compile(dsl_source)
+    }
+    
+    /// Load from pre-compiled bytecode (for hot reload)
+    ///
+    /// Fails if the payload lacks the bytecode header or was written with a
+    /// different [`BYTECODE_VERSION`].
+    pub fn from_bytecode(data: &[u8]) -> Result<Self, CompilationError> {
+        let payload = Self::check_bytecode_header(data)?;
+        let (rules, functions, symbols, regexes, sets, pool, memo): ExportData = bincode::deserialize(payload)
+            .map_err(|e| CompilationError::CompileError(e.to_string()))?;
+        
+        Self::from_compiled(rules, functions, symbols, regexes, sets, pool, memo)
+    }
+    
+    /// Validate the magic bytes and format version, returning the payload
+    fn check_bytecode_header(data: &[u8]) -> Result<&[u8], CompilationError> {
+        let header_len = BYTECODE_MAGIC.len() + 2;
+        
+        if data.len() < header_len || !data.starts_with(BYTECODE_MAGIC) {
+            return Err(CompilationError::CompileError(
+                "incompatible bytecode version: missing header".to_string(),
+            ));
+        }
+        
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != BYTECODE_VERSION {
+            return Err(CompilationError::CompileError(format!(
+                "incompatible bytecode version {} (expected {})",
+                version, BYTECODE_VERSION
+            )));
+        }
+        
+        Ok(&data[header_len..])
+    }
+    
+    /// Load from compiled rules exported with [`RuleEngine::to_json`]
+    pub fn from_json(json: &str) -> Result<Self, CompilationError> {
+        let (rules, functions, symbols, regexes, sets, pool, memo): ExportData = serde_json::from_str(json)
+            .map_err(|e| CompilationError::CompileError(e.to_string()))?;
+        
+        Self::from_compiled(rules, functions, symbols, regexes, sets, pool, memo)
+    }
+    
+    fn from_compiled(
+        rules: Vec<CompiledRule>,
+        functions: Vec<CompiledFunction>,
+        symbols: SymbolTable,
+        regexes: RegexTable,
+        sets: SetTable,
+        pool: ConstantPool,
+        memo: MemoTable,
+    ) -> Result<Self, CompilationError> {
+        let mut func_map = HashMap::default();
+        for func in functions {
+            func_map.insert(func.name.clone(), func);
+        }
+        
+        compiler::validate_indexes(&rules, &func_map, &symbols, &regexes, &sets, &pool, &memo)?;
+        Ok(Self::from_parts(rules, func_map, symbols, regexes, sets, pool, memo))
+    }
+    
+    fn from_parts(
+        rules: Vec<CompiledRule>,
+        functions: HashMap<String, CompiledFunction>,
+        symbols: SymbolTable,
+        regexes: RegexTable,
+        sets: SetTable,
+        pool: ConstantPool,
+        memo: MemoTable,
+    ) -> Self {
+        let mut engine = Self {
+            compiled_rules: Arc::default(),
+            rule_enabled: Arc::default(),
+            rule_stats: Arc::new([]),
+            global_functions: Arc::new(functions),
+            symbols: Arc::new(symbols),
+            regexes: Arc::new(regexes),
+            sets: Arc::new(sets),
+            pool: Arc::new(pool),
+            memo: Arc::new(memo),
+            generation: next_generation(),
+            actions: Arc::default(),
+            schema: Arc::default(),
+            limits: CompileLimits::default(),
+            default_priority: parser::parser::DEFAULT_PRIORITY,
+            host_functions: Arc::default(),
+            config: EngineConfig::default(),
+        };
+        engine.replace_rules(rules);
+        engine
+    }
+    
+    /// Set the host functions rules may call
+    ///
+    /// Use this to supply host functions to an engine loaded with
+    /// [`RuleEngine::from_bytecode`] or [`RuleEngine::from_json`]. Names are
+    /// not re-checked against the loaded rules; a call whose host function is
+    /// missing evaluates to Null.
+    pub fn with_host_functions(mut self, host_functions: HostFunctions) -> Self {
+        self.host_functions = Arc::new(host_functions);
+        self
+    }
+    
+    /// Set the maximum number of iterations a `while` loop may run
+    pub fn with_max_loop_iterations(mut self, max: usize) -> Self {
+        self.config.max_loop_iterations = max;
+        self
+    }
+    
+    /// Set the maximum depth of nested global function calls
+    pub fn with_max_call_depth(mut self, max: usize) -> Self {
+        self.config.max_call_depth = max;
+        self
+    }
+    
+    /// Record an error when rules read absent profile/transaction fields
+    pub fn with_strict_fields(mut self, strict: bool) -> Self {
+        self.config.strict_fields = strict;
+        self.generation = next_generation();
+        self
+    }
+    
+    /// Remove exact-duplicate actions from results, keeping first-seen order
+    pub fn with_dedup_actions(mut self, dedup: bool) -> Self {
+        self.config.dedup_actions = dedup;
+        self
+    }
+    
+    /// Keep only the last fraud score set during an execution; shorthand
+    /// for [`FraudScorePolicy::Last`] (or [`FraudScorePolicy::KeepAll`])
+    pub fn with_last_fraud_score_wins(self, last_wins: bool) -> Self {
+        self.with_fraud_score_policy(if last_wins {
+            FraudScorePolicy::Last
+        } else {
+            FraudScorePolicy::KeepAll
+        })
+    }
+    
+    /// Collapse the fraud scores set during an execution into a single
+    /// `SetFraudScore` using `policy`
+    pub fn with_fraud_score_policy(mut self, policy: FraudScorePolicy) -> Self {
+        self.config.fraud_score_policy = policy;
+        self
+    }
+    
+    /// Record executed/skipped rule ids and per-rule timings
+    pub fn with_collect_metadata(mut self, collect: bool) -> Self {
+        self.config.collect_metadata = collect;
+        self
+    }
+    
+    /// Set what integer arithmetic does on overflow
+    pub fn with_numeric_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.config.numeric_overflow = policy;
+        self.generation = next_generation();
+        self
+    }
+    
+    /// Set whether comparisons with Null are false or unknown
+    pub fn with_null_semantics(mut self, semantics: NullSemantics) -> Self {
+        self.config.null_semantics = semantics;
+        self.generation = next_generation();
+        self
+    }
+    
+    /// Cache the results of pure top-level conditions; see
+    /// [`EngineConfig::memoize_conditions`]
+    pub fn with_memoize_conditions(mut self, memoize: bool) -> Self {
+        self.config.memoize_conditions = memoize;
+        self
+    }
+    
+    /// Count executions and fires per rule; see [`RuleEngine::get_rule_stats`]
+    pub fn with_collect_rule_stats(mut self, collect: bool) -> Self {
+        self.config.collect_rule_stats = collect;
+        self
+    }
+    
+    /// Set whether all rules run or only up to the first that fires
+    pub fn with_execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+    
+    /// Replace all execution settings, e.g. with [`EngineConfig::production`]
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self.generation = next_generation();
+        self
+    }
+    
+    /// Get the engine's execution settings
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+    
+    /// Serialize to bytecode for storage/hot reload
+    ///
+    /// The output starts with a header of magic bytes and
+    /// [`BYTECODE_VERSION`], checked by [`RuleEngine::from_bytecode`].
+    pub fn to_bytecode(&self) -> Result<Vec<u8>, CompilationError> {
+        let mut data = BYTECODE_MAGIC.to_vec();
+        data.extend_from_slice(&BYTECODE_VERSION.to_le_bytes());
+        
+        bincode::serialize_into(&mut data, &self.export_data())
+            .map_err(|e| CompilationError::CompileError(e.to_string()))?;
+        
+        Ok(data)
+    }
+    
+    /// Serialize the compiled rules and functions as pretty-printed JSON
+    ///
+    /// Carries the same data as [`RuleEngine::to_bytecode`] in a readable
+    /// form for inspecting bytecode and diffing deployments. Functions are
+    /// sorted by name so identical engines export identical JSON.
+    pub fn to_json(&self) -> Result<String, CompilationError> {
+        serde_json::to_string_pretty(&self.export_data())
+            .map_err(|e| CompilationError::CompileError(e.to_string()))
+    }
+    
+    /// The `(rules, functions, symbols, regexes, sets, pool, memo)` shared
+    /// by the bytecode and JSON formats
+    fn export_data(&self) -> ExportData {
+        let mut functions: Vec<_> = self.global_functions.values().cloned().collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        
+        (
+            self.current_rules(),
+            functions,
+            (*self.symbols).clone(),
+            (*self.regexes).clone(),
+            (*self.sets).clone(),
+            (*self.pool).clone(),
+            (*self.memo).clone(),
+        )
+    }
+    
+    /// Execute rules against transaction and profile
+    ///
+    /// This is the HOT PATH - optimized for minimal latency.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::{RuleEngine, Transaction, UserProfile, Value};
+    /// use ahash::HashMap;
+    ///
+    /// let engine = RuleEngine::from_dsl("rule \"test\" { priority: 1, if (true) {} }").unwrap();
+    /// let txn = Transaction::new();
+    /// let profile = UserProfile::new();
+    ///
+    /// let result = engine.execute(txn, profile);
+    /// ```
+    pub fn execute(
+        &self,
+        transaction: Transaction,
+        profile: UserProfile,
+    ) -> ExecutionResult {
+        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
+        self.run(&mut ctx, None, None, &[])
+    }
+    
+    /// Execute rules like [`RuleEngine::execute`], reusing a caller-owned
+    /// context
+    ///
+    /// The context is [reset](runtime::ExecutionContext::reset) first, so its
+    /// operand stack, locals and field slots keep their allocations from the
+    /// previous call. Hot callers can keep one context per worker thread to
+    /// avoid rebuilding them for every transaction. Any context works, even
+    /// one last used with a different engine; settings such as an
+    /// `instruction_budget` stay on the context and keep applying.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::runtime::ExecutionContext;
+    /// use fraud_rule_engine::{RuleEngine, Transaction, UserProfile, Value};
+    ///
+    /// let engine = RuleEngine::from_dsl(
+    ///     r#"rule "big" { priority: 1, if (txn.amount > 100) { setFraudScore(0.5); } }"#,
+    /// )
+    /// .unwrap();
+    ///
+    /// let mut scratch = ExecutionContext::new(Transaction::new(), UserProfile::new());
+    /// for amount in [50, 500] {
+    ///     let txn = Transaction::new().with_field("amount", Value::Int(amount));
+    ///     let result = engine.execute_with_context(&mut scratch, txn, UserProfile::new());
+    ///     assert_eq!(result.actions.len(), usize::from(amount > 100));
+    /// }
+    /// ```
+    pub fn execute_with_context(
+        &self,
+        ctx: &mut runtime::ExecutionContext,
+        transaction: Transaction,
+        profile: UserProfile,
+    ) -> ExecutionResult {
+        ctx.reset(transaction, profile);
+        self.run(ctx, None, None, &[])
+    }
+    
+    /// Execute only the rules tagged with at least one of `tags`
+    ///
+    /// Lets services share one compiled rule set while each runs its own
+    /// category, e.g. `&["aml"]`. Rules filtered out are not run and not
+    /// listed in `metadata.skipped_rules`. An empty filter runs every rule,
+    /// like [`RuleEngine::execute`].
+    pub fn execute_tagged(
+        &self,
+        transaction: Transaction,
+        profile: UserProfile,
+        tags: &[&str],
+    ) -> ExecutionResult {
+        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
+        self.run(&mut ctx, None, None, tags)
+    }
+    
+    /// Execute rules and explain why each rule fired
+    ///
+    /// Produces the same [`ExecutionResult`] as [`RuleEngine::execute`], plus
+    /// for every executed rule the outcome of its top-level `if` conditions,
+    /// the fields it read and the actions it emitted. Tracing is only switched
+    /// on here, so `execute` pays nothing beyond a skipped check per traced
+    /// condition and field read. Intended for analysts, not the hot path.
+    pub fn explain(&self, transaction: Transaction, profile: UserProfile) -> ExplainResult {
+        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
+        let mut rules = Vec::new();
+        let result = self.run(&mut ctx, Some(&mut rules), None, &[]);
+        
+        ExplainResult { rules, result }
+    }
+    
+    /// Execute rules and record every VM instruction with the stack it saw
+    ///
+    /// Produces the same [`ExecutionResult`] as [`RuleEngine::execute`].
+    /// Useful for working out why a condition evaluated unexpectedly; the
+    /// trace copies the stack at every step, so this is far slower than
+    /// `execute` and only meant for debugging.
+    pub fn execute_debug(&self, transaction: Transaction, profile: UserProfile) -> DebugResult {
+        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
+        let mut steps = Vec::new();
+        let result = self.run(&mut ctx, None, Some(&mut steps), &[]);
+        
+        DebugResult { steps, result }
+    }
+    
+    /// Execute rules without committing to their profile mutations
+    ///
+    /// Produces the same result as [`RuleEngine::execute`], flagged with
+    /// `metadata.dry_run`, and records every profile field the rules changed
+    /// as old → new in `metadata.profile_changes` so a rule change can be
+    /// reviewed against production-like data. Callers must not persist the
+    /// returned profile.
+    pub fn dry_run(&self, transaction: Transaction, profile: UserProfile) -> ExecutionResult {
+        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
+        ctx.record_profile_changes = true;
+        let mut result = self.run(&mut ctx, None, None, &[]);
+        result.metadata.dry_run = true;
+        result
+    }
+    
+    /// Run this engine and `other` on copies of the same input and report
+    /// how their results differ
+    ///
+    /// Meant for checking a rule change before deploying it, with `self`
+    /// the current rules and `other` the candidate. Each engine runs with
+    /// its own settings.
+    pub fn diff_execute(&self, other: &RuleEngine, transaction: Transaction, profile: UserProfile) -> ExecutionDiff {
+        let ours = self.execute(transaction.clone(), profile.clone());
+        let theirs = other.execute(transaction, profile);
+        ExecutionDiff::between(&ours, &theirs)
+    }
+    
+    /// Execute rules with a cap on the total number of VM instructions
+    ///
+    /// When the budget runs out, execution stops cleanly: the current rule is
+    /// abandoned, no further rules run, and `metadata.budget_exceeded` is set.
+    /// This bounds latency even for runaway loops or deep recursion.
+    pub fn execute_with_budget(
+        &self,
+        transaction: Transaction,
+        profile: UserProfile,
+        max_instructions: u64,
+    ) -> ExecutionResult {
+        let mut ctx = runtime::ExecutionContext::new(transaction, profile);
+        ctx.instruction_budget = max_instructions;
+        self.run(&mut ctx, None, None, &[])
+    }
+    
+    /// Execute rules against many transactions
+    ///
+    /// Results are identical to calling [`RuleEngine::execute`] for each input
+    /// in order, but a single execution context is reused so its stack and
+    /// local-variable allocations are not rebuilt per transaction.
+    pub fn execute_batch(&self, inputs: Vec<(Transaction, UserProfile)>) -> Vec<ExecutionResult> {
+        self.execute_stream(inputs).collect()
+    }
+    
+    /// Lazily execute rules against each input of a possibly unbounded
+    /// stream, e.g. messages from a queue consumer
+    ///
+    /// Each result is produced only when the returned iterator is advanced,
+    /// and like [`RuleEngine::execute_batch`] a single execution context is
+    /// reused between inputs. The iterator is `Send` when the input iterator
+    /// is, so it can be moved to a worker thread.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use fraud_rule_engine::{RuleEngine, Transaction, UserProfile, Value};
+    ///
+    /// let engine = RuleEngine::from_dsl(
+    ///     r#"rule "big" { priority: 1, if (txn.amount > 100) { setFraudScore(0.5); } }"#,
+    /// )
+    /// .unwrap();
+    ///
+    /// let inputs = (0..3).map(|i| {
+    ///     (Transaction::new().with_field("amount", Value::Int(i * 100)), UserProfile::new())
+    /// });
+    /// let flagged = engine.execute_stream(inputs).filter(|r| !r.actions.is_empty()).count();
+    /// assert_eq!(flagged, 1);
+    /// ```
+    pub fn execute_stream<'a, I>(&'a self, inputs: I) -> impl Iterator<Item = ExecutionResult> + 'a
+    where
+        I: IntoIterator<Item = (Transaction, UserProfile)>,
+        I::IntoIter: 'a,
+    {
+        let mut ctx = runtime::ExecutionContext::new(Transaction::new(), UserProfile::new());
+        
+        inputs.into_iter().map(move |(transaction, profile)| {
+            ctx.reset(transaction, profile);
+            self.run(&mut ctx, None, None, &[])
+        })
+    }
+    
+    /// Evaluate a rule's `enabled:` condition with the truthiness of an `if`;
+    /// a condition that fails with an error keeps the rule from running
+    fn rule_gate_open(&self, rule: &CompiledRule, ctx: &mut runtime::ExecutionContext) -> bool {
+        let errors_before = ctx.errors.len();
+        ctx.clear_locals();
+        ctx.stack.clear();
+        runtime::vm::VM::execute(&rule.gate, ctx, &self.global_functions, &self.host_functions);
+        let open = ctx.errors.len() == errors_before && ctx.pop().is_some_and(|value| value.as_bool());
+        ctx.stack.clear();
+        
+        for _ in errors_before..ctx.errors.len() {
+            ctx.metadata.error_locations.push(ErrorLocation {
+                rule_id: rule.id.clone(),
+                line: None,
+            });
+        }
+        open
+    }
+    
+    /// Run all rules against a prepared context, moving the outputs out of it
+    ///
+    /// When `explanations` is given, each rule runs with tracing enabled and
+    /// its explanation is appended. A non-empty `tags` limits the run to
+    /// rules with at least one of them.
+    fn run(
+        &self,
+        ctx: &mut runtime::ExecutionContext,
+        mut explanations: Option<&mut Vec<RuleExplanation>>,
+        mut steps: Option<&mut Vec<TraceStep>>,
+        tags: &[&str],
+    ) -> ExecutionResult {
+        let start = std::time::Instant::now();
+        
+        ctx.max_loop_iterations = self.config.max_loop_iterations;
+        ctx.max_call_depth = self.config.max_call_depth;
+        ctx.strict_fields = self.config.strict_fields;
+        ctx.numeric_overflow = self.config.numeric_overflow;
+        ctx.null_semantics = self.config.null_semantics;
+        ctx.bind_symbols(Arc::clone(&self.symbols));
+        ctx.regexes = Arc::clone(&self.regexes);
+        ctx.sets = Arc::clone(&self.sets);
+        ctx.pool = Arc::clone(&self.pool);
+        ctx.memo = Arc::clone(&self.memo);
+        // Traced runs must evaluate every condition to report on it
+        if self.config.memoize_conditions && explanations.is_none() && steps.is_none() {
+            ctx.condition_cache
+                .get_or_insert_with(runtime::context::ConditionCache::default)
+                .bind(self.generation);
+        } else {
+            ctx.condition_cache = None;
+        }
+        
+        // Group whose remaining rules are skipped after one of its rules
+        // returned
+        let mut returned_group: Option<&str> = None;
+        
+        // Execute each enabled rule in priority order
+        let rules = self.compiled_rules.iter().zip(self.rule_enabled.iter()).zip(self.rule_stats.iter());
+        for ((rule, enabled), stats) in rules {
+            if returned_group.is_some() && returned_group == rule.group.as_ref().map(|g| g.name.as_str()) {
+                continue;
+            }
+            if !tags.is_empty() && !rule.tags.iter().any(|tag| tags.contains(&tag.as_str())) {
+                continue;
+            }
+            if !enabled.load(Ordering::Relaxed) {
+                if self.config.collect_metadata {
+                    ctx.metadata.skipped_rules.push(rule.id.clone());
+                }
+                continue;
+            }
+            // An `enabled:` condition is evaluated when the rule's turn comes,
+            // so it sees changes made by earlier rules
+            if !rule.gate.is_empty() && !self.rule_gate_open(rule, ctx) {
+                if ctx.metadata.budget_exceeded {
+                    break;
+                }
+                if self.config.collect_metadata {
+                    ctx.metadata.skipped_rules.push(rule.id.clone());
+                }
+                continue;
+            }
+            
+            let rule_start = self.config.collect_metadata.then(std::time::Instant::now);
+            let actions_before = ctx.actions.len();
+            let errors_before = ctx.errors.len();
+            if explanations.is_some() {
+                ctx.trace = Some(runtime::context::RuleTrace::default());
+            }
+            
+            // Each rule starts with its own locals and an empty stack; profile
+            // and transaction changes and emitted actions carry over
+            ctx.clear_locals();
+            ctx.stack.clear();
+            ctx.aborted = false;
+            ctx.rule_fired = false;
+            if let Some(cache) = ctx.condition_cache.as_mut() {
+                cache.cancel();
+            }
+            
+            // Execute rule bytecode
+            match steps.as_deref_mut() {
+                Some(steps) => {
+                    let mut tracer = |instruction: &Instruction, stack: &[Value]| {
+                        steps.push(TraceStep {
+                            rule_id: rule.id.clone(),
+                            instruction: instruction.clone(),
+                            stack: stack.to_vec(),
+                        });
+                    };
+                    runtime::vm::VM::execute_traced(
+                        &rule.bytecode,
+                        ctx,
+                        &self.global_functions,
+                        &self.host_functions,
+                        Some(&mut tracer),
+                    );
+                }
+                None => runtime::vm::VM::execute(&rule.bytecode, ctx, &self.global_functions, &self.host_functions),
+            }
+            
+            for &pc in &ctx.error_pcs[errors_before..] {
+                ctx.metadata.error_locations.push(ErrorLocation {
+                    rule_id: rule.id.clone(),
+                    line: rule.line_at(pc),
+                });
+            }
+            
+            if let (Some(explanations), Some(trace)) = (explanations.as_deref_mut(), ctx.trace.take()) {
+                explanations.push(RuleExplanation {
+                    rule_id: rule.id.clone(),
+                    conditions: trace
+                        .conditions
+                        .into_iter()
+                        .map(|(index, result)| ConditionTrace {
+                            condition: rule.conditions.get(index).cloned().unwrap_or_default(),
+                            result,
+                        })
+                        .collect(),
+                    fields_read: trace.fields_read,
+                    actions: ctx.actions[actions_before..].to_vec(),
+                });
+            }
+            
+            if self.config.collect_rule_stats {
+                stats.record(ctx.rule_fired);
+            }
+            
+            if let Some(rule_start) = rule_start {
+                ctx.metadata.executed_rules.push(rule.id.clone());
+                ctx.metadata.rule_timings.insert(
+                    rule.id.clone(),
+                    rule_start.elapsed(),
+                );
+            }
+            
+            if ctx.metadata.budget_exceeded {
+                break;
+            }
+            
+            // Check for short-circuit: a `return` in a group only ends the
+            // group, anywhere else it ends the execution
+            if ctx.should_return {
+                ctx.metadata.short_circuited = true;
+                match &rule.group {
+                    Some(group) => {
+                        returned_group = Some(&group.name);
+                        ctx.should_return = false;
+                    }
+                    None => break,
+                }
+            }
+            if self.config.mode == ExecutionMode::FirstMatch && ctx.rule_fired {
+                break;
+            }
+        }
+        
+        actions::apply_fraud_score_policy(&mut ctx.actions, self.config.fraud_score_policy);
+        if self.config.dedup_actions {
+            actions::dedup_actions(&mut ctx.actions);
+        }
+        
+        ctx.flush_fields();
+        ctx.metadata.total_duration = start.elapsed();
+        ctx.metadata.errors = std::mem::take(&mut ctx.errors);
+        
+        ExecutionResult {
+            profile: std::mem::take(&mut ctx.profile),
+            transaction: std::mem::take(&mut ctx.transaction),
+            actions: std::mem::take(&mut ctx.actions),
+            metadata: std::mem::take(&mut ctx.metadata),
+        }
+    }
+    
+    /// Validate DSL source: syntax, constants, and that every called
+    /// function exists
+    pub fn validate_dsl(dsl_source: &str) -> Result<(), CompilationError> {
+        let ast = parser::parse(dsl_source)?;
+        compiler::compile(
+            ast,
+            &ActionRegistry::default(),
+            &HostFunctions::default(),
+            &Schema::default(),
+            &CompileLimits::default(),
+        )?;
+        Ok(())
+    }
+    
+    /// Get metadata about loaded rules
+    pub fn get_rules_metadata(&self) -> Vec<RuleMetadata> {
+        self.compiled_rules
+            .iter()
+            .zip(self.rule_enabled.iter())
+            .map(|(r, enabled)| RuleMetadata {
+                id: r.id.clone(),
+                priority: r.priority,
+                enabled: enabled.load(Ordering::Relaxed),
+                group: r.group.as_ref().map(|g| g.name.clone()),
+                tags: r.tags.clone(),
+            })
+            .collect()
+    }
+    
+    /// Get metadata about the rules whose priority is in `min..=max`, in
+    /// execution order
+    pub fn get_rules_by_priority(&self, min: i32, max: i32) -> Vec<RuleMetadata> {
+        self.get_rules_metadata()
+            .into_iter()
+            .filter(|r| (min..=max).contains(&r.priority))
+            .collect()
+    }
+    
+    /// Get metadata about a single rule
+    pub fn get_rule(&self, id: &str) -> Option<RuleMetadata> {
+        self.get_rules_metadata().into_iter().find(|r| r.id == id)
+    }
+    
+    /// Compile a single rule and add it to the engine
+    ///
+    /// The fragment must contain exactly one rule outside any group and no
+    /// functions; it may call global functions already loaded. Constants are
+    /// not kept after compilation, so any the rule uses must be declared in
+    /// the fragment. Rules stay ordered by priority, with the new rule after
+    /// existing rules of equal priority. Only the
+    /// changed rule is compiled, but this engine gets its own copy of the
+    /// rule list: clones made earlier keep the old rules and no longer share
+    /// enabled flags with this engine.
+    pub fn add_rule(&mut self, dsl_fragment: &str) -> Result<(), CompilationError> {
+        let program = parser::parse_with_options(dsl_fragment, &self.limits, self.default_priority)?;
+        
+        if program.rules.len() != 1 || !program.functions.is_empty() || !program.groups.is_empty() {
+            return Err(CompilationError::CompileError(format!(
+                "expected exactly one rule, found {} rule(s), {} group(s) and {} function(s)",
+                program.rules.len(),
+                program.groups.len(),
+                program.functions.len()
+            )));
+        }
+        
+        // New field names extend the engine's table; existing ids are unchanged
+        let mut env = compiler::compiler::CompileEnv::from_constants(&program.constants)?;
+        env.symbols = (*self.symbols).clone();
+        env.regexes = (*self.regexes).clone();
+        env.sets = (*self.sets).clone();
+        env.pool = (*self.pool).clone();
+        env.memo = (*self.memo).clone();
+        env.actions = (*self.actions).clone();
+        env.schema = (*self.schema).clone();
+        self.limits.check_program(&program)?;
+        let compiled = compiler::compiler::Compiler::compile_rule(&program.rules[0], &mut env)?;
+        compiler::validate_semantics(
+            std::slice::from_ref(&compiled),
+            &self.global_functions,
+            &self.host_functions,
+        )?;
+        if self.compiled_rules.iter().any(|r| r.id == compiled.id) {
+            return Err(CompilationError::CompileError(format!(
+                "rule '{}' already exists",
+                compiled.id
+            )));
+        }
+        
+        let mut rules = self.current_rules();
+        rules.push(compiled);
+        self.limits.check_rule_count(rules.len())?;
+        self.limits.check_instructions(
+            rules.iter().map(|r| r.bytecode.len() + r.gate.len()).sum::<usize>()
+                + self.global_functions.values().map(|f| f.bytecode.len()).sum::<usize>(),
+        )?;
+        CompiledRule::sort_for_execution(&mut rules);
+        self.replace_rules(rules);
+        self.symbols = Arc::new(env.symbols);
+        self.regexes = Arc::new(env.regexes);
+        self.sets = Arc::new(env.sets);
+        self.pool = Arc::new(env.pool);
+        self.memo = Arc::new(env.memo);
+        
+        Ok(())
+    }
+    
+    /// Remove a rule by id
+    ///
+    /// Like [`RuleEngine::add_rule`], this detaches the engine's rule list
+    /// from earlier clones.
+    pub fn remove_rule(&mut self, id: &str) -> Result<(), CompilationError> {
+        let mut rules = self.current_rules();
+        let index = rules
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or_else(|| CompilationError::UnknownRule(id.to_string()))?;
+        
+        rules.remove(index);
+        self.replace_rules(rules);
+        
+        Ok(())
+    }
+    
+    /// The compiled rules with their live enabled flags
+    fn current_rules(&self) -> Vec<CompiledRule> {
+        self.compiled_rules
+            .iter()
+            .zip(self.rule_enabled.iter())
+            .map(|(rule, enabled)| CompiledRule {
+                enabled: enabled.load(Ordering::Relaxed),
+                ..rule.clone()
+            })
+            .collect()
+    }
+    
+    fn replace_rules(&mut self, rules: Vec<CompiledRule>) {
+        self.rule_enabled = Arc::new(rules.iter().map(|r| AtomicBool::new(r.enabled)).collect());
+        // Rules that stay keep their counts
+        let stats = self.get_rule_stats();
+        self.rule_stats = rules
+            .iter()
+            .map(|rule| {
+                stats
+                    .iter()
+                    .find(|s| s.id == rule.id)
+                    .map_or_else(RuleCounters::default, RuleCounters::from)
+            })
+            .collect();
+        self.compiled_rules = Arc::new(rules);
+        // Clones that add different rules would otherwise share memo entry
+        // indexes under one generation, and a reused context could serve one
+        // rule's cached condition result to another
+        self.generation = next_generation();
+    }
+    
+    /// Execution and fire counts per rule, in execution order
+    ///
+    /// Counts accumulate across calls while
+    /// [`EngineConfig::collect_rule_stats`] is on, and are shared by every
+    /// clone of this engine. A rule counts as executed when its body runs
+    /// (not when it is disabled, filtered out or gated off) and as fired
+    /// when a top-level `if` condition of it held.
+    pub fn get_rule_stats(&self) -> Vec<RuleStats> {
+        self.compiled_rules
+            .iter()
+            .zip(self.rule_stats.iter())
+            .map(|(rule, stats)| stats.snapshot(&rule.id))
+            .collect()
+    }
+    
+    /// Set every rule's counts back to zero
+    pub fn reset_rule_stats(&self) {
+        for stats in self.rule_stats.iter() {
+            stats.reset();
+        }
+    }
+    
+    /// Enable or disable a rule at runtime without recompiling
+    ///
+    /// Returns false if no rule has the given id. The change is visible to
+    /// every clone of this engine (they share the flags) and applies from
+    /// the next rule evaluation; `execute` reads the flag with a plain atomic
+    /// load, so toggling never blocks execution.
+    pub fn set_rule_enabled(&self, id: &str, enabled: bool) -> bool {
+        match self.compiled_rules.iter().position(|r| r.id == id) {
+            Some(index) => {
+                self.rule_enabled[index].store(enabled, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Get list of global functions
+    pub fn get_functions(&self) -> Vec<String> {
+        self.global_functions.keys().cloned().collect()
+    }
+}
 
 /// Metadata about a rule
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -332,12 +1851,113 @@ pub struct RuleMetadata {
     pub id: String,
     pub priority: i32,
     pub enabled: bool,
+    /// Name of the group the rule belongs to, if any
+    pub group: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Usage counts of a rule, from [`RuleEngine::get_rule_stats`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleStats {
+    pub id: String,
+    /// Times the rule's body ran
+    pub execution_count: u64,
+    /// Times a top-level `if` condition of the rule held
+    pub fire_count: u64,
+    /// When the rule last fired, in milliseconds since the Unix epoch like
+    /// DSL datetimes
+    pub last_fired: Option<i64>,
+}
+
+/// Live counters behind a [`RuleStats`]
+#[derive(Debug, Default)]
+struct RuleCounters {
+    executions: AtomicU64,
+    fires: AtomicU64,
+    /// Milliseconds since the Unix epoch; 0 if the rule never fired
+    last_fired: AtomicU64,
+}
+
+impl RuleCounters {
+    fn record(&self, fired: bool) {
+        self.executions.fetch_add(1, Ordering::Relaxed);
+        if fired {
+            self.fires.fetch_add(1, Ordering::Relaxed);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64);
+            self.last_fired.fetch_max(now, Ordering::Relaxed);
+        }
+    }
+    
+    fn reset(&self) {
+        for counter in [&self.executions, &self.fires, &self.last_fired] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+    
+    fn snapshot(&self, id: &str) -> RuleStats {
+        RuleStats {
+            id: id.to_string(),
+            execution_count: self.executions.load(Ordering::Relaxed),
+            fire_count: self.fires.load(Ordering::Relaxed),
+            last_fired: match self.last_fired.load(Ordering::Relaxed) {
+                0 => None,
+                millis => Some(millis as i64),
+            },
+        }
+    }
+}
+
+impl From<&RuleStats> for RuleCounters {
+    fn from(stats: &RuleStats) -> Self {
+        Self {
+            executions: AtomicU64::new(stats.execution_count),
+            fires: AtomicU64::new(stats.fire_count),
+            last_fired: AtomicU64::new(stats.last_fired.map_or(0, |millis| millis as u64)),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_typed_field_getters() {
+        let txn = Transaction::new().with_fields([
+            ("amount", Value::Float(120.5)),
+            ("count", Value::Int(3)),
+            ("price", Value::Decimal(rust_decimal::Decimal::new(999, 2))),
+            ("country", Value::from("NZ")),
+            ("verified", Value::Bool(true)),
+            ("missing", Value::Null),
+        ]);
+
+        assert_eq!(txn.get_float("amount"), Some(120.5));
+        assert_eq!(txn.get_int("amount"), Some(120));
+        assert_eq!(txn.get_float("count"), Some(3.0));
+        assert_eq!(txn.get_int("count"), Some(3));
+        assert_eq!(txn.get_float("price"), Some(9.99));
+        assert_eq!(txn.get_string("country"), Some("NZ"));
+        assert_eq!(txn.get_bool("verified"), Some(true));
+
+        // Missing fields, Null and other types are None
+        assert_eq!(txn.get_int("nope"), None);
+        assert_eq!(txn.get_float("missing"), None);
+        assert_eq!(txn.get_int("country"), None);
+        assert_eq!(txn.get_float("verified"), None);
+        assert_eq!(txn.get_string("count"), None);
+        assert_eq!(txn.get_bool("country"), None);
+
+        let profile = UserProfile::new()
+            .with_field("risk", Value::Int(1))
+            .with_fields(vec![("risk".to_string(), Value::Int(2)), ("tier".to_string(), Value::from("gold"))]);
+        assert_eq!(profile.get_int("risk"), Some(2));
+        assert_eq!(profile.get_string("tier"), Some("gold"));
+        assert_eq!(profile.get_bool("tier"), None);
+    }
+
     #[test]
     fn test_basic_rule_execution() {
         let dsl = r#"
@@ -362,6 +1982,28 @@ mod tests {
         assert_eq!(result.metadata.executed_rules.len(), 1);
     }
     
+    #[test]
+    fn test_execution_diff_matches_actions_as_multiset() {
+        let result = |actions: Vec<Action>| ExecutionResult {
+            profile: UserProfile::new(),
+            transaction: Transaction::new(),
+            actions,
+            metadata: ExecutionMetadata::default(),
+        };
+        let ours = result(vec![
+            Action::set_fraud_score(0.5),
+            Action::create_comment("a"),
+            Action::create_comment("a"),
+        ]);
+        let theirs = result(vec![Action::create_comment("a"), Action::set_fraud_score(0.5)]);
+        
+        // Order is ignored, but the second comment has no partner
+        let diff = ExecutionDiff::between(&ours, &theirs);
+        assert_eq!(diff.only_in_self, vec![Action::create_comment("a")]);
+        assert!(diff.only_in_other.is_empty());
+        assert!(ExecutionDiff::between(&theirs, &theirs).is_empty());
+    }
+
     #[test]
     fn test_profile_mutation() {
         let dsl = r#"
@@ -414,4 +2056,143 @@ mod tests {
         assert!(result.metadata.short_circuited);
         assert_eq!(result.metadata.executed_rules.len(), 1);
     }
+    
+    #[test]
+    fn test_bytecode_round_trip() {
+        let engine = RuleEngine::from_dsl(
+            r#"rule "test" { priority: 1, if (txn.amount > 10) { setFraudScore(0.5); } }"#,
+        )
+        .unwrap();
+        
+        let bytecode = engine.to_bytecode().unwrap();
+        assert!(bytecode.starts_with(BYTECODE_MAGIC));
+        
+        let reloaded = RuleEngine::from_bytecode(&bytecode).unwrap();
+        let result = reloaded.execute(
+            Transaction::new().with_field("amount", Value::Int(20)),
+            UserProfile::new(),
+        );
+        
+        assert_eq!(result.actions, vec![Action::set_fraud_score(0.5)]);
+    }
+    
+    #[test]
+    fn test_bytecode_header_mismatch_rejected() {
+        let engine = RuleEngine::from_dsl(r#"rule "test" { priority: 1, if (true) {} }"#).unwrap();
+        let bytecode = engine.to_bytecode().unwrap();
+        
+        let mut old_version = bytecode.clone();
+        old_version[4..6].copy_from_slice(&(BYTECODE_VERSION + 1).to_le_bytes());
+        let err = RuleEngine::from_bytecode(&old_version).err().unwrap();
+        assert!(err.to_string().contains("incompatible bytecode version"));
+        
+        // Payloads written before the header existed
+        let headerless = &bytecode[BYTECODE_MAGIC.len() + 2..];
+        let err = RuleEngine::from_bytecode(headerless).err().unwrap();
+        assert!(err.to_string().contains("missing header"));
+        
+        assert!(RuleEngine::from_bytecode(b"FR").is_err());
+    }
+    
+    #[test]
+    fn test_stack_underflow_aborts_rule() {
+        use compiler::bytecode::{ActionType, Instruction};
+        
+        // `Add` with a single operand, as if the bytecode had been truncated
+        let truncated = CompiledRule {
+            id: "truncated".to_string(),
+            priority: 200,
+            enabled: true,
+            gate: Vec::new(),
+            bytecode: vec![
+                Instruction::Push(Value::Int(1)),
+                Instruction::Add,
+                Instruction::Push(Value::Float(0.9)),
+                Instruction::CallAction(ActionType::SetFraudScore, 1),
+            ],
+            conditions: Vec::new(),
+            group: None,
+            tags: Vec::new(),
+            lines: Vec::new(),
+        };
+        let healthy = CompiledRule {
+            id: "healthy".to_string(),
+            priority: 100,
+            enabled: true,
+            gate: Vec::new(),
+            bytecode: vec![
+                Instruction::Push(Value::Float(0.1)),
+                Instruction::CallAction(ActionType::SetFraudScore, 1),
+            ],
+            conditions: Vec::new(),
+            group: None,
+            tags: Vec::new(),
+            lines: Vec::new(),
+        };
+        
+        let engine = RuleEngine::from_compiled(
+            vec![truncated, healthy],
+            Vec::new(),
+            SymbolTable::default(),
+            RegexTable::default(),
+            SetTable::default(),
+            ConstantPool::default(),
+            MemoTable::default(),
+        )
+        .unwrap();
+        let result = engine.execute(Transaction::new(), UserProfile::new());
+        
+        // The broken rule stops at the underflow; later rules still run
+        assert_eq!(result.metadata.errors, vec![ExecutionError::StackUnderflow]);
+        assert_eq!(result.actions, vec![Action::set_fraud_score(0.1)]);
+        assert_eq!(result.metadata.executed_rules, vec!["truncated", "healthy"]);
+    }
+    
+    #[test]
+    fn test_from_bytecode_rejects_oversized_skips_and_counts() {
+        use compiler::bytecode::{BuiltinFn, Instruction};
+        
+        // Bypass load validation to write the payload a corrupt store would hold
+        let payload = |bytecode: Vec<Instruction>| {
+            let rule = CompiledRule {
+                id: "corrupt".to_string(),
+                priority: 100,
+                enabled: true,
+                gate: Vec::new(),
+                bytecode,
+                conditions: Vec::new(),
+                group: None,
+                tags: Vec::new(),
+                lines: Vec::new(),
+            };
+            let mut memo = MemoTable::default();
+            memo.add(Vec::new());
+            RuleEngine::from_parts(
+                vec![rule],
+                HashMap::default(),
+                SymbolTable::default(),
+                RegexTable::default(),
+                SetTable::default(),
+                ConstantPool::default(),
+                memo,
+            )
+            .to_bytecode()
+            .unwrap()
+        };
+        let load_error = |bytecode| RuleEngine::from_bytecode(&payload(bytecode)).err().unwrap().to_string();
+        
+        assert_eq!(
+            load_error(vec![Instruction::MemoCondition(0, usize::MAX), Instruction::Push(Value::Bool(true))]),
+            format!("Compilation error: memo skip {} exceeds the 2 instructions of rule 'corrupt'", usize::MAX)
+        );
+        assert_eq!(
+            load_error(vec![Instruction::BuildArray(1 << 60)]),
+            format!("Compilation error: array length {} exceeds the 1 instructions of rule 'corrupt'", 1usize << 60)
+        );
+        assert!(load_error(vec![Instruction::CallBuiltin(BuiltinFn::Abs, usize::MAX)]).contains("argument count"));
+        
+        // Counts the bytecode can supply still load
+        let fine = payload(vec![Instruction::Push(Value::Int(1)), Instruction::BuildArray(1), Instruction::Pop]);
+        assert!(RuleEngine::from_bytecode(&fine).is_ok());
+    }
 }
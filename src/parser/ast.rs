@@ -1,10 +1,21 @@
 // src/parser/ast.rs
 //! Abstract Syntax Tree definitions for the rule DSL
 
-#[derive(Debug, Clone, PartialEq)]
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Program {
+    pub constants: Vec<ConstNode>,
     pub functions: Vec<FunctionNode>,
     pub rules: Vec<RuleNode>,
+    pub groups: Vec<GroupNode>,
+}
+
+/// Top-level `const NAME = expr;`, evaluated at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstNode {
+    pub name: String,
+    pub value: Expression,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -19,7 +30,24 @@ pub struct RuleNode {
     pub id: String,
     pub priority: i32,
     pub enabled: bool,
+    /// Condition from an `enabled: <expression>` header field; the rule is
+    /// skipped whenever it does not hold
+    pub enabled_when: Option<Expression>,
+    /// Labels from the `tags: [...]` header field, for filtering at execution
+    pub tags: Vec<String>,
     pub body: Vec<Statement>,
+    /// Source line of every statement in `body`, nested ones included, in
+    /// the order a pre-order walk (and the compiler) visits them
+    pub lines: Vec<usize>,
+}
+
+/// `group "name" { rule ... }`: rules run together at the group's priority,
+/// and a `return` in one of them only skips the rest of the group
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupNode {
+    pub name: String,
+    pub priority: i32,
+    pub rules: Vec<RuleNode>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -31,20 +59,58 @@ pub enum Statement {
         else_block: Option<Vec<Statement>>,
     },
     
+    /// for (item in array) { ... }
+    ForEach {
+        var: String,
+        iterable: Expression,
+        body: Vec<Statement>,
+    },
+    
+    /// while (condition) { ... }
+    WhileLoop {
+        condition: Expression,
+        body: Vec<Statement>,
+    },
+    
+    /// { ... } (a nested block, scoping the variables declared in it)
+    Block(Vec<Statement>),
+    
+    /// let x = value; (a local visible until the end of the enclosing block)
+    Let {
+        name: String,
+        value: Expression,
+    },
+    
     /// variable assignment: profile.field = value
     Assignment {
         target: String,
         value: Expression,
     },
     
+    /// Compound assignment: profile.field += value
+    CompoundAssignment {
+        target: String,
+        op: BinaryOp,
+        value: Expression,
+    },
+    
     /// Action call: createCase("HIGH", "reason")
     ActionCall {
         action: String,
         args: Vec<Expression>,
     },
     
-    /// return; (short-circuit)
-    Return,
+    /// return; (short-circuit), or return value; from a function
+    Return(Option<Expression>),
+    
+    /// require(condition); (record an error and stop the rule if false)
+    Require(Expression),
+    
+    /// break; (leave the innermost loop)
+    Break,
+    
+    /// continue; (next iteration of the innermost loop)
+    Continue,
     
     /// Expression statement (function call, etc.)
     Expression(Expression),
@@ -59,6 +125,13 @@ pub enum Expression {
         right: Box<Expression>,
     },
     
+    /// Conditional expression: cond ? a : b
+    Ternary {
+        condition: Box<Expression>,
+        then_expr: Box<Expression>,
+        else_expr: Box<Expression>,
+    },
+    
     /// Unary operation: !a, -a
     Unary {
         op: UnaryOp,
@@ -71,6 +144,13 @@ pub enum Expression {
         field: String,
     },
     
+    /// Field access on a computed object: profile.address.country,
+    /// items[0].amount
+    ObjectField {
+        object: Box<Expression>,
+        field: String,
+    },
+    
     /// Array access: array[index]
     ArrayAccess {
         array: Box<Expression>,
@@ -90,6 +170,18 @@ pub enum Expression {
         args: Vec<Expression>,
     },
     
+    /// Object literal: { key: value, ... }
+    ObjectLiteral(Vec<(String, Expression)>),
+    
+    /// Array literal: [a, b, c]
+    ArrayLiteral(Vec<Expression>),
+    
+    /// Predicate passed to `any`/`all`: x => x > 5000
+    Lambda {
+        param: String,
+        body: Box<Expression>,
+    },
+    
     /// Literal value
     Literal(Literal),
     
@@ -105,6 +197,7 @@ pub enum BinaryOp {
     Mul,
     Div,
     Mod,
+    Pow,
     
     // Comparison
     Eq,
@@ -114,9 +207,23 @@ pub enum BinaryOp {
     Lt,
     Lte,
     
+    // Membership: item in array, item not in array
+    In,
+    NotIn,
+    
     // Logical
     And,
     Or,
+    
+    // Null-coalescing: left unless it is null, else right
+    Coalesce,
+    
+    // Bitwise, on integers
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -145,3 +252,119 @@ impl From<Literal> for crate::Value {
         }
     }
 }
+
+impl BinaryOp {
+    /// Operator as written in the DSL
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Pow => "**",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Gte => ">=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Lte => "<=",
+            BinaryOp::In => "in",
+            BinaryOp::NotIn => "not in",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::Coalesce => "??",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+}
+
+/// Renders an expression back to DSL source. Nested binary and ternary
+/// operands are parenthesized, so the output is unambiguous but may not match
+/// the original text character for character.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Binary { left, op, right } => {
+                write_operand(f, left)?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, right)
+            }
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                write_operand(f, condition)?;
+                f.write_str(" ? ")?;
+                write_operand(f, then_expr)?;
+                f.write_str(" : ")?;
+                write_operand(f, else_expr)
+            }
+            Expression::Unary { op, operand } => {
+                f.write_str(match op {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Neg => "-",
+                })?;
+                write_operand(f, operand)
+            }
+            Expression::FieldAccess { object, field } => write!(f, "{}.{}", object, field),
+            Expression::ObjectField { object, field } => {
+                write_operand(f, object)?;
+                write!(f, ".{}", field)
+            }
+            Expression::ArrayAccess { array, index } => write!(f, "{}[{}]", array, index),
+            Expression::FunctionCall { name, args } => {
+                write!(f, "{}(", name)?;
+                write_list(f, args)?;
+                f.write_str(")")
+            }
+            Expression::MethodCall { object, method, args } => {
+                write!(f, "{}.{}(", object, method)?;
+                write_list(f, args)?;
+                f.write_str(")")
+            }
+            Expression::ObjectLiteral(entries) => {
+                f.write_str("{ ")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                f.write_str(" }")
+            }
+            Expression::ArrayLiteral(elements) => {
+                f.write_str("[")?;
+                write_list(f, elements)?;
+                f.write_str("]")
+            }
+            Expression::Literal(lit) => match lit {
+                Literal::Null => f.write_str("null"),
+                Literal::Bool(b) => write!(f, "{}", b),
+                Literal::Int(n) => write!(f, "{}", n),
+                Literal::Float(n) => write!(f, "{:?}", n),
+                Literal::String(s) => write!(f, "{:?}", s),
+            },
+            Expression::Variable(name) => f.write_str(name),
+            Expression::Lambda { param, body } => write!(f, "{} => {}", param, body),
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter<'_>, expr: &Expression) -> fmt::Result {
+    match expr {
+        Expression::Binary { .. } | Expression::Ternary { .. } => write!(f, "({})", expr),
+        _ => write!(f, "{}", expr),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, exprs: &[Expression]) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", expr)?;
+    }
+    Ok(())
+}
@@ -8,9 +8,16 @@ pub enum Token {
     // Keywords
     Rule,
     Function,
+    Const,
     If,
     Else,
+    For,
+    While,
+    Break,
+    Continue,
     Return,
+    Require,
+    In,
     True,
     False,
     Null,
@@ -25,6 +32,7 @@ pub enum Token {
     Plus,
     Minus,
     Star,
+    StarStar,
     Slash,
     Percent,
     
@@ -38,6 +46,13 @@ pub enum Token {
     AndAnd,
     OrOr,
     Not,
+    NotIn, // `not in` or `!in`
+    
+    Amp,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
     
     // Delimiters
     LeftParen,
@@ -50,13 +65,49 @@ pub enum Token {
     Comma,
     Semicolon,
     Colon,
+    Question,
+    QuestionQuestion,
+    FatArrow,
     Dot,
     Assign,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
     
     // Special
     Eof,
 }
 
+/// Reserved words and the tokens they lex to
+const KEYWORDS: &[(&str, Token)] = &[
+    ("rule", Token::Rule),
+    ("function", Token::Function),
+    ("const", Token::Const),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("for", Token::For),
+    ("while", Token::While),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("return", Token::Return),
+    ("require", Token::Require),
+    ("in", Token::In),
+    ("true", Token::True),
+    ("false", Token::False),
+    ("null", Token::Null),
+];
+
+impl Token {
+    /// Source text of a keyword token, if this is one
+    pub fn keyword(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, token)| token == self)
+            .map(|(word, _)| *word)
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -69,6 +120,14 @@ impl fmt::Display for Token {
     }
 }
 
+/// A token together with the source position where it starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize,
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
@@ -101,6 +160,17 @@ impl Lexer {
         }
     }
     
+    /// Read the next token along with its starting line and column
+    pub fn next_spanned_token(&mut self) -> Result<SpannedToken, LexError> {
+        self.skip_whitespace_and_comments();
+        
+        let line = self.line;
+        let column = self.column;
+        let token = self.next_token()?;
+        
+        Ok(SpannedToken { token, line, column })
+    }
+    
     pub fn next_token(&mut self) -> Result<Token, LexError> {
         self.skip_whitespace_and_comments();
         
@@ -148,24 +218,52 @@ impl Lexer {
                 self.advance();
                 return Ok(Token::Colon);
             }
+            '?' => {
+                self.advance();
+                if self.current_char() == '?' {
+                    self.advance();
+                    return Ok(Token::QuestionQuestion);
+                }
+                return Ok(Token::Question);
+            }
             '.' => {
                 self.advance();
                 return Ok(Token::Dot);
             }
             '+' => {
                 self.advance();
+                if self.current_char() == '=' {
+                    self.advance();
+                    return Ok(Token::PlusEq);
+                }
                 return Ok(Token::Plus);
             }
             '-' => {
                 self.advance();
+                if self.current_char() == '=' {
+                    self.advance();
+                    return Ok(Token::MinusEq);
+                }
                 return Ok(Token::Minus);
             }
             '*' => {
                 self.advance();
+                if self.current_char() == '*' {
+                    self.advance();
+                    return Ok(Token::StarStar);
+                }
+                if self.current_char() == '=' {
+                    self.advance();
+                    return Ok(Token::StarEq);
+                }
                 return Ok(Token::Star);
             }
             '/' => {
                 self.advance();
+                if self.current_char() == '=' {
+                    self.advance();
+                    return Ok(Token::SlashEq);
+                }
                 return Ok(Token::Slash);
             }
             '%' => {
@@ -178,6 +276,10 @@ impl Lexer {
                     self.advance();
                     return Ok(Token::EqEq);
                 }
+                if self.current_char() == '>' {
+                    self.advance();
+                    return Ok(Token::FatArrow);
+                }
                 return Ok(Token::Assign);
             }
             '!' => {
@@ -186,6 +288,11 @@ impl Lexer {
                     self.advance();
                     return Ok(Token::NotEq);
                 }
+                if self.in_keyword_at(0) {
+                    self.advance();
+                    self.advance();
+                    return Ok(Token::NotIn);
+                }
                 return Ok(Token::Not);
             }
             '>' => {
@@ -194,6 +301,10 @@ impl Lexer {
                     self.advance();
                     return Ok(Token::Gte);
                 }
+                if self.current_char() == '>' {
+                    self.advance();
+                    return Ok(Token::ShiftRight);
+                }
                 return Ok(Token::Gt);
             }
             '<' => {
@@ -202,6 +313,10 @@ impl Lexer {
                     self.advance();
                     return Ok(Token::Lte);
                 }
+                if self.current_char() == '<' {
+                    self.advance();
+                    return Ok(Token::ShiftLeft);
+                }
                 return Ok(Token::Lt);
             }
             '&' => {
@@ -210,7 +325,7 @@ impl Lexer {
                     self.advance();
                     return Ok(Token::AndAnd);
                 }
-                return Err(self.error("Expected '&&'"));
+                return Ok(Token::Amp);
             }
             '|' => {
                 self.advance();
@@ -218,7 +333,11 @@ impl Lexer {
                     self.advance();
                     return Ok(Token::OrOr);
                 }
-                return Err(self.error("Expected '||'"));
+                return Ok(Token::Pipe);
+            }
+            '^' => {
+                self.advance();
+                return Ok(Token::Caret);
             }
             '"' => return self.read_string(),
             _ => {}
@@ -251,17 +370,26 @@ impl Lexer {
         
         let identifier: String = self.input[start..self.position].iter().collect();
         
-        let token = match identifier.as_str() {
-            "rule" => Token::Rule,
-            "function" => Token::Function,
-            "if" => Token::If,
-            "else" => Token::Else,
-            "return" => Token::Return,
-            "true" => Token::True,
-            "false" => Token::False,
-            "null" => Token::Null,
-            _ => Token::Identifier(identifier),
-        };
+        // `not` is only a word when followed by `in`; elsewhere it stays an
+        // ordinary identifier
+        if identifier == "not" {
+            let gap = self.input[self.position..]
+                .iter()
+                .take_while(|c| c.is_whitespace())
+                .count();
+            if gap > 0 && self.in_keyword_at(gap) {
+                for _ in 0..gap + 2 {
+                    self.advance();
+                }
+                return Ok(Token::NotIn);
+            }
+        }
+        
+        let token = KEYWORDS
+            .iter()
+            .find(|(word, _)| *word == identifier)
+            .map(|(_, token)| token.clone())
+            .unwrap_or(Token::Identifier(identifier));
         
         Ok(token)
     }
@@ -269,22 +397,37 @@ impl Lexer {
     fn read_number(&mut self) -> Result<Token, LexError> {
         let start = self.position;
         let mut has_dot = false;
+        let mut has_exponent = false;
         
         while !self.is_at_end() {
             let ch = self.current_char();
-            if ch.is_ascii_digit() {
+            if ch.is_ascii_digit() || ch == '_' {
                 self.advance();
             } else if ch == '.' && !has_dot {
                 has_dot = true;
                 self.advance();
+            } else if (ch == 'e' || ch == 'E') && self.exponent_follows() {
+                has_exponent = true;
+                self.advance(); // e
+                if matches!(self.current_char(), '+' | '-') {
+                    self.advance();
+                }
+                while self.current_char().is_ascii_digit() || self.current_char() == '_' {
+                    self.advance();
+                }
+                break;
             } else {
                 break;
             }
         }
         
-        let num_str: String = self.input[start..self.position].iter().collect();
+        // Underscores are digit separators: 1_000_000
+        let num_str: String = self.input[start..self.position]
+            .iter()
+            .filter(|&&c| c != '_')
+            .collect();
         
-        if has_dot {
+        if has_dot || has_exponent {
             let num = num_str.parse::<f64>()
                 .map_err(|_| self.error(&format!("Invalid float: {}", num_str)))?;
             Ok(Token::Number(num))
@@ -296,9 +439,12 @@ impl Lexer {
     }
     
     fn read_string(&mut self) -> Result<Token, LexError> {
+        if self.triple_quote_at(self.position) {
+            return self.read_raw_string();
+        }
+        
         self.advance(); // consume opening "
         
-        let start = self.position;
         let mut result = String::new();
         
         while !self.is_at_end() && self.current_char() != '"' {
@@ -336,6 +482,36 @@ impl Lexer {
         Ok(Token::String(result))
     }
     
+    /// `"""..."""`: backslashes, quotes and newlines are taken literally
+    /// up to the first closing triple quote
+    fn read_raw_string(&mut self) -> Result<Token, LexError> {
+        // Report an unterminated raw string where it starts, not at EOF
+        let start = self.error("Unterminated raw string");
+        
+        for _ in 0..3 {
+            self.advance();
+        }
+        
+        let mut result = String::new();
+        while !self.triple_quote_at(self.position) {
+            if self.is_at_end() {
+                return Err(start);
+            }
+            result.push(self.current_char());
+            self.advance(); // keeps line/column right across newlines
+        }
+        
+        for _ in 0..3 {
+            self.advance();
+        }
+        
+        Ok(Token::String(result))
+    }
+    
+    fn triple_quote_at(&self, position: usize) -> bool {
+        self.input.get(position..position + 3) == Some(&['"', '"', '"'])
+    }
+    
     fn skip_whitespace_and_comments(&mut self) {
         while !self.is_at_end() {
             let ch = self.current_char();
@@ -372,8 +548,30 @@ impl Lexer {
         }
     }
     
+    /// Whether the `e`/`E` at the current position starts an exponent
+    /// (`e5`, `e-3`, `E+2`) rather than, say, an identifier
+    fn exponent_follows(&self) -> bool {
+        let char_at = |offset: usize| self.input.get(self.position + offset).copied();
+        
+        match char_at(1) {
+            Some('+') | Some('-') => char_at(2).is_some_and(|c| c.is_ascii_digit()),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+    
+    /// Whether the keyword `in` starts `offset` characters ahead
+    fn in_keyword_at(&self, offset: usize) -> bool {
+        let char_at = |i: usize| self.input.get(self.position + offset + i).copied();
+        
+        char_at(0) == Some('i')
+            && char_at(1) == Some('n')
+            && !char_at(2).is_some_and(|c| c.is_alphanumeric() || c == '_')
+    }
+    
     fn current_char(&self) -> char {
-        self.input[self.position]
+        // '\0' past the end lets two-character operator checks run at EOF
+        self.input.get(self.position).copied().unwrap_or('\0')
     }
     
     fn peek(&self) -> Option<char> {
@@ -384,6 +582,11 @@ impl Lexer {
         }
     }
     
+    /// Skip one character, so lexing can resume after an error
+    pub fn skip_char(&mut self) {
+        self.advance();
+    }
+    
     fn advance(&mut self) {
         if !self.is_at_end() {
             if self.current_char() == '\n' {
@@ -432,10 +635,30 @@ mod tests {
 
     #[test]
     fn test_numbers() {
-        let mut lexer = Lexer::new("42 3.14");
+        let mut lexer = Lexer::new("42 2.5");
         
         assert_eq!(lexer.next_token().unwrap(), Token::Integer(42));
-        assert_eq!(lexer.next_token().unwrap(), Token::Number(3.14));
+        assert_eq!(lexer.next_token().unwrap(), Token::Number(2.5));
+    }
+
+    #[test]
+    fn test_number_separators_and_exponents() {
+        let mut lexer = Lexer::new("1_000_000 1e3 1.5e-2 2.5E+1 1_0.5 7");
+        
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(1_000_000));
+        assert_eq!(lexer.next_token().unwrap(), Token::Number(1000.0));
+        assert_eq!(lexer.next_token().unwrap(), Token::Number(0.015));
+        assert_eq!(lexer.next_token().unwrap(), Token::Number(25.0));
+        assert_eq!(lexer.next_token().unwrap(), Token::Number(10.5));
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(7));
+    }
+
+    #[test]
+    fn test_number_followed_by_identifier() {
+        let mut lexer = Lexer::new("2else");
+        
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(2));
+        assert_eq!(lexer.next_token().unwrap(), Token::Else);
     }
 
     #[test]
@@ -446,6 +669,37 @@ mod tests {
         assert_eq!(lexer.next_token().unwrap(), Token::String("world\n".to_string()));
     }
 
+    #[test]
+    fn test_raw_string() {
+        let source = r#""""{"reason": "C:\temp\n"}
+line two""" next"#;
+        let mut lexer = Lexer::new(source);
+        
+        assert_eq!(
+            lexer.next_spanned_token().unwrap().token,
+            Token::String("{\"reason\": \"C:\\temp\\n\"}\nline two".to_string())
+        );
+        
+        // Positions after the string account for its embedded newline
+        let next = lexer.next_spanned_token().unwrap();
+        assert_eq!(next.token, Token::Identifier("next".to_string()));
+        assert_eq!((next.line, next.column), (2, 13));
+    }
+
+    #[test]
+    fn test_unterminated_raw_string() {
+        let mut lexer = Lexer::new("x \"\"\"never\nclosed\"\"");
+        
+        lexer.next_token().unwrap();
+        let err = lexer.next_token().unwrap_err();
+        assert_eq!(err.message, "Unterminated raw string");
+        assert_eq!((err.line, err.column), (1, 3));
+        
+        // An empty ordinary string is not a raw string
+        let mut lexer = Lexer::new("\"\" x");
+        assert_eq!(lexer.next_token().unwrap(), Token::String(String::new()));
+    }
+
     #[test]
     fn test_identifiers() {
         let mut lexer = Lexer::new("profile txn_count _test");
@@ -456,6 +710,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_in_keyword() {
+        let mut lexer = Lexer::new("in inside");
+        
+        assert_eq!(lexer.next_token().unwrap(), Token::In);
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("inside".to_string()));
+    }
+
+    #[test]
+    fn test_not_in_tokens() {
+        let mut lexer = Lexer::new("not in !in not\n  in !inside not inner !x not");
+        
+        assert_eq!(lexer.next_token().unwrap(), Token::NotIn);
+        assert_eq!(lexer.next_token().unwrap(), Token::NotIn);
+        assert_eq!(lexer.next_token().unwrap(), Token::NotIn);
+        assert_eq!(lexer.next_token().unwrap(), Token::Not);
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("inside".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("not".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("inner".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Not);
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("x".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("not".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_compound_assignment_tokens() {
+        let mut lexer = Lexer::new("+= -= *= /= + =");
+        
+        assert_eq!(lexer.next_token().unwrap(), Token::PlusEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::MinusEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::StarEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::SlashEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::Plus);
+        assert_eq!(lexer.next_token().unwrap(), Token::Assign);
+    }
+
+    #[test]
+    fn test_power_token() {
+        let mut lexer = Lexer::new("2 ** 3 * 4 *= 1");
+        
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(2));
+        assert_eq!(lexer.next_token().unwrap(), Token::StarStar);
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(3));
+        assert_eq!(lexer.next_token().unwrap(), Token::Star);
+        assert_eq!(lexer.next_token().unwrap(), Token::Integer(4));
+        assert_eq!(lexer.next_token().unwrap(), Token::StarEq);
+    }
+
+    #[test]
+    fn test_bitwise_tokens() {
+        let mut lexer = Lexer::new("a && b & c || d | e ^ f << 2 >> 1 >= <=");
+        
+        let tokens: Vec<Token> = std::iter::from_fn(|| match lexer.next_token().unwrap() {
+            Token::Eof => None,
+            Token::Identifier(_) | Token::Integer(_) => Some(None),
+            token => Some(Some(token)),
+        })
+        .flatten()
+        .collect();
+        
+        assert_eq!(
+            tokens,
+            vec![
+                Token::AndAnd,
+                Token::Amp,
+                Token::OrOr,
+                Token::Pipe,
+                Token::Caret,
+                Token::ShiftLeft,
+                Token::ShiftRight,
+                Token::Gte,
+                Token::Lte,
+            ]
+        );
+        
+        // `&&` is always taken greedily, so `&&&` is `&&` then `&`
+        let mut lexer = Lexer::new("&&&");
+        assert_eq!(lexer.next_token().unwrap(), Token::AndAnd);
+        assert_eq!(lexer.next_token().unwrap(), Token::Amp);
+    }
+
+    #[test]
+    fn test_spanned_tokens() {
+        let mut lexer = Lexer::new("rule \"x\" {\n    priority: 10\n}");
+        
+        let rule = lexer.next_spanned_token().unwrap();
+        assert_eq!((rule.token, rule.line, rule.column), (Token::Rule, 1, 1));
+        
+        let id = lexer.next_spanned_token().unwrap();
+        assert_eq!((id.line, id.column), (1, 6));
+        
+        lexer.next_spanned_token().unwrap(); // {
+        let priority = lexer.next_spanned_token().unwrap();
+        assert_eq!(priority.token, Token::Identifier("priority".to_string()));
+        assert_eq!((priority.line, priority.column), (2, 5));
+    }
+
     #[test]
     fn test_comments() {
         let mut lexer = Lexer::new("rule // comment\nif /* block comment */ else");
@@ -5,16 +5,124 @@
 
 pub mod ast;
 pub mod lexer;
+#[allow(clippy::module_inception)]
 pub mod parser;
 
+use crate::compiler::limits::CompileLimits;
 use crate::CompilationError;
+use ahash::HashMap;
 pub use ast::Program;
+pub use parser::ParseError;
 
 /// Parse DSL source code into an AST
 pub fn parse(source: &str) -> Result<Program, CompilationError> {
-    let mut parser = parser::Parser::new(source)
-        .map_err(|e| CompilationError::ParseError(e.to_string()))?;
+    parse_with_limits(source, &CompileLimits::default())
+}
+
+/// Parse DSL source code, rejecting expressions and blocks nested deeper
+/// than `limits.max_expression_depth` with a
+/// [`CompilationError::CompileError`]
+///
+/// The other limits apply to the parsed program and are checked by
+/// [`compile`](crate::compiler::compile).
+pub fn parse_with_limits(source: &str, limits: &CompileLimits) -> Result<Program, CompilationError> {
+    parse_with_options(source, limits, parser::DEFAULT_PRIORITY)
+}
+
+/// Parse DSL source code like [`parse_with_limits`], giving rules that omit
+/// `priority` the priority `default_priority`
+pub fn parse_with_options(
+    source: &str,
+    limits: &CompileLimits,
+    default_priority: i32,
+) -> Result<Program, CompilationError> {
+    let mut parser = parser::Parser::new(source)?
+        .with_max_depth(limits.max_expression_depth)
+        .with_default_priority(default_priority);
     
-    parser.parse()
-        .map_err(|e| CompilationError::ParseError(e.to_string()))
+    let program = parser.parse().map_err(|err| {
+        if parser.depth_exceeded() {
+            CompilationError::CompileError(format!("{} at {}:{}", err.message, err.line, err.column))
+        } else {
+            err.into()
+        }
+    })?;
+    
+    Ok(program)
+}
+
+/// Parse DSL source code, reporting every syntax error rather than only the
+/// first
+///
+/// Each broken rule, function or constant is reported and left out of the
+/// returned program; the rest of the source is still parsed. Use this for
+/// authoring tools, and [`parse`] to load rules.
+pub fn parse_all(source: &str) -> (Program, Vec<ParseError>) {
+    parser::Parser::parse_all(source)
+}
+
+/// Parse several named sources (e.g. one per file) into a single program
+///
+/// Functions, constants and groups from every source are visible to rules in
+/// all the others. A rule id, function, constant or group defined twice is
+/// an error naming the sources involved, and a syntax error names the source
+/// it is in.
+pub fn parse_sources(sources: &[(&str, &str)]) -> Result<Program, CompilationError> {
+    parse_sources_with_options(sources, &CompileLimits::default(), parser::DEFAULT_PRIORITY)
+}
+
+/// Parse several named sources like [`parse_sources`], with the limits and
+/// default priority of [`parse_with_options`]
+pub fn parse_sources_with_options(
+    sources: &[(&str, &str)],
+    limits: &CompileLimits,
+    default_priority: i32,
+) -> Result<Program, CompilationError> {
+    let mut program = Program::default();
+    let mut defined: HashMap<(&str, String), &str> = HashMap::default();
+    
+    for &(name, source) in sources {
+        let parsed = parse_with_options(source, limits, default_priority).map_err(|err| match err {
+            CompilationError::ParseError { message, line, column } => CompilationError::ParseError {
+                message: format!("{} (in '{}')", message, name),
+                line,
+                column,
+            },
+            other => other,
+        })?;
+        
+        let rules = parsed.rules.iter().chain(parsed.groups.iter().flat_map(|g| &g.rules));
+        let names = rules
+            .map(|r| ("rule", r.id.clone()))
+            .chain(parsed.functions.iter().map(|f| ("function", f.name.clone())))
+            .chain(parsed.constants.iter().map(|c| ("constant", c.name.clone())))
+            .chain(parsed.groups.iter().map(|g| ("group", g.name.clone())));
+        for (kind, item) in names {
+            if let Some(first) = defined.insert((kind, item.clone()), name) {
+                let message = if first == name {
+                    format!("{} '{}' is defined more than once in '{}'", kind, item, name)
+                } else {
+                    format!("{} '{}' is defined in both '{}' and '{}'", kind, item, first, name)
+                };
+                return Err(CompilationError::CompileError(message));
+            }
+        }
+        
+        program.constants.extend(parsed.constants);
+        program.functions.extend(parsed.functions);
+        program.rules.extend(parsed.rules);
+        program.groups.extend(parsed.groups);
+    }
+    
+    Ok(program)
+}
+
+impl From<parser::ParseError> for CompilationError {
+    fn from(err: parser::ParseError) -> Self {
+        CompilationError::ParseError {
+            message: err.message,
+            line: err.line,
+            column: err.column,
+        }
+    }
 }
@@ -2,17 +2,20 @@
 //! Parser that converts tokens into an Abstract Syntax Tree
 
 use super::ast::*;
-use super::lexer::{Lexer, LexError, Token};
+use super::lexer::{Lexer, LexError, SpannedToken, Token};
+use crate::compiler::limits::CompileLimits;
 use std::fmt;
 
 #[derive(Debug)]
 pub struct ParseError {
     pub message: String,
+    pub line: usize,
+    pub column: usize,
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Parse error: {}", self.message)
+        write!(f, "Parse error at {}:{}: {}", self.line, self.column, self.message)
     }
 }
 
@@ -21,7 +24,9 @@ impl std::error::Error for ParseError {}
 impl From<LexError> for ParseError {
     fn from(err: LexError) -> Self {
         ParseError {
-            message: err.to_string(),
+            message: err.message,
+            line: err.line,
+            column: err.column,
         }
     }
 }
@@ -29,40 +34,178 @@ impl From<LexError> for ParseError {
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
+    /// Source position of `current_token`
+    line: usize,
+    column: usize,
+    /// Line of each statement parsed so far in the current rule or
+    /// function, in pre-order
+    statement_lines: Vec<usize>,
+    /// Current and maximum nesting of expressions and blocks
+    depth: usize,
+    max_depth: usize,
+    /// Whether parsing failed because `max_depth` was exceeded
+    depth_exceeded: bool,
+    /// Priority of rules that do not declare one
+    default_priority: i32,
+    /// What each unclosed `{` opened and the line it is on, innermost last
+    open_braces: Vec<(String, usize)>,
 }
 
+/// Priority of a rule that does not declare one, unless configured otherwise
+pub const DEFAULT_PRIORITY: i32 = 100;
+
 impl Parser {
     pub fn new(input: &str) -> Result<Self, ParseError> {
         let mut lexer = Lexer::new(input);
-        let current_token = lexer.next_token()?;
+        let first = lexer.next_spanned_token()?;
+
+        Ok(Self::with_first_token(lexer, first))
+    }
 
-        Ok(Self {
+    fn with_first_token(lexer: Lexer, first: SpannedToken) -> Self {
+        Self {
             lexer,
-            current_token,
-        })
+            current_token: first.token,
+            line: first.line,
+            column: first.column,
+            statement_lines: Vec::new(),
+            depth: 0,
+            max_depth: CompileLimits::default().max_expression_depth,
+            depth_exceeded: false,
+            default_priority: DEFAULT_PRIORITY,
+            open_braces: Vec::new(),
+        }
+    }
+
+    /// Limit how deeply expressions and blocks may nest
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Priority given to rules that omit `priority`
+    pub fn with_default_priority(mut self, priority: i32) -> Self {
+        self.default_priority = priority;
+        self
+    }
+
+    /// Whether the last error was hitting the nesting limit
+    pub fn depth_exceeded(&self) -> bool {
+        self.depth_exceeded
+    }
+
+    /// Run a recursive parse one nesting level deeper, failing past the
+    /// limit so deeply nested input cannot overflow the stack
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= self.max_depth {
+            self.depth_exceeded = true;
+            return Err(self.error(format!("nesting exceeds the limit of {}", self.max_depth)));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
+    /// Parse a whole program, stopping at the first error
     pub fn parse(&mut self) -> Result<Program, ParseError> {
-        let mut functions = Vec::new();
-        let mut rules = Vec::new();
+        let mut program = Program::default();
 
         while self.current_token != Token::Eof {
-            match &self.current_token {
-                Token::Function => {
-                    functions.push(self.parse_function()?);
+            self.parse_item(&mut program)?;
+        }
+
+        Ok(program)
+    }
+
+    /// Parse a whole program, collecting every error instead of stopping
+    ///
+    /// After an error the parser skips ahead to the next `rule`, `function`
+    /// or `const` keyword and carries on. The program holds the items that
+    /// parsed cleanly. Characters the lexer rejects are reported and
+    /// skipped.
+    pub fn parse_all(input: &str) -> (Program, Vec<ParseError>) {
+        let mut errors = Vec::new();
+        let mut lexer = Lexer::new(input);
+        let first = loop {
+            match lexer.next_spanned_token() {
+                Ok(first) => break first,
+                Err(err) => {
+                    errors.push(err.into());
+                    lexer.skip_char();
                 }
-                Token::Rule => {
-                    rules.push(self.parse_rule()?);
+            }
+        };
+
+        let mut parser = Self::with_first_token(lexer, first);
+        let mut program = Program::default();
+
+        while parser.current_token != Token::Eof {
+            let start = (parser.line, parser.column);
+            if let Err(err) = parser.parse_item(&mut program) {
+                errors.push(err);
+                parser.statement_lines.clear();
+                parser.open_braces.clear();
+
+                // Always move past the token that failed, then resync
+                if (parser.line, parser.column) == start {
+                    parser.skip_token();
                 }
-                _ => {
-                    return Err(ParseError {
-                        message: format!("Expected 'function' or 'rule', got {}", self.current_token),
-                    });
+                while !matches!(
+                    parser.current_token,
+                    Token::Rule | Token::Function | Token::Const | Token::Eof
+                ) {
+                    parser.skip_token();
                 }
             }
         }
 
-        Ok(Program { functions, rules })
+        (program, errors)
+    }
+
+    /// Parse one top-level item into `program`
+    fn parse_item(&mut self, program: &mut Program) -> Result<(), ParseError> {
+        match &self.current_token {
+            Token::Const => {
+                program.constants.push(self.parse_const()?);
+            }
+            Token::Function => {
+                program.functions.push(self.parse_function()?);
+            }
+            Token::Rule => {
+                program.rules.push(self.parse_rule()?);
+            }
+            Token::Identifier(name) if name == "group" => {
+                program.groups.push(self.parse_group()?);
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "Expected 'const', 'function', 'group' or 'rule', got {}",
+                    self.current_token
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move to the next token, skipping characters the lexer rejects
+    fn skip_token(&mut self) {
+        while self.advance().is_err() {
+            self.lexer.skip_char();
+        }
+    }
+
+    fn parse_const(&mut self) -> Result<ConstNode, ParseError> {
+        self.expect(Token::Const)?;
+
+        let name = self.expect_identifier()?;
+        self.expect(Token::Assign)?;
+        let value = self.parse_expression()?;
+        self.expect(Token::Semicolon)?;
+
+        Ok(ConstNode { name, value })
     }
 
     fn parse_function(&mut self) -> Result<FunctionNode, ParseError> {
@@ -86,11 +229,12 @@ impl Parser {
         }
 
         self.expect(Token::RightParen)?;
-        self.expect(Token::LeftBrace)?;
+        self.open_brace(format!("function '{}'", name))?;
 
         let body = self.parse_block()?;
 
-        self.expect(Token::RightBrace)?;
+        self.close_brace()?;
+        self.statement_lines.clear();
 
         Ok(FunctionNode { name, params, body })
     }
@@ -100,13 +244,15 @@ impl Parser {
 
         let id = self.expect_string()?;
 
-        self.expect(Token::LeftBrace)?;
+        self.open_brace(format!("rule '{}'", id))?;
 
         // Parse rule metadata
-        let mut priority = 100;
+        let mut priority = self.default_priority;
         let mut enabled = true;
+        let mut enabled_when = None;
+        let mut tags = Vec::new();
 
-        // Look for priority and enabled fields
+        // Look for priority, enabled and tags fields
         while matches!(self.current_token, Token::Identifier(_)) {
             let field_name = self.expect_identifier()?;
             self.expect(Token::Colon)?;
@@ -117,32 +263,22 @@ impl Parser {
                         priority = n as i32;
                         self.advance()?;
                     } else {
-                        return Err(ParseError {
-                            message: "Expected integer for priority".to_string(),
-                        });
+                        return Err(self.error("Expected integer for priority"));
                     }
                 }
                 "enabled" => {
-                    match self.current_token {
-                        Token::True => {
-                            enabled = true;
-                            self.advance()?;
-                        }
-                        Token::False => {
-                            enabled = false;
-                            self.advance()?;
-                        }
-                        _ => {
-                            return Err(ParseError {
-                                message: "Expected true or false for enabled".to_string(),
-                            });
-                        }
+                    // `true`/`false`, or a condition checked when the rule's
+                    // turn comes
+                    match self.parse_expression()? {
+                        Expression::Literal(Literal::Bool(value)) => enabled = value,
+                        condition => enabled_when = Some(condition),
                     }
                 }
+                "tags" => {
+                    tags = self.parse_tags()?;
+                }
                 _ => {
-                    return Err(ParseError {
-                        message: format!("Unknown rule field: {}", field_name),
-                    });
+                    return Err(self.error(format!("Unknown rule field: {}", field_name)));
                 }
             }
 
@@ -155,17 +291,103 @@ impl Parser {
         // Parse rule body (statements)
         let body = self.parse_block()?;
 
-        self.expect(Token::RightBrace)?;
+        self.close_brace()?;
 
         Ok(RuleNode {
             id,
             priority,
             enabled,
+            enabled_when,
+            tags,
             body,
+            lines: std::mem::take(&mut self.statement_lines),
+        })
+    }
+
+    /// `["aml", "geo"]`: a bracketed list of string literals
+    fn parse_tags(&mut self) -> Result<Vec<String>, ParseError> {
+        self.expect(Token::LeftBracket)?;
+
+        let mut tags = Vec::new();
+        while self.current_token != Token::RightBracket {
+            match &self.current_token {
+                Token::String(tag) => {
+                    tags.push(tag.clone());
+                    self.advance()?;
+                }
+                _ => {
+                    return Err(self.error(format!(
+                        "Expected string for tag, got {}",
+                        self.current_token
+                    )));
+                }
+            }
+
+            if self.current_token == Token::Comma {
+                self.advance()?;
+            } else {
+                break;
+            }
+        }
+
+        self.expect(Token::RightBracket)?;
+
+        Ok(tags)
+    }
+
+    /// `group "name" { priority: N, rule ... }`
+    ///
+    /// `group` is only special at the top level, so fields and variables
+    /// named `group` still work.
+    fn parse_group(&mut self) -> Result<GroupNode, ParseError> {
+        self.advance()?;
+
+        let name = self.expect_string()?;
+
+        self.open_brace(format!("group '{}'", name))?;
+
+        let mut priority = 100;
+        if matches!(&self.current_token, Token::Identifier(field) if field == "priority") {
+            self.advance()?;
+            self.expect(Token::Colon)?;
+            if let Token::Integer(n) = self.current_token {
+                priority = n as i32;
+                self.advance()?;
+            } else {
+                return Err(self.error("Expected integer for priority"));
+            }
+
+            // Skip optional comma
+            if self.current_token == Token::Comma {
+                self.advance()?;
+            }
+        }
+
+        let mut rules = Vec::new();
+        while self.current_token != Token::RightBrace {
+            if self.current_token != Token::Rule {
+                return Err(self.error(format!(
+                    "Expected 'rule' in group '{}', got {}",
+                    name, self.current_token
+                )));
+            }
+            rules.push(self.parse_rule()?);
+        }
+
+        self.close_brace()?;
+
+        Ok(GroupNode {
+            name,
+            priority,
+            rules,
         })
     }
 
     fn parse_block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.nested(Self::parse_block_statements)
+    }
+
+    fn parse_block_statements(&mut self) -> Result<Vec<Statement>, ParseError> {
         let mut statements = Vec::new();
 
         while self.current_token != Token::RightBrace && self.current_token != Token::Eof {
@@ -176,14 +398,50 @@ impl Parser {
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        self.statement_lines.push(self.line);
+
         match &self.current_token {
+            Token::LeftBrace => {
+                self.open_brace("block".to_string())?;
+                let block = self.parse_block()?;
+                self.close_brace()?;
+                Ok(Statement::Block(block))
+            }
             Token::If => self.parse_if_statement(),
+            Token::For => self.parse_for_statement(),
+            Token::While => self.parse_while_statement(),
             Token::Return => {
+                self.advance()?;
+                let value = match self.current_token {
+                    Token::Semicolon | Token::RightBrace => None,
+                    _ => Some(self.parse_expression()?),
+                };
+                if self.current_token == Token::Semicolon {
+                    self.advance()?;
+                }
+                Ok(Statement::Return(value))
+            }
+            Token::Require => {
+                self.advance()?;
+                self.expect(Token::LeftParen)?;
+                let condition = self.parse_expression()?;
+                self.expect(Token::RightParen)?;
+                if self.current_token == Token::Semicolon {
+                    self.advance()?;
+                }
+                Ok(Statement::Require(condition))
+            }
+            Token::Break | Token::Continue => {
+                let statement = if self.current_token == Token::Break {
+                    Statement::Break
+                } else {
+                    Statement::Continue
+                };
                 self.advance()?;
                 if self.current_token == Token::Semicolon {
                     self.advance()?;
                 }
-                Ok(Statement::Return)
+                Ok(statement)
             }
             Token::Identifier(name) => {
                 let name_clone = name.clone();
@@ -198,7 +456,7 @@ impl Parser {
                     if self.current_token == Token::Semicolon {
                         self.advance()?;
                     }
-                    return Ok(Statement::Assignment { target: var_name, value });
+                    return Ok(Statement::Let { name: var_name, value });
                 }
 
                 // Check if it's an assignment or function/action call
@@ -220,11 +478,45 @@ impl Parser {
                             target: format!("{}.{}", name_clone, field),
                             value,
                         })
+                    } else if let Some(op) = self.compound_assignment_op() {
+                        // Compound assignment: profile.field += value
+                        self.advance()?;
+                        let value = self.parse_expression()?;
+
+                        if self.current_token == Token::Semicolon {
+                            self.advance()?;
+                        }
+
+                        Ok(Statement::CompoundAssignment {
+                            target: format!("{}.{}", name_clone, field),
+                            op,
+                            value,
+                        })
                     } else {
-                        // Method call or other expression
-                        return Err(ParseError {
-                            message: "Expected assignment or method call".to_string(),
-                        });
+                        // Method call statement: seen.push(x), profile.recent.push(x)
+                        let base = if self.current_token == Token::LeftParen {
+                            self.advance()?;
+                            let args = self.parse_argument_list()?;
+                            self.expect(Token::RightParen)?;
+                            Expression::MethodCall {
+                                object: Box::new(Expression::Variable(name_clone)),
+                                method: field,
+                                args,
+                            }
+                        } else {
+                            Expression::FieldAccess { object: name_clone, field }
+                        };
+
+                        let expr = self.parse_postfix_operators(base)?;
+                        if !matches!(expr, Expression::MethodCall { .. }) {
+                            return Err(self.error("Expected assignment or method call"));
+                        }
+
+                        if self.current_token == Token::Semicolon {
+                            self.advance()?;
+                        }
+
+                        Ok(Statement::Expression(expr))
                     }
                 } else if self.current_token == Token::LeftParen {
                     // Function/action call
@@ -262,18 +554,42 @@ impl Parser {
                         target: name_clone,
                         value,
                     })
-                } else {
-                    Err(ParseError {
-                        message: format!("Unexpected token after identifier: {}", self.current_token),
+                } else if let Some(op) = self.compound_assignment_op() {
+                    // Compound assignment to a local variable
+                    self.advance()?;
+                    let value = self.parse_expression()?;
+
+                    if self.current_token == Token::Semicolon {
+                        self.advance()?;
+                    }
+
+                    Ok(Statement::CompoundAssignment {
+                        target: name_clone,
+                        op,
+                        value,
                     })
+                } else {
+                    Err(self.error(format!("Unexpected token after identifier: {}", self.current_token)))
                 }
             }
-            _ => Err(ParseError {
-                message: format!("Unexpected statement: {}", self.current_token),
-            }),
+            _ => Err(self.error(format!("Unexpected statement: {}", self.current_token))),
+        }
+    }
+
+    /// Map a compound assignment token (`+=`, `-=`, ...) to its binary operator
+    fn compound_assignment_op(&self) -> Option<BinaryOp> {
+        match self.current_token {
+            Token::PlusEq => Some(BinaryOp::Add),
+            Token::MinusEq => Some(BinaryOp::Sub),
+            Token::StarEq => Some(BinaryOp::Mul),
+            Token::SlashEq => Some(BinaryOp::Div),
+            _ => None,
         }
     }
 
+    /// Both branches must be brace-delimited (`else if` aside), so there is
+    /// no dangling-else ambiguity: in `if (a) { if (b) {} else {} }` the
+    /// `else` sits inside the outer braces and can only bind to `if (b)`.
     fn parse_if_statement(&mut self) -> Result<Statement, ParseError> {
         self.expect(Token::If)?;
         self.expect(Token::LeftParen)?;
@@ -281,21 +597,28 @@ impl Parser {
         let condition = self.parse_expression()?;
 
         self.expect(Token::RightParen)?;
-        self.expect(Token::LeftBrace)?;
+        self.open_brace("if block".to_string())?;
 
         let then_block = self.parse_block()?;
 
-        self.expect(Token::RightBrace)?;
+        self.close_brace()?;
 
         let else_block = if self.current_token == Token::Else {
             self.advance()?;
-            self.expect(Token::LeftBrace)?;
 
-            let block = self.parse_block()?;
+            // `else if` chains nest the next if statement inside the else block
+            if self.current_token == Token::If {
+                self.statement_lines.push(self.line);
+                Some(vec![self.parse_if_statement()?])
+            } else {
+                self.open_brace("else block".to_string())?;
 
-            self.expect(Token::RightBrace)?;
+                let block = self.parse_block()?;
 
-            Some(block)
+                self.close_brace()?;
+
+                Some(block)
+            }
         } else {
             None
         };
@@ -307,8 +630,82 @@ impl Parser {
         })
     }
 
+    fn parse_for_statement(&mut self) -> Result<Statement, ParseError> {
+        self.expect(Token::For)?;
+        self.expect(Token::LeftParen)?;
+
+        let var = self.expect_identifier()?;
+        self.expect(Token::In)?;
+        let iterable = self.parse_expression()?;
+
+        self.expect(Token::RightParen)?;
+        self.open_brace("for loop".to_string())?;
+
+        let body = self.parse_block()?;
+
+        self.close_brace()?;
+
+        Ok(Statement::ForEach {
+            var,
+            iterable,
+            body,
+        })
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Statement, ParseError> {
+        self.expect(Token::While)?;
+        self.expect(Token::LeftParen)?;
+
+        let condition = self.parse_expression()?;
+
+        self.expect(Token::RightParen)?;
+        self.open_brace("while loop".to_string())?;
+
+        let body = self.parse_block()?;
+
+        self.close_brace()?;
+
+        Ok(Statement::WhileLoop { condition, body })
+    }
+
     fn parse_expression(&mut self) -> Result<Expression, ParseError> {
-        self.parse_logical_or()
+        self.nested(Self::parse_ternary)
+    }
+
+    /// cond ? a : b (lowest precedence, right-associative)
+    fn parse_ternary(&mut self) -> Result<Expression, ParseError> {
+        let condition = self.parse_null_coalesce()?;
+
+        if self.current_token != Token::Question {
+            return Ok(condition);
+        }
+
+        self.advance()?;
+        let then_expr = self.parse_expression()?;
+        self.expect(Token::Colon)?;
+        let else_expr = self.parse_expression()?;
+
+        Ok(Expression::Ternary {
+            condition: Box::new(condition),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        })
+    }
+
+    fn parse_null_coalesce(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.parse_logical_or()?;
+
+        while self.current_token == Token::QuestionQuestion {
+            self.advance()?;
+            let right = self.parse_logical_or()?;
+            left = Expression::Binary {
+                left: Box::new(left),
+                op: BinaryOp::Coalesce,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
     }
 
     fn parse_logical_or(&mut self) -> Result<Expression, ParseError> {
@@ -367,7 +764,7 @@ impl Parser {
     }
 
     fn parse_comparison(&mut self) -> Result<Expression, ParseError> {
-        let mut left = self.parse_addition()?;
+        let mut left = self.parse_bitwise_or()?;
 
         loop {
             let op = match self.current_token {
@@ -375,11 +772,59 @@ impl Parser {
                 Token::Gte => BinaryOp::Gte,
                 Token::Lt => BinaryOp::Lt,
                 Token::Lte => BinaryOp::Lte,
+                Token::In => BinaryOp::In,
+                Token::NotIn => BinaryOp::NotIn,
                 _ => break,
             };
 
             self.advance()?;
-            let right = self.parse_addition()?;
+            let right = self.parse_bitwise_or()?;
+
+            left = Expression::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    /// Bitwise operators bind tighter than comparisons, so
+    /// `profile.flags & 4 != 0` means `(profile.flags & 4) != 0`. Among
+    /// themselves they follow C: shifts, then `&`, then `^`, then `|`.
+    fn parse_bitwise_or(&mut self) -> Result<Expression, ParseError> {
+        self.parse_binary_level(&[(Token::Pipe, BinaryOp::BitOr)], Self::parse_bitwise_xor)
+    }
+
+    fn parse_bitwise_xor(&mut self) -> Result<Expression, ParseError> {
+        self.parse_binary_level(&[(Token::Caret, BinaryOp::BitXor)], Self::parse_bitwise_and)
+    }
+
+    fn parse_bitwise_and(&mut self) -> Result<Expression, ParseError> {
+        self.parse_binary_level(&[(Token::Amp, BinaryOp::BitAnd)], Self::parse_shift)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expression, ParseError> {
+        self.parse_binary_level(
+            &[(Token::ShiftLeft, BinaryOp::Shl), (Token::ShiftRight, BinaryOp::Shr)],
+            Self::parse_addition,
+        )
+    }
+
+    /// Parse a left-associative chain of the given operators over operands
+    /// parsed by `next`
+    fn parse_binary_level(
+        &mut self,
+        ops: &[(Token, BinaryOp)],
+        next: fn(&mut Self) -> Result<Expression, ParseError>,
+    ) -> Result<Expression, ParseError> {
+        let mut left = next(self)?;
+
+        while let Some((_, op)) = ops.iter().find(|(token, _)| *token == self.current_token) {
+            let op = op.clone();
+            self.advance()?;
+            let right = next(self)?;
 
             left = Expression::Binary {
                 left: Box::new(left),
@@ -442,7 +887,7 @@ impl Parser {
         match self.current_token {
             Token::Not => {
                 self.advance()?;
-                let operand = self.parse_unary()?;
+                let operand = self.nested(Self::parse_unary)?;
                 Ok(Expression::Unary {
                     op: UnaryOp::Not,
                     operand: Box::new(operand),
@@ -450,19 +895,56 @@ impl Parser {
             }
             Token::Minus => {
                 self.advance()?;
-                let operand = self.parse_unary()?;
-                Ok(Expression::Unary {
-                    op: UnaryOp::Neg,
-                    operand: Box::new(operand),
+                let operand = self.nested(Self::parse_unary)?;
+                // `-5` is a negative literal, so schema checks and constant
+                // contexts see a number rather than a negation
+                Ok(match operand {
+                    Expression::Literal(Literal::Int(n)) if n.checked_neg().is_some() => {
+                        Expression::Literal(Literal::Int(-n))
+                    }
+                    Expression::Literal(Literal::Float(f)) => Expression::Literal(Literal::Float(-f)),
+                    operand => Expression::Unary {
+                        op: UnaryOp::Neg,
+                        operand: Box::new(operand),
+                    },
                 })
             }
-            _ => self.parse_postfix(),
+            // Unary plus is a no-op, for symmetry in signed thresholds
+            Token::Plus => {
+                self.advance()?;
+                self.nested(Self::parse_unary)
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    /// `a ** b`: binds tighter than `*` and is right-associative. The base
+    /// is a postfix expression, so `-2 ** 2` is `-(2 ** 2)`, while the
+    /// exponent may itself be negated: `2 ** -1`.
+    fn parse_power(&mut self) -> Result<Expression, ParseError> {
+        let base = self.parse_postfix()?;
+
+        if self.current_token != Token::StarStar {
+            return Ok(base);
         }
+
+        self.advance()?;
+        let exponent = self.parse_unary()?;
+
+        Ok(Expression::Binary {
+            left: Box::new(base),
+            op: BinaryOp::Pow,
+            right: Box::new(exponent),
+        })
     }
 
     fn parse_postfix(&mut self) -> Result<Expression, ParseError> {
-        let mut expr = self.parse_primary()?;
+        let expr = self.parse_primary()?;
+        self.parse_postfix_operators(expr)
+    }
 
+    /// Apply any `.field`, `.method(...)` and `[index]` suffixes to `expr`
+    fn parse_postfix_operators(&mut self, mut expr: Expression) -> Result<Expression, ParseError> {
         loop {
             match self.current_token {
                 Token::Dot => {
@@ -480,18 +962,18 @@ impl Parser {
                             method: field,
                             args,
                         };
-                    } else {
+                    } else if let Expression::Variable(obj) = expr {
                         // Simple field access
-                        if let Expression::Variable(obj) = expr {
-                            expr = Expression::FieldAccess {
-                                object: obj,
-                                field,
-                            };
-                        } else {
-                            return Err(ParseError {
-                                message: "Invalid field access".to_string(),
-                            });
-                        }
+                        expr = Expression::FieldAccess {
+                            object: obj,
+                            field,
+                        };
+                    } else {
+                        // Field of a nested object: profile.address.country
+                        expr = Expression::ObjectField {
+                            object: Box::new(expr),
+                            field,
+                        };
                     }
                 }
                 Token::LeftBracket => {
@@ -554,6 +1036,15 @@ impl Parser {
                         name: name_clone,
                         args,
                     })
+                } else if self.current_token == Token::FatArrow {
+                    // Lambda: the body extends as far as an expression can
+                    self.advance()?;
+                    let body = self.parse_expression()?;
+
+                    Ok(Expression::Lambda {
+                        param: name_clone,
+                        body: Box::new(body),
+                    })
                 } else {
                     Ok(Expression::Variable(name_clone))
                 }
@@ -564,28 +1055,84 @@ impl Parser {
                 self.expect(Token::RightParen)?;
                 Ok(expr)
             }
-            _ => Err(ParseError {
-                message: format!("Unexpected token in expression: {}", self.current_token),
-            }),
+            Token::LeftBrace => self.parse_object_literal(),
+            Token::LeftBracket => self.parse_array_literal(),
+            _ => Err(self.error(format!("Unexpected token in expression: {}", self.current_token))),
         }
     }
 
-    fn parse_argument_list(&mut self) -> Result<Vec<Expression>, ParseError> {
-        let mut args = Vec::new();
+    fn parse_object_literal(&mut self) -> Result<Expression, ParseError> {
+        self.open_brace("object literal".to_string())?;
+
+        let mut entries = Vec::new();
+        while self.current_token != Token::RightBrace {
+            // Keys may be identifiers, strings, or bare keywords like `rule`
+            let key = match &self.current_token {
+                Token::Identifier(s) | Token::String(s) => s.clone(),
+                token => match token.keyword() {
+                    Some(word) => word.to_string(),
+                    None => {
+                        return Err(self.error(format!(
+                            "Expected object key, got {}",
+                            self.current_token
+                        )));
+                    }
+                },
+            };
+            self.advance()?;
+            self.expect(Token::Colon)?;
 
-        if self.current_token != Token::RightParen {
-            loop {
-                args.push(self.parse_expression()?);
+            let value = self.parse_expression()?;
+            entries.push((key, value));
 
-                if self.current_token == Token::Comma {
-                    self.advance()?;
-                } else {
-                    break;
-                }
+            if self.current_token == Token::Comma {
+                self.advance()?;
+            } else {
+                break;
             }
         }
 
-        Ok(args)
+        self.close_brace()?;
+
+        Ok(Expression::ObjectLiteral(entries))
+    }
+
+    /// `[a, b, c]`, with an optional trailing comma
+    fn parse_array_literal(&mut self) -> Result<Expression, ParseError> {
+        self.expect(Token::LeftBracket)?;
+
+        let mut elements = Vec::new();
+        while self.current_token != Token::RightBracket {
+            elements.push(self.parse_expression()?);
+
+            if self.current_token == Token::Comma {
+                self.advance()?;
+            } else {
+                break;
+            }
+        }
+
+        self.expect(Token::RightBracket)?;
+
+        Ok(Expression::ArrayLiteral(elements))
+    }
+
+    fn parse_argument_list(&mut self) -> Result<Vec<Expression>, ParseError> {
+        let mut args = Vec::new();
+
+        if self.current_token != Token::RightParen {
+            loop {
+                args.push(self.parse_expression()?);
+
+                if self.current_token == Token::Comma {
+                    self.advance()?;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        Ok(args)
     }
 
     fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
@@ -593,12 +1140,25 @@ impl Parser {
             self.advance()?;
             Ok(())
         } else {
-            Err(ParseError {
-                message: format!("Expected {:?}, got {}", expected, self.current_token),
-            })
+            Err(self.error(format!("Expected {:?}, got {}", expected, self.current_token)))
         }
     }
 
+    /// Expect a `{` opening `construct`, so running out of input before
+    /// its `}` can say what was left open
+    fn open_brace(&mut self, construct: String) -> Result<(), ParseError> {
+        let line = self.line;
+        self.expect(Token::LeftBrace)?;
+        self.open_braces.push((construct, line));
+        Ok(())
+    }
+
+    fn close_brace(&mut self) -> Result<(), ParseError> {
+        self.expect(Token::RightBrace)?;
+        self.open_braces.pop();
+        Ok(())
+    }
+
     fn expect_identifier(&mut self) -> Result<String, ParseError> {
         match &self.current_token {
             Token::Identifier(name) => {
@@ -606,9 +1166,7 @@ impl Parser {
                 self.advance()?;
                 Ok(result)
             }
-            _ => Err(ParseError {
-                message: format!("Expected identifier, got {}", self.current_token),
-            }),
+            _ => Err(self.error(format!("Expected identifier, got {}", self.current_token))),
         }
     }
 
@@ -619,16 +1177,37 @@ impl Parser {
                 self.advance()?;
                 Ok(result)
             }
-            _ => Err(ParseError {
-                message: format!("Expected string, got {}", self.current_token),
-            }),
+            _ => Err(self.error(format!("Expected string, got {}", self.current_token))),
         }
     }
 
     fn advance(&mut self) -> Result<(), ParseError> {
-        self.current_token = self.lexer.next_token()?;
+        let next = self.lexer.next_spanned_token()?;
+        self.current_token = next.token;
+        self.line = next.line;
+        self.column = next.column;
         Ok(())
     }
+
+    /// Build an error positioned at the current token
+    ///
+    /// At the end of the input, the error instead names the innermost
+    /// construct whose `{` was never closed.
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let message = match (&self.current_token, self.open_braces.last()) {
+            (Token::Eof, Some((construct, line))) => format!(
+                "unexpected end of input while parsing {}, expected '}}' to close the '{{' on line {}",
+                construct, line
+            ),
+            _ => message.into(),
+        };
+
+        ParseError {
+            message,
+            line: self.line,
+            column: self.column,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -680,6 +1259,636 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_else_if_chain() {
+        let input = r#"
+            rule "test" {
+                priority: 100,
+                if (txn.amount > 1000) {
+                    setFraudScore(0.9);
+                } else if (txn.amount > 500) {
+                    setFraudScore(0.5);
+                } else {
+                    setFraudScore(0.1);
+                }
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        let stmt = &program.rules[0].body[0];
+        if let Statement::IfStatement { else_block: Some(else_block), .. } = stmt {
+            assert_eq!(else_block.len(), 1);
+            match &else_block[0] {
+                Statement::IfStatement { else_block, .. } => assert!(else_block.is_some()),
+                _ => panic!("Expected nested if statement"),
+            }
+        } else {
+            panic!("Expected if statement with else block");
+        }
+    }
+
+    #[test]
+    fn test_parse_in_operator() {
+        let input = r#"
+            rule "test" {
+                if (txn.country in profile.blocked_countries) {
+                    setDecision("BLOCK");
+                }
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        if let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] {
+            match condition {
+                Expression::Binary { left, op, right } => {
+                    assert_eq!(*op, BinaryOp::In);
+                    assert!(matches!(**left, Expression::FieldAccess { ref object, .. } if object == "txn"));
+                    assert!(matches!(**right, Expression::FieldAccess { ref object, .. } if object == "profile"));
+                }
+                _ => panic!("Expected binary expression"),
+            }
+        } else {
+            panic!("Expected if statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_not_in_operator() {
+        for source in ["txn.amount + 1 not in profile.seen && ok", "txn.amount + 1 !in profile.seen && ok"] {
+            let input = format!(r#"rule "test" {{ if ({}) {{ }} }}"#, source);
+            let mut parser = Parser::new(&input).unwrap();
+            let program = parser.parse().unwrap();
+
+            let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+                panic!("Expected if statement");
+            };
+            // Same precedence as `in`: tighter than `&&`, looser than `+`
+            assert_eq!(condition.to_string(), "((txn.amount + 1) not in profile.seen) && ok");
+        }
+    }
+
+    #[test]
+    fn test_parse_method_call_statements() {
+        let input = r#"function track() { profile.recent.push(txn.amount, 5); seen.push(1); }"#;
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        let rendered: Vec<String> = program.functions[0]
+            .body
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Expression(expr) => expr.to_string(),
+                other => panic!("Expected expression statement, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(rendered, ["profile.recent.push(txn.amount, 5)", "seen.push(1)"]);
+
+        // A bare field is not a statement
+        let mut parser = Parser::new(r#"function track() { profile.recent; }"#).unwrap();
+        assert_eq!(parser.parse().unwrap_err().message, "Expected assignment or method call");
+    }
+
+    #[test]
+    fn test_dangling_else_binds_to_inner_if() {
+        let input = r#"rule "test" { if (a) { if (b) { x = 1; } else { x = 2; } } }"#;
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        let Statement::IfStatement { then_block, else_block, .. } = &program.rules[0].body[0] else {
+            panic!("Expected if statement");
+        };
+        assert!(else_block.is_none());
+
+        let [Statement::IfStatement { else_block: inner_else, .. }] = &then_block[..] else {
+            panic!("Expected a single nested if statement");
+        };
+        assert_eq!(inner_else.as_ref().map(Vec::len), Some(1));
+
+        // An unbraced branch is rejected rather than guessed at
+        let mut parser = Parser::new(r#"rule "test" { if (a) if (b) { } else { } }"#).unwrap();
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_bitwise_precedence() {
+        let cases = [
+            ("profile.flags & 4 != 0", "(profile.flags & 4) != 0"),
+            ("a | b ^ c & d", "a | (b ^ (c & d))"),
+            ("1 << n + 1 == x && ok", "((1 << (n + 1)) == x) && ok"),
+            ("a >> 1 >> 2 < b", "((a >> 1) >> 2) < b"),
+        ];
+        for (source, expected) in cases {
+            let input = format!(r#"rule "test" {{ if ({}) {{ }} }}"#, source);
+            let mut parser = Parser::new(&input).unwrap();
+            let program = parser.parse().unwrap();
+
+            let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+                panic!("Expected if statement");
+            };
+            assert_eq!(condition.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_compound_assignment() {
+        let input = r#"
+            function update() {
+                profile.count += 1;
+                txn.fee *= 2;
+                total -= 0.5;
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+        let body = &program.functions[0].body;
+
+        assert_eq!(body.len(), 3);
+        assert!(matches!(
+            &body[0],
+            Statement::CompoundAssignment { target, op: BinaryOp::Add, .. } if target == "profile.count"
+        ));
+        assert!(matches!(
+            &body[1],
+            Statement::CompoundAssignment { target, op: BinaryOp::Mul, .. } if target == "txn.fee"
+        ));
+        assert!(matches!(
+            &body[2],
+            Statement::CompoundAssignment { target, op: BinaryOp::Sub, .. } if target == "total"
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let input = "rule \"test\" {\n    priority: 100,\n    if (txn.amount > ) {\n    }\n}";
+
+        let mut parser = Parser::new(input).unwrap();
+        let err = parser.parse().unwrap_err();
+
+        assert_eq!((err.line, err.column), (3, 22));
+        assert_eq!(
+            err.to_string(),
+            "Parse error at 3:22: Unexpected token in expression: RightParen"
+        );
+    }
+
+    #[test]
+    fn test_lex_error_position() {
+        let mut parser = Parser::new("rule \"test\" {\n  if (a # b) {}\n}").unwrap();
+        let err = parser.parse().unwrap_err();
+
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "Unexpected character: '#'");
+    }
+
+    #[test]
+    fn test_parse_all_recovers_after_errors() {
+        let input = r#"
+            rule "missing_paren" {
+                priority: 10,
+                if (txn.amount > 100 {
+                    setFraudScore(0.5);
+                }
+            }
+
+            rule "good" {
+                priority: 5,
+                if (txn.amount > 1000) { setFraudScore(0.9); }
+            }
+
+            rule "bad_priority" {
+                priority: high,
+                if (true) {}
+            }
+        "#;
+
+        let (program, errors) = Parser::parse_all(input);
+
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!((errors[0].line, errors[1].line), (4, 15));
+        assert_eq!(program.rules.len(), 1);
+        assert_eq!(program.rules[0].id, "good");
+        assert!(Parser::new(input).unwrap().parse().is_err());
+
+        // Characters the lexer rejects are reported and skipped too
+        let (program, errors) = Parser::parse_all("# rule \"a\" { if (#true) {} } rule \"b\" {}");
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(errors[0].message, "Unexpected character: '#'");
+        let ids: Vec<_> = program.rules.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["b"]);
+    }
+
+    #[test]
+    fn test_parse_for_loop() {
+        let input = r#"
+            rule "test" {
+                for (amount in profile.recent_amounts) {
+                    total += amount;
+                }
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        match &program.rules[0].body[0] {
+            Statement::ForEach { var, iterable, body } => {
+                assert_eq!(var, "amount");
+                assert!(matches!(iterable, Expression::FieldAccess { field, .. } if field == "recent_amounts"));
+                assert_eq!(body.len(), 1);
+            }
+            _ => panic!("Expected for loop"),
+        }
+    }
+
+    #[test]
+    fn test_parse_break_continue() {
+        let input = r#"
+            rule "test" {
+                for (amount in profile.recent_amounts) {
+                    if (amount < 0) { continue; }
+                    break
+                }
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        match &program.rules[0].body[0] {
+            Statement::ForEach { body, .. } => {
+                assert!(matches!(&body[0], Statement::IfStatement { then_block, .. } if then_block == &vec![Statement::Continue]));
+                assert_eq!(body[1], Statement::Break);
+            }
+            _ => panic!("Expected for loop"),
+        }
+    }
+
+    #[test]
+    fn test_parse_object_literal() {
+        let input = r#"
+            rule "test" {
+                if (true) {
+                    createCase("HIGH", "reason", { rule: "v1", "risk level": 2, score: txn.score, });
+                    createCase("LOW", "reason", {});
+                }
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        if let Statement::IfStatement { then_block, .. } = &program.rules[0].body[0] {
+            match &then_block[0] {
+                Statement::ActionCall { args, .. } => match &args[2] {
+                    Expression::ObjectLiteral(entries) => {
+                        let keys: Vec<_> = entries.iter().map(|(k, _)| k.as_str()).collect();
+                        assert_eq!(keys, vec!["rule", "risk level", "score"]);
+                    }
+                    _ => panic!("Expected object literal"),
+                },
+                _ => panic!("Expected action call"),
+            }
+            match &then_block[1] {
+                Statement::ActionCall { args, .. } => {
+                    assert_eq!(args[2], Expression::ObjectLiteral(vec![]));
+                }
+                _ => panic!("Expected action call"),
+            }
+        } else {
+            panic!("Expected if statement");
+        }
+    }
+
+    #[test]
+    fn test_parse_nested_ternary() {
+        let input = r#"
+            function score() {
+                let s = txn.amount > 1000 ? 0.8 : txn.amount > 500 ? 0.5 : 0.2;
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        match &program.functions[0].body[0] {
+            Statement::Let { value: Expression::Ternary { then_expr, else_expr, .. }, .. } => {
+                assert_eq!(**then_expr, Expression::Literal(Literal::Float(0.8)));
+                assert!(matches!(**else_expr, Expression::Ternary { .. }));
+            }
+            _ => panic!("Expected assignment of a ternary"),
+        }
+    }
+
+    #[test]
+    fn test_parse_power_precedence() {
+        let input = r#"
+            function f() {
+                let x = 3 * 2 ** 3 ** 2;
+                let y = -2 ** 2;
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+        let body = &program.functions[0].body;
+
+        let value = |stmt: &Statement| match stmt {
+            Statement::Let { value, .. } => value.to_string(),
+            _ => panic!("Expected let"),
+        };
+
+        assert_eq!(value(&body[0]), "3 * (2 ** (3 ** 2))");
+        assert_eq!(value(&body[1]), "-(2 ** 2)");
+    }
+
+    #[test]
+    fn test_parse_nested_field_access() {
+        let input = r#"
+            function f() {
+                let c = profile.address.country;
+                let a = items[0].amount;
+            }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        match &program.functions[0].body[0] {
+            Statement::Let { value: Expression::ObjectField { object, field }, .. } => {
+                assert_eq!(field, "country");
+                assert_eq!(
+                    **object,
+                    Expression::FieldAccess {
+                        object: "profile".to_string(),
+                        field: "address".to_string(),
+                    }
+                );
+            }
+            _ => panic!("Expected assignment of a nested field"),
+        }
+        assert!(matches!(
+            &program.functions[0].body[1],
+            Statement::Let { value: Expression::ObjectField { .. }, .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_const() {
+        let input = r#"
+            const HIGH_AMOUNT = 1000;
+            const LABEL = "high";
+            rule "r" { priority: 1, if (txn.amount > HIGH_AMOUNT) {} }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.constants.len(), 2);
+        assert_eq!(program.constants[0].name, "HIGH_AMOUNT");
+        assert_eq!(program.constants[0].value, Expression::Literal(Literal::Int(1000)));
+        assert_eq!(program.rules.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_return_value() {
+        let input = r#"
+            function f(x) { return x * 2; }
+            rule "r" { if (true) { return } }
+        "#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        match &program.functions[0].body[0] {
+            Statement::Return(Some(value)) => assert_eq!(value.to_string(), "x * 2"),
+            other => panic!("expected return with value, got {:?}", other),
+        }
+        let Statement::IfStatement { then_block, .. } = &program.rules[0].body[0] else {
+            panic!("expected if statement");
+        };
+        assert_eq!(then_block[0], Statement::Return(None));
+    }
+
+    #[test]
+    fn test_parse_require() {
+        let input = r#"rule "r" { require(profile.risk_score >= 0); if (true) {} }"#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        match &program.rules[0].body[0] {
+            Statement::Require(condition) => assert_eq!(condition.to_string(), "profile.risk_score >= 0"),
+            other => panic!("expected require, got {:?}", other),
+        }
+
+        let err = Parser::new(r#"rule "r" { require profile.ok; }"#).unwrap().parse().unwrap_err();
+        assert!(err.message.contains("Expected LeftParen"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_parse_lambda_argument() {
+        let input = r#"rule "r" { if (profile.recent.any(x => x > 5000 && x != 0)) {} }"#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+            panic!("expected if statement");
+        };
+        let Expression::MethodCall { method, args, .. } = condition else {
+            panic!("expected method call, got {:?}", condition);
+        };
+        assert_eq!(method, "any");
+        // The body takes the whole rest of the argument
+        match &args[..] {
+            [Expression::Lambda { param, body }] => {
+                assert_eq!(param, "x");
+                assert_eq!(body.to_string(), "(x > 5000) && (x != 0)");
+            }
+            other => panic!("expected one lambda, got {:?}", other),
+        }
+        assert_eq!(condition.to_string(), "profile.recent.any(x => (x > 5000) && (x != 0))");
+    }
+
+    #[test]
+    fn test_parse_unary_plus() {
+        let input = r#"rule "r" { if (txn.delta >= +5 && txn.delta < - +5) {} }"#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+            panic!("expected if statement");
+        };
+        assert_eq!(condition.to_string(), "(txn.delta >= 5) && (txn.delta < -5)");
+    }
+
+    #[test]
+    fn test_parse_negative_literals() {
+        let input = r#"rule "r" { if (txn.delta > -5 && txn.rate < -2.5 && txn.x == -2 ** 2 && txn.y == -txn.z) {} }"#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+            panic!("expected if statement");
+        };
+        let mut comparisons = Vec::new();
+        let mut expr = condition;
+        while let Expression::Binary { left, op: BinaryOp::And, right } = expr {
+            comparisons.push(&**right);
+            expr = left;
+        }
+        comparisons.push(expr);
+        comparisons.reverse();
+
+        let right_of = |e: &Expression| match e {
+            Expression::Binary { right, .. } => (**right).clone(),
+            _ => panic!("expected comparison, got {:?}", e),
+        };
+        assert_eq!(right_of(comparisons[0]), Expression::Literal(Literal::Int(-5)));
+        assert_eq!(right_of(comparisons[1]), Expression::Literal(Literal::Float(-2.5)));
+        // A negated power or field stays a negation
+        assert!(matches!(right_of(comparisons[2]), Expression::Unary { op: UnaryOp::Neg, .. }));
+        assert!(matches!(right_of(comparisons[3]), Expression::Unary { op: UnaryOp::Neg, .. }));
+    }
+
+    #[test]
+    fn test_parse_array_literal() {
+        let input = r#"rule "r" { if (txn.country in ["RU", "KP",] && [] == [txn.a, [1, 2]]) {} }"#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+            panic!("expected if statement");
+        };
+        assert_eq!(condition.to_string(), r#"(txn.country in ["RU", "KP"]) && ([] == [txn.a, [1, 2]])"#);
+    }
+
+    #[test]
+    fn test_unterminated_rule_names_the_open_brace() {
+        let input = "rule \"velocity\" {\n    priority: 10,\n    if (txn.amount > 100) {\n        setFraudScore(0.5);\n    }\n";
+        let err = Parser::new(input).unwrap().parse().unwrap_err();
+        assert_eq!(
+            err.message,
+            "unexpected end of input while parsing rule 'velocity', expected '}' to close the '{' on line 1"
+        );
+        assert_eq!(err.line, 6);
+
+        // The innermost unclosed construct is named, even mid-expression
+        let input = "rule \"r\" {\n    if (true) {\n        profile.x = ";
+        let err = Parser::new(input).unwrap().parse().unwrap_err();
+        assert_eq!(
+            err.message,
+            "unexpected end of input while parsing if block, expected '}' to close the '{' on line 2"
+        );
+    }
+
+    #[test]
+    fn test_unterminated_function_names_the_open_brace() {
+        let input = "function score(x) {\n    if (x > 1) { return 1; }\n    return x * 2;\n";
+        let err = Parser::new(input).unwrap().parse().unwrap_err();
+        assert_eq!(
+            err.message,
+            "unexpected end of input while parsing function 'score', expected '}' to close the '{' on line 1"
+        );
+
+        // Closed braces do not linger into later items
+        let (program, errors) = Parser::parse_all("function f() { }\nrule \"r\" { if (true) { ");
+        assert_eq!(program.functions.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("parsing if block"), "{}", errors[0].message);
+    }
+
+    #[test]
+    fn test_parse_null_coalesce() {
+        let input = r#"rule "r" { if (profile.count ?? 0 > 3 || x ?? y ?? true) {} }"#;
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        let Statement::IfStatement { condition, .. } = &program.rules[0].body[0] else {
+            panic!("expected if statement");
+        };
+        // `??` binds looser than `||` and associates to the left
+        let Expression::Binary { left, op: BinaryOp::Coalesce, right } = condition else {
+            panic!("expected ??, got {:?}", condition);
+        };
+        assert_eq!(left.to_string(), "(profile.count ?? ((0 > 3) || x)) ?? y");
+        assert_eq!(**right, Expression::Literal(Literal::Bool(true)));
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let input = r#"
+            rule "structuring" { priority: 10, tags: ["aml", "geo"], if (true) {} }
+            rule "untagged" { tags: [], if (true) {} }
+        "#;
+
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        assert_eq!(program.rules[0].tags, ["aml", "geo"]);
+        assert_eq!(program.rules[0].priority, 10);
+        assert!(program.rules[1].tags.is_empty());
+
+        let err = Parser::new(r#"rule "r" { tags: [aml], if (true) {} }"#).unwrap().parse().unwrap_err();
+        assert!(err.message.contains("Expected string for tag"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_parse_enabled_condition() {
+        let input = r#"
+            rule "premium" { enabled: profile.tier == "premium", if (true) {} }
+            rule "off" { enabled: false, if (true) {} }
+            rule "on" { enabled: true, if (true) {} }
+        "#;
+
+        let program = Parser::new(input).unwrap().parse().unwrap();
+
+        assert!(matches!(
+            program.rules[0].enabled_when,
+            Some(Expression::Binary { op: BinaryOp::Eq, .. })
+        ));
+        assert!(program.rules[0].enabled);
+        // Plain booleans stay static and need no evaluation
+        assert!(!program.rules[1].enabled);
+        assert!(program.rules[1].enabled_when.is_none());
+        assert!(program.rules[2].enabled);
+        assert!(program.rules[2].enabled_when.is_none());
+    }
+
+    #[test]
+    fn test_parse_let_and_block() {
+        let input = r#"function f() { let x = 1; { let x = 2; profile.inner = x; } }"#;
+
+        let program = Parser::new(input).unwrap().parse().unwrap();
+        let body = &program.functions[0].body;
+
+        assert!(matches!(&body[0], Statement::Let { name, value: Expression::Literal(Literal::Int(1)) } if name == "x"));
+        let Statement::Block(block) = &body[1] else {
+            panic!("expected a block, got {:?}", body[1]);
+        };
+        assert_eq!(block.len(), 2);
+        assert!(matches!(&block[0], Statement::Let { name, .. } if name == "x"));
+    }
+
+    #[test]
+    fn test_parse_group() {
+        let input = r#"
+            group "velocity" {
+                priority: 50,
+                rule "burst" { priority: 2, if (txn.group == "retail") { return; } }
+                rule "daily" { priority: 1, if (true) {} }
+            }
+            rule "loose" { priority: 10, if (true) {} }
+        "#;
+
+        let mut parser = Parser::new(input).unwrap();
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.rules.len(), 1);
+        assert_eq!(program.groups.len(), 1);
+        let group = &program.groups[0];
+        assert_eq!((group.name.as_str(), group.priority), ("velocity", 50));
+        let ids: Vec<_> = group.rules.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["burst", "daily"]);
+
+        let err = Parser::new(r#"group "g" { const X = 1; }"#).unwrap().parse().unwrap_err();
+        assert!(err.message.contains("Expected 'rule' in group 'g'"), "{}", err.message);
+    }
+
     #[test]
     fn test_parse_function() {
         let input = r#"
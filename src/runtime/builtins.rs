@@ -0,0 +1,339 @@
+// src/runtime/builtins.rs
+//! Built-in functions callable from rules
+//!
+//! Builtins are resolved by name at compile time and dispatched here by the VM,
+//! without going through the user-defined function table. Arguments of the
+//! wrong type (or a wrong argument count) produce `Value::Null`.
+
+use crate::compiler::bytecode::BuiltinFn;
+use crate::Value;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Invoke a builtin with already-evaluated arguments
+pub fn call(builtin: BuiltinFn, args: &[Value]) -> Value {
+    match builtin {
+        BuiltinFn::Abs => abs(args),
+        BuiltinFn::Min => min_max(args, true),
+        BuiltinFn::Max => min_max(args, false),
+        BuiltinFn::Clamp => clamp(args),
+        BuiltinFn::Floor => float_op(args, f64::floor, Decimal::floor),
+        BuiltinFn::Ceil => float_op(args, f64::ceil, Decimal::ceil),
+        BuiltinFn::Round => round(args),
+        BuiltinFn::Sqrt => sqrt(args),
+        BuiltinFn::Decimal => decimal(args),
+        BuiltinFn::Now => now(args),
+        BuiltinFn::HoursBetween => hours_between(args),
+        BuiltinFn::ParseDate => parse_date(args),
+        BuiltinFn::ToInt => convert(args, |x| Value::Int(x.as_int())),
+        BuiltinFn::ToFloat => convert(args, |x| match x.as_float() {
+            f if f.is_finite() => Value::Float(f),
+            _ => Value::Null,
+        }),
+        BuiltinFn::ToString => convert(args, |x| Value::String(x.as_string())),
+        BuiltinFn::ToBool => convert(args, |x| Value::Bool(x.as_bool())),
+        #[cfg(feature = "crypto")]
+        BuiltinFn::Sha256 => hash_hex::<sha2::Sha256>(args),
+        #[cfg(feature = "crypto")]
+        BuiltinFn::Md5 => hash_hex::<md5::Md5>(args),
+        // Only bytecode built with the `crypto` feature can contain these
+        #[cfg(not(feature = "crypto"))]
+        BuiltinFn::Sha256 | BuiltinFn::Md5 => Value::Null,
+    }
+}
+
+/// `sha256(x)` / `md5(x)`: lowercase hex digest of a string's UTF-8 bytes
+/// or of raw bytes
+#[cfg(feature = "crypto")]
+fn hash_hex<D: sha2::Digest>(args: &[Value]) -> Value {
+    let digest = match args {
+        [Value::String(s)] => D::digest(s.as_bytes()),
+        [Value::Bytes(b)] => D::digest(b),
+        _ => return Value::Null,
+    };
+    Value::String(crate::runtime::value::to_hex(&digest))
+}
+
+/// `toInt(x)`, `toFloat(x)`, `toString(x)`, `toBool(x)`: explicit
+/// conversions following `Value::as_int` and friends, so a string that is
+/// not a number converts to 0 and `toBool` is truthiness
+fn convert(args: &[Value], to: fn(&Value) -> Value) -> Value {
+    match args {
+        [x] => to(x),
+        _ => Value::Null,
+    }
+}
+
+fn abs(args: &[Value]) -> Value {
+    match args {
+        [Value::Int(n)] => Value::Int(n.wrapping_abs()),
+        [Value::Float(f)] => Value::Float(f.abs()),
+        [Value::Decimal(d)] => Value::Decimal(d.abs()),
+        _ => Value::Null,
+    }
+}
+
+/// `min(a, b)` / `max(a, b)`: stays an Int when both arguments are Ints and
+/// a Decimal when either one is
+fn min_max(args: &[Value], is_min: bool) -> Value {
+    match args {
+        [Value::Int(a), Value::Int(b)] => Value::Int(if is_min { *a.min(b) } else { *a.max(b) }),
+        [a @ Value::Decimal(_), b] | [a, b @ Value::Decimal(_)] => match (a.as_decimal(), b.as_decimal()) {
+            (Some(a), Some(b)) => Value::Decimal(if is_min { a.min(b) } else { a.max(b) }),
+            _ => Value::Null,
+        },
+        [a, b] if a.is_numeric() && b.is_numeric() => {
+            let (a, b) = (a.as_float(), b.as_float());
+            Value::Float(if is_min { a.min(b) } else { a.max(b) })
+        }
+        _ => Value::Null,
+    }
+}
+
+/// `clamp(x, lo, hi)`: `x` bounded to `[lo, hi]`, with the result type
+/// following `min`/`max`. An inverted range (`lo > hi`) gives `lo`.
+fn clamp(args: &[Value]) -> Value {
+    match args {
+        [x, lo, hi] if x.is_numeric() && lo.is_numeric() && hi.is_numeric() => {
+            if lo.compare(hi) == Some(std::cmp::Ordering::Greater) {
+                return lo.clone();
+            }
+            min_max(&[min_max(&[x.clone(), hi.clone()], true), lo.clone()], false)
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Apply a rounding function; Ints are already whole and pass through
+fn float_op(args: &[Value], op: fn(f64) -> f64, decimal_op: fn(&Decimal) -> Decimal) -> Value {
+    match args {
+        [Value::Int(n)] => Value::Int(*n),
+        [Value::Float(f)] => Value::Float(op(*f)),
+        [Value::Decimal(d)] => Value::Decimal(decimal_op(d)),
+        _ => Value::Null,
+    }
+}
+
+/// `round(x)` or `round(x, precision)` with precision in decimal places
+fn round(args: &[Value]) -> Value {
+    match args {
+        [x] => float_op(std::slice::from_ref(x), f64::round, |d| {
+            d.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+        }),
+        [Value::Int(n), Value::Int(_)] => Value::Int(*n),
+        [Value::Float(f), Value::Int(precision)] => {
+            let factor = 10f64.powi(*precision as i32);
+            Value::Float((f * factor).round() / factor)
+        }
+        [Value::Decimal(d), Value::Int(precision)] => Value::Decimal(
+            d.round_dp_with_strategy((*precision).max(0) as u32, RoundingStrategy::MidpointAwayFromZero),
+        ),
+        _ => Value::Null,
+    }
+}
+
+/// Square root; negative inputs have no real result and yield Null
+fn sqrt(args: &[Value]) -> Value {
+    match args {
+        [x] if x.is_numeric() && x.as_float() >= 0.0 => Value::Float(x.as_float().sqrt()),
+        _ => Value::Null,
+    }
+}
+
+/// `decimal(x)`: convert a string or number to a fixed-point Decimal;
+/// unparseable strings yield Null
+fn decimal(args: &[Value]) -> Value {
+    match args {
+        [Value::String(s)] => Decimal::from_str(s.trim()).map_or(Value::Null, Value::Decimal),
+        [x] => x.as_decimal().map_or(Value::Null, Value::Decimal),
+        _ => Value::Null,
+    }
+}
+
+/// `now()`: the current time
+fn now(args: &[Value]) -> Value {
+    if !args.is_empty() {
+        return Value::Null;
+    }
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64);
+    Value::DateTime(millis)
+}
+
+/// `hoursBetween(a, b)`: absolute distance in (fractional) hours; Ints are
+/// accepted as millisecond timestamps
+fn hours_between(args: &[Value]) -> Value {
+    const MILLIS_PER_HOUR: f64 = 3_600_000.0;
+    
+    match args {
+        [a @ (Value::DateTime(_) | Value::Int(_)), b @ (Value::DateTime(_) | Value::Int(_))] => {
+            Value::Float(a.as_int().abs_diff(b.as_int()) as f64 / MILLIS_PER_HOUR)
+        }
+        _ => Value::Null,
+    }
+}
+
+/// `parseDate(s)`: RFC 3339 (`2024-01-31T12:00:00Z`), a naive date-time taken
+/// as UTC (`2024-01-31T12:00:00`), or a bare date (`2024-01-31`)
+fn parse_date(args: &[Value]) -> Value {
+    let [Value::String(s)] = args else {
+        return Value::Null;
+    };
+    let s = s.trim();
+    
+    let millis = DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.timestamp_millis())
+        .or_else(|_| NaiveDateTime::from_str(s).map(|dt| dt.and_utc().timestamp_millis()))
+        .or_else(|_| {
+            NaiveDate::from_str(s).map(|d| d.and_time(Default::default()).and_utc().timestamp_millis())
+        });
+    
+    millis.map_or(Value::Null, Value::DateTime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abs() {
+        assert_eq!(call(BuiltinFn::Abs, &[Value::Int(-5)]), Value::Int(5));
+        assert_eq!(call(BuiltinFn::Abs, &[Value::Float(-2.5)]), Value::Float(2.5));
+        assert_eq!(call(BuiltinFn::Abs, &[Value::from("x")]), Value::Null);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(call(BuiltinFn::ToInt, &[Value::from("1000")]), Value::Int(1000));
+        assert_eq!(call(BuiltinFn::ToInt, &[Value::from("abc")]), Value::Int(0));
+        assert_eq!(call(BuiltinFn::ToInt, &[Value::Float(7.9)]), Value::Int(7));
+        assert_eq!(call(BuiltinFn::ToFloat, &[Value::from("2.5")]), Value::Float(2.5));
+        assert_eq!(call(BuiltinFn::ToFloat, &[Value::from("NaN")]), Value::Null);
+        assert_eq!(call(BuiltinFn::ToString, &[Value::Int(42)]), Value::from("42"));
+        assert_eq!(call(BuiltinFn::ToBool, &[Value::Int(0)]), Value::Bool(false));
+        assert_eq!(call(BuiltinFn::ToBool, &[Value::from("no")]), Value::Bool(true));
+        assert_eq!(call(BuiltinFn::ToInt, &[]), Value::Null);
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(call(BuiltinFn::Min, &[Value::Int(3), Value::Int(7)]), Value::Int(3));
+        assert_eq!(call(BuiltinFn::Max, &[Value::Int(3), Value::Int(7)]), Value::Int(7));
+        assert_eq!(call(BuiltinFn::Max, &[Value::Int(3), Value::Float(2.5)]), Value::Float(3.0));
+        assert_eq!(call(BuiltinFn::Min, &[Value::Int(3), Value::Null]), Value::Null);
+        assert_eq!(call(BuiltinFn::Min, &[Value::Int(3)]), Value::Null);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let clamp = |x: Value, lo: Value, hi: Value| call(BuiltinFn::Clamp, &[x, lo, hi]);
+
+        // Below, at the edges of, inside and above the range
+        assert_eq!(clamp(Value::Int(-3), Value::Int(0), Value::Int(10)), Value::Int(0));
+        assert_eq!(clamp(Value::Int(0), Value::Int(0), Value::Int(10)), Value::Int(0));
+        assert_eq!(clamp(Value::Int(10), Value::Int(0), Value::Int(10)), Value::Int(10));
+        assert_eq!(clamp(Value::Int(4), Value::Int(0), Value::Int(10)), Value::Int(4));
+        assert_eq!(clamp(Value::Int(42), Value::Int(0), Value::Int(10)), Value::Int(10));
+
+        assert_eq!(clamp(Value::Float(1.7), Value::Float(0.0), Value::Float(1.0)), Value::Float(1.0));
+        assert_eq!(clamp(Value::Float(0.25), Value::Int(0), Value::Int(1)), Value::Float(0.25));
+        assert_eq!(clamp(Value::Int(5), Value::Int(0), Value::Float(1.0)), Value::Float(1.0));
+        assert_eq!(
+            clamp(Value::Decimal(Decimal::new(150, 2)), Value::Int(0), Value::Int(1)),
+            Value::Decimal(Decimal::ONE)
+        );
+
+        // An inverted range gives the lower bound
+        assert_eq!(clamp(Value::Int(5), Value::Int(10), Value::Int(0)), Value::Int(10));
+
+        assert_eq!(clamp(Value::Null, Value::Int(0), Value::Int(1)), Value::Null);
+        assert_eq!(clamp(Value::Int(5), Value::from("0"), Value::Int(1)), Value::Null);
+        assert_eq!(call(BuiltinFn::Clamp, &[Value::Int(5), Value::Int(0)]), Value::Null);
+    }
+
+    #[test]
+    fn test_floor_ceil() {
+        assert_eq!(call(BuiltinFn::Floor, &[Value::Float(2.7)]), Value::Float(2.0));
+        assert_eq!(call(BuiltinFn::Ceil, &[Value::Float(2.1)]), Value::Float(3.0));
+        assert_eq!(call(BuiltinFn::Ceil, &[Value::Int(4)]), Value::Int(4));
+        assert_eq!(call(BuiltinFn::Floor, &[Value::Bool(true)]), Value::Null);
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(call(BuiltinFn::Round, &[Value::Float(2.5)]), Value::Float(3.0));
+        assert_eq!(
+            call(BuiltinFn::Round, &[Value::Float(0.87654), Value::Int(2)]),
+            Value::Float(0.88)
+        );
+        assert_eq!(call(BuiltinFn::Round, &[Value::Int(7), Value::Int(2)]), Value::Int(7));
+        assert_eq!(call(BuiltinFn::Round, &[Value::from("1.5")]), Value::Null);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(call(BuiltinFn::Sqrt, &[Value::Int(16)]), Value::Float(4.0));
+        assert_eq!(call(BuiltinFn::Sqrt, &[Value::Float(-1.0)]), Value::Null);
+        assert_eq!(call(BuiltinFn::Sqrt, &[]), Value::Null);
+    }
+
+    #[test]
+    fn test_decimal() {
+        let cents = Decimal::new(10050, 2);
+        
+        assert_eq!(call(BuiltinFn::Decimal, &[Value::from("100.50")]), Value::Decimal(cents));
+        assert_eq!(call(BuiltinFn::Decimal, &[Value::Int(3)]), Value::Decimal(Decimal::from(3)));
+        assert_eq!(call(BuiltinFn::Decimal, &[Value::from("abc")]), Value::Null);
+        assert_eq!(
+            call(BuiltinFn::Round, &[Value::Decimal(Decimal::new(12345, 3)), Value::Int(2)]),
+            Value::Decimal(Decimal::new(1235, 2))
+        );
+        assert_eq!(
+            call(BuiltinFn::Max, &[Value::Decimal(cents), Value::Int(200)]),
+            Value::Decimal(Decimal::from(200))
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        let new_year = call(BuiltinFn::ParseDate, &[Value::from("2024-01-01")]);
+        let later = call(BuiltinFn::ParseDate, &[Value::from("2024-01-01T06:30:00Z")]);
+        
+        assert_eq!(new_year, Value::DateTime(1_704_067_200_000));
+        assert_eq!(
+            call(BuiltinFn::ParseDate, &[Value::from("2024-01-01T08:30:00+02:00")]),
+            later
+        );
+        assert_eq!(call(BuiltinFn::HoursBetween, &[new_year.clone(), later.clone()]), Value::Float(6.5));
+        assert_eq!(call(BuiltinFn::HoursBetween, &[later, new_year.clone()]), Value::Float(6.5));
+        assert_eq!(call(BuiltinFn::ParseDate, &[Value::from("yesterday")]), Value::Null);
+        assert_eq!(call(BuiltinFn::HoursBetween, &[new_year, Value::from("x")]), Value::Null);
+        assert!(matches!(call(BuiltinFn::Now, &[]), Value::DateTime(ms) if ms > 1_704_067_200_000));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_hashes() {
+        assert_eq!(
+            call(BuiltinFn::Sha256, &[Value::from("abc")]),
+            Value::from("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            call(BuiltinFn::Sha256, &[Value::from("")]),
+            Value::from("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            call(BuiltinFn::Md5, &[Value::from("abc")]),
+            Value::from("900150983cd24fb0d6963f7d28e17f72")
+        );
+        // Bytes hash their raw contents
+        assert_eq!(
+            call(BuiltinFn::Md5, &[Value::Bytes(b"abc".to_vec())]),
+            call(BuiltinFn::Md5, &[Value::from("abc")])
+        );
+        assert_eq!(call(BuiltinFn::Sha256, &[Value::Int(1)]), Value::Null);
+    }
+}
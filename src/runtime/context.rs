@@ -1,8 +1,27 @@
 // src/runtime/context.rs
 //! Execution context that maintains state during rule execution
 
-use crate::{Action, ExecutionMetadata, Transaction, UserProfile, Value};
+use crate::compiler::memo::{FieldRead, MemoTable};
+use crate::compiler::pool::ConstantPool;
+use crate::compiler::regexes::RegexTable;
+use crate::compiler::sets::SetTable;
+use crate::compiler::symbols::{SymbolId, SymbolTable};
+use crate::runtime::HashKey;
+use crate::{
+    Action, ExecutionError, ExecutionMetadata, FieldChange, NullSemantics, OverflowPolicy, Transaction,
+    UserProfile, Value,
+};
 use ahash::HashMap;
+use std::sync::Arc;
+
+/// Default cap on `while` loop iterations per loop execution
+pub const DEFAULT_MAX_LOOP_ITERATIONS: usize = 10_000;
+
+/// Default cap on nested global function calls
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
+/// Most results a [`ConditionCache`] holds; it starts over when full
+pub const MAX_CACHED_CONDITIONS: usize = 10_000;
 
 /// Mutable context for rule execution
 pub struct ExecutionContext {
@@ -21,11 +40,214 @@ pub struct ExecutionContext {
     /// Whether a return statement was executed
     pub should_return: bool,
 
+    /// Whether the VM aborted the current rule after a stack underflow or a
+    /// failed `require`
+    pub aborted: bool,
+
+    /// Whether a top-level `if` condition of the current rule held
+    pub rule_fired: bool,
+
     /// Stack for bytecode VM
     pub stack: Vec<Value>,
 
+    /// Start of the running function's frame in `stack`; values below it
+    /// belong to callers and cannot be popped
+    pub stack_base: usize,
+
     /// Local variables
     pub local_vars: HashMap<String, Value>,
+
+    /// Start in `shadowed` of each open block scope, innermost last
+    scopes: Vec<usize>,
+
+    /// Locals declared in open block scopes with the values they shadowed,
+    /// restored when their scope exits
+    shadowed: Vec<(String, Option<Value>)>,
+
+    /// Runtime errors recorded during execution
+    pub errors: Vec<ExecutionError>,
+
+    /// Index of the rule instruction executing; stays on the call while a
+    /// global function runs
+    pub pc: usize,
+
+    /// Value of `pc` when each of `errors` was recorded
+    pub error_pcs: Vec<usize>,
+
+    /// Maximum iterations a single `while` loop may run
+    pub max_loop_iterations: usize,
+
+    /// Whether reading an absent profile/txn field records an error
+    pub strict_fields: bool,
+
+    /// What integer arithmetic does on overflow
+    pub numeric_overflow: OverflowPolicy,
+
+    /// Whether comparisons with Null are false or unknown
+    pub null_semantics: NullSemantics,
+
+    /// Current depth of nested global function calls
+    pub call_depth: usize,
+
+    /// Maximum depth of nested global function calls
+    pub max_call_depth: usize,
+
+    /// Maximum number of instructions the VM may execute
+    pub instruction_budget: u64,
+
+    /// Instructions executed so far
+    pub instructions_executed: u64,
+
+    /// Record profile field changes in `metadata.profile_changes`, for
+    /// `RuleEngine::dry_run`
+    pub record_profile_changes: bool,
+
+    /// Condition and field-read recording for `RuleEngine::explain`; None
+    /// outside of explain so the normal path does no extra work
+    pub trace: Option<RuleTrace>,
+
+    /// Field names the executing bytecode was compiled against
+    pub symbols: Arc<SymbolTable>,
+
+    /// Regex patterns referenced by `MatchRegex`
+    pub regexes: Arc<RegexTable>,
+
+    /// Membership sets referenced by `SetContains`
+    pub sets: Arc<SetTable>,
+
+    /// Literals referenced by `LoadConst`, `EqConst` and `NeConst`
+    pub pool: Arc<ConstantPool>,
+
+    /// Fields read by the conditions `MemoCondition` refers to
+    pub memo: Arc<MemoTable>,
+
+    /// Cached condition results; None unless the engine memoizes conditions
+    pub condition_cache: Option<ConditionCache>,
+
+    /// Profile fields by symbol, looked up in `profile` on first use
+    profile_slots: Vec<FieldSlot>,
+
+    /// Transaction fields by symbol, looked up in `transaction` on first use
+    txn_slots: Vec<FieldSlot>,
+}
+
+/// A profile/transaction field as seen by the VM during one execution
+///
+/// Each field is hashed into its map at most once per execution; after
+/// that the VM reads and writes the slot by index. Written slots are copied
+/// back into the map by [`ExecutionContext::flush_fields`].
+#[derive(Debug, Clone, Default)]
+enum FieldSlot {
+    #[default]
+    Unread,
+    /// Looked up in the map; None if the field is absent
+    Read(Option<Value>),
+    /// Assigned during this execution
+    Written(Value),
+}
+
+impl FieldSlot {
+    /// The slot's value, looking it up in `fields` on first use
+    #[inline]
+    fn load<'s>(&'s mut self, fields: &HashMap<String, Value>, name: &str) -> Option<&'s Value> {
+        if let FieldSlot::Unread = self {
+            *self = FieldSlot::Read(fields.get(name).cloned());
+        }
+        match self {
+            FieldSlot::Read(value) => value.as_ref(),
+            FieldSlot::Written(value) => Some(value),
+            FieldSlot::Unread => None,
+        }
+    }
+}
+
+/// What the VM observed while running one rule with tracing enabled
+#[derive(Debug, Clone, Default)]
+pub struct RuleTrace {
+    /// (condition index, result) for each traced condition evaluated
+    pub conditions: Vec<(usize, bool)>,
+
+    /// Profile/transaction fields read, as `profile.x` / `txn.x`, with the
+    /// value seen on first read
+    pub fields_read: Vec<(String, Value)>,
+}
+
+impl RuleTrace {
+    /// Record a field read, keeping only the first read of each field
+    pub fn record_field(&mut self, name: String, value: &Value) {
+        if !self.fields_read.iter().any(|(n, _)| *n == name) {
+            self.fields_read.push((name, value.clone()));
+        }
+    }
+}
+
+/// Results of memoizable top-level conditions, keyed by memo table entry
+/// and the values of the fields the condition reads
+///
+/// Kept by [`ExecutionContext::reset`], so results carry over between the
+/// inputs of a batch and between executions reusing one context. Results
+/// belong to the engine that computed them and are dropped when the
+/// context runs another one.
+#[derive(Debug, Default)]
+pub struct ConditionCache {
+    /// Engine generation the results were computed by
+    generation: u64,
+    results: HashMap<(usize, Vec<HashKey>), bool>,
+    /// Key of the condition being evaluated after a miss, and the error
+    /// count before it
+    pending: Option<((usize, Vec<HashKey>), usize)>,
+}
+
+impl ConditionCache {
+    /// Drop results computed by an engine of another generation
+    pub fn bind(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.results.clear();
+            self.generation = generation;
+        }
+        self.pending = None;
+    }
+
+    /// Cached result of memo entry `entry` for `key`; on a miss, the next
+    /// [`ConditionCache::finish`] stores the result
+    pub fn lookup(&mut self, entry: usize, key: Vec<HashKey>, errors: usize) -> Option<bool> {
+        let key = (entry, key);
+        match self.results.get(&key) {
+            Some(&result) => Some(result),
+            None => {
+                self.pending = Some((key, errors));
+                None
+            }
+        }
+    }
+
+    /// Store the result of the condition that missed, unless evaluating it
+    /// recorded an error, which a cached result would not repeat
+    pub fn finish(&mut self, result: bool, errors: usize) {
+        let Some((key, errors_before)) = self.pending.take() else {
+            return;
+        };
+        if errors == errors_before {
+            if self.results.len() >= MAX_CACHED_CONDITIONS {
+                self.results.clear();
+            }
+            self.results.insert(key, result);
+        }
+    }
+
+    /// Forget a condition that missed and never finished, e.g. because its
+    /// rule was aborted
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
 }
 
 impl ExecutionContext {
@@ -34,69 +256,257 @@ impl ExecutionContext {
             transaction,
             profile,
             actions: Vec::new(),
-            metadata: ExecutionMetadata {
-                executed_rules: Vec::new(),
-                skipped_rules: Vec::new(),
-                rule_timings: HashMap::default(),
-                total_duration: std::time::Duration::ZERO,
-                short_circuited: false,
-            },
+            metadata: ExecutionMetadata::default(),
             should_return: false,
+            aborted: false,
+            rule_fired: false,
             stack: Vec::with_capacity(128), // Pre-allocate for performance
+            stack_base: 0,
             local_vars: HashMap::default(),
+            scopes: Vec::new(),
+            shadowed: Vec::new(),
+            errors: Vec::new(),
+            pc: 0,
+            error_pcs: Vec::new(),
+            max_loop_iterations: DEFAULT_MAX_LOOP_ITERATIONS,
+            strict_fields: false,
+            numeric_overflow: OverflowPolicy::Wrap,
+            null_semantics: NullSemantics::Rust,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            instruction_budget: u64::MAX,
+            instructions_executed: 0,
+            record_profile_changes: false,
+            trace: None,
+            symbols: Arc::default(),
+            regexes: Arc::default(),
+            sets: Arc::default(),
+            pool: Arc::default(),
+            memo: Arc::default(),
+            condition_cache: None,
+            profile_slots: Vec::new(),
+            txn_slots: Vec::new(),
+        }
+    }
+
+    /// Prepare the context for a new execution, keeping its allocations
+    ///
+    /// Execution limits (loop, call depth, budget), `strict_fields`,
+    /// `numeric_overflow`, `null_semantics` and
+    /// `record_profile_changes` are settings rather than state, so they are
+    /// left as they are. The condition cache is kept so its results can be
+    /// reused.
+    pub fn reset(&mut self, transaction: Transaction, profile: UserProfile) {
+        self.transaction = transaction;
+        self.profile = profile;
+        self.actions.clear();
+        self.metadata = ExecutionMetadata::default();
+        self.should_return = false;
+        self.aborted = false;
+        self.rule_fired = false;
+        self.stack.clear();
+        self.stack_base = 0;
+        self.clear_locals();
+        self.errors.clear();
+        self.pc = 0;
+        self.error_pcs.clear();
+        self.call_depth = 0;
+        self.instructions_executed = 0;
+        self.trace = None;
+        self.profile_slots.clear();
+        self.txn_slots.clear();
+    }
+
+    /// Prepare field slots for bytecode compiled against `symbols`
+    pub fn bind_symbols(&mut self, symbols: Arc<SymbolTable>) {
+        self.profile_slots.clear();
+        self.profile_slots.resize(symbols.len(), FieldSlot::Unread);
+        self.txn_slots.clear();
+        self.txn_slots.resize(symbols.len(), FieldSlot::Unread);
+        self.symbols = symbols;
+    }
+
+    /// Copy fields written by the VM back into the profile and transaction
+    ///
+    /// Clears the slots, so call [`ExecutionContext::bind_symbols`] again
+    /// before executing more bytecode. Their allocations are kept for reuse.
+    pub fn flush_fields(&mut self) {
+        let mut slots = std::mem::take(&mut self.profile_slots);
+        // Slots are sized to the symbol table, so every id has a name
+        for (id, slot) in slots.drain(..).enumerate() {
+            if let (FieldSlot::Written(value), Some(name)) = (slot, self.symbols.name(id as SymbolId)) {
+                let name = name.to_string();
+                self.record_profile_change(&name, &value);
+                self.profile.fields.insert(name, value);
+            }
+        }
+        self.profile_slots = slots;
+        for (id, slot) in self.txn_slots.drain(..).enumerate() {
+            if let (FieldSlot::Written(value), Some(name)) = (slot, self.symbols.name(id as SymbolId)) {
+                self.transaction.fields.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    /// Load an interned profile field; None if the profile lacks it
+    #[inline]
+    pub fn load_profile_field(&mut self, id: SymbolId) -> Option<&Value> {
+        if id as usize >= self.profile_slots.len() {
+            self.add_error(Self::unknown_symbol(id));
+            return None;
+        }
+        // Slots are sized to the symbol table, so the id has a name
+        let name = self.symbols.name(id)?;
+        self.profile_slots.get_mut(id as usize)?.load(&self.profile.fields, name)
+    }
+
+    /// Store an interned profile field
+    #[inline]
+    pub fn store_profile_field(&mut self, id: SymbolId, value: Value) {
+        match self.profile_slots.get_mut(id as usize) {
+            Some(slot) => *slot = FieldSlot::Written(value),
+            None => self.add_error(Self::unknown_symbol(id)),
+        }
+    }
+
+    /// Load an interned transaction field; None if the transaction lacks it
+    #[inline]
+    pub fn load_txn_field(&mut self, id: SymbolId) -> Option<&Value> {
+        if id as usize >= self.txn_slots.len() {
+            self.add_error(Self::unknown_symbol(id));
+            return None;
+        }
+        // Slots are sized to the symbol table, so the id has a name
+        let name = self.symbols.name(id)?;
+        self.txn_slots.get_mut(id as usize)?.load(&self.transaction.fields, name)
+    }
+
+    /// Store an interned transaction field
+    #[inline]
+    pub fn store_txn_field(&mut self, id: SymbolId, value: Value) {
+        match self.txn_slots.get_mut(id as usize) {
+            Some(slot) => *slot = FieldSlot::Written(value),
+            None => self.add_error(Self::unknown_symbol(id)),
         }
     }
 
+    /// Error for a field id past the symbol table, as only corrupt bytecode
+    /// has
+    fn unknown_symbol(id: SymbolId) -> ExecutionError {
+        ExecutionError::RuntimeError(format!("unknown field symbol {}", id))
+    }
+
+    /// Cache key for memo table entry `entry`: the current values of the
+    /// fields it reads, absent ones as Null; None if one is an array or
+    /// object, which cannot be hashed
+    pub fn memo_key(&mut self, entry: usize) -> Option<Vec<HashKey>> {
+        let reads = self.memo.reads(entry)?;
+        let mut key = Vec::with_capacity(reads.len());
+        for read in reads {
+            // An unknown id is not cached; loading it records the error
+            let value = match *read {
+                FieldRead::Profile(id) => {
+                    self.profile_slots.get_mut(id as usize)?.load(&self.profile.fields, self.symbols.name(id)?)
+                }
+                FieldRead::Txn(id) => {
+                    self.txn_slots.get_mut(id as usize)?.load(&self.transaction.fields, self.symbols.name(id)?)
+                }
+            };
+            key.push(HashKey::new(value.cloned().unwrap_or(Value::Null))?);
+        }
+        Some(key)
+    }
+
     /// Push value onto stack
     #[inline]
     pub fn push(&mut self, value: Value) {
         self.stack.push(value);
     }
 
-    /// Pop value from stack
+    /// Pop value from stack; None at the bottom of the current frame
     #[inline]
     pub fn pop(&mut self) -> Option<Value> {
-        self.stack.pop()
+        if self.stack.len() > self.stack_base {
+            self.stack.pop()
+        } else {
+            None
+        }
     }
 
-    /// Peek at top of stack without removing
+    /// Peek at top of the current frame without removing
     #[inline]
     pub fn peek(&self) -> Option<&Value> {
-        self.stack.last()
+        self.stack[self.stack_base..].last()
     }
 
-    /// Get profile field value
-    #[inline]
+    /// Get profile field value by name, including unflushed VM writes
     pub fn get_profile_field(&self, field: &str) -> Value {
-        self.profile
-            .fields
-            .get(field)
-            .cloned()
-            .unwrap_or(Value::Null)
+        Self::get_field(&self.symbols, &self.profile_slots, &self.profile.fields, field)
     }
 
-    /// Set profile field value
-    #[inline]
+    /// Set profile field value by name
     pub fn set_profile_field(&mut self, field: String, value: Value) {
+        Self::forget_slot(&self.symbols, &mut self.profile_slots, &field);
+        self.record_profile_change(&field, &value);
         self.profile.fields.insert(field, value);
     }
 
-    /// Get transaction field value
-    #[inline]
+    /// Record a profile write as a change, if changes are being recorded
+    ///
+    /// Must be called before the profile map is updated. A field written
+    /// more than once keeps its original old value, and a field written back
+    /// to its original value is dropped.
+    fn record_profile_change(&mut self, field: &str, new: &Value) {
+        if !self.record_profile_changes {
+            return;
+        }
+
+        let changes = &mut self.metadata.profile_changes;
+        let index = changes.iter().position(|c| c.field == field);
+        let old = match index {
+            Some(i) => changes.remove(i).old,
+            None => self.profile.fields.get(field).cloned().unwrap_or(Value::Null),
+        };
+        if old != *new {
+            let change = FieldChange { field: field.to_string(), old, new: new.clone() };
+            match index {
+                Some(i) => changes.insert(i, change),
+                None => changes.push(change),
+            }
+        }
+    }
+
+    /// Get transaction field value by name, including unflushed VM writes
     pub fn get_txn_field(&self, field: &str) -> Value {
-        self.transaction
-            .fields
-            .get(field)
-            .cloned()
-            .unwrap_or(Value::Null)
+        Self::get_field(&self.symbols, &self.txn_slots, &self.transaction.fields, field)
     }
 
-    /// Set transaction field value
-    #[inline]
+    /// Set transaction field value by name
     pub fn set_txn_field(&mut self, field: String, value: Value) {
+        Self::forget_slot(&self.symbols, &mut self.txn_slots, &field);
         self.transaction.fields.insert(field, value);
     }
 
+    fn get_field(
+        symbols: &SymbolTable,
+        slots: &[FieldSlot],
+        fields: &HashMap<String, Value>,
+        field: &str,
+    ) -> Value {
+        let slot = symbols.get(field).and_then(|id| slots.get(id as usize));
+        match slot {
+            Some(FieldSlot::Written(value)) => value.clone(),
+            _ => fields.get(field).cloned().unwrap_or(Value::Null),
+        }
+    }
+
+    /// Drop any cached or written slot so the next load sees the map
+    fn forget_slot(symbols: &SymbolTable, slots: &mut [FieldSlot], field: &str) {
+        if let Some(slot) = symbols.get(field).and_then(|id| slots.get_mut(id as usize)) {
+            *slot = FieldSlot::Unread;
+        }
+    }
+
     /// Get local variable
     #[inline]
     pub fn get_local(&self, name: &str) -> Value {
@@ -109,11 +519,74 @@ impl ExecutionContext {
         self.local_vars.insert(name, value);
     }
 
+    /// Remove every local variable and open block scope
+    pub fn clear_locals(&mut self) {
+        self.local_vars.clear();
+        self.scopes.clear();
+        self.shadowed.clear();
+    }
+
+    /// Open a block scope for locals declared with `declare_local`
+    #[inline]
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(self.shadowed.len());
+    }
+
+    /// Declare a local in the innermost block scope, shadowing any local of
+    /// the same name until the scope exits
+    ///
+    /// Outside any block scope this is the same as `set_local`.
+    pub fn declare_local(&mut self, name: String, value: Value) {
+        if self.scopes.is_empty() {
+            self.set_local(name, value);
+            return;
+        }
+        let previous = self.local_vars.insert(name.clone(), value);
+        self.shadowed.push((name, previous));
+    }
+
+    /// Close the innermost block scope, dropping the locals declared in it
+    /// and restoring the ones they shadowed
+    pub fn exit_scope(&mut self) {
+        let Some(start) = self.scopes.pop() else {
+            return;
+        };
+        // In reverse, so a name declared twice ends up with its value from
+        // before the scope
+        for (name, previous) in self.shadowed.drain(start..).rev() {
+            match previous {
+                Some(value) => self.local_vars.insert(name, value),
+                None => self.local_vars.remove(&name),
+            };
+        }
+    }
+
+    /// Number of open block scopes
+    #[inline]
+    pub fn scope_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Close block scopes until only `depth` remain, as when a function
+    /// returns from inside a block
+    pub fn exit_scopes_to(&mut self, depth: usize) {
+        while self.scopes.len() > depth {
+            self.exit_scope();
+        }
+    }
+
     /// Add an action to be executed
     #[inline]
     pub fn add_action(&mut self, action: Action) {
         self.actions.push(action);
     }
+
+    /// Record a runtime error without aborting execution
+    #[inline]
+    pub fn add_error(&mut self, error: ExecutionError) {
+        self.errors.push(error);
+        self.error_pcs.push(self.pc);
+    }
 }
 
 #[cfg(test)]
@@ -132,6 +605,57 @@ mod tests {
         assert_eq!(ctx.pop(), None);
     }
 
+    #[test]
+    fn test_reset_clears_execution_state() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        ctx.push(Value::Int(1));
+        ctx.set_local("x".to_string(), Value::Int(2));
+        ctx.add_action(Action::set_fraud_score(0.5));
+        ctx.should_return = true;
+        ctx.max_call_depth = 8;
+
+        ctx.reset(
+            Transaction::new().with_field("amount", Value::Int(10)),
+            UserProfile::new(),
+        );
+
+        assert!(ctx.stack.is_empty());
+        assert_eq!(ctx.get_local("x"), Value::Null);
+        assert!(ctx.actions.is_empty());
+        assert!(!ctx.should_return);
+        assert_eq!(ctx.get_txn_field("amount"), Value::Int(10));
+        assert_eq!(ctx.max_call_depth, 8);
+    }
+
+    #[test]
+    fn test_block_scopes_restore_shadowed_locals() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        ctx.declare_local("x".to_string(), Value::Int(1));
+
+        ctx.enter_scope();
+        ctx.declare_local("x".to_string(), Value::Int(2));
+        ctx.declare_local("y".to_string(), Value::Int(3));
+        ctx.declare_local("x".to_string(), Value::Int(4));
+        // Plain stores reach the existing local without scoping it
+        ctx.set_local("z".to_string(), Value::Int(5));
+        ctx.enter_scope();
+        ctx.declare_local("y".to_string(), Value::Int(6));
+        assert_eq!(ctx.get_local("x"), Value::Int(4));
+        assert_eq!(ctx.get_local("y"), Value::Int(6));
+
+        ctx.exit_scope();
+        assert_eq!(ctx.get_local("y"), Value::Int(3));
+        ctx.exit_scopes_to(0);
+        assert_eq!(ctx.scope_depth(), 0);
+        assert_eq!(ctx.get_local("x"), Value::Int(1));
+        assert_eq!(ctx.get_local("y"), Value::Null);
+        assert_eq!(ctx.get_local("z"), Value::Int(5));
+
+        // Exiting with no open scope does nothing
+        ctx.exit_scope();
+        assert_eq!(ctx.get_local("x"), Value::Int(1));
+    }
+
     #[test]
     fn test_field_access() {
         let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
@@ -140,4 +664,102 @@ mod tests {
         assert_eq!(ctx.get_profile_field("count"), Value::Int(5));
         assert_eq!(ctx.get_profile_field("missing"), Value::Null);
     }
+
+    #[test]
+    fn test_records_profile_changes() {
+        let mut symbols = SymbolTable::default();
+        let count = symbols.intern("count");
+        let tier = symbols.intern("tier");
+        let flag = symbols.intern("flag");
+
+        let mut ctx = ExecutionContext::new(
+            Transaction::new(),
+            UserProfile::new()
+                .with_field("count", Value::Int(5))
+                .with_field("flag", Value::Bool(true)),
+        );
+        ctx.record_profile_changes = true;
+        ctx.bind_symbols(Arc::new(symbols));
+
+        ctx.store_profile_field(count, Value::Int(6));
+        ctx.store_profile_field(tier, Value::from("GOLD"));
+        ctx.store_profile_field(flag, Value::Bool(true));
+        ctx.flush_fields();
+
+        // A later write keeps the original old value; writing the original
+        // value back drops the change
+        ctx.set_profile_field("count".to_string(), Value::Int(7));
+        ctx.set_profile_field("tier".to_string(), Value::Null);
+
+        assert_eq!(
+            ctx.metadata.profile_changes,
+            vec![FieldChange {
+                field: "count".to_string(),
+                old: Value::Int(5),
+                new: Value::Int(7),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_interned_fields_flush_to_maps() {
+        let mut symbols = SymbolTable::default();
+        let count = symbols.intern("count");
+        let missing = symbols.intern("missing");
+
+        let mut ctx = ExecutionContext::new(
+            Transaction::new(),
+            UserProfile::new().with_field("count", Value::Int(5)),
+        );
+        ctx.bind_symbols(Arc::new(symbols));
+
+        assert_eq!(ctx.load_profile_field(count), Some(&Value::Int(5)));
+        assert_eq!(ctx.load_profile_field(missing), None);
+
+        ctx.store_profile_field(count, Value::Int(6));
+        ctx.store_txn_field(missing, Value::Bool(true));
+        assert_eq!(ctx.load_profile_field(count), Some(&Value::Int(6)));
+        assert_eq!(ctx.get_profile_field("count"), Value::Int(6));
+        assert_eq!(ctx.profile.fields.get("count"), Some(&Value::Int(5)));
+
+        ctx.flush_fields();
+        assert_eq!(ctx.profile.fields.get("count"), Some(&Value::Int(6)));
+        assert_eq!(ctx.transaction.fields.get("missing"), Some(&Value::Bool(true)));
+        assert!(!ctx.profile.fields.contains_key("missing"));
+    }
+
+    #[test]
+    fn test_unknown_field_symbol_records_error() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        ctx.bind_symbols(Arc::new(SymbolTable::default()));
+
+        assert_eq!(ctx.load_profile_field(3), None);
+        ctx.store_txn_field(3, Value::Int(1));
+        assert_eq!(ctx.load_txn_field(3), None);
+
+        let error = ExecutionError::RuntimeError("unknown field symbol 3".to_string());
+        assert_eq!(ctx.errors, vec![error.clone(), error.clone(), error]);
+    }
+
+    #[test]
+    fn test_condition_cache() {
+        let key = || vec![HashKey::new(Value::from("KP")).unwrap()];
+        let mut cache = ConditionCache::default();
+        cache.bind(1);
+
+        assert_eq!(cache.lookup(0, key(), 0), None);
+        cache.finish(true, 0);
+        assert_eq!(cache.lookup(0, key(), 0), Some(true));
+
+        // A result reached with a new error is not stored
+        assert_eq!(cache.lookup(1, key(), 0), None);
+        cache.finish(false, 1);
+        assert_eq!(cache.len(), 1);
+
+        // Results of another engine are dropped
+        cache.bind(1);
+        assert_eq!(cache.len(), 1);
+        cache.bind(2);
+        assert!(cache.is_empty());
+    }
 }
@@ -1,10 +1,11 @@
 // src/runtime/mod.rs
 //! Runtime components for executing bytecode
 
+pub mod builtins;
 pub mod context;
 pub mod value;
 pub mod vm;
 
 pub use context::ExecutionContext;
-pub use value::Value;
-pub use vm::VM;
+pub use value::{HashKey, Value, ValueType};
+pub use vm::{HostFn, HostFunctions, Tracer, VM};
@@ -2,8 +2,13 @@
 //! Dynamic value type supporting common data types used in fraud rules
 
 use ahash::HashMap;
+use chrono::{DateTime, SecondsFormat};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 /// Dynamic value type
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -12,9 +17,61 @@ pub enum Value {
     Bool(bool),
     Int(i64),
     Float(f64),
+    /// Fixed-point decimal for money amounts that must sum exactly
+    Decimal(Decimal),
+    /// Point in time as milliseconds since the Unix epoch (UTC)
+    DateTime(i64),
     String(String),
     Array(Vec<Value>),
+    /// Equality ignores key order; `Display` prints keys sorted
     Object(HashMap<String, Value>),
+    /// Raw bytes, e.g. a device fingerprint; base64 in JSON
+    Bytes(#[serde(with = "bytes_base64")] Vec<u8>),
+}
+
+/// The kind of a [`Value`], without its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Null,
+    Bool,
+    Int,
+    Float,
+    Decimal,
+    DateTime,
+    String,
+    Array,
+    Object,
+    Bytes,
+}
+
+impl ValueType {
+    /// Whether values of the two types can be compared with `==` and `<`
+    /// meaningfully: the same type, or any two numeric types
+    pub fn is_comparable_with(self, other: ValueType) -> bool {
+        self == other || (self.is_numeric() && other.is_numeric())
+    }
+
+    pub fn is_numeric(self) -> bool {
+        matches!(self, ValueType::Int | ValueType::Float | ValueType::Decimal)
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ValueType::Null => "null",
+            ValueType::Bool => "bool",
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::Decimal => "decimal",
+            ValueType::DateTime => "datetime",
+            ValueType::String => "string",
+            ValueType::Array => "array",
+            ValueType::Object => "object",
+            ValueType::Bytes => "bytes",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 impl Value {
@@ -25,9 +82,12 @@ impl Value {
             Value::Bool(b) => *b,
             Value::Int(n) => *n != 0,
             Value::Float(f) => *f != 0.0,
+            Value::Decimal(d) => !d.is_zero(),
+            Value::DateTime(_) => true,
             Value::String(s) => !s.is_empty(),
             Value::Array(a) => !a.is_empty(),
             Value::Object(o) => !o.is_empty(),
+            Value::Bytes(b) => !b.is_empty(),
         }
     }
     
@@ -36,7 +96,10 @@ impl Value {
         match self {
             Value::Int(n) => *n,
             Value::Float(f) => *f as i64,
-            Value::Bool(b) => if *b { 1 } else { 0 },
+            Value::Decimal(d) => d.trunc().to_i64().unwrap_or(0),
+            Value::DateTime(ms) => *ms,
+            Value::Bool(true) => 1,
+            Value::Bool(false) => 0,
             Value::String(s) => s.parse().unwrap_or(0),
             _ => 0,
         }
@@ -47,12 +110,26 @@ impl Value {
         match self {
             Value::Float(f) => *f,
             Value::Int(n) => *n as f64,
-            Value::Bool(b) => if *b { 1.0 } else { 0.0 },
+            Value::Decimal(d) => d.to_f64().unwrap_or(0.0),
+            Value::DateTime(ms) => *ms as f64,
+            Value::Bool(true) => 1.0,
+            Value::Bool(false) => 0.0,
             Value::String(s) => s.parse().unwrap_or(0.0),
             _ => 0.0,
         }
     }
     
+    /// Convert a numeric value to a decimal; floats that cannot be
+    /// represented (NaN, infinities) yield None
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Decimal(d) => Some(*d),
+            Value::Int(n) => Some(Decimal::from(*n)),
+            Value::Float(f) => Decimal::from_f64(*f),
+            _ => None,
+        }
+    }
+    
     /// Convert value to string
     pub fn as_string(&self) -> String {
         match self {
@@ -60,9 +137,12 @@ impl Value {
             Value::Bool(b) => b.to_string(),
             Value::Int(n) => n.to_string(),
             Value::Float(f) => f.to_string(),
+            Value::Decimal(d) => d.to_string(),
+            Value::DateTime(ms) => format_millis(*ms),
             Value::String(s) => s.clone(),
             Value::Array(_) => "[Array]".to_string(),
             Value::Object(_) => "[Object]".to_string(),
+            Value::Bytes(b) => to_hex(b),
         }
     }
     
@@ -89,7 +169,128 @@ impl Value {
     
     /// Check if value is numeric
     pub fn is_numeric(&self) -> bool {
-        matches!(self, Value::Int(_) | Value::Float(_))
+        matches!(self, Value::Int(_) | Value::Float(_) | Value::Decimal(_))
+    }
+    
+    /// Get the kind of this value
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Null => ValueType::Null,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Int(_) => ValueType::Int,
+            Value::Float(_) => ValueType::Float,
+            Value::Decimal(_) => ValueType::Decimal,
+            Value::DateTime(_) => ValueType::DateTime,
+            Value::String(_) => ValueType::String,
+            Value::Array(_) => ValueType::Array,
+            Value::Object(_) => ValueType::Object,
+            Value::Bytes(_) => ValueType::Bytes,
+        }
+    }
+    
+    /// Order two values the way the DSL's `<`, `<=`, `>` and `>=` do
+    ///
+    /// Returns None when either side is Null or the types cannot be ordered
+    /// against each other (e.g. a string and a number), so every ordering
+    /// comparison involving a missing field is false - both `x > 5` and
+    /// `x <= 5` are false when `x` is Null. Numbers compare by value across
+    /// Int, Float and Decimal, so `Int(2)` and `Float(2.0)` are Equal here;
+    /// strings, dates and bools compare with their own kind.
+    #[inline]
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                Some(self.as_decimal()?.cmp(&other.as_decimal()?))
+            }
+            (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+            (Value::Float(x), Value::Float(y)) => x.partial_cmp(y),
+            (Value::Int(x), Value::Float(y)) => (*x as f64).partial_cmp(y),
+            (Value::Float(x), Value::Int(y)) => x.partial_cmp(&(*y as f64)),
+            (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+            (Value::DateTime(x), Value::DateTime(y)) => Some(x.cmp(y)),
+            (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+            _ => None,
+        }
+    }
+}
+
+/// Ordering consistent with the derived `PartialEq`: values that are `==`
+/// are Equal, and otherwise values are ordered as by [`Value::compare`].
+/// Where `compare` finds two unequal values Equal, e.g. `Int(2)` and
+/// `Float(2.0)`, they are unordered (None) rather than Equal.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            return Some(Ordering::Equal);
+        }
+        self.compare(other).filter(|ordering| ordering.is_ne())
+    }
+}
+
+/// A scalar [`Value`] usable as a hash map or set key
+///
+/// `Value` itself is not `Eq`, since a Float NaN is unequal to itself.
+/// A `HashKey` compares like `==` on `Value` (so `Int(1)` and `Float(1.0)`
+/// are different keys, and `-0.0` is the same key as `0.0`), except that
+/// every NaN is the same key. Floats hash by bit pattern after that
+/// normalization. Arrays and objects cannot be keys.
+#[derive(Debug, Clone)]
+pub struct HashKey(Value);
+
+impl HashKey {
+    /// The key for a value; None for an array or object
+    pub fn new(value: Value) -> Option<Self> {
+        match value {
+            Value::Array(_) | Value::Object(_) => None,
+            value => Some(Self(value)),
+        }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+
+    pub fn into_value(self) -> Value {
+        self.0
+    }
+
+    /// Bits of a float key: one pattern for every NaN, and `-0.0` as `0.0`
+    fn float_bits(f: f64) -> u64 {
+        if f.is_nan() {
+            f64::NAN.to_bits()
+        } else if f == 0.0 {
+            0.0f64.to_bits()
+        } else {
+            f.to_bits()
+        }
+    }
+}
+
+impl PartialEq for HashKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Float(a), Value::Float(b)) => Self::float_bits(*a) == Self::float_bits(*b),
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for HashKey {}
+
+impl Hash for HashKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.0).hash(state);
+        match &self.0 {
+            Value::Null => {}
+            Value::Bool(b) => b.hash(state),
+            Value::Int(n) | Value::DateTime(n) => n.hash(state),
+            Value::Float(f) => Self::float_bits(*f).hash(state),
+            // Decimals hash normalized, so `1.0` and `1.00` agree like `==`
+            Value::Decimal(d) => d.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::Array(_) | Value::Object(_) => unreachable!("arrays and objects are not hash keys"),
+        }
     }
 }
 
@@ -100,6 +301,8 @@ impl fmt::Display for Value {
             Value::Bool(b) => write!(f, "{}", b),
             Value::Int(n) => write!(f, "{}", n),
             Value::Float(n) => write!(f, "{}", n),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::DateTime(ms) => write!(f, "{}", format_millis(*ms)),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::Array(arr) => {
                 write!(f, "[")?;
@@ -112,8 +315,12 @@ impl fmt::Display for Value {
                 write!(f, "]")
             }
             Value::Object(obj) => {
+                // Sorted so the same object always prints the same way
+                let mut entries: Vec<_> = obj.iter().collect();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
                 write!(f, "{{")?;
-                for (i, (k, v)) in obj.iter().enumerate() {
+                for (i, (k, v)) in entries.into_iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
@@ -121,10 +328,55 @@ impl fmt::Display for Value {
                 }
                 write!(f, "}}")
             }
+            Value::Bytes(b) => write!(f, "0x{}", to_hex(b)),
         }
     }
 }
 
+/// Lowercase hex rendering of bytes
+pub fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+/// Serde for `Value::Bytes`: base64 in human-readable formats (JSON), plain
+/// bytes otherwise (bincode)
+mod bytes_base64 {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            STANDARD.decode(encoded).map_err(de::Error::custom)
+        } else {
+            Vec::deserialize(deserializer)
+        }
+    }
+}
+
+/// RFC 3339 rendering of a millisecond timestamp, falling back to the raw
+/// number when it is out of chrono's range
+fn format_millis(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_else(|| ms.to_string())
+}
+
 // Convenient conversions
 impl From<bool> for Value {
     fn from(b: bool) -> Self {
@@ -150,6 +402,12 @@ impl From<f64> for Value {
     }
 }
 
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
 impl From<String> for Value {
     fn from(s: String) -> Self {
         Value::String(s)
@@ -177,6 +435,29 @@ impl<T: Into<Value>> From<Option<T>> for Value {
     }
 }
 
+/// Plain JSON (not the tagged form `Value` serializes to). Numbers become
+/// Int when they fit in an i64 and Float otherwise; objects and arrays keep
+/// their nesting.
+impl From<serde_json::Value> for Value {
+    fn from(json: serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => n.as_f64().map_or(Value::Null, Value::Float),
+            },
+            serde_json::Value::String(s) => Value::String(s),
+            serde_json::Value::Array(items) => {
+                Value::Array(items.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(map) => Value::Object(
+                map.into_iter().map(|(k, v)| (k, Value::from(v))).collect(),
+            ),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,6 +480,146 @@ mod tests {
         assert_eq!(Value::Float(42.5).as_float(), 42.5);
     }
 
+    #[test]
+    fn test_decimal_conversion() {
+        let d = Value::Decimal(Decimal::new(-4275, 2));
+        
+        assert_eq!(d.as_int(), -42);
+        assert_eq!(d.as_float(), -42.75);
+        assert_eq!(d.as_string(), "-42.75");
+        assert!(d.as_bool());
+        assert!(d.is_numeric());
+        assert!(!Value::Decimal(Decimal::ZERO).as_bool());
+    }
+
+    #[test]
+    fn test_datetime_conversion() {
+        let dt = Value::DateTime(1_704_067_200_000);
+        
+        assert_eq!(dt.as_int(), 1_704_067_200_000);
+        assert_eq!(dt.as_string(), "2024-01-01T00:00:00.000Z");
+        
+        let json = serde_json::to_string(&dt).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), dt);
+        let bytes = bincode::serialize(&dt).unwrap();
+        assert_eq!(bincode::deserialize::<Value>(&bytes).unwrap(), dt);
+    }
+
+    #[test]
+    fn test_bytes_serde() {
+        let bytes = Value::Bytes(vec![0xde, 0xad, 0xbe, 0xef]);
+
+        let json = serde_json::to_string(&bytes).unwrap();
+        assert_eq!(json, r#"{"Bytes":"3q2+7w=="}"#);
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), bytes);
+
+        let encoded = bincode::serialize(&bytes).unwrap();
+        assert_eq!(bincode::deserialize::<Value>(&encoded).unwrap(), bytes);
+
+        assert_eq!(bytes.to_string(), "0xdeadbeef");
+        assert_ne!(bytes, Value::Bytes(vec![0xde, 0xad]));
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(Value::Int(1) < Value::Int(2));
+        assert!(Value::Float(2.5) > Value::Float(-1.0));
+        assert!(Value::Int(2) < Value::Float(2.5));
+        assert!(Value::Decimal(Decimal::new(15, 1)) > Value::Int(1));
+        assert!(Value::from("apple") < Value::from("banana"));
+        assert!(Value::DateTime(1_000) < Value::DateTime(2_000));
+        
+        // Equal values order Equal, even those `compare` can't order
+        assert_eq!(Value::Null.partial_cmp(&Value::Null), Some(Ordering::Equal));
+        let array = Value::from(vec![1, 2]);
+        assert_eq!(array.partial_cmp(&array.clone()), Some(Ordering::Equal));
+        
+        // Mixed kinds and Null are unordered
+        assert_eq!(Value::from("1").partial_cmp(&Value::Int(1)), None);
+        assert_eq!(Value::Null.partial_cmp(&Value::Int(0)), None);
+        assert_eq!(Value::Int(0).partial_cmp(&Value::Null), None);
+        assert_eq!(Value::Float(f64::NAN).partial_cmp(&Value::Float(f64::NAN)), None);
+        
+        // `compare` treats numbers by value; PartialOrd keeps to PartialEq
+        assert_eq!(Value::Int(2).compare(&Value::Float(2.0)), Some(Ordering::Equal));
+        assert_ne!(Value::Int(2), Value::Float(2.0));
+        assert_eq!(Value::Int(2).partial_cmp(&Value::Float(2.0)), None);
+        assert_eq!(Value::Null.compare(&Value::Null), None);
+    }
+
+    #[test]
+    fn test_object_equality_ignores_key_order() {
+        let forward: HashMap<_, _> = [("a", 1), ("b", 2), ("c", 3)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), Value::Int(v)))
+            .collect();
+        let backward: HashMap<_, _> = [("c", 3), ("b", 2), ("a", 1)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), Value::Int(v)))
+            .collect();
+        
+        let nested = |inner: &HashMap<String, Value>| {
+            let mut outer = HashMap::default();
+            outer.insert("inner".to_string(), Value::Object(inner.clone()));
+            outer.insert("id".to_string(), Value::from("x"));
+            Value::Object(outer)
+        };
+        assert_eq!(Value::Object(forward.clone()), Value::Object(backward.clone()));
+        assert_eq!(nested(&forward), nested(&backward));
+        
+        let mut other = backward;
+        other.insert("c".to_string(), Value::Int(4));
+        assert_ne!(Value::Object(forward), Value::Object(other));
+    }
+
+    #[test]
+    fn test_object_display_sorts_keys() {
+        let mut inner = HashMap::default();
+        inner.insert("z".to_string(), Value::Null);
+        inner.insert("m".to_string(), Value::Bool(true));
+        
+        let mut obj = HashMap::default();
+        obj.insert("zeta".to_string(), Value::Int(1));
+        obj.insert("alpha".to_string(), Value::from("a"));
+        obj.insert("mid".to_string(), Value::Object(inner));
+        obj.insert("beta".to_string(), Value::from(vec![2, 1]));
+        
+        assert_eq!(
+            Value::Object(obj).to_string(),
+            r#"{"alpha": "a", "beta": [2, 1], "mid": {"m": true, "z": null}, "zeta": 1}"#
+        );
+        assert_eq!(Value::Object(HashMap::default()).to_string(), "{}");
+    }
+
+    #[test]
+    fn test_hash_keys() {
+        use ahash::HashSet;
+        
+        let values = vec![Value::Int(42), Value::from("US"), Value::Int(-7), Value::from("")];
+        let set: HashSet<HashKey> = values.iter().cloned().filter_map(HashKey::new).collect();
+        
+        assert_eq!(set.len(), 4);
+        for value in &values {
+            assert!(set.contains(&HashKey::new(value.clone()).unwrap()), "{}", value);
+        }
+        let mut round_trip: Vec<Value> = set.into_iter().map(HashKey::into_value).collect();
+        round_trip.sort_by_key(|v| v.to_string());
+        assert_eq!(round_trip, vec![Value::from(""), Value::from("US"), Value::Int(-7), Value::Int(42)]);
+        
+        let key = |value: Value| HashKey::new(value).unwrap();
+        // Keys keep `==` between kinds, but every NaN is one key
+        assert_ne!(key(Value::Int(1)), key(Value::Float(1.0)));
+        assert_ne!(key(Value::from("1")), key(Value::Int(1)));
+        assert_eq!(key(Value::Float(-0.0)), key(Value::Float(0.0)));
+        assert_eq!(key(Value::Float(f64::NAN)), key(Value::Float(-f64::NAN)));
+        assert_eq!(key(Value::Decimal(Decimal::new(10, 1))), key(Value::Decimal(Decimal::new(100, 2))));
+        let floats: HashSet<HashKey> = [0.0, -0.0, f64::NAN, f64::NAN].into_iter().map(|f| key(Value::Float(f))).collect();
+        assert_eq!(floats.len(), 2);
+        
+        assert!(HashKey::new(Value::from(vec![1])).is_none());
+        assert!(HashKey::new(Value::Object(HashMap::default())).is_none());
+    }
+
     #[test]
     fn test_from_conversions() {
         assert_eq!(Value::from(true), Value::Bool(true));
@@ -206,4 +627,23 @@ mod tests {
         assert_eq!(Value::from(42.5), Value::Float(42.5));
         assert_eq!(Value::from("test"), Value::String("test".to_string()));
     }
+
+    #[test]
+    fn test_from_plain_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(r#"{"card": {"bin": "411111", "limits": [500, 2.5, null]}}"#)
+                .unwrap();
+
+        let Value::Object(root) = Value::from(json) else {
+            panic!("expected object");
+        };
+        let Some(Value::Object(card)) = root.get("card") else {
+            panic!("expected nested object");
+        };
+        assert_eq!(card.get("bin"), Some(&Value::from("411111")));
+        assert_eq!(
+            card.get("limits"),
+            Some(&Value::Array(vec![Value::Int(500), Value::Float(2.5), Value::Null]))
+        );
+    }
 }
@@ -3,10 +3,44 @@
 //!
 //! This is the HOT PATH - every nanosecond counts here!
 
-use crate::compiler::bytecode::{ActionType, Instruction};
+use crate::actions::{Decision, UnknownDecision};
+use crate::compiler::bytecode::{ActionType, BuiltinFn, Instruction};
+use crate::runtime::builtins;
 use crate::runtime::context::ExecutionContext;
-use crate::{Action, CompiledFunction, Value};
+use crate::{Action, CompiledFunction, ExecutionError, NullSemantics, OverflowPolicy, Value};
 use ahash::HashMap;
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// A function supplied by the host application and callable from rules
+///
+/// Host functions run inline on the hot path, so they must be fast and pure:
+/// no blocking I/O, no dependence on state that changes between calls, and
+/// no panics. Pre-load anything slow (e.g. a BIN table) into the closure.
+pub type HostFn = Arc<dyn Fn(&[Value]) -> Value + Send + Sync>;
+
+/// Host functions by name
+pub type HostFunctions = HashMap<String, HostFn>;
+
+/// Debugging hook called with each instruction and the operand stack (bottom
+/// first) before the instruction runs; see [`VM::execute_traced`]
+pub type Tracer<'a> = dyn FnMut(&Instruction, &[Value]) + 'a;
+
+/// Pop an operand, or record a stack underflow and abort the current rule
+/// rather than carrying on with a missing value
+macro_rules! pop {
+    ($ctx:expr) => {
+        match $ctx.pop() {
+            Some(value) => value,
+            None => {
+                $ctx.add_error(ExecutionError::StackUnderflow);
+                $ctx.aborted = true;
+                return;
+            }
+        }
+    };
+}
 
 pub struct VM;
 
@@ -14,51 +48,116 @@ impl VM {
     /// Execute bytecode in the given context
     ///
     /// This is the performance-critical path!
+    #[inline]
     pub fn execute(
         bytecode: &[Instruction],
         ctx: &mut ExecutionContext,
         functions: &HashMap<String, CompiledFunction>,
+        host_functions: &HostFunctions,
+    ) {
+        // The no-op tracer is monomorphized away
+        Self::run(bytecode, ctx, functions, host_functions, &mut |_, _| {});
+    }
+
+    /// Execute bytecode like [`VM::execute`], calling `tracer` with each
+    /// instruction and the operand stack (bottom first) before it runs
+    ///
+    /// Instructions of called global functions are traced too. For
+    /// debugging only; use `execute` on the hot path.
+    pub fn execute_traced(
+        bytecode: &[Instruction],
+        ctx: &mut ExecutionContext,
+        functions: &HashMap<String, CompiledFunction>,
+        host_functions: &HostFunctions,
+        tracer: Option<&mut Tracer<'_>>,
+    ) {
+        match tracer {
+            Some(tracer) => Self::run(bytecode, ctx, functions, host_functions, tracer),
+            None => Self::execute(bytecode, ctx, functions, host_functions),
+        }
+    }
+
+    fn run<F: FnMut(&Instruction, &[Value]) + ?Sized>(
+        bytecode: &[Instruction],
+        ctx: &mut ExecutionContext,
+        functions: &HashMap<String, CompiledFunction>,
+        host_functions: &HostFunctions,
+        tracer: &mut F,
     ) {
         let mut pc = 0; // Program counter
 
         while pc < bytecode.len() {
+            ctx.instructions_executed += 1;
+            if ctx.instructions_executed > ctx.instruction_budget {
+                ctx.metadata.budget_exceeded = true;
+                break;
+            }
+
+            if ctx.call_depth == 0 {
+                ctx.pc = pc;
+            }
+
             let instruction = &bytecode[pc];
+            tracer(instruction, &ctx.stack);
 
             match instruction {
                 Instruction::Push(value) => {
                     ctx.push(value.clone());
                 }
 
+                Instruction::LoadConst(index) => {
+                    let value = ctx.pool.get(*index).cloned().unwrap_or(Value::Null);
+                    ctx.push(value);
+                }
+
                 Instruction::Pop => {
-                    ctx.pop();
+                    pop!(ctx);
                 }
 
                 Instruction::Dup => {
-                    if let Some(value) = ctx.peek() {
-                        ctx.push(value.clone());
-                    }
+                    let value = pop!(ctx);
+                    ctx.push(value.clone());
+                    ctx.push(value);
                 }
 
-                Instruction::LoadProfileField(field) => {
-                    let value = ctx.get_profile_field(field);
+                Instruction::LoadProfileField(id) => {
+                    let value = ctx.load_profile_field(*id).cloned();
+                    if ctx.strict_fields && value.is_none() {
+                        ctx.add_error(ExecutionError::RuntimeError(format!(
+                            "unknown field: profile.{}",
+                            ctx.symbols.name(*id).unwrap_or_default()
+                        )));
+                    }
+                    let value = value.unwrap_or(Value::Null);
+                    if let Some(trace) = ctx.trace.as_mut() {
+                        trace.record_field(format!("profile.{}", ctx.symbols.name(*id).unwrap_or_default()), &value);
+                    }
                     ctx.push(value);
                 }
 
-                Instruction::StoreProfileField(field) => {
-                    if let Some(value) = ctx.pop() {
-                        ctx.set_profile_field(field.clone(), value);
-                    }
+                Instruction::StoreProfileField(id) => {
+                    let value = pop!(ctx);
+                    ctx.store_profile_field(*id, value);
                 }
 
-                Instruction::LoadTxnField(field) => {
-                    let value = ctx.get_txn_field(field);
+                Instruction::LoadTxnField(id) => {
+                    let value = ctx.load_txn_field(*id).cloned();
+                    if ctx.strict_fields && value.is_none() {
+                        ctx.add_error(ExecutionError::RuntimeError(format!(
+                            "unknown field: txn.{}",
+                            ctx.symbols.name(*id).unwrap_or_default()
+                        )));
+                    }
+                    let value = value.unwrap_or(Value::Null);
+                    if let Some(trace) = ctx.trace.as_mut() {
+                        trace.record_field(format!("txn.{}", ctx.symbols.name(*id).unwrap_or_default()), &value);
+                    }
                     ctx.push(value);
                 }
 
-                Instruction::StoreTxnField(field) => {
-                    if let Some(value) = ctx.pop() {
-                        ctx.set_txn_field(field.clone(), value);
-                    }
+                Instruction::StoreTxnField(id) => {
+                    let value = pop!(ctx);
+                    ctx.store_txn_field(*id, value);
                 }
 
                 Instruction::LoadLocal(name) => {
@@ -67,99 +166,167 @@ impl VM {
                 }
 
                 Instruction::StoreLocal(name) => {
-                    if let Some(value) = ctx.pop() {
-                        ctx.set_local(name.clone(), value);
-                    }
+                    let value = pop!(ctx);
+                    ctx.set_local(name.clone(), value);
+                }
+
+                Instruction::DeclareLocal(name) => {
+                    let value = pop!(ctx);
+                    ctx.declare_local(name.clone(), value);
                 }
 
+                Instruction::EnterScope => ctx.enter_scope(),
+
+                Instruction::ExitScope => ctx.exit_scope(),
+
                 Instruction::Add => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Self::add(a, b));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    let result = Self::checked_add(ctx.numeric_overflow, a, b);
+                    Self::push_result(ctx, result);
                 }
 
                 Instruction::Sub => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Self::sub(a, b));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    let policy = ctx.numeric_overflow;
+                    let result = Self::int_overflow(policy, &a, &b, "-", i64::checked_sub, i64::saturating_sub)
+                        .unwrap_or_else(|| Self::finite(Self::sub(a, b)));
+                    Self::push_result(ctx, result);
                 }
 
                 Instruction::Mul => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Self::mul(a, b));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    let policy = ctx.numeric_overflow;
+                    let result = Self::int_overflow(policy, &a, &b, "*", i64::checked_mul, i64::saturating_mul)
+                        .unwrap_or_else(|| Self::finite(Self::mul(a, b)));
+                    Self::push_result(ctx, result);
                 }
 
                 Instruction::Div => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Self::div(a, b));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    // Only `i64::MIN / -1` can overflow; zero divisors are
+                    // left to `div`
+                    let result = match b {
+                        Value::Int(-1) => Self::int_overflow(
+                            ctx.numeric_overflow,
+                            &a,
+                            &b,
+                            "/",
+                            i64::checked_div,
+                            i64::saturating_div,
+                        ),
+                        _ => None,
+                    };
+                    let result = result.unwrap_or_else(|| Self::div(a, b).and_then(Self::finite));
+                    Self::push_result(ctx, result);
                 }
 
                 Instruction::Mod => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Self::modulo(a, b));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    Self::push_result(ctx, Self::modulo(a, b).and_then(Self::finite));
+                }
+
+                Instruction::Pow => {
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    Self::push_result(ctx, Self::finite(Self::pow(a, b)));
                 }
 
                 Instruction::Neg => {
-                    if let Some(a) = ctx.pop() {
-                        ctx.push(Self::neg(a));
-                    }
+                    let a = Self::numeric_string(pop!(ctx));
+                    // `-x` overflows exactly when `0 - x` does
+                    let result = Self::int_overflow(
+                        ctx.numeric_overflow,
+                        &Value::Int(0),
+                        &a,
+                        "-",
+                        i64::checked_sub,
+                        i64::saturating_sub,
+                    )
+                    .unwrap_or_else(|| Self::neg(a).and_then(Self::finite));
+                    Self::push_result(ctx, result);
+                }
+
+                Instruction::BitAnd
+                | Instruction::BitOr
+                | Instruction::BitXor
+                | Instruction::Shl
+                | Instruction::Shr => {
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::bitwise(instruction, a, b));
                 }
 
                 Instruction::Eq => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(Self::eq(&a, &b)));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Value::Bool(Self::eq(&a, &b)));
                 }
 
                 Instruction::Ne => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(!Self::eq(&a, &b)));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Value::Bool(!Self::eq(&a, &b)));
+                }
+
+                Instruction::EqConst(index) => {
+                    let a = pop!(ctx);
+                    let b = ctx.pool.get(*index).unwrap_or(&Value::Null);
+                    let equal = Self::eq(&a, b);
+                    ctx.push(Value::Bool(equal));
+                }
+
+                Instruction::NeConst(index) => {
+                    let a = pop!(ctx);
+                    let b = ctx.pool.get(*index).unwrap_or(&Value::Null);
+                    let equal = Self::eq(&a, b);
+                    ctx.push(Value::Bool(!equal));
                 }
 
                 Instruction::Gt => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(Self::gt(&a, &b)));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::ordering(ctx.null_semantics, &a, &b, Ordering::is_gt));
                 }
 
                 Instruction::Gte => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(Self::gt(&a, &b) || Self::eq(&a, &b)));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::ordering(ctx.null_semantics, &a, &b, Ordering::is_ge));
                 }
 
                 Instruction::Lt => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(Self::lt(&a, &b)));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::ordering(ctx.null_semantics, &a, &b, Ordering::is_lt));
                 }
 
                 Instruction::Lte => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(Self::lt(&a, &b) || Self::eq(&a, &b)));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::ordering(ctx.null_semantics, &a, &b, Ordering::is_le));
                 }
 
                 Instruction::And => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(a.as_bool() && b.as_bool()));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::logical(ctx.null_semantics, &a, &b, false));
                 }
 
                 Instruction::Or => {
-                    if let (Some(b), Some(a)) = (ctx.pop(), ctx.pop()) {
-                        ctx.push(Value::Bool(a.as_bool() || b.as_bool()));
-                    }
+                    let b = pop!(ctx);
+                    let a = pop!(ctx);
+                    ctx.push(Self::logical(ctx.null_semantics, &a, &b, true));
                 }
 
                 Instruction::Not => {
-                    if let Some(a) = ctx.pop() {
-                        ctx.push(Value::Bool(!a.as_bool()));
-                    }
+                    let a = pop!(ctx);
+                    let unknown = ctx.null_semantics == NullSemantics::Sql && a.is_null();
+                    ctx.push(if unknown { Value::Null } else { Value::Bool(!a.as_bool()) });
                 }
 
                 Instruction::Jump(target) => {
@@ -168,104 +335,339 @@ impl VM {
                 }
 
                 Instruction::JumpIfFalse(target) => {
-                    if let Some(condition) = ctx.pop() {
-                        if !condition.as_bool() {
-                            pc = *target;
-                            continue;
-                        }
+                    let condition = pop!(ctx);
+                    if !condition.as_bool() {
+                        pc = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::JumpIfTrue(target) => {
+                    let condition = pop!(ctx);
+                    if condition.as_bool() {
+                        pc = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::JumpIfUnknown(target) => {
+                    if ctx.null_semantics == NullSemantics::Sql && ctx.peek().is_some_and(Value::is_null) {
+                        ctx.pop();
+                        pc = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::ShortCircuitAnd(target) => {
+                    // Only a false left operand decides `&&`, not an unknown one
+                    let sql = ctx.null_semantics == NullSemantics::Sql;
+                    if ctx.peek().is_some_and(|left| !(left.as_bool() || (sql && left.is_null()))) {
+                        ctx.pop();
+                        pc = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::ShortCircuitOr(target) => {
+                    if ctx.peek().is_some_and(Value::as_bool) {
+                        ctx.pop();
+                        pc = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::JumpIfNotNull(target) => {
+                    let value = pop!(ctx);
+                    if !value.is_null() {
+                        pc = *target;
+                        continue;
+                    }
+                }
+
+                Instruction::LoopGuard(target) => {
+                    let count = pop!(ctx);
+                    if count.as_int() as u64 > ctx.max_loop_iterations as u64 {
+                        ctx.add_error(ExecutionError::RuntimeError(format!(
+                            "loop iteration limit of {} exceeded",
+                            ctx.max_loop_iterations
+                        )));
+                        pc = *target;
+                        continue;
                     }
                 }
 
                 Instruction::Return => {
-                    ctx.should_return = true;
+                    // Inside a function this only ends the call; its return
+                    // value is on top of the stack
+                    if ctx.call_depth == 0 {
+                        ctx.should_return = true;
+                    }
                     break;
                 }
 
+                Instruction::Assert(source) => {
+                    if !pop!(ctx).as_bool() {
+                        ctx.add_error(ExecutionError::RuntimeError(format!(
+                            "require failed: {}",
+                            source
+                        )));
+                        ctx.aborted = true;
+                        return;
+                    }
+                }
+
                 Instruction::CallGlobal(func_name, arg_count) => {
                     if let Some(func) = functions.get(func_name) {
                         // Pop arguments and store as locals
                         let mut args = Vec::new();
                         for _ in 0..*arg_count {
-                            if let Some(arg) = ctx.pop() {
-                                args.push(arg);
-                            }
+                            args.push(pop!(ctx));
                         }
                         args.reverse(); // Arguments are in reverse order on stack
 
-                        // Set up parameter bindings
-                        for (i, param) in func.params.iter().enumerate() {
-                            if let Some(arg) = args.get(i) {
-                                ctx.set_local(param.clone(), arg.clone());
-                            }
+                        // Refuse to recurse past the depth limit rather than
+                        // overflowing the native stack
+                        if ctx.call_depth >= ctx.max_call_depth {
+                            ctx.add_error(ExecutionError::RuntimeError(format!(
+                                "maximum call depth of {} exceeded calling '{}'",
+                                ctx.max_call_depth, func_name
+                            )));
+                            ctx.push(Value::Null);
+                            pc += 1;
+                            continue;
+                        }
+
+                        // Bind the parameters in a scope of their own, so
+                        // neither they nor the body's `let`s overwrite the
+                        // caller's locals
+                        let scope_depth = ctx.scope_depth();
+                        ctx.enter_scope();
+                        let mut args = args.into_iter();
+                        for param in &func.params {
+                            ctx.declare_local(param.clone(), args.next().unwrap_or(Value::Null));
                         }
 
-                        // Execute function bytecode
-                        Self::execute(&func.bytecode, ctx, functions);
+                        // Execute function bytecode in its own stack frame:
+                        // it cannot pop the caller's operands, and whatever
+                        // it leaves is discarded except the value on top,
+                        // which is the call's result
+                        let frame = ctx.stack.len();
+                        let caller_base = std::mem::replace(&mut ctx.stack_base, frame);
+                        ctx.call_depth += 1;
+                        Self::run(&func.bytecode, ctx, functions, host_functions, tracer);
+                        ctx.call_depth -= 1;
+                        // Also closes blocks a `return` left early
+                        ctx.exit_scopes_to(scope_depth);
+                        ctx.stack_base = caller_base;
+                        if ctx.aborted {
+                            return;
+                        }
+                        let result = ctx.stack.split_off(frame).pop().unwrap_or(Value::Null);
+                        ctx.push(result);
+                        pc += 1;
+                        continue;
+                    } else if let Some(host_fn) = host_functions.get(func_name) {
+                        // DSL functions take precedence; a host function's
+                        // result is the value of the call
+                        let Some(start) = ctx
+                            .stack
+                            .len()
+                            .checked_sub(*arg_count)
+                            .filter(|&start| start >= ctx.stack_base)
+                        else {
+                            ctx.add_error(ExecutionError::StackUnderflow);
+                            ctx.aborted = true;
+                            return;
+                        };
+                        let args = ctx.stack.split_off(start);
+                        ctx.push(host_fn(&args));
+                        pc += 1;
+                        continue;
                     }
+
+                    // Unknown functions evaluate to null so statement calls
+                    // leave the stack balanced
+                    ctx.push(Value::Null);
+                }
+
+                Instruction::CallBuiltin(builtin, arg_count) => {
+                    let mut args = Vec::with_capacity(*arg_count);
+                    for _ in 0..*arg_count {
+                        args.push(pop!(ctx));
+                    }
+                    args.reverse();
+
+                    ctx.push(builtins::call(*builtin, &args));
                 }
 
                 Instruction::CallAction(action_type, arg_count) => {
                     // Pop arguments
                     let mut args = Vec::new();
                     for _ in 0..*arg_count {
-                        if let Some(arg) = ctx.pop() {
-                            args.push(arg);
-                        }
+                        args.push(pop!(ctx));
                     }
                     args.reverse();
 
-                    // Create action based on type
-                    let action = Self::create_action(action_type, args);
-                    ctx.add_action(action);
+                    // Create action based on type; None if it was rejected
+                    if let Some(action) = Self::create_action(ctx, action_type, args) {
+                        ctx.add_action(action);
+                    }
                 }
 
                 Instruction::ArrayAccess => {
-                    if let (Some(index), Some(array)) = (ctx.pop(), ctx.pop()) {
-                        if let Value::Array(arr) = array {
-                            let idx = index.as_int() as usize;
-                            let value = arr.get(idx).cloned().unwrap_or(Value::Null);
-                            ctx.push(value);
-                        } else {
-                            ctx.push(Value::Null);
-                        }
+                    let index = pop!(ctx);
+                    let array = pop!(ctx);
+                    if let Value::Array(arr) = array {
+                        let idx = index.as_int() as usize;
+                        let value = arr.get(idx).cloned().unwrap_or(Value::Null);
+                        ctx.push(value);
+                    } else {
+                        ctx.push(Value::Null);
                     }
                 }
 
                 Instruction::ArrayContains => {
-                    if let (Some(item), Some(array)) = (ctx.pop(), ctx.pop()) {
-                        if let Value::Array(arr) = array {
-                            ctx.push(Value::Bool(arr.contains(&item)));
-                        } else {
-                            ctx.push(Value::Bool(false));
-                        }
-                    }
+                    // Stack: haystack below needle. Arrays check membership,
+                    // strings check for a substring; anything else is false
+                    let needle = pop!(ctx);
+                    let haystack = pop!(ctx);
+                    let found = match (&haystack, &needle) {
+                        (Value::Array(arr), _) => arr.contains(&needle),
+                        (Value::String(s), Value::String(sub)) => s.contains(sub.as_str()),
+                        _ => false,
+                    };
+                    ctx.push(Value::Bool(found));
+                }
+
+                Instruction::ArrayLength => {
+                    let array = pop!(ctx);
+                    let len = match array {
+                        Value::Array(arr) => arr.len() as i64,
+                        _ => 0,
+                    };
+                    ctx.push(Value::Int(len));
                 }
 
                 Instruction::ObjectGet(field) => {
-                    if let Some(obj) = ctx.pop() {
-                        if let Value::Object(map) = obj {
-                            let value = map.get(field).cloned().unwrap_or(Value::Null);
-                            ctx.push(value);
-                        } else {
-                            ctx.push(Value::Null);
-                        }
+                    let obj = pop!(ctx);
+                    if let Value::Object(map) = obj {
+                        let value = map.get(field).cloned().unwrap_or(Value::Null);
+                        ctx.push(value);
+                    } else {
+                        ctx.push(Value::Null);
                     }
                 }
 
+                Instruction::BuildObject(entry_count) => {
+                    let mut object = HashMap::default();
+                    for _ in 0..*entry_count {
+                        let value = pop!(ctx);
+                        let key = pop!(ctx);
+                        // Entries are popped in reverse, so `or_insert` keeps
+                        // the last-written value for a duplicate key
+                        object.entry(key.as_string()).or_insert(value);
+                    }
+                    ctx.push(Value::Object(object));
+                }
+
+                Instruction::BuildArray(len) => {
+                    let mut array = Vec::with_capacity(*len);
+                    for _ in 0..*len {
+                        array.push(pop!(ctx));
+                    }
+                    array.reverse();
+                    ctx.push(Value::Array(array));
+                }
+
+                Instruction::SetContains(index) => {
+                    let needle = pop!(ctx);
+                    let found = ctx.sets.contains(*index, needle);
+                    ctx.push(Value::Bool(found));
+                }
+
                 Instruction::MethodCall(method, arg_count) => {
                     // Pop arguments
                     let mut args = Vec::new();
                     for _ in 0..*arg_count {
-                        if let Some(arg) = ctx.pop() {
-                            args.push(arg);
-                        }
+                        args.push(pop!(ctx));
                     }
                     args.reverse();
 
                     // Pop object
-                    if let Some(obj) = ctx.pop() {
-                        let result = Self::call_method(&obj, method, args);
-                        ctx.push(result);
+                    let obj = pop!(ctx);
+                    // Aggregates add like `+`, so they follow the overflow policy
+                    let result = match (&obj, method.as_str(), args.as_slice()) {
+                        (Value::Array(arr), "sum", []) => Self::sum(ctx.numeric_overflow, arr),
+                        (Value::Array(arr), "avg", []) => Self::average(ctx.numeric_overflow, arr),
+                        _ => Ok(Self::call_method(&obj, method, args)),
+                    };
+                    Self::push_result(ctx, result);
+                }
+
+                Instruction::MatchRegex(index) => {
+                    let value = pop!(ctx);
+                    let result = match &value {
+                        Value::String(s) => ctx.regexes.is_match(*index, s).map_or(Value::Null, Value::Bool),
+                        _ => Value::Null,
+                    };
+                    ctx.push(result);
+                }
+
+                Instruction::ArrayPush(capped) => {
+                    let max_len = if *capped { Some(pop!(ctx)) } else { None };
+                    let item = pop!(ctx);
+                    let mut array = pop!(ctx);
+                    if let Err(e) = Self::array_push(&mut array, item, max_len.as_ref()) {
+                        ctx.add_error(e);
+                    }
+                    ctx.push(array);
+                }
+
+                Instruction::TraceCondition(index) => {
+                    if ctx.condition_cache.is_some() {
+                        // An unknown result has no Bool to cache
+                        let result = match ctx.peek() {
+                            Some(Value::Null) if ctx.null_semantics == NullSemantics::Sql => None,
+                            value => value.map(Value::as_bool),
+                        };
+                        let errors = ctx.errors.len();
+                        if let Some(cache) = ctx.condition_cache.as_mut() {
+                            match result {
+                                Some(result) => cache.finish(result, errors),
+                                None => cache.cancel(),
+                            }
+                        }
+                    }
+                    if ctx.trace.is_some() {
+                        let result = ctx.peek().is_some_and(Value::as_bool);
+                        if let Some(trace) = ctx.trace.as_mut() {
+                            trace.conditions.push((*index, result));
+                        }
+                    }
+                }
+
+                Instruction::MarkFired => {
+                    ctx.rule_fired = true;
+                }
+
+                Instruction::MemoCondition(entry, skip) => {
+                    let key = match ctx.condition_cache {
+                        Some(_) if *skip > 0 => ctx.memo_key(*entry),
+                        _ => None,
+                    };
+                    if let Some(key) = key {
+                        let errors = ctx.errors.len();
+                        let cached = ctx
+                            .condition_cache
+                            .as_mut()
+                            .and_then(|cache| cache.lookup(*entry, key, errors));
+                        // A hit pushes the result and resumes at the
+                        // condition's TraceCondition
+                        if let Some(result) = cached {
+                            ctx.push(Value::Bool(result));
+                            pc += skip;
+                            continue;
+                        }
                     }
                 }
             }
@@ -277,6 +679,9 @@ impl VM {
     // Arithmetic operations
     #[inline]
     fn add(a: Value, b: Value) -> Value {
+        if let Some((x, y)) = Self::decimal_operands(&a, &b) {
+            return x.checked_add(y).map_or(Value::Null, Value::Decimal);
+        }
         match (a, b) {
             (Value::Int(x), Value::Int(y)) => Value::Int(x.wrapping_add(y)),
             (Value::Float(x), Value::Float(y)) => Value::Float(x + y),
@@ -292,6 +697,9 @@ impl VM {
 
     #[inline]
     fn sub(a: Value, b: Value) -> Value {
+        if let Some((x, y)) = Self::decimal_operands(&a, &b) {
+            return x.checked_sub(y).map_or(Value::Null, Value::Decimal);
+        }
         match (a, b) {
             (Value::Int(x), Value::Int(y)) => Value::Int(x.wrapping_sub(y)),
             (Value::Float(x), Value::Float(y)) => Value::Float(x - y),
@@ -303,6 +711,9 @@ impl VM {
 
     #[inline]
     fn mul(a: Value, b: Value) -> Value {
+        if let Some((x, y)) = Self::decimal_operands(&a, &b) {
+            return x.checked_mul(y).map_or(Value::Null, Value::Decimal);
+        }
         match (a, b) {
             (Value::Int(x), Value::Int(y)) => Value::Int(x.wrapping_mul(y)),
             (Value::Float(x), Value::Float(y)) => Value::Float(x * y),
@@ -312,69 +723,297 @@ impl VM {
         }
     }
 
+    /// Divide two values; a zero divisor is reported as an error
     #[inline]
-    fn div(a: Value, b: Value) -> Value {
+    fn div(a: Value, b: Value) -> Result<Value, ExecutionError> {
+        if let Some((x, y)) = Self::decimal_operands(&a, &b) {
+            if y.is_zero() {
+                return Err(Self::division_by_zero());
+            }
+            return Ok(x.checked_div(y).map_or(Value::Null, Value::Decimal));
+        }
         match (a, b) {
-            (Value::Int(x), Value::Int(y)) if y != 0 => Value::Int(x / y),
-            (Value::Float(x), Value::Float(y)) if y != 0.0 => Value::Float(x / y),
-            (Value::Int(x), Value::Float(y)) if y != 0.0 => Value::Float(x as f64 / y),
-            (Value::Float(x), Value::Int(y)) if y != 0 => Value::Float(x / y as f64),
-            _ => Value::Null,
+            (Value::Int(_), Value::Int(0))
+            | (Value::Float(_), Value::Int(0)) => Err(Self::division_by_zero()),
+            (Value::Float(_), Value::Float(y)) | (Value::Int(_), Value::Float(y)) if y == 0.0 => {
+                Err(Self::division_by_zero())
+            }
+            (Value::Int(x), Value::Int(y)) => Ok(Value::Int(x.wrapping_div(y))),
+            (Value::Float(x), Value::Float(y)) => Ok(Value::Float(x / y)),
+            (Value::Int(x), Value::Float(y)) => Ok(Value::Float(x as f64 / y)),
+            (Value::Float(x), Value::Int(y)) => Ok(Value::Float(x / y as f64)),
+            _ => Ok(Value::Null),
         }
     }
 
+    /// Remainder with the sign of the dividend, like Rust's `%`; a zero
+    /// divisor is reported as an error, as in `div`
     #[inline]
-    fn modulo(a: Value, b: Value) -> Value {
+    fn modulo(a: Value, b: Value) -> Result<Value, ExecutionError> {
+        if let Some((x, y)) = Self::decimal_operands(&a, &b) {
+            if y.is_zero() {
+                return Err(Self::division_by_zero());
+            }
+            return Ok(x.checked_rem(y).map_or(Value::Null, Value::Decimal));
+        }
         match (a, b) {
-            (Value::Int(x), Value::Int(y)) if y != 0 => Value::Int(x % y),
+            (Value::Int(_), Value::Int(0))
+            | (Value::Float(_), Value::Int(0)) => Err(Self::division_by_zero()),
+            (Value::Float(_), Value::Float(y)) | (Value::Int(_), Value::Float(y)) if y == 0.0 => {
+                Err(Self::division_by_zero())
+            }
+            (Value::Int(x), Value::Int(y)) => Ok(Value::Int(x.wrapping_rem(y))),
+            (Value::Float(x), Value::Float(y)) => Ok(Value::Float(x % y)),
+            (Value::Int(x), Value::Float(y)) => Ok(Value::Float(x as f64 % y)),
+            (Value::Float(x), Value::Int(y)) => Ok(Value::Float(x % y as f64)),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// Exponentiation; Int and Decimal results stay exact while the
+    /// exponent is a non-negative integer and the result fits, and fall
+    /// back to Float otherwise (negative exponents, overflow)
+    #[inline]
+    fn pow(a: Value, b: Value) -> Value {
+        match (&a, &b) {
+            (Value::Int(x), Value::Int(y)) => u32::try_from(*y)
+                .ok()
+                .and_then(|y| x.checked_pow(y))
+                .map_or_else(|| Value::Float((*x as f64).powf(*y as f64)), Value::Int),
+            (Value::Decimal(x), Value::Int(y)) => u64::try_from(*y)
+                .ok()
+                .and_then(|y| Self::decimal_pow(*x, y))
+                .map_or_else(|| Value::Float(a.as_float().powf(*y as f64)), Value::Decimal),
+            _ if a.is_numeric() && b.is_numeric() => Value::Float(a.as_float().powf(b.as_float())),
             _ => Value::Null,
         }
     }
 
+    /// Exponentiation by squaring; None on overflow
+    fn decimal_pow(mut base: Decimal, mut exp: u64) -> Option<Decimal> {
+        let mut result = Decimal::ONE;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.checked_mul(base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.checked_mul(base)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// Integer arithmetic under a non-wrapping overflow policy
+    ///
+    /// None unless both operands are Ints and the policy is not Wrap, in
+    /// which case the usual (wrapping) arithmetic applies.
+    #[inline]
+    fn int_overflow(
+        policy: OverflowPolicy,
+        a: &Value,
+        b: &Value,
+        symbol: &str,
+        checked: fn(i64, i64) -> Option<i64>,
+        saturating: fn(i64, i64) -> i64,
+    ) -> Option<Result<Value, ExecutionError>> {
+        let (Value::Int(x), Value::Int(y)) = (a, b) else {
+            return None;
+        };
+        match policy {
+            OverflowPolicy::Wrap => None,
+            OverflowPolicy::Saturate => Some(Ok(Value::Int(saturating(*x, *y)))),
+            OverflowPolicy::Error => Some(checked(*x, *y).map(Value::Int).ok_or_else(|| {
+                ExecutionError::RuntimeError(format!("integer overflow: {} {} {}", x, symbol, y))
+            })),
+        }
+    }
+
+    /// `a + b` under the overflow policy, rejecting a non-finite result
+    #[inline]
+    fn checked_add(policy: OverflowPolicy, a: Value, b: Value) -> Result<Value, ExecutionError> {
+        Self::int_overflow(policy, &a, &b, "+", i64::checked_add, i64::saturating_add)
+            .unwrap_or_else(|| Self::finite(Self::add(a, b)))
+    }
+
+    fn division_by_zero() -> ExecutionError {
+        ExecutionError::RuntimeError("division by zero".to_string())
+    }
+
+    /// Reject NaN and infinite Float results, which would otherwise spread
+    /// through later arithmetic and cannot be written as JSON
+    #[inline]
+    fn finite(value: Value) -> Result<Value, ExecutionError> {
+        match value {
+            Value::Float(x) if !x.is_finite() => Err(ExecutionError::RuntimeError(format!(
+                "arithmetic produced a non-finite number ({})",
+                x
+            ))),
+            value => Ok(value),
+        }
+    }
+
+    /// Push an operation's result, or record its error and push Null
+    #[inline]
+    fn push_result(ctx: &mut ExecutionContext, result: Result<Value, ExecutionError>) {
+        let value = result.unwrap_or_else(|e| {
+            ctx.add_error(e);
+            Value::Null
+        });
+        ctx.push(value);
+    }
+
+    /// When either operand is a Decimal, both operands as decimals; Ints and
+    /// Floats mixed with a Decimal are promoted so the result stays exact
+    #[inline]
+    fn decimal_operands(a: &Value, b: &Value) -> Option<(Decimal, Decimal)> {
+        match (a, b) {
+            (Value::Decimal(_), _) | (_, Value::Decimal(_)) => Some((a.as_decimal()?, b.as_decimal()?)),
+            _ => None,
+        }
+    }
+
+    /// Append `item` to an array in place, treating Null (a missing field)
+    /// as an empty array
+    ///
+    /// With `max_len`, the oldest elements are dropped so at most that many
+    /// remain, which keeps rolling windows bounded. On error `array` is left
+    /// unchanged.
+    fn array_push(array: &mut Value, item: Value, max_len: Option<&Value>) -> Result<(), ExecutionError> {
+        let max_len = match max_len {
+            None => None,
+            Some(Value::Int(n)) if *n >= 0 => Some(*n as usize),
+            Some(other) => {
+                return Err(ExecutionError::RuntimeError(format!(
+                    "push() max length must be a non-negative integer, got {}",
+                    other
+                )));
+            }
+        };
+
+        if *array == Value::Null {
+            *array = Value::Array(Vec::new());
+        }
+        let Value::Array(items) = array else {
+            return Err(ExecutionError::RuntimeError(format!(
+                "push() on a non-array value: {}",
+                array
+            )));
+        };
+
+        items.push(item);
+        if let Some(max_len) = max_len {
+            let excess = items.len().saturating_sub(max_len);
+            items.drain(..excess);
+        }
+        Ok(())
+    }
+
+    /// Bitwise operations on Ints; Null for any other operands, and for
+    /// shifts by a negative amount or by 64 or more
+    #[inline]
+    fn bitwise(op: &Instruction, a: Value, b: Value) -> Value {
+        let (Value::Int(x), Value::Int(y)) = (a, b) else {
+            return Value::Null;
+        };
+        let shift = u32::try_from(y).ok();
+        let result = match op {
+            Instruction::BitAnd => Some(x & y),
+            Instruction::BitOr => Some(x | y),
+            Instruction::BitXor => Some(x ^ y),
+            Instruction::Shl => shift.and_then(|s| x.checked_shl(s)),
+            Instruction::Shr => shift.and_then(|s| x.checked_shr(s)),
+            _ => None,
+        };
+        result.map_or(Value::Null, Value::Int)
+    }
+
     #[inline]
-    fn neg(a: Value) -> Value {
+    /// `-x` of a number; Null stays Null, anything else is an error
+    fn neg(a: Value) -> Result<Value, ExecutionError> {
         match a {
-            Value::Int(x) => Value::Int(-x),
-            Value::Float(x) => Value::Float(-x),
-            _ => Value::Null,
+            Value::Int(x) => Ok(Value::Int(x.wrapping_neg())),
+            Value::Float(x) => Ok(Value::Float(-x)),
+            Value::Decimal(x) => Ok(Value::Decimal(-x)),
+            Value::Null => Ok(Value::Null),
+            other => Err(ExecutionError::RuntimeError(format!("cannot negate a {}", other.value_type()))),
+        }
+    }
+
+    /// A string that spells a number, as that number (Int when it is a whole
+    /// number, else Float); any other value unchanged
+    fn numeric_string(value: Value) -> Value {
+        match &value {
+            Value::String(s) => {
+                let s = s.trim();
+                s.parse()
+                    .map(Value::Int)
+                    .or_else(|_| s.parse().map(Value::Float))
+                    .unwrap_or(value)
+            }
+            _ => value,
         }
     }
 
     // Comparison operations
+
+    /// Result of `<`, `<=`, `>` or `>=`; under SQL null semantics a Null
+    /// operand makes it unknown rather than false
     #[inline]
-    fn eq(a: &Value, b: &Value) -> bool {
-        a == b
+    fn ordering(semantics: NullSemantics, a: &Value, b: &Value, holds: fn(Ordering) -> bool) -> Value {
+        if semantics == NullSemantics::Sql && (a.is_null() || b.is_null()) {
+            return Value::Null;
+        }
+        Value::Bool(a.compare(b).is_some_and(holds))
     }
 
+    /// `&&` (`decisive` false) or `||` (`decisive` true) of two evaluated
+    /// operands: an operand equal to `decisive` decides the result, and
+    /// otherwise, under SQL null semantics, a Null operand makes it unknown
     #[inline]
-    fn gt(a: &Value, b: &Value) -> bool {
-        match (a, b) {
-            (Value::Int(x), Value::Int(y)) => x > y,
-            (Value::Float(x), Value::Float(y)) => x > y,
-            (Value::Int(x), Value::Float(y)) => (*x as f64) > *y,
-            (Value::Float(x), Value::Int(y)) => *x > (*y as f64),
-            (Value::String(x), Value::String(y)) => x > y,
-            _ => false,
+    fn logical(semantics: NullSemantics, a: &Value, b: &Value, decisive: bool) -> Value {
+        let unknown = |value: &Value| semantics == NullSemantics::Sql && value.is_null();
+        if [a, b].into_iter().any(|value| !unknown(value) && value.as_bool() == decisive) {
+            Value::Bool(decisive)
+        } else if unknown(a) || unknown(b) {
+            Value::Null
+        } else {
+            Value::Bool(!decisive)
         }
     }
 
+    /// Equality for `==`, and negated for `!=`
+    ///
+    /// Numbers compare by value across Int, Float and Decimal, so
+    /// `Int(1) == Float(1.0)`. Everything else, including Null, compares
+    /// strictly with its own kind.
     #[inline]
-    fn lt(a: &Value, b: &Value) -> bool {
+    fn eq(a: &Value, b: &Value) -> bool {
+        if let Some((x, y)) = Self::decimal_operands(a, b) {
+            return x == y;
+        }
         match (a, b) {
-            (Value::Int(x), Value::Int(y)) => x < y,
-            (Value::Float(x), Value::Float(y)) => x < y,
-            (Value::Int(x), Value::Float(y)) => (*x as f64) < *y,
-            (Value::Float(x), Value::Int(y)) => *x < (*y as f64),
-            (Value::String(x), Value::String(y)) => x < y,
-            _ => false,
+            (Value::Int(i), Value::Float(f)) | (Value::Float(f), Value::Int(i)) => Self::int_eq_float(*i, *f),
+            _ => a == b,
         }
     }
 
-    // Action creation
-    fn create_action(action_type: &ActionType, args: Vec<Value>) -> Action {
-        match action_type {
+    /// Exact Int/Float equality
+    ///
+    /// Casting the Int to f64 would round large values (2^53 + 1 would equal
+    /// 2^53), so the Float is converted instead when it is a whole number in
+    /// i64 range.
+    #[inline]
+    fn int_eq_float(i: i64, f: f64) -> bool {
+        f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 && f as i64 == i
+    }
+
+    /// Build the action for a call; None (with an error recorded) when its
+    /// arguments cannot produce a valid action
+    fn create_action(ctx: &mut ExecutionContext, action_type: &ActionType, args: Vec<Value>) -> Option<Action> {
+        let action = match action_type {
             ActionType::CreateCase => {
-                let severity = args.get(0).map(|v| v.as_string()).unwrap_or_default();
+                let severity = args.first().map(|v| v.as_string()).unwrap_or_default();
                 let reason = args.get(1).map(|v| v.as_string()).unwrap_or_default();
                 let metadata = args.get(2).map(|v| v.as_object()).unwrap_or_default();
 
@@ -385,15 +1024,24 @@ impl VM {
                 }
             }
             ActionType::CreateComment => {
-                let comment = args.get(0).map(|v| v.as_string()).unwrap_or_default();
+                // `createComment(text)` or `createComment(case_id, text)`; a
+                // null case id leaves the comment unattached
+                let (case_id, comment) = match args.as_slice() {
+                    [case_id, comment, ..] => {
+                        let case_id = (!case_id.is_null()).then(|| case_id.as_string());
+                        (case_id, comment.as_string())
+                    }
+                    [comment] => (None, comment.as_string()),
+                    [] => (None, String::new()),
+                };
 
                 Action::CreateComment {
-                    case_id: None,
+                    case_id,
                     comment,
                 }
             }
             ActionType::SendAuthAdvise => {
-                let channel = args.get(0).map(|v| v.as_string()).unwrap_or_default();
+                let channel = args.first().map(|v| v.as_string()).unwrap_or_default();
                 let template = args.get(1).map(|v| v.as_string()).unwrap_or_default();
                 let params = args.get(2).map(|v| v.as_object()).unwrap_or_default();
 
@@ -404,19 +1052,39 @@ impl VM {
                 }
             }
             ActionType::SetFraudScore => {
-                let score = args.get(0).map(|v| v.as_float()).unwrap_or(0.0);
+                let score = args.first().map(|v| v.as_float()).unwrap_or(0.0);
+
+                // Scores are probabilities; NaN has no sensible clamp
+                if score.is_nan() {
+                    ctx.add_error(ExecutionError::RuntimeError(
+                        "setFraudScore() rejected a NaN score".to_string(),
+                    ));
+                    return None;
+                }
 
-                Action::SetFraudScore { score }
+                Action::SetFraudScore {
+                    score: score.clamp(0.0, 1.0),
+                }
             }
             ActionType::SetDecision => {
-                let decision = args.get(0).map(|v| v.as_string()).unwrap_or_default();
+                let name = args.first().map(|v| v.as_string()).unwrap_or_default();
+
+                // An unrecognized decision is flagged for a human rather than
+                // silently passed downstream
+                let decision = name.parse().unwrap_or_else(|e: UnknownDecision| {
+                    ctx.add_error(ExecutionError::RuntimeError(e.to_string()));
+                    Decision::Review
+                });
 
                 Action::SetDecision { decision }
             }
-            ActionType::Custom(name) => {
+            ActionType::Custom(name, param_names) => {
+                // Registered actions name their params; anything past the
+                // declared names falls back to positional keys
                 let mut params = HashMap::default();
                 for (i, arg) in args.iter().enumerate() {
-                    params.insert(format!("arg{}", i), arg.clone());
+                    let key = param_names.get(i).cloned().unwrap_or_else(|| format!("arg{}", i));
+                    params.insert(key, arg.clone());
                 }
 
                 Action::Custom {
@@ -424,23 +1092,94 @@ impl VM {
                     params,
                 }
             }
-        }
+        };
+
+        Some(action)
     }
 
     // Method calls
     fn call_method(obj: &Value, method: &str, args: Vec<Value>) -> Value {
-        match (obj, method) {
-            (Value::Array(arr), "length") => Value::Int(arr.len() as i64),
-            (Value::String(s), "length") => Value::Int(s.len() as i64),
+        match (obj, method, args.as_slice()) {
+            (Value::Array(arr), "length", []) => Value::Int(arr.len() as i64),
+            (Value::Array(arr), "get", [Value::Int(index)]) => usize::try_from(*index)
+                .ok()
+                .and_then(|i| arr.get(i))
+                .cloned()
+                .unwrap_or(Value::Null),
+            (Value::Array(arr), "contains", [item]) => Value::Bool(arr.contains(item)),
+            (Value::Array(arr), "min", []) => Self::numbers(arr)
+                .reduce(|a, b| builtins::call(BuiltinFn::Min, &[a, b]))
+                .unwrap_or(Value::Null),
+            (Value::Array(arr), "max", []) => Self::numbers(arr)
+                .reduce(|a, b| builtins::call(BuiltinFn::Max, &[a, b]))
+                .unwrap_or(Value::Null),
+            (Value::String(s), "length", []) => Value::Int(s.len() as i64),
+            (Value::String(s), "toLowerCase", []) => Value::String(s.to_lowercase()),
+            (Value::String(s), "toUpperCase", []) => Value::String(s.to_uppercase()),
+            (Value::String(s), "trim", []) => Value::String(s.trim().to_string()),
+            (Value::String(s), "startsWith", [Value::String(prefix)]) => {
+                Value::Bool(s.starts_with(prefix.as_str()))
+            }
+            (Value::String(s), "endsWith", [Value::String(suffix)]) => {
+                Value::Bool(s.ends_with(suffix.as_str()))
+            }
+            (Value::String(s), "contains", [Value::String(needle)]) => {
+                Value::Bool(s.contains(needle.as_str()))
+            }
+            (Value::Object(o), "has", [Value::String(key)]) => Value::Bool(o.contains_key(key)),
+            (_, "has", [_]) => Value::Bool(false),
+            (Value::Object(o), "keys", []) => {
+                Value::Array(Self::sorted_entries(o).map(|(k, _)| Value::String(k.clone())).collect())
+            }
+            (Value::Object(o), "values", []) => {
+                Value::Array(Self::sorted_entries(o).map(|(_, v)| v.clone()).collect())
+            }
             _ => Value::Null,
         }
     }
+
+    /// Object entries in key order, so `keys()` and `values()` line up and
+    /// are deterministic
+    fn sorted_entries(object: &HashMap<String, Value>) -> impl Iterator<Item = (&String, &Value)> {
+        let mut entries: Vec<_> = object.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+
+    /// Numeric elements of an array; aggregates skip everything else
+    fn numbers(arr: &[Value]) -> impl Iterator<Item = Value> + '_ {
+        arr.iter().filter(|v| v.is_numeric()).cloned()
+    }
+
+    /// Sum of the numeric elements, added as `+` adds them
+    fn sum(policy: OverflowPolicy, arr: &[Value]) -> Result<Value, ExecutionError> {
+        Self::numbers(arr).try_fold(Value::Int(0), |sum, value| Self::checked_add(policy, sum, value))
+    }
+
+    /// Mean of the numeric elements: Decimal if any is a Decimal, otherwise
+    /// Float; Null when there are none
+    fn average(policy: OverflowPolicy, arr: &[Value]) -> Result<Value, ExecutionError> {
+        let count = Self::numbers(arr).count();
+        if count == 0 {
+            return Ok(Value::Null);
+        }
+
+        Ok(match Self::sum(policy, arr)? {
+            Value::Decimal(sum) => sum
+                .checked_div(Decimal::from(count))
+                .map_or(Value::Null, Value::Decimal),
+            sum => Value::Float(sum.as_float() / count as f64),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::pool::ConstantPool;
+    use crate::compiler::symbols::SymbolTable;
     use crate::{Transaction, UserProfile};
+    use std::sync::Arc;
 
     #[test]
     fn test_arithmetic() {
@@ -452,11 +1191,62 @@ mod tests {
             Instruction::Add,
         ];
 
-        VM::execute(&bytecode, &mut ctx, &HashMap::default());
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
 
         assert_eq!(ctx.pop(), Some(Value::Int(15)));
     }
 
+    #[test]
+    fn test_modulo() {
+        let run = |a: Value, b: Value| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            let bytecode = vec![Instruction::Push(a), Instruction::Push(b), Instruction::Mod];
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            (ctx.pop(), ctx.errors)
+        };
+
+        assert_eq!(run(Value::Float(10.5), Value::Float(3.0)), (Some(Value::Float(1.5)), vec![]));
+        assert_eq!(run(Value::Int(10), Value::Int(3)), (Some(Value::Int(1)), vec![]));
+        // Mixed operands promote to Float, and the sign follows the dividend
+        assert_eq!(run(Value::Int(7), Value::Float(2.5)), (Some(Value::Float(2.0)), vec![]));
+        assert_eq!(run(Value::Float(-7.5), Value::Int(2)), (Some(Value::Float(-1.5)), vec![]));
+
+        for divisor in [Value::Float(0.0), Value::Int(0)] {
+            assert_eq!(
+                run(Value::Float(5.0), divisor),
+                (Some(Value::Null), vec![ExecutionError::RuntimeError("division by zero".to_string())])
+            );
+        }
+        assert_eq!(
+            run(Value::Int(5), Value::Float(0.0)),
+            (Some(Value::Null), vec![ExecutionError::RuntimeError("division by zero".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_constant_pool() {
+        let mut pool = ConstantPool::default();
+        let us = pool.intern(Value::from("US"));
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        ctx.pool = Arc::new(pool);
+
+        let bytecode = vec![
+            Instruction::LoadConst(us),
+            Instruction::Dup,
+            Instruction::EqConst(us),
+            Instruction::Push(Value::from("GB")),
+            Instruction::NeConst(us),
+            Instruction::LoadConst(us + 1),
+        ];
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+        // An index past the pool, as only corrupt bytecode has, reads Null
+        assert_eq!(
+            ctx.stack,
+            vec![Value::from("US"), Value::Bool(true), Value::Bool(true), Value::Null]
+        );
+    }
+
     #[test]
     fn test_comparison() {
         let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
@@ -467,9 +1257,594 @@ mod tests {
             Instruction::Gt,
         ];
 
-        VM::execute(&bytecode, &mut ctx, &HashMap::default());
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+        assert_eq!(ctx.pop(), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_stack_underflow_aborts() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+
+        let bytecode = vec![
+            Instruction::Push(Value::Int(10)),
+            Instruction::Sub,
+            Instruction::Push(Value::Int(99)),
+        ];
+
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+        assert!(ctx.aborted);
+        assert_eq!(ctx.errors, vec![ExecutionError::StackUnderflow]);
+        assert!(ctx.stack.is_empty());
+    }
+
+    #[test]
+    fn test_decimal_arithmetic() {
+        let tenth = Value::Decimal(Decimal::new(1, 1));
+        let fifth = Value::Decimal(Decimal::new(2, 1));
+
+        assert_eq!(VM::add(tenth.clone(), fifth.clone()), Value::Decimal(Decimal::new(3, 1)));
+        assert_eq!(VM::mul(tenth.clone(), Value::Int(3)), Value::Decimal(Decimal::new(3, 1)));
+        assert_eq!(VM::sub(Value::Int(1), tenth.clone()), Value::Decimal(Decimal::new(9, 1)));
+        assert!(VM::eq(&Value::Decimal(Decimal::from(2)), &Value::Int(2)));
+        assert_eq!(fifth.compare(&tenth), Some(Ordering::Greater));
+        assert_eq!(tenth.compare(&Value::Float(0.15)), Some(Ordering::Less));
+        assert_eq!(VM::div(tenth, Value::Int(0)), Err(VM::division_by_zero()));
+    }
+
+    #[test]
+    fn test_contains_on_arrays_and_strings() {
+        let cases = [
+            (Value::from(vec!["US", "UK"]), Value::from("UK"), true),
+            (Value::from(vec!["US", "UK"]), Value::from("FR"), false),
+            (Value::from("Partial refund issued"), Value::from("refund"), true),
+            (Value::from("Purchase"), Value::from("refund"), false),
+            (Value::from("Purchase 42"), Value::Int(42), false),
+            (Value::Int(12345), Value::from("23"), false),
+            (Value::Null, Value::from("x"), false),
+        ];
+
+        for (haystack, needle, expected) in cases {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            let bytecode = vec![
+                Instruction::Push(haystack.clone()),
+                Instruction::Push(needle.clone()),
+                Instruction::ArrayContains,
+            ];
+
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+            assert_eq!(ctx.pop(), Some(Value::Bool(expected)), "{:?} contains {:?}", haystack, needle);
+        }
+    }
+
+    #[test]
+    fn test_not_in_negates_contains() {
+        // `item not in x` compiles to ArrayContains followed by Not, so a
+        // non-collection right side is never "containing" anything
+        let cases = [
+            (Value::from(vec!["US", "UK"]), Value::from("UK"), false),
+            (Value::from(vec!["US", "UK"]), Value::from("FR"), true),
+            (Value::Int(5), Value::Int(5), true),
+            (Value::Null, Value::from("x"), true),
+        ];
+
+        for (haystack, needle, expected) in cases {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            let bytecode = vec![
+                Instruction::Push(haystack.clone()),
+                Instruction::Push(needle.clone()),
+                Instruction::ArrayContains,
+                Instruction::Not,
+            ];
+
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+            assert_eq!(ctx.pop(), Some(Value::Bool(expected)), "{:?} not in {:?}", needle, haystack);
+        }
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(VM::pow(Value::Int(2), Value::Int(10)), Value::Int(1024));
+        assert_eq!(VM::pow(Value::Float(2.0), Value::Float(0.5)), Value::Float(2f64.sqrt()));
+        assert_eq!(VM::pow(Value::Int(2), Value::Int(-1)), Value::Float(0.5));
+        assert_eq!(VM::pow(Value::Int(10), Value::Int(30)), Value::Float(1e30));
+        assert_eq!(
+            VM::pow(Value::Int(2), Value::Int(i64::MAX)),
+            Value::Float(f64::INFINITY)
+        );
+        assert_eq!(
+            VM::pow(Value::Decimal(Decimal::new(11, 1)), Value::Int(2)),
+            Value::Decimal(Decimal::new(121, 2))
+        );
+        assert_eq!(VM::pow(Value::from("2"), Value::Int(2)), Value::Null);
+    }
+
+    #[test]
+    fn test_null_comparisons_are_false() {
+        let pairs = [
+            (Value::Null, Value::Int(5)),
+            (Value::Int(5), Value::Null),
+            (Value::Null, Value::Null),
+        ];
+
+        for (lhs, rhs) in pairs {
+            assert_eq!(lhs.compare(&rhs), None);
+
+            for op in [Instruction::Gt, Instruction::Gte, Instruction::Lt, Instruction::Lte] {
+                let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+                let bytecode = vec![
+                    Instruction::Push(lhs.clone()),
+                    Instruction::Push(rhs.clone()),
+                    op.clone(),
+                ];
+
+                VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+                assert_eq!(ctx.pop(), Some(Value::Bool(false)), "{:?} {:?} {:?}", lhs, op, rhs);
+
+                // Under SQL semantics the comparison is unknown instead
+                ctx.null_semantics = NullSemantics::Sql;
+                VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+                assert_eq!(ctx.pop(), Some(Value::Null), "{:?} {:?} {:?}", lhs, op, rhs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sql_null_logic() {
+        let run = |semantics, bytecode: Vec<Instruction>| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            ctx.null_semantics = semantics;
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            ctx.stack
+        };
+        let binary = |a: Value, op: Instruction, b: Value| vec![Instruction::Push(a), Instruction::Push(b), op];
+        let (t, f, unknown) = (Value::Bool(true), Value::Bool(false), Value::Null);
+
+        // Kleene logic: only the operand that decides the result wins over unknown
+        let cases = [
+            (binary(unknown.clone(), Instruction::And, f.clone()), f.clone()),
+            (binary(unknown.clone(), Instruction::And, t.clone()), unknown.clone()),
+            (binary(t.clone(), Instruction::Or, unknown.clone()), t.clone()),
+            (binary(f.clone(), Instruction::Or, unknown.clone()), unknown.clone()),
+            (vec![Instruction::Push(unknown.clone()), Instruction::Not], unknown.clone()),
+        ];
+        for (bytecode, expected) in cases {
+            assert_eq!(run(NullSemantics::Sql, bytecode.clone()), vec![expected], "{:?}", bytecode);
+        }
+        assert_eq!(run(NullSemantics::Rust, binary(unknown.clone(), Instruction::Or, f.clone())), vec![f.clone()]);
+
+        // An unknown left operand does not short-circuit under SQL semantics
+        let short_and = vec![
+            Instruction::Push(unknown.clone()),
+            Instruction::ShortCircuitAnd(3),
+            Instruction::Push(Value::Int(1)),
+        ];
+        assert_eq!(run(NullSemantics::Rust, short_and.clone()), vec![]);
+        assert_eq!(run(NullSemantics::Sql, short_and), vec![unknown.clone(), Value::Int(1)]);
+
+        // JumpIfUnknown only acts on Null under SQL semantics
+        let jump = |condition: Value| {
+            vec![Instruction::Push(condition), Instruction::JumpIfUnknown(3), Instruction::Push(Value::Int(1))]
+        };
+        assert_eq!(run(NullSemantics::Rust, jump(unknown.clone())), vec![unknown.clone(), Value::Int(1)]);
+        assert_eq!(run(NullSemantics::Sql, jump(unknown.clone())), vec![]);
+        assert_eq!(run(NullSemantics::Sql, jump(f.clone())), vec![f, Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_array_push() {
+        let mut array = Value::from(vec![1, 2, 3]);
+        VM::array_push(&mut array, Value::Int(4), None).unwrap();
+        assert_eq!(array, Value::from(vec![1, 2, 3, 4]));
+
+        // Capped pushes drop the oldest elements
+        VM::array_push(&mut array, Value::Int(5), Some(&Value::Int(3))).unwrap();
+        assert_eq!(array, Value::from(vec![3, 4, 5]));
+
+        let mut missing = Value::Null;
+        VM::array_push(&mut missing, Value::Int(1), None).unwrap();
+        assert_eq!(missing, Value::from(vec![1]));
+
+        let mut scalar = Value::Int(7);
+        assert!(VM::array_push(&mut scalar, Value::Int(1), None).is_err());
+        assert!(VM::array_push(&mut array, Value::Int(6), Some(&Value::Int(-1))).is_err());
+        assert_eq!(scalar, Value::Int(7));
+        assert_eq!(array, Value::from(vec![3, 4, 5]));
+    }
 
+    #[test]
+    fn test_array_get() {
+        let array = Value::from(vec![10, 20]);
+
+        assert_eq!(VM::call_method(&array, "get", vec![Value::Int(1)]), Value::Int(20));
+        assert_eq!(VM::call_method(&array, "get", vec![Value::Int(2)]), Value::Null);
+        assert_eq!(VM::call_method(&array, "get", vec![Value::Int(-1)]), Value::Null);
+    }
+
+    #[test]
+    fn test_bitwise_ops() {
+        let run = |a: Value, b: Value, op: Instruction| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            VM::execute(
+                &[Instruction::Push(a), Instruction::Push(b), op],
+                &mut ctx,
+                &HashMap::default(),
+                &HashMap::default(),
+            );
+            assert!(ctx.errors.is_empty());
+            ctx.pop().unwrap()
+        };
+
+        assert_eq!(run(Value::Int(0b1100), Value::Int(0b1010), Instruction::BitAnd), Value::Int(0b1000));
+        assert_eq!(run(Value::Int(0b1100), Value::Int(0b1010), Instruction::BitOr), Value::Int(0b1110));
+        assert_eq!(run(Value::Int(0b1100), Value::Int(0b1010), Instruction::BitXor), Value::Int(0b0110));
+        assert_eq!(run(Value::Int(1), Value::Int(4), Instruction::Shl), Value::Int(16));
+        assert_eq!(run(Value::Int(-16), Value::Int(2), Instruction::Shr), Value::Int(-4));
+
+        // Out-of-range shifts and non-int operands yield Null
+        assert_eq!(run(Value::Int(1), Value::Int(64), Instruction::Shl), Value::Null);
+        assert_eq!(run(Value::Int(1), Value::Int(-1), Instruction::Shr), Value::Null);
+        assert_eq!(run(Value::Float(4.0), Value::Int(4), Instruction::BitAnd), Value::Null);
+        assert_eq!(run(Value::Bool(true), Value::Bool(false), Instruction::BitOr), Value::Null);
+        assert_eq!(run(Value::Int(4), Value::Null, Instruction::BitXor), Value::Null);
+    }
+
+    #[test]
+    fn test_int_float_equality() {
+        assert!(VM::eq(&Value::Int(1), &Value::Float(1.0)));
+        assert!(VM::eq(&Value::Float(-3.0), &Value::Int(-3)));
+        assert!(!VM::eq(&Value::Int(2), &Value::Float(2.5)));
+        assert!(!VM::eq(&Value::Int(1), &Value::Float(f64::NAN)));
+
+        // No rounding through f64: 2^53 + 1 is not representable as a float
+        let big = (1_i64 << 53) + 1;
+        assert!(!VM::eq(&Value::Int(big), &Value::Float((1_i64 << 53) as f64)));
+        assert!(!VM::eq(&Value::Int(i64::MAX), &Value::Float(i64::MAX as f64)));
+
+        // Other kinds stay strict
+        assert!(!VM::eq(&Value::from("1"), &Value::Int(1)));
+        assert!(!VM::eq(&Value::Bool(true), &Value::Int(1)));
+        assert!(!VM::eq(&Value::Null, &Value::Int(0)));
+        assert!(VM::eq(&Value::from("a"), &Value::from("a")));
+
+        // `!=` stays the negation of `==`
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        let bytecode = vec![
+            Instruction::Push(Value::Int(1)),
+            Instruction::Push(Value::Float(1.0)),
+            Instruction::Ne,
+            Instruction::Push(Value::Int(2)),
+            Instruction::Push(Value::Float(2.5)),
+            Instruction::Ne,
+        ];
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
         assert_eq!(ctx.pop(), Some(Value::Bool(true)));
+        assert_eq!(ctx.pop(), Some(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_division_by_zero_records_error() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+
+        let bytecode = vec![
+            Instruction::Push(Value::Int(10)),
+            Instruction::Push(Value::Int(0)),
+            Instruction::Div,
+            Instruction::Push(Value::Int(10)),
+            Instruction::Push(Value::Int(0)),
+            Instruction::Mod,
+        ];
+
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+        assert_eq!(ctx.pop(), Some(Value::Null));
+        assert_eq!(ctx.pop(), Some(Value::Null));
+        assert_eq!(ctx.errors.len(), 2);
+        assert_eq!(
+            ctx.errors[0],
+            ExecutionError::RuntimeError("division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn test_function_stack_effects_do_not_leak() {
+        let function = |name: &str, bytecode| {
+            let func = CompiledFunction {
+                name: name.to_string(),
+                params: Vec::new(),
+                bytecode,
+            };
+            (name.to_string(), func)
+        };
+        let functions: HashMap<String, CompiledFunction> = [
+            // Leaves extra values behind and never returns
+            function("noisy", vec![
+                Instruction::Push(Value::Int(1)),
+                Instruction::Push(Value::Int(2)),
+                Instruction::Push(Value::Int(100)),
+            ]),
+            // Tries to pop the caller's operand
+            function("greedy", vec![Instruction::Pop]),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        let bytecode = vec![
+            Instruction::Push(Value::Int(10)),
+            Instruction::CallGlobal("noisy".to_string(), 0),
+            Instruction::Pop,
+            Instruction::Push(Value::Int(5)),
+            Instruction::Gt,
+        ];
+        VM::execute(&bytecode, &mut ctx, &functions, &HashMap::default());
+
+        assert_eq!(ctx.stack, vec![Value::Bool(true)]);
+        assert!(ctx.errors.is_empty());
+
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        let bytecode = vec![
+            Instruction::Push(Value::Int(10)),
+            Instruction::CallGlobal("greedy".to_string(), 0),
+        ];
+        VM::execute(&bytecode, &mut ctx, &functions, &HashMap::default());
+
+        assert_eq!(ctx.stack, vec![Value::Int(10)]);
+        assert_eq!(ctx.errors, vec![ExecutionError::StackUnderflow]);
+        assert_eq!(ctx.stack_base, 0);
+    }
+
+    #[test]
+    fn test_execute_traced_sees_stack_before_each_instruction() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+        let bytecode = vec![
+            Instruction::Push(Value::Int(2)),
+            Instruction::Push(Value::Int(3)),
+            Instruction::Mul,
+        ];
+
+        let mut steps = Vec::new();
+        let mut tracer = |instruction: &Instruction, stack: &[Value]| {
+            steps.push((instruction.clone(), stack.to_vec()));
+        };
+        VM::execute_traced(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default(), Some(&mut tracer));
+
+        assert_eq!(
+            steps,
+            vec![
+                (Instruction::Push(Value::Int(2)), vec![]),
+                (Instruction::Push(Value::Int(3)), vec![Value::Int(2)]),
+                (Instruction::Mul, vec![Value::Int(2), Value::Int(3)]),
+            ]
+        );
+        assert_eq!(ctx.pop(), Some(Value::Int(6)));
+    }
+
+    #[test]
+    fn test_jump_if_not_null_coalesces() {
+        // left ?? 0, as the compiler emits it
+        let run = |left: Value| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            let bytecode = vec![
+                Instruction::Push(left),
+                Instruction::Dup,
+                Instruction::JumpIfNotNull(5),
+                Instruction::Pop,
+                Instruction::Push(Value::Int(0)),
+            ];
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            ctx.stack
+        };
+
+        assert_eq!(run(Value::Null), vec![Value::Int(0)]);
+        assert_eq!(run(Value::Int(7)), vec![Value::Int(7)]);
+        // Only null falls through; falsy values are kept
+        assert_eq!(run(Value::Bool(false)), vec![Value::Bool(false)]);
+        assert_eq!(run(Value::from("")), vec![Value::from("")]);
+    }
+
+    #[test]
+    fn test_integer_overflow_policies() {
+        let run = |policy, a: i64, op: Instruction, b: Option<i64>| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            ctx.numeric_overflow = policy;
+            let mut bytecode = vec![Instruction::Push(Value::Int(a))];
+            bytecode.extend(b.map(|b| Instruction::Push(Value::Int(b))));
+            bytecode.push(op);
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            (ctx.pop(), ctx.errors)
+        };
+        let (max, min) = (i64::MAX, i64::MIN);
+
+        let cases = [
+            (max, Instruction::Add, Some(1), min, max),
+            (min, Instruction::Sub, Some(1), max, min),
+            (max, Instruction::Mul, Some(2), -2, max),
+            (min, Instruction::Div, Some(-1), min, max),
+            (min, Instruction::Neg, None, min, max),
+        ];
+        for (a, op, b, wrapped, saturated) in cases {
+            assert_eq!(run(OverflowPolicy::Wrap, a, op.clone(), b), (Some(Value::Int(wrapped)), vec![]), "{:?}", op);
+            assert_eq!(run(OverflowPolicy::Saturate, a, op.clone(), b), (Some(Value::Int(saturated)), vec![]), "{:?}", op);
+
+            let (value, errors) = run(OverflowPolicy::Error, a, op.clone(), b);
+            assert_eq!(value, Some(Value::Null), "{:?}", op);
+            assert!(matches!(&errors[..], [ExecutionError::RuntimeError(e)] if e.starts_with("integer overflow")), "{:?}", errors);
+        }
+
+        // In-range results are unaffected, and only Ints are checked
+        assert_eq!(run(OverflowPolicy::Error, max - 1, Instruction::Add, Some(1)), (Some(Value::Int(max)), vec![]));
+        assert_eq!(run(OverflowPolicy::Error, 7, Instruction::Div, Some(-1)), (Some(Value::Int(-7)), vec![]));
+        assert_eq!(run(OverflowPolicy::Error, min, Instruction::Mod, Some(-1)), (Some(Value::Int(0)), vec![]));
+    }
+
+    #[test]
+    fn test_aggregates_follow_overflow_policy() {
+        let run = |policy, values: Vec<Value>, method: &str| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            ctx.numeric_overflow = policy;
+            let bytecode = vec![
+                Instruction::Push(Value::Array(values)),
+                Instruction::MethodCall(method.to_string(), 0),
+            ];
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            (ctx.pop(), ctx.errors)
+        };
+        let overflowing = || vec![Value::Int(i64::MAX), Value::Int(1)];
+
+        assert_eq!(run(OverflowPolicy::Wrap, overflowing(), "sum"), (Some(Value::Int(i64::MIN)), vec![]));
+        assert_eq!(run(OverflowPolicy::Saturate, overflowing(), "sum"), (Some(Value::Int(i64::MAX)), vec![]));
+        assert_eq!(
+            run(OverflowPolicy::Saturate, overflowing(), "avg"),
+            (Some(Value::Float(i64::MAX as f64 / 2.0)), vec![])
+        );
+        for method in ["sum", "avg"] {
+            let (value, errors) = run(OverflowPolicy::Error, overflowing(), method);
+            assert_eq!(value, Some(Value::Null));
+            assert!(matches!(&errors[..], [ExecutionError::RuntimeError(e)] if e.starts_with("integer overflow")), "{:?}", errors);
+        }
+
+        // Float sums that overflow to infinity are rejected like `+`
+        let (value, errors) = run(OverflowPolicy::Wrap, vec![Value::Float(f64::MAX), Value::Float(f64::MAX)], "sum");
+        assert_eq!(value, Some(Value::Null));
+        assert!(matches!(&errors[..], [ExecutionError::RuntimeError(e)] if e.contains("non-finite")), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_non_finite_floats_record_error() {
+        let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+
+        let bytecode = vec![
+            Instruction::Push(Value::Float(0.0)),
+            Instruction::Push(Value::Float(0.0)),
+            Instruction::Div,
+            Instruction::Push(Value::Float(1e308)),
+            Instruction::Push(Value::Float(10.0)),
+            Instruction::Mul,
+        ];
+
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+
+        assert_eq!(ctx.pop(), Some(Value::Null));
+        assert_eq!(ctx.pop(), Some(Value::Null));
+        assert_eq!(
+            ctx.errors,
+            vec![
+                ExecutionError::RuntimeError("division by zero".to_string()),
+                ExecutionError::RuntimeError(
+                    "arithmetic produced a non-finite number (inf)".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negate() {
+        let run = |operand: Value| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            let bytecode = vec![Instruction::Push(operand), Instruction::Neg];
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            (ctx.pop(), ctx.errors)
+        };
+
+        assert_eq!(run(Value::from("5")), (Some(Value::Int(-5)), vec![]));
+        assert_eq!(run(Value::from(" 2.5 ")), (Some(Value::Float(-2.5)), vec![]));
+        assert_eq!(run(Value::Null), (Some(Value::Null), vec![]));
+
+        for operand in [Value::Bool(true), Value::from("five")] {
+            let (value, errors) = run(operand.clone());
+            let expected = format!("cannot negate a {}", operand.value_type());
+            assert_eq!(value, Some(Value::Null));
+            assert_eq!(errors, vec![ExecutionError::RuntimeError(expected)]);
+        }
+    }
+
+    #[test]
+    fn test_object_methods() {
+        let object = Value::Object(HashMap::from_iter([
+            ("b".to_string(), Value::Int(2)),
+            ("a".to_string(), Value::from("x")),
+            ("c".to_string(), Value::Null),
+        ]));
+        let key = |k: &str| vec![Value::from(k)];
+
+        assert_eq!(VM::call_method(&object, "has", key("a")), Value::Bool(true));
+        assert_eq!(VM::call_method(&object, "has", key("c")), Value::Bool(true));
+        assert_eq!(VM::call_method(&object, "has", key("d")), Value::Bool(false));
+        assert_eq!(
+            VM::call_method(&object, "keys", vec![]),
+            Value::Array(vec![Value::from("a"), Value::from("b"), Value::from("c")])
+        );
+        assert_eq!(
+            VM::call_method(&object, "values", vec![]),
+            Value::Array(vec![Value::from("x"), Value::Int(2), Value::Null])
+        );
+
+        // Non-object receivers
+        let array = Value::Array(vec![Value::from("a")]);
+        assert_eq!(VM::call_method(&array, "has", key("a")), Value::Bool(false));
+        assert_eq!(VM::call_method(&Value::Null, "has", key("a")), Value::Bool(false));
+        assert_eq!(VM::call_method(&array, "keys", vec![]), Value::Null);
+        assert_eq!(VM::call_method(&Value::from("ab"), "values", vec![]), Value::Null);
+    }
+
+    #[test]
+    fn test_array_aggregates() {
+        let mixed = Value::Array(vec![
+            Value::Int(10),
+            Value::Float(2.5),
+            Value::from("n/a"),
+            Value::Null,
+            Value::Int(-3),
+        ]);
+        let ints = Value::Array(vec![Value::Int(4), Value::Int(1), Value::Int(7)]);
+        let empty = Value::Array(vec![]);
+        // `sum` and `avg` need the context's overflow policy
+        let aggregate = |array: &Value, method: &str| {
+            let mut ctx = ExecutionContext::new(Transaction::new(), UserProfile::new());
+            let bytecode = vec![Instruction::Push(array.clone()), Instruction::MethodCall(method.to_string(), 0)];
+            VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+            ctx.pop().unwrap()
+        };
+
+        assert_eq!(aggregate(&mixed, "sum"), Value::Float(9.5));
+        assert_eq!(aggregate(&mixed, "avg"), Value::Float(9.5 / 3.0));
+        assert_eq!(VM::call_method(&mixed, "min", vec![]), Value::Float(-3.0));
+        assert_eq!(VM::call_method(&mixed, "max", vec![]), Value::Float(10.0));
+
+        // All-Int arrays stay Int, except for the average
+        assert_eq!(aggregate(&ints, "sum"), Value::Int(12));
+        assert_eq!(aggregate(&ints, "avg"), Value::Float(4.0));
+        assert_eq!(VM::call_method(&ints, "min", vec![]), Value::Int(1));
+        assert_eq!(VM::call_method(&ints, "max", vec![]), Value::Int(7));
+
+        let cents = Value::Array(vec![Value::Decimal(Decimal::new(1050, 2)), Value::Int(2)]);
+        assert_eq!(aggregate(&cents, "avg"), Value::Decimal(Decimal::new(625, 2)));
+
+        assert_eq!(aggregate(&empty, "sum"), Value::Int(0));
+        assert_eq!(aggregate(&empty, "avg"), Value::Null);
+        assert_eq!(VM::call_method(&empty, "min", vec![]), Value::Null);
+        assert_eq!(VM::call_method(&empty, "max", vec![]), Value::Null);
+        assert_eq!(aggregate(&Value::Int(3), "sum"), Value::Null);
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let s = Value::from("  Fraud@Test.COM ");
+
+        assert_eq!(VM::call_method(&s, "trim", vec![]), Value::from("Fraud@Test.COM"));
+        assert_eq!(VM::call_method(&s, "toLowerCase", vec![]), Value::from("  fraud@test.com "));
+        assert_eq!(VM::call_method(&s, "toUpperCase", vec![]), Value::from("  FRAUD@TEST.COM "));
+        assert_eq!(VM::call_method(&s, "startsWith", vec![Value::from("  F")]), Value::Bool(true));
+        assert_eq!(VM::call_method(&s, "endsWith", vec![Value::from(".com")]), Value::Bool(false));
+        assert_eq!(VM::call_method(&s, "contains", vec![Value::from("@")]), Value::Bool(true));
+
+        // Type mismatches yield Null
+        assert_eq!(VM::call_method(&s, "startsWith", vec![Value::Int(1)]), Value::Null);
+        assert_eq!(VM::call_method(&Value::Int(5), "toLowerCase", vec![]), Value::Null);
     }
 
     #[test]
@@ -478,16 +1853,20 @@ mod tests {
             Transaction::new(),
             UserProfile::new().with_field("count", Value::Int(5)),
         );
+        let mut symbols = SymbolTable::default();
+        let count = symbols.intern("count");
+        ctx.bind_symbols(Arc::new(symbols));
 
         let bytecode = vec![
-            Instruction::LoadProfileField("count".to_string()),
+            Instruction::LoadProfileField(count),
             Instruction::Push(Value::Int(1)),
             Instruction::Add,
-            Instruction::StoreProfileField("count".to_string()),
+            Instruction::StoreProfileField(count),
         ];
 
-        VM::execute(&bytecode, &mut ctx, &HashMap::default());
+        VM::execute(&bytecode, &mut ctx, &HashMap::default(), &HashMap::default());
+        ctx.flush_fields();
 
-        assert_eq!(ctx.get_profile_field("count"), Value::Int(6));
+        assert_eq!(ctx.profile.fields.get("count"), Some(&Value::Int(6)));
     }
 }
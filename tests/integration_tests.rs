@@ -1,7 +1,8 @@
 // tests/integration_tests.rs
 //! Integration tests for the fraud rule engine
 
-use fraud_rule_engine::{RuleEngine, Transaction, UserProfile, Value, Action};
+use fraud_rule_engine::{Action, ActionRegistry, CompilationError, CompileLimits, Decision, EngineConfig, ErrorLocation, ExecutionError, ExecutionMode, FieldChange, FraudScorePolicy, HostFn, HostFunctions, NullSemantics, OverflowPolicy, RuleEngine, Schema, Transaction, UserProfile, Value, ValueType};
+use std::sync::Arc;
 
 #[test]
 fn test_simple_rule_execution() {
@@ -91,6 +92,35 @@ fn test_if_else_statement() {
     }
 }
 
+#[test]
+fn test_else_if_chain() {
+    let dsl = r#"
+        rule "tiered_amount" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.9);
+            } else if (txn.amount > 500) {
+                setFraudScore(0.5);
+            } else {
+                setFraudScore(0.1);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    for (amount, expected) in [(5000.0, 0.9), (750.0, 0.5), (100.0, 0.1)] {
+        let txn = Transaction::new().with_field("amount", Value::Float(amount));
+        let result = engine.execute(txn, UserProfile::new());
+        
+        assert_eq!(result.actions.len(), 1);
+        match &result.actions[0] {
+            Action::SetFraudScore { score } => assert_eq!(*score, expected),
+            _ => panic!("Expected SetFraudScore"),
+        }
+    }
+}
+
 #[test]
 fn test_short_circuit() {
     let dsl = r#"
@@ -227,168 +257,3981 @@ fn test_global_functions() {
 }
 
 #[test]
-fn test_create_case_action() {
+fn test_function_return_values() {
     let dsl = r#"
-        rule "test" {
+        function weighted(amount, weight) {
+            return amount * weight + 1;
+        }
+        
+        function firstOver(values, limit) {
+            for (v in values) {
+                if (v > limit) { return v; }
+            }
+        }
+        
+        function clampScore(score) {
+            if (score > 1) { return 1.0; }
+            return score;
+        }
+        
+        rule "main" {
             priority: 100,
-            if (txn.amount > 1000) {
-                createCase("HIGH", "Large transaction");
+            if (true) {
+                let w = weighted(txn.amount, 2);
+                profile.weighted = w;
+                profile.nested = weighted(weighted(1, 2), 3);
+                profile.first = firstOver(profile.values, 4);
+                profile.none = firstOver(profile.values, 10);
+                profile.score = clampScore(w / 10);
             }
         }
+        
+        rule "after" {
+            priority: 50,
+            if (true) { profile.after = true; }
+        }
     "#;
     
     let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let profile = UserProfile::new()
+        .with_field("values", Value::Array(vec![Value::Int(1), Value::Int(5), Value::Int(9)]));
+    let result = engine.execute(Transaction::new().with_field("amount", Value::Int(20)), profile);
     
-    let transaction = Transaction::new()
-        .with_field("amount", Value::Float(5000.0));
+    assert_eq!(result.profile.fields.get("weighted"), Some(&Value::Int(41)));
+    assert_eq!(result.profile.fields.get("nested"), Some(&Value::Int(10)));
+    assert_eq!(result.profile.fields.get("first"), Some(&Value::Int(5)));
+    // Falling off the end returns null
+    assert_eq!(result.profile.fields.get("none"), Some(&Value::Null));
+    assert_eq!(result.profile.fields.get("score"), Some(&Value::Float(1.0)));
+    // `return` in a function ends the call, not the rule run
+    assert_eq!(result.profile.fields.get("after"), Some(&Value::Bool(true)));
+    assert!(result.metadata.errors.is_empty());
+}
+
+#[test]
+fn test_return_value_outside_function_is_rejected() {
+    let err = RuleEngine::from_dsl(r#"rule "r" { priority: 1, if (true) { return 5; } }"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("return with a value is only allowed in functions"), "{}", err);
+}
+
+#[test]
+fn test_negated_conditions() {
+    let dsl = r#"
+        rule "guards" {
+            priority: 100,
+            if (true) {
+                if (!txn.verified) { profile.unverified = true; }
+                if (txn.country not in profile.home_countries) { profile.abroad = true; }
+                if (txn.verified && !(txn.amount > 100)) { profile.small_verified = true; }
+                if (!txn.verified || txn.amount > 100) { profile.review = true; }
+                profile.label = !txn.verified ? "new" : "known";
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let homes = Value::Array(vec![Value::from("US"), Value::from("CA")]);
     
-    let result = engine.execute(transaction, UserProfile::new());
+    let cases = [
+        (false, "US", 50, [true, false, false, true], "new"),
+        (true, "FR", 50, [false, true, true, false], "known"),
+        (true, "CA", 500, [false, false, false, true], "known"),
+    ];
+    for (verified, country, amount, expected, label) in cases {
+        let txn = Transaction::new()
+            .with_field("verified", Value::Bool(verified))
+            .with_field("country", Value::from(country))
+            .with_field("amount", Value::Int(amount));
+        let result = engine.execute(txn, UserProfile::new().with_field("home_countries", homes.clone()));
+        
+        let flags = ["unverified", "abroad", "small_verified", "review"]
+            .map(|field| result.profile.fields.get(field) == Some(&Value::Bool(true)));
+        assert_eq!(flags, expected, "{} {} {}", verified, country, amount);
+        assert_eq!(result.profile.fields.get("label"), Some(&Value::from(label)));
+    }
+}
+
+#[test]
+fn test_from_sources_shares_functions() {
+    let functions = r#"
+        const LARGE = 1000;
+        
+        function bump(profile) {
+            profile.hits = profile.hits + 1;
+        }
+    "#;
+    let rules = r#"
+        rule "large" {
+            priority: 10,
+            if (txn.amount > LARGE) {
+                bump(profile);
+                setFraudScore(0.5);
+            }
+        }
+    "#;
     
-    assert_eq!(result.actions.len(), 1);
-    match &result.actions[0] {
-        Action::CreateCase { severity, reason, .. } => {
-            assert_eq!(severity, "HIGH");
-            assert_eq!(reason, "Large transaction");
+    let engine = RuleEngine::from_sources(&[("functions.rules", functions), ("amount.rules", rules)]).unwrap();
+    let result = engine.execute(
+        Transaction::new().with_field("amount", Value::Int(5000)),
+        UserProfile::new().with_field("hits", Value::Int(1)),
+    );
+    
+    assert_eq!(result.profile.fields.get("hits"), Some(&Value::Int(2)));
+    assert_eq!(result.actions, vec![Action::set_fraud_score(0.5)]);
+}
+
+#[test]
+fn test_from_sources_rejects_duplicates() {
+    let a = r#"rule "velocity" { priority: 1, if (true) {} }"#;
+    let b = r#"
+        group "checks" {
+            priority: 5,
+            rule "velocity" { priority: 1, if (true) {} }
         }
-        _ => panic!("Expected CreateCase action"),
-    }
+    "#;
+    
+    let err = RuleEngine::from_sources(&[("a.rules", a), ("b.rules", b)]).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Compilation error: rule 'velocity' is defined in both 'a.rules' and 'b.rules'"
+    );
+    
+    let err = RuleEngine::from_sources(&[("a.rules", "function f() {} function f() {}")]).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Compilation error: function 'f' is defined more than once in 'a.rules'"
+    );
+    
+    let err = RuleEngine::from_sources(&[("a.rules", a), ("c.rules", "rule \"x\" {")]).err().unwrap();
+    assert!(err.to_string().ends_with("(in 'c.rules')"), "{}", err);
 }
 
 #[test]
-fn test_bytecode_serialization() {
+fn test_array_aggregate_methods() {
     let dsl = r#"
-        rule "test" {
+        rule "velocity" {
             priority: 100,
-            if (txn.amount > 1000) {
-                setFraudScore(0.8);
+            if (profile.recent_amounts.sum() > 10000) {
+                profile.avg = profile.recent_amounts.avg();
+                profile.spread = profile.recent_amounts.max() - profile.recent_amounts.min();
+                profile.empty_avg = profile.none.avg();
             }
         }
     "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let amounts = Value::Array(vec![Value::Int(4000), Value::Float(6500.5), Value::Int(500)]);
+    let profile = UserProfile::new()
+        .with_field("recent_amounts", amounts)
+        .with_field("none", Value::Array(vec![]));
+    let result = engine.execute(Transaction::new(), profile);
     
+    assert_eq!(result.profile.fields.get("avg"), Some(&Value::Float(11000.5 / 3.0)));
+    assert_eq!(result.profile.fields.get("spread"), Some(&Value::Float(6000.5)));
+    assert_eq!(result.profile.fields.get("empty_avg"), Some(&Value::Null));
+}
+
+#[test]
+fn test_execute_stream_is_lazy() {
+    let dsl = r#"
+        rule "count" {
+            priority: 100,
+            if (txn.amount > 100) {
+                profile.flagged = true;
+                setFraudScore(0.5);
+            }
+        }
+    "#;
     let engine = RuleEngine::from_dsl(dsl).unwrap();
     
-    // Serialize to bytecode
-    let bytecode = engine.to_bytecode().unwrap();
+    let consumed = std::cell::Cell::new(0);
+    let inputs = [50, 500, 5000].into_iter().map(|amount| {
+        consumed.set(consumed.get() + 1);
+        (Transaction::new().with_field("amount", Value::Int(amount)), UserProfile::new())
+    });
     
-    // Deserialize back
-    let engine2 = RuleEngine::from_bytecode(&bytecode).unwrap();
+    let mut results = engine.execute_stream(inputs);
+    assert_eq!(consumed.get(), 0);
     
-    // Test that it works the same
-    let transaction = Transaction::new()
-        .with_field("amount", Value::Float(5000.0));
+    let first = results.next().unwrap();
+    assert_eq!(consumed.get(), 1);
+    assert!(first.actions.is_empty());
     
-    let result = engine2.execute(transaction, UserProfile::new());
+    // State from one input does not leak into the next
+    let rest: Vec<_> = results.collect();
+    assert_eq!(consumed.get(), 3);
+    assert_eq!(rest.len(), 2);
+    for result in &rest {
+        assert_eq!(result.actions, vec![Action::set_fraud_score(0.5)]);
+        assert_eq!(result.profile.fields.get("flagged"), Some(&Value::Bool(true)));
+    }
     
-    assert_eq!(result.actions.len(), 1);
+    fn assert_send<T: Send>(_: T) {}
+    assert_send(engine.execute_stream(vec![(Transaction::new(), UserProfile::new())]));
 }
 
 #[test]
-fn test_rule_priority_ordering() {
+fn test_numeric_overflow_policy() {
     let dsl = r#"
-        rule "low_priority" {
-            priority: 50,
+        rule "count" {
+            priority: 100,
             if (true) {
-                profile.order = "second";
+                profile.txn_count += txn.count;
+                profile.literal = 9223372036854775807 + 1;
             }
         }
-        
-        rule "high_priority" {
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let run = |policy| {
+        engine.clone().with_numeric_overflow(policy).execute(
+            Transaction::new().with_field("count", Value::Int(10)),
+            UserProfile::new().with_field("txn_count", Value::Int(i64::MAX - 5)),
+        )
+    };
+    
+    // Wrap is the default
+    let result = engine.execute(
+        Transaction::new().with_field("count", Value::Int(10)),
+        UserProfile::new().with_field("txn_count", Value::Int(i64::MAX - 5)),
+    );
+    assert_eq!(result.profile.fields.get("txn_count"), Some(&Value::Int(i64::MIN + 4)));
+    
+    let result = run(OverflowPolicy::Saturate);
+    assert_eq!(result.profile.fields.get("txn_count"), Some(&Value::Int(i64::MAX)));
+    // Literal arithmetic is not folded when it overflows, so it follows the policy too
+    assert_eq!(result.profile.fields.get("literal"), Some(&Value::Int(i64::MAX)));
+    
+    let result = run(OverflowPolicy::Error);
+    assert_eq!(result.profile.fields.get("txn_count"), Some(&Value::Null));
+    assert_eq!(
+        result.metadata.errors[0],
+        ExecutionError::RuntimeError(format!("integer overflow: {} + 10", i64::MAX - 5))
+    );
+    assert_eq!(result.metadata.errors.len(), 2);
+}
+
+#[test]
+fn test_null_coalescing_default() {
+    let dsl = r#"
+        rule "count" {
             priority: 100,
             if (true) {
-                profile.order = "first";
+                profile.count = (profile.count ?? 0) + 1;
+                profile.country = txn.country ?? profile.home_country ?? "unknown";
             }
         }
     "#;
-    
     let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
     let result = engine.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(result.profile.fields.get("count"), Some(&Value::Int(1)));
+    assert_eq!(result.profile.fields.get("country"), Some(&Value::from("unknown")));
     
-    // High priority rule should execute first and set order to "first"
-    // Then low priority sets it to "second"
-    assert_eq!(
-        result.profile.fields.get("order"),
-        Some(&Value::String("second".to_string()))
-    );
+    let profile = UserProfile::new()
+        .with_field("count", Value::Int(4))
+        .with_field("home_country", Value::from("NZ"));
+    let result = engine.execute(Transaction::new(), profile);
+    assert_eq!(result.profile.fields.get("count"), Some(&Value::Int(5)));
+    assert_eq!(result.profile.fields.get("country"), Some(&Value::from("NZ")));
 }
 
 #[test]
-fn test_disabled_rule() {
+fn test_execution_result_serializes_to_json() {
     let dsl = r#"
-        rule "disabled" {
+        rule "flag" {
             priority: 100,
-            enabled: false,
-            if (true) {
+            if (txn.amount > 100) {
+                profile.flagged = true;
                 setFraudScore(0.9);
             }
         }
-        
-        rule "enabled" {
-            priority: 90,
-            enabled: true,
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new().with_field("amount", Value::Int(500));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&result).unwrap()).unwrap();
+    assert_eq!(json["profile"]["flagged"], serde_json::json!({"Bool": true}));
+    assert_eq!(json["actions"][0], serde_json::json!({"type": "set_fraud_score", "score": 0.9}));
+    assert_eq!(json["metadata"]["executed_rules"], serde_json::json!(["flag"]));
+    
+    let metadata = &result.metadata;
+    assert_eq!(json["metadata"]["total_duration_us"], metadata.total_duration.as_micros() as u64);
+    assert_eq!(json["metadata"]["rule_timings_us"]["flag"], metadata.rule_timings["flag"].as_micros() as u64);
+    assert!(json["metadata"].get("total_duration").is_none());
+}
+
+#[test]
+fn test_object_methods() {
+    let dsl = r#"
+        rule "limits" {
+            priority: 100,
             if (true) {
-                setFraudScore(0.5);
+                let limits = {card: 500, wire: 2000};
+                profile.has_wire = limits.has("wire");
+                profile.has_cash = limits.has("cash");
+                profile.kinds = limits.keys();
+                profile.limits = limits.values();
+                
+                let flagged = 0;
+                for (count in profile.merchant_counts.values()) {
+                    if (count > 3) { flagged = flagged + 1; }
+                }
+                profile.merchants = profile.merchant_counts.keys();
+                profile.flagged_merchants = flagged;
+                profile.missing = profile.none.keys();
             }
         }
     "#;
-    
     let engine = RuleEngine::from_dsl(dsl).unwrap();
-    let result = engine.execute(Transaction::new(), UserProfile::new());
     
-    // Only the enabled rule should execute
-    assert_eq!(result.metadata.executed_rules.len(), 1);
-    assert_eq!(result.metadata.skipped_rules.len(), 1);
+    let counts = Value::Object([("m1", 5), ("m2", 1), ("m3", 4)].into_iter().map(|(k, v)| (k.to_string(), Value::Int(v))).collect());
+    let profile = UserProfile::new().with_field("merchant_counts", counts);
+    let result = engine.execute(Transaction::new(), profile);
+    let field = |name: &str| result.profile.fields.get(name).cloned();
     
-    match &result.actions[0] {
-        Action::SetFraudScore { score } => assert_eq!(*score, 0.5),
-        _ => panic!("Expected SetFraudScore"),
-    }
+    assert_eq!(field("has_wire"), Some(Value::Bool(true)));
+    assert_eq!(field("has_cash"), Some(Value::Bool(false)));
+    assert_eq!(field("kinds"), Some(Value::Array(vec![Value::from("card"), Value::from("wire")])));
+    assert_eq!(field("limits"), Some(Value::Array(vec![Value::Int(500), Value::Int(2000)])));
+    assert_eq!(field("flagged_merchants"), Some(Value::Int(2)));
+    assert_eq!(field("merchants"), Some(Value::Array(vec![Value::from("m1"), Value::from("m2"), Value::from("m3")])));
+    assert_eq!(field("missing"), Some(Value::Null));
 }
 
 #[test]
-fn test_performance_500_rules() {
-    use std::time::Instant;
+fn test_compile_warns_about_statements_after_return() {
+    let dsl = r#"
+        rule "early_exit" {
+            priority: 100,
+            return;
+            setFraudScore(0.9);
+        }
+        
+        rule "fine" {
+            priority: 50,
+            if (txn.amount > 100) { return; }
+            setFraudScore(0.5);
+        }
+    "#;
+    let program = fraud_rule_engine::parser::parse(dsl).unwrap();
+    let ((rules, ..), warnings) = fraud_rule_engine::compiler::compile_with_warnings(
+        program,
+        &ActionRegistry::default(),
+        &HostFunctions::default(),
+        &Schema::default(),
+        &CompileLimits::default(),
+    )
+    .unwrap();
     
-    // Generate 500 simple rules
-    let mut dsl = String::new();
-    for i in 0..500 {
-        dsl.push_str(&format!(
-            r#"
-            rule "rule_{}" {{
-                priority: {},
-                if (txn.amount > {}) {{
-                    profile.rule_{}_executed = true;
-                }}
-            }}
-            "#,
-            i, 1000 - i, i * 10, i
-        ));
-    }
+    assert_eq!(rules.len(), 2);
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert_eq!(warnings[0].to_string(), "rule 'early_exit', line 5: unreachable statement after return");
+}
+
+#[test]
+fn test_signed_operands() {
+    let dsl = r#"
+        rule "signs" {
+            priority: 100,
+            if (txn.delta > -"5" && txn.delta < +5) {
+                profile.limit = -txn.limit;
+                profile.plus = +5;
+                profile.bad = -true;
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("delta", Value::Int(3))
+        .with_field("limit", Value::from("250"));
+    let result = engine.execute(txn, UserProfile::new());
     
-    let engine = RuleEngine::from_dsl(&dsl).unwrap();
+    assert_eq!(result.profile.fields.get("limit"), Some(&Value::Int(-250)));
+    assert_eq!(result.profile.fields.get("plus"), Some(&Value::Int(5)));
+    assert_eq!(result.profile.fields.get("bad"), Some(&Value::Null));
+    assert_eq!(result.metadata.errors, vec![ExecutionError::RuntimeError("cannot negate a bool".to_string())]);
+}
+
+#[test]
+fn test_suppression_cooldown() {
+    let dsl = r#"
+        const CASE_KEY = "large_amount";
+        
+        rule "large_amount" {
+            priority: 100,
+            if (txn.amount > 1000 && !suppressed(CASE_KEY, 24)) {
+                createCase("HIGH", "large amount");
+                suppress("large_amount");
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = || Transaction::new().with_field("amount", Value::Int(5000));
+    let cases = |result: &fraud_rule_engine::ExecutionResult| {
+        result.actions.iter().filter(|a| matches!(a, Action::CreateCase { .. })).count()
+    };
     
-    let transaction = Transaction::new()
-        .with_field("amount", Value::Float(5000.0));
+    let first = engine.execute(txn(), UserProfile::new());
+    assert_eq!(cases(&first), 1);
+    assert!(matches!(first.profile.fields.get("_suppress_large_amount"), Some(Value::DateTime(_))));
     
-    let profile = UserProfile::new();
+    // Within the window the case is not created again
+    let second = engine.execute(txn(), first.profile);
+    assert_eq!(cases(&second), 0);
     
-    // Warm up
-    for _ in 0..10 {
-        engine.execute(transaction.clone(), profile.clone());
-    }
+    // Once the window has passed it fires again
+    let Some(Value::DateTime(at)) = second.profile.fields.get("_suppress_large_amount") else {
+        panic!("expected a suppression timestamp");
+    };
+    let expired = UserProfile::new().with_field("_suppress_large_amount", Value::DateTime(at - 25 * 3_600_000));
+    assert_eq!(cases(&engine.execute(txn(), expired)), 1);
     
-    // Measure
-    let start = Instant::now();
-    let iterations = 100;
+    let err = RuleEngine::from_dsl(r#"rule "r" { if (suppressed(txn.kind, 1)) {} }"#).err().unwrap();
+    assert!(err.to_string().contains("suppressed() key must be a string literal or string constant"), "{}", err);
+}
+
+#[test]
+fn test_conversion_builtins() {
+    let dsl = r#"
+        rule "limit" {
+            priority: 100,
+            if (toInt(txn.amount) > 500) {
+                profile.over = true;
+                profile.bad = toInt(txn.note);
+                profile.label = toString(toFloat(txn.amount) / 4);
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("amount", Value::from("1000"))
+        .with_field("note", Value::from("n/a"));
+    let result = engine.execute(txn, UserProfile::new());
     
-    for _ in 0..iterations {
-        engine.execute(transaction.clone(), profile.clone());
+    assert_eq!(result.profile.fields.get("over"), Some(&Value::Bool(true)));
+    assert_eq!(result.profile.fields.get("bad"), Some(&Value::Int(0)));
+    assert_eq!(result.profile.fields.get("label"), Some(&Value::from("250")));
+    
+    // Without the conversion the string never compares with a number
+    let engine = RuleEngine::from_dsl(&dsl.replace("toInt(txn.amount)", "txn.amount")).unwrap();
+    let txn = Transaction::new().with_field("amount", Value::from("1000"));
+    assert!(!engine.execute(txn, UserProfile::new()).profile.fields.contains_key("over"));
+}
+
+#[test]
+fn test_compile_limits() {
+    let nested = |depth: usize| {
+        format!(
+            r#"rule "deep" {{ priority: 1, if (txn.amount > {}1{}) {{ setFraudScore(0.5); }} }}"#,
+            "(".repeat(depth),
+            ")".repeat(depth)
+        )
+    };
+    let limits = CompileLimits::new().with_max_expression_depth(32);
+    
+    assert!(RuleEngine::from_dsl_with_limits(&nested(20), limits).is_ok());
+    match RuleEngine::from_dsl_with_limits(&nested(40), limits) {
+        Err(CompilationError::CompileError(message)) => {
+            assert!(message.starts_with("nesting exceeds the limit of 32 at 1:"), "{}", message)
+        }
+        other => panic!("expected a nesting error, got {:?}", other.err()),
     }
     
-    let elapsed = start.elapsed();
-    let avg_time = elapsed / iterations;
+    // The default limit also stops input that would overflow the stack
+    assert!(RuleEngine::from_dsl(&nested(60)).is_ok());
+    assert!(matches!(RuleEngine::from_dsl(&nested(100_000)), Err(CompilationError::CompileError(_))));
+    let negations = format!(r#"rule "r" {{ priority: 1, if ({}true) {{}} }}"#, "!".repeat(100_000));
+    assert!(matches!(RuleEngine::from_dsl(&negations), Err(CompilationError::CompileError(_))));
     
-    println!("Average execution time for 500 rules: {:?}", avg_time);
+    let dsl = r#"
+        rule "a" { priority: 2, if (txn.amount > 10) { profile.x = 1; profile.y = 2; } }
+        rule "b" { priority: 1, if (true) { setFraudScore(0.1); } }
+    "#;
+    let error = |limits| RuleEngine::from_dsl_with_limits(dsl, limits).err().map(|e| e.to_string());
+    assert_eq!(error(CompileLimits::new()), None);
+    assert_eq!(
+        error(CompileLimits::new().with_max_rules(1)),
+        Some("Compilation error: too many rules: 2 (the limit is 1)".to_string())
+    );
+    assert_eq!(
+        error(CompileLimits::new().with_max_statements_per_rule(2)),
+        Some("Compilation error: too many statements in rule 'a': 3 (the limit is 2)".to_string())
+    );
+    assert!(error(CompileLimits::new().with_max_instructions(5)).unwrap().contains("too many bytecode instructions"));
     
-    // Should be under 2ms on most systems
-    assert!(avg_time.as_millis() < 5, "Execution took {:?}, expected < 5ms", avg_time);
+    // Rules added later are held to the same limits
+    let mut engine = RuleEngine::from_dsl_with_limits(dsl, CompileLimits::new().with_max_rules(2)).unwrap();
+    let err = engine.add_rule(r#"rule "c" { priority: 1, if (true) {} }"#).unwrap_err();
+    assert_eq!(err.to_string(), "Compilation error: too many rules: 3 (the limit is 2)");
+}
+
+#[test]
+fn test_any_all_quantifiers() {
+    let dsl = r#"
+        const LIMIT = 5000;
+        
+        rule "quantifiers" {
+            priority: 100,
+            if (true) {
+                profile.any_large = profile.recent.any(x => x > LIMIT);
+                profile.all_small = profile.recent.all(amount => amount < LIMIT);
+                profile.all_positive = profile.recent.all(x => x > 0);
+                profile.any_over_limit = profile.recent.any(LIMIT => LIMIT > 9000);
+                profile.empty_any = profile.none.any(x => true);
+                profile.empty_all = profile.none.all(x => false);
+                profile.nested = profile.batches.any(b => b.all(x => x > 100));
+                profile.limit = LIMIT;
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let ints = |xs: &[i64]| Value::Array(xs.iter().map(|&x| Value::Int(x)).collect());
+    let profile = UserProfile::new()
+        .with_field("recent", Value::Array(vec![Value::Int(120), Value::Float(7500.5), Value::Int(40)]))
+        .with_field("none", Value::Array(vec![]))
+        .with_field("batches", Value::Array(vec![ints(&[50, 300]), ints(&[200, 400])]));
+    let result = engine.execute(Transaction::new(), profile);
+    let field = |name: &str| result.profile.get_bool(name);
+    
+    assert_eq!(field("any_large"), Some(true));
+    assert_eq!(field("all_small"), Some(false));
+    assert_eq!(field("all_positive"), Some(true));
+    assert_eq!(field("any_over_limit"), Some(false));
+    assert_eq!(field("empty_any"), Some(false));
+    assert_eq!(field("empty_all"), Some(true));
+    assert_eq!(field("nested"), Some(true));
+    // A lambda parameter only shadows a constant inside its predicate
+    assert_eq!(result.profile.get_int("limit"), Some(5000));
+    assert!(result.metadata.errors.is_empty());
+    
+    // `any` stops at the first true element, `all` at the first false one
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mut host_functions = HostFunctions::default();
+    let check: HostFn = Arc::new(move |args| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        args[0].clone()
+    });
+    host_functions.insert("check".to_string(), check);
+    let engine = RuleEngine::from_dsl_with_host_functions(
+        r#"rule "r" { priority: 1, if (profile.flags.any(f => check(f)) && !profile.flags.all(f => check(f))) {} }"#,
+        host_functions,
+    )
+    .unwrap();
+    let flags = Value::Array(vec![Value::Bool(false), Value::Bool(true), Value::Bool(true)]);
+    engine.execute(Transaction::new(), UserProfile::new().with_field("flags", flags));
+    assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 2 + 1);
+    
+    let err = RuleEngine::from_dsl(r#"rule "r" { if (true) { profile.f = x => 1; } }"#).err().unwrap();
+    assert!(err.to_string().contains("'x => 1' can only be the argument of any() or all()"), "{}", err);
+    let err = RuleEngine::from_dsl(r#"rule "r" { if (profile.a.any(1)) {} }"#).err().unwrap();
+    assert!(err.to_string().contains("any() takes a lambda"), "{}", err);
+}
+
+#[test]
+fn test_quantifier_parameter_does_not_overwrite_outer_local() {
+    let dsl = r#"
+        rule "scoped" {
+            if (true) {
+                let x = 5;
+                profile.any_large = txn.items.any(x => x > 100);
+                profile.after_any = x;
+                profile.all_small = txn.items.all(x => x < 100);
+                profile.after_all = x;
+                profile.nested = txn.batches.any(x => x.all(x => x > 0));
+                profile.after_nested = x;
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("items", Value::Array(vec![Value::Int(1), Value::Int(2)]))
+        .with_field("batches", Value::Array(vec![Value::Array(vec![Value::Int(3)])]));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert_eq!(result.profile.get_bool("any_large"), Some(false));
+    assert_eq!(result.profile.get_bool("all_small"), Some(true));
+    assert_eq!(result.profile.get_bool("nested"), Some(true));
+    assert_eq!(result.profile.get_int("after_any"), Some(5));
+    assert_eq!(result.profile.get_int("after_all"), Some(5));
+    assert_eq!(result.profile.get_int("after_nested"), Some(5));
+}
+
+#[test]
+fn test_compile_report() {
+    let dsl = r#"
+        function isLarge(amount) { return amount > 1000; }
+        
+        rule "large" {
+            priority: 100,
+            if (isLarge(txn.amount) && profile.country != txn.country) {
+                createCase("HIGH", "large foreign transaction");
+                profile.flagged = true;
+            }
+        }
+        
+        rule "velocity" {
+            priority: 50,
+            if (profile.txn_count_1h > 20) {
+                setFraudScore(0.7);
+                return;
+                setDecision("BLOCK");
+            }
+        }
+    "#;
+    let (engine, report) = RuleEngine::from_dsl_with_report(dsl).unwrap();
+    
+    assert_eq!(report.fields, ["profile.country", "profile.flagged", "profile.txn_count_1h", "txn.amount", "txn.country"]);
+    assert_eq!(report.functions, ["isLarge"]);
+    assert_eq!(report.actions, ["createCase", "setDecision", "setFraudScore"]);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(report.warnings[0].owner, "rule 'velocity'");
+    
+    // Sizes match the exported bytecode
+    let exported: serde_json::Value = serde_json::from_str(&engine.to_json().unwrap()).unwrap();
+    let length = |item: &serde_json::Value| item["bytecode"].as_array().unwrap().len();
+    let rule_sizes: Vec<(String, usize)> = exported[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| (rule["id"].as_str().unwrap().to_string(), length(rule)))
+        .collect();
+    assert_eq!(report.rule_sizes, rule_sizes);
+    assert_eq!(report.rule_sizes[0].0, "large");
+    assert!(report.rule_sizes.iter().all(|(_, size)| *size > 0));
+    
+    let function_size = length(&exported[1][0]);
+    let rule_total: usize = report.rule_sizes.iter().map(|(_, size)| size).sum();
+    assert_eq!(report.total_instructions, rule_total + function_size);
+}
+
+#[test]
+fn test_create_comment_with_case_id() {
+    let dsl = r#"
+        rule "annotate" {
+            if (true) {
+                createComment("unattached");
+                createComment(txn.case_id, "attached");
+                createComment(txn.missing, "no case yet");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new().with_field("case_id", Value::from("CASE-42"));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert_eq!(
+        result.actions,
+        vec![
+            Action::CreateComment { case_id: None, comment: "unattached".to_string() },
+            Action::CreateComment { case_id: Some("CASE-42".to_string()), comment: "attached".to_string() },
+            Action::CreateComment { case_id: None, comment: "no case yet".to_string() },
+        ]
+    );
+}
+
+#[test]
+fn test_send_auth_advise_params() {
+    let dsl = r#"
+        rule "otp" {
+            if (txn.otp != null) {
+                sendAuthAdvise("SMS", "otp_challenge", { code: txn.otp, amount: txn.amount });
+                sendAuthAdvise("EMAIL", "notice");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("otp", Value::from("482913"))
+        .with_field("amount", Value::Int(250));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert_eq!(result.actions.len(), 2);
+    match &result.actions[0] {
+        Action::SendAuthAdvise { channel, template, params } => {
+            assert_eq!(channel, "SMS");
+            assert_eq!(template, "otp_challenge");
+            assert_eq!(params.len(), 2);
+            assert_eq!(params.get("code"), Some(&Value::from("482913")));
+            assert_eq!(params.get("amount"), Some(&Value::Int(250)));
+        }
+        _ => panic!("Expected SendAuthAdvise action"),
+    }
+    // The two-argument form still works, with no params
+    assert_eq!(result.actions[1], Action::send_auth_advise("EMAIL", "notice"));
+}
+
+#[test]
+fn test_large_list_membership() {
+    let deny: Vec<String> = (0..5000).map(|i| format!("\"m{}\"", i)).collect();
+    let dsl = format!(
+        r#"
+        const DENY_MERCHANTS = [{}];
+        
+        rule "deny_list" {{
+            if (txn.merchant in DENY_MERCHANTS) {{
+                setDecision("BLOCK");
+            }}
+        }}
+        rule "allow_list" {{
+            if (txn.mcc not in [5411, 5412, 5441, 5451, 5462, 5499, 5811, 5812, 5813, 5814, 5912, 5921, 5931, 5941, 5942, 5943]) {{
+                createComment("unusual merchant category");
+            }}
+        }}
+        rule "same_channel" {{
+            if (txn.channel in [txn.home_channel, "branch"]) {{
+                setFraudScore(0.1);
+            }}
+        }}
+    "#,
+        deny.join(", ")
+    );
+    
+    let engine = RuleEngine::from_dsl(&dsl).unwrap();
+    let txn = |merchant: Value, mcc: i64| {
+        Transaction::new()
+            .with_field("merchant", merchant)
+            .with_field("mcc", Value::Int(mcc))
+            .with_field("channel", Value::from("web"))
+            .with_field("home_channel", Value::from("web"))
+    };
+    
+    let result = engine.execute(txn(Value::from("m4999"), 5411), UserProfile::new());
+    assert_eq!(
+        result.actions,
+        vec![Action::SetDecision { decision: Decision::Block }, Action::set_fraud_score(0.1)]
+    );
+    
+    // Membership is `==`: no match across types or on a near miss
+    for merchant in [Value::from("m5000"), Value::Int(1), Value::Null] {
+        let result = engine.execute(txn(merchant, 7995), UserProfile::new());
+        assert_eq!(result.actions.len(), 2, "{:?}", result.actions);
+        assert!(matches!(&result.actions[0], Action::CreateComment { .. }));
+    }
+    
+    // The sets survive export
+    for restored in [
+        RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap(),
+        RuleEngine::from_json(&engine.to_json().unwrap()).unwrap(),
+    ] {
+        let result = restored.execute(txn(Value::from("m17"), 5411), UserProfile::new());
+        assert_eq!(result.actions[0], Action::SetDecision { decision: Decision::Block });
+    }
+}
+
+#[test]
+fn test_string_literals_after_export() {
+    let dsl = r#"
+        function label(channel) {
+            if (channel == "web") { return "online"; }
+            return "offline";
+        }
+        
+        rule "blocked_merchant" {
+            if (txn.merchant == "casino_royale" || txn.channel != "card_present") {
+                createComment(label(txn.channel));
+            }
+        }
+        rule "country" {
+            if (txn.country in ["RU", "KP"]) {
+                createComment("sanctioned " + txn.country);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("merchant", Value::from("corner_store"))
+        .with_field("channel", Value::from("web"))
+        .with_field("country", Value::from("KP"));
+    let comment = |text: &str| Action::CreateComment { case_id: None, comment: text.to_string() };
+    let expected = vec![comment("online"), comment("sanctioned KP")];
+    
+    assert_eq!(engine.execute(txn.clone(), UserProfile::new()).actions, expected);
+    
+    for mut restored in [
+        RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap(),
+        RuleEngine::from_json(&engine.to_json().unwrap()).unwrap(),
+    ] {
+        assert_eq!(restored.execute(txn.clone(), UserProfile::new()).actions, expected);
+        
+        // Rules added later reuse the restored literals alongside new ones
+        restored
+            .add_rule(r#"rule "added" { if (txn.channel == "web") { createComment("added " + label("branch")); } }"#)
+            .unwrap();
+        let actions = restored.execute(txn.clone(), UserProfile::new()).actions;
+        assert_eq!(actions.last(), Some(&comment("added offline")));
+    }
+}
+
+#[test]
+fn test_execute_with_reused_context() {
+    let dsl = r#"
+        rule "velocity" {
+            if (txn.amount > 100) {
+                profile.count = (profile.count ?? 0) + 1;
+                setFraudScore(0.5);
+            }
+            if (txn.amount == 0) {
+                require(false);
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let other = RuleEngine::from_dsl(r#"rule "other" { if (txn.other) { profile.other = 1; } }"#).unwrap();
+    
+    let inputs = [
+        (Transaction::new().with_field("amount", Value::Int(500)), UserProfile::new().with_field("count", Value::Int(2))),
+        (Transaction::new().with_field("amount", Value::Int(0)), UserProfile::new()),
+        (Transaction::new().with_field("amount", Value::Int(50)), UserProfile::new()),
+    ];
+    
+    // A context last used by another engine is fine too
+    let mut scratch = fraud_rule_engine::runtime::ExecutionContext::new(Transaction::new(), UserProfile::new());
+    other.execute_with_context(&mut scratch, Transaction::new().with_field("other", Value::Bool(true)), UserProfile::new());
+    
+    for (txn, profile) in inputs {
+        let expected = engine.execute(txn.clone(), profile.clone());
+        let reused = engine.execute_with_context(&mut scratch, txn, profile);
+        
+        assert_eq!(reused.actions, expected.actions);
+        assert_eq!(reused.profile.fields, expected.profile.fields);
+        assert_eq!(reused.metadata.errors, expected.metadata.errors);
+        assert_eq!(reused.metadata.executed_rules, expected.metadata.executed_rules);
+    }
+}
+
+#[test]
+fn test_memoized_conditions() {
+    let dsl = r#"
+        rule "high_risk_country" {
+            if (txn.country in ["RU", "KP", "IR"] && txn.amount / txn.count > 100) {
+                setFraudScore(0.9);
+                profile.flags = profile.flags + 1;
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap().with_memoize_conditions(true);
+    let txn = |country: &str, count: i64| {
+        Transaction::new()
+            .with_field("country", Value::from(country))
+            .with_field("amount", Value::Int(1000))
+            .with_field("count", Value::Int(count))
+    };
+    let flags = |n: i64| UserProfile::new().with_field("flags", Value::Int(n));
+    let mut scratch = fraud_rule_engine::runtime::ExecutionContext::new(Transaction::new(), UserProfile::new());
+    
+    let first = engine.execute_with_context(&mut scratch, txn("KP", 2), flags(0));
+    let cold = scratch.instructions_executed;
+    
+    // The same field values hit the cache, so the condition is not
+    // evaluated again; the body still runs against the new profile
+    let second = engine.execute_with_context(&mut scratch, txn("KP", 2), flags(5));
+    assert!(scratch.instructions_executed < cold, "{} vs {}", scratch.instructions_executed, cold);
+    assert_eq!(second.actions, first.actions);
+    assert_eq!(second.profile.fields.get("flags"), Some(&Value::Int(6)));
+    
+    let miss = engine.execute_with_context(&mut scratch, txn("US", 2), flags(0));
+    assert!(miss.actions.is_empty());
+    
+    // A condition that records an error is evaluated (and reports) every time
+    for _ in 0..2 {
+        let result = engine.execute_with_context(&mut scratch, txn("KP", 0), flags(0));
+        assert_eq!(result.metadata.errors.len(), 1);
+    }
+    
+    // Without the flag every execution evaluates the condition
+    let plain = engine.clone().with_memoize_conditions(false);
+    plain.execute_with_context(&mut scratch, txn("KP", 2), flags(0));
+    let uncached = scratch.instructions_executed;
+    plain.execute_with_context(&mut scratch, txn("KP", 2), flags(0));
+    assert_eq!(scratch.instructions_executed, uncached);
+    
+    let inputs: Vec<_> = ["KP", "US", "KP", "IR", "KP"].iter().map(|c| (txn(c, 2), flags(0))).collect();
+    let memoized = engine.execute_batch(inputs.clone());
+    let evaluated = plain.execute_batch(inputs);
+    for (memoized, evaluated) in memoized.iter().zip(&evaluated) {
+        assert_eq!(memoized.actions, evaluated.actions);
+        assert_eq!(memoized.profile.fields, evaluated.profile.fields);
+    }
+}
+
+#[test]
+fn test_memoized_conditions_across_diverged_clones() {
+    let base = RuleEngine::from_dsl(r#"rule "base" { if (txn.channel == "web") {} }"#)
+        .unwrap()
+        .with_memoize_conditions(true);
+    let mut high = base.clone();
+    high.add_rule(r#"rule "high" { if (txn.amount > 100) { setFraudScore(0.9); } }"#).unwrap();
+    let mut low = base.clone();
+    low.add_rule(r#"rule "low" { if (txn.amount < 100) { setFraudScore(0.1); } }"#).unwrap();
+    
+    // Both new conditions got the same memo entry and read the same field,
+    // so only the engines' generations keep their cached results apart
+    let txn = || Transaction::new().with_field("amount", Value::Int(500));
+    let mut scratch = fraud_rule_engine::runtime::ExecutionContext::new(Transaction::new(), UserProfile::new());
+    let fired = high.execute_with_context(&mut scratch, txn(), UserProfile::new());
+    assert_eq!(fired.actions, vec![Action::set_fraud_score(0.9)]);
+    let result = low.execute_with_context(&mut scratch, txn(), UserProfile::new());
+    assert!(result.actions.is_empty(), "{:?}", result.actions);
+    
+    let mut fewer = high.clone();
+    fewer.remove_rule("base").unwrap();
+    let result = fewer.execute_with_context(&mut scratch, txn(), UserProfile::new());
+    assert_eq!(result.actions, vec![Action::set_fraud_score(0.9)]);
+}
+
+#[test]
+fn test_rules_by_priority() {
+    let dsl = r#"
+        rule "low" { priority: 10, if (true) {} }
+        rule "mid" { priority: 50, if (true) {} }
+        rule "high" { priority: 100, if (true) {} }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let ids = |min, max| -> Vec<String> {
+        engine.get_rules_by_priority(min, max).into_iter().map(|r| r.id).collect()
+    };
+    assert_eq!(ids(40, 100), ["high", "mid"]);
+    assert_eq!(ids(10, 10), ["low"]);
+    assert_eq!(ids(i32::MIN, i32::MAX), ["high", "mid", "low"]);
+    assert!(ids(60, 90).is_empty());
+    assert!(ids(100, 10).is_empty());
+}
+
+#[test]
+fn test_duplicate_rule_ids_rejected() {
+    let err = RuleEngine::from_dsl(
+        r#"
+        rule "velocity" { priority: 10, if (true) {} }
+        rule "velocity" { priority: 20, if (true) {} }
+    "#,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(err.to_string(), "Compilation error: duplicate rule 'velocity'");
+    
+    // Grouped rules share the same namespace
+    let err = RuleEngine::from_dsl(
+        r#"
+        rule "velocity" { if (true) {} }
+        group "limits" { rule "velocity" { if (true) {} } }
+    "#,
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(err, CompilationError::CompileError(message) if message == "duplicate rule 'velocity'"));
+}
+
+#[test]
+fn test_configured_default_priority() {
+    let dsl = r#"
+        rule "explicit_low" { priority: 5, if (true) {} }
+        rule "implicit" { if (true) {} }
+        rule "explicit_high" { priority: 60, if (true) {} }
+    "#;
+    
+    let priorities = |engine: &RuleEngine| -> Vec<(String, i32)> {
+        engine.get_rules_metadata().into_iter().map(|r| (r.id, r.priority)).collect()
+    };
+    let pairs = |list: &[(&str, i32)]| -> Vec<(String, i32)> {
+        list.iter().map(|(id, p)| (id.to_string(), *p)).collect()
+    };
+    
+    assert_eq!(
+        priorities(&RuleEngine::from_dsl(dsl).unwrap()),
+        pairs(&[("implicit", 100), ("explicit_high", 60), ("explicit_low", 5)])
+    );
+    
+    let mut engine = RuleEngine::from_dsl_with_default_priority(dsl, 50).unwrap();
+    engine.add_rule(r#"rule "added" { if (true) {} }"#).unwrap();
+    assert_eq!(
+        priorities(&engine),
+        pairs(&[("explicit_high", 60), ("implicit", 50), ("added", 50), ("explicit_low", 5)])
+    );
+}
+
+#[test]
+fn test_clamp_fraud_score() {
+    let dsl = r#"
+        rule "score" {
+            if (true) {
+                setFraudScore(clamp(txn.score, 0.0, 1.0));
+                profile.bounded = clamp(txn.count, 1, 5);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let run = |score: Value, count: i64| {
+        let transaction = Transaction::new()
+            .with_field("score", score)
+            .with_field("count", Value::Int(count));
+        engine.execute(transaction, UserProfile::new())
+    };
+    
+    for (score, count, expected_score, expected_count) in [
+        (-0.5, 0, 0.0, 1),
+        (0.4, 3, 0.4, 3),
+        (1.0, 5, 1.0, 5),
+        (3.2, 9, 1.0, 5),
+    ] {
+        let result = run(Value::Float(score), count);
+        assert_eq!(result.actions, vec![Action::set_fraud_score(expected_score)], "score {}", score);
+        assert_eq!(result.profile.fields.get("bounded"), Some(&Value::Int(expected_count)));
+    }
+}
+
+#[test]
+fn test_dynamic_enabled_condition() {
+    let dsl = r#"
+        rule "premium_limit" {
+            priority: 100,
+            enabled: profile.tier == "premium",
+            if (txn.amount > 5000) {
+                setFraudScore(0.2);
+            }
+        }
+        
+        rule "baseline" {
+            priority: 90,
+            if (txn.amount > 5000) {
+                setFraudScore(0.6);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = || Transaction::new().with_field("amount", Value::Int(8000));
+    
+    let premium = engine.execute(txn(), UserProfile::new().with_field("tier", Value::from("premium")));
+    assert_eq!(premium.actions, vec![Action::set_fraud_score(0.2), Action::set_fraud_score(0.6)]);
+    assert!(premium.metadata.skipped_rules.is_empty());
+    
+    // The rule is disabled for everyone else, including profiles without a tier
+    for profile in [UserProfile::new().with_field("tier", Value::from("basic")), UserProfile::new()] {
+        let result = engine.execute(txn(), profile);
+        assert_eq!(result.actions, vec![Action::set_fraud_score(0.6)]);
+        assert_eq!(result.metadata.skipped_rules, vec!["premium_limit".to_string()]);
+        assert_eq!(result.metadata.executed_rules, vec!["baseline".to_string()]);
+    }
+    
+    // The gate survives a bytecode round trip
+    let restored = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap();
+    let basic = restored.execute(txn(), UserProfile::new().with_field("tier", Value::from("basic")));
+    assert_eq!(basic.metadata.skipped_rules, vec!["premium_limit".to_string()]);
+}
+
+#[test]
+fn test_null_semantics() {
+    let dsl = r#"
+        rule "missing_field" {
+            if (profile.missing > 5) {
+                createComment("high");
+            } else {
+                createComment("low");
+            }
+            if (!(profile.missing > 5)) {
+                createComment("not high");
+            }
+            if (profile.missing == null) {
+                createComment("missing");
+            }
+            profile.either = profile.missing > 5 || txn.amount > 100;
+            profile.both = profile.missing > 5 && txn.amount > 100;
+            profile.label = profile.missing > 5 ? "high" : "low";
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = || Transaction::new().with_field("amount", Value::Int(500));
+    
+    // Null is falsy: the comparison is false and the else branch runs
+    let rust = engine.execute(txn(), UserProfile::new());
+    assert_eq!(
+        rust.actions,
+        vec![
+            Action::create_comment("low"),
+            Action::create_comment("not high"),
+            Action::create_comment("missing"),
+        ]
+    );
+    assert_eq!(rust.profile.get_bool("either"), Some(true));
+    assert_eq!(rust.profile.get_bool("both"), Some(false));
+    assert_eq!(rust.profile.get_string("label"), Some("low"));
+    
+    // The comparison is unknown: neither branch runs, negating it stays
+    // unknown, and only `==` still tests for null
+    let sql = engine.with_null_semantics(NullSemantics::Sql);
+    let result = sql.execute(txn(), UserProfile::new());
+    assert_eq!(result.actions, vec![Action::create_comment("missing")]);
+    assert_eq!(result.profile.get_bool("either"), Some(true));
+    assert_eq!(result.profile.get_bool("both"), None);
+    assert_eq!(result.profile.get_string("label"), None);
+    
+    // A present field compares the same way in both modes
+    let present = sql.execute(txn(), UserProfile::new().with_field("missing", Value::Int(9)));
+    assert_eq!(present.actions, vec![Action::create_comment("high")]);
+    
+    // An unknown condition is not cached as false
+    let memoized = sql.with_memoize_conditions(true);
+    let batch = memoized.execute_batch(vec![(txn(), UserProfile::new()), (txn(), UserProfile::new())]);
+    assert!(batch.iter().all(|result| result.actions == [Action::create_comment("missing")]));
+}
+
+#[test]
+fn test_rule_stats_count_executions_and_fires() {
+    let dsl = r#"
+        rule "large" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.7);
+            }
+        }
+        
+        rule "off" {
+            priority: 50,
+            enabled: false,
+            if (true) {}
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let amount = |amount| Transaction::new().with_field("amount", Value::Int(amount));
+    
+    // Nothing is counted unless asked for
+    engine.execute(amount(5000), UserProfile::new());
+    assert!(engine.get_rule_stats().iter().all(|stats| stats.execution_count == 0));
+    
+    let mut engine = engine.with_collect_rule_stats(true);
+    for value in [5000, 10, 2000] {
+        engine.execute(amount(value), UserProfile::new());
+    }
+    
+    let stats = engine.get_rule_stats();
+    assert_eq!(stats.len(), 2);
+    assert_eq!((stats[0].id.as_str(), stats[0].execution_count, stats[0].fire_count), ("large", 3, 2));
+    assert!(stats[0].last_fired.is_some());
+    // A disabled rule never runs
+    assert_eq!((stats[1].execution_count, stats[1].fire_count, stats[1].last_fired), (0, 0, None));
+    
+    // Counts survive adding a rule
+    engine.add_rule(r#"rule "new" { if (true) {} }"#).unwrap();
+    let stats = engine.get_rule_stats();
+    assert_eq!(stats.iter().find(|s| s.id == "large").map(|s| s.fire_count), Some(2));
+    assert_eq!(stats.iter().find(|s| s.id == "new").map(|s| s.execution_count), Some(0));
+    
+    engine.reset_rule_stats();
+    assert!(engine.get_rule_stats().iter().all(|stats| stats.execution_count == 0 && stats.last_fired.is_none()));
+}
+
+#[test]
+fn test_rule_stats_from_multiple_threads() {
+    let dsl = r#"
+        rule "even" {
+            if (txn.n % 2 == 0) {
+                setFraudScore(0.5);
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap().with_collect_rule_stats(true);
+    
+    // Clones share the counters, so every thread's executions add up
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let engine = engine.clone();
+            std::thread::spawn(move || {
+                for n in 0..250 {
+                    engine.execute(Transaction::new().with_field("n", Value::Int(n)), UserProfile::new());
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    
+    let stats = &engine.get_rule_stats()[0];
+    assert_eq!(stats.execution_count, 2000);
+    assert_eq!(stats.fire_count, 1000);
+}
+
+#[test]
+fn test_diff_execute_reports_extra_case() {
+    let current = RuleEngine::from_dsl(r#"
+        rule "large" {
+            if (txn.amount > 1000) {
+                setFraudScore(0.7);
+                profile.flagged = true;
+            }
+        }
+    "#).unwrap();
+    let candidate = RuleEngine::from_dsl(r#"
+        rule "large" {
+            if (txn.amount > 1000) {
+                setFraudScore(0.7);
+                createCase("HIGH", "Large amount");
+                profile.flagged = true;
+                profile.reviews = 1;
+            }
+        }
+    "#).unwrap();
+    
+    let diff = current.diff_execute(
+        &candidate,
+        Transaction::new().with_field("amount", Value::Int(5000)),
+        UserProfile::new(),
+    );
+    
+    assert!(diff.only_in_self.is_empty());
+    assert_eq!(diff.only_in_other, vec![Action::create_case("HIGH", "Large amount")]);
+    assert_eq!(
+        diff.profile_changes,
+        vec![FieldChange { field: "reviews".to_string(), old: Value::Null, new: Value::Int(1) }]
+    );
+    
+    // Below the threshold neither version does anything
+    let same = current.diff_execute(&candidate, Transaction::new().with_field("amount", Value::Int(10)), UserProfile::new());
+    assert!(same.is_empty());
+}
+
+#[test]
+fn test_float_modulo() {
+    let dsl = r#"
+        rule "round_amounts" {
+            if (txn.amount % 100.0 == 0.0) {
+                createComment("round amount");
+            }
+            profile.cents = txn.amount % 1;
+            profile.bad = txn.amount % 0.0;
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new().with_field("amount", Value::Float(500.25)), UserProfile::new());
+    
+    assert!(result.actions.is_empty());
+    assert_eq!(result.profile.get_float("cents"), Some(0.25));
+    assert_eq!(result.metadata.errors, vec![ExecutionError::RuntimeError("division by zero".to_string())]);
+    
+    let result = engine.execute(Transaction::new().with_field("amount", Value::Float(300.0)), UserProfile::new());
+    assert_eq!(result.actions, vec![Action::create_comment("round amount")]);
+}
+
+#[test]
+fn test_let_is_block_scoped() {
+    let dsl = r#"
+        function scoped(n) {
+            if (n > 0) {
+                let seen = n;
+                return seen;
+            }
+            return 0;
+        }
+
+        rule "scopes" {
+            if (txn.amount > 0) {
+                let x = txn.amount;
+                profile.inner = x;
+            }
+            profile.after = x;
+            
+            let total = 0;
+            for (amount in txn.history) {
+                let total = amount;
+                if (total > 50) { break; }
+                profile.last_small = total;
+            }
+            profile.total = total;
+            
+            // A return inside the function's block still drops its scope
+            let seen = 7;
+            profile.called = scoped(3);
+            profile.seen = seen;
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("amount", Value::Int(5))
+        .with_field("history", Value::from(vec![Value::Int(10), Value::Int(20), Value::Int(90)]));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert!(result.metadata.errors.is_empty(), "{:?}", result.metadata.errors);
+    assert_eq!(result.profile.get_int("inner"), Some(5));
+    assert_eq!(result.profile.fields.get("after").unwrap_or(&Value::Null), &Value::Null);
+    assert_eq!(result.profile.get_int("last_small"), Some(20));
+    assert_eq!(result.profile.get_int("total"), Some(0));
+    assert_eq!(result.profile.get_int("called"), Some(3));
+    assert_eq!(result.profile.get_int("seen"), Some(7));
+}
+
+#[test]
+fn test_calls_and_loops_keep_caller_locals() {
+    let dsl = r#"
+        function double(x) {
+            let y = x * 2;
+            return y;
+        }
+
+        rule "caller" {
+            if (true) {
+                let y = 1;
+                let x = 7;
+                let item = "outer";
+                profile.doubled = double(5);
+                profile.y = y;
+                profile.x = x;
+                
+                for (item in txn.items) {
+                    profile.last_item = item;
+                }
+                profile.item = item;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new().with_field("items", Value::from(vec!["a", "b"]));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert!(result.metadata.errors.is_empty(), "{:?}", result.metadata.errors);
+    assert_eq!(result.profile.get_int("doubled"), Some(10));
+    assert_eq!(result.profile.get_int("y"), Some(1));
+    assert_eq!(result.profile.get_int("x"), Some(7));
+    assert_eq!(result.profile.get_string("last_item"), Some("b"));
+    assert_eq!(result.profile.get_string("item"), Some("outer"));
+}
+
+#[test]
+fn test_function_assignments_stay_in_the_call() {
+    let dsl = r#"
+        function count(items) {
+            x = 0;
+            for (item in items) {
+                if (true) {
+                    x += 1;
+                }
+            }
+            return x;
+        }
+
+        function peek() {
+            return outer;
+        }
+
+        rule "caller" {
+            if (true) {
+                let x = 7;
+                let outer = "caller";
+                profile.count = count(txn.items);
+                profile.x = x;
+                profile.peeked = peek();
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new().with_field("items", Value::from(vec!["a", "b", "c"]));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert!(result.metadata.errors.is_empty(), "{:?}", result.metadata.errors);
+    assert_eq!(result.profile.get_int("count"), Some(3));
+    assert_eq!(result.profile.get_int("x"), Some(7));
+    assert_eq!(result.profile.fields.get("peeked"), Some(&Value::Null));
+}
+
+#[test]
+fn test_recursive_while_loop_hits_iteration_limit() {
+    let dsl = r#"
+        function spin(n) {
+            while (true) {
+                if (n > 0) {
+                    spin(0);
+                } else {
+                    return 0;
+                }
+            }
+        }
+
+        rule "recurse" {
+            if (true) {
+                profile.spun = spin(1);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap().with_max_loop_iterations(100);
+    let result = engine.execute_with_budget(Transaction::new(), UserProfile::new(), 1_000_000);
+    
+    // The inner call's loop has its own counter, so the outer one still
+    // reaches the cap instead of being reset on every iteration
+    assert!(!result.metadata.budget_exceeded);
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("loop iteration limit of 100 exceeded".to_string())]
+    );
+}
+
+#[test]
+fn test_create_case_action() {
+    let dsl = r#"
+        rule "test" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                createCase("HIGH", "Large transaction");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new()
+        .with_field("amount", Value::Float(5000.0));
+    
+    let result = engine.execute(transaction, UserProfile::new());
+    
+    assert_eq!(result.actions.len(), 1);
+    match &result.actions[0] {
+        Action::CreateCase { severity, reason, .. } => {
+            assert_eq!(severity, "HIGH");
+            assert_eq!(reason, "Large transaction");
+        }
+        _ => panic!("Expected CreateCase action"),
+    }
+}
+
+#[test]
+fn test_bytecode_serialization() {
+    let dsl = r#"
+        rule "test" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.8);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    // Serialize to bytecode
+    let bytecode = engine.to_bytecode().unwrap();
+    
+    // Deserialize back
+    let engine2 = RuleEngine::from_bytecode(&bytecode).unwrap();
+    
+    // Test that it works the same
+    let transaction = Transaction::new()
+        .with_field("amount", Value::Float(5000.0));
+    
+    let result = engine2.execute(transaction, UserProfile::new());
+    
+    assert_eq!(result.actions.len(), 1);
+}
+
+#[test]
+fn test_rule_priority_ordering() {
+    let dsl = r#"
+        rule "low_priority" {
+            priority: 50,
+            if (true) {
+                profile.order = "second";
+            }
+        }
+        
+        rule "high_priority" {
+            priority: 100,
+            if (true) {
+                profile.order = "first";
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    
+    // High priority rule should execute first and set order to "first"
+    // Then low priority sets it to "second"
+    assert_eq!(
+        result.profile.fields.get("order"),
+        Some(&Value::String("second".to_string()))
+    );
+}
+
+#[test]
+fn test_disabled_rule() {
+    let dsl = r#"
+        rule "disabled" {
+            priority: 100,
+            enabled: false,
+            if (true) {
+                setFraudScore(0.9);
+            }
+        }
+        
+        rule "enabled" {
+            priority: 90,
+            enabled: true,
+            if (true) {
+                setFraudScore(0.5);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    
+    // Only the enabled rule should execute
+    assert_eq!(result.metadata.executed_rules.len(), 1);
+    assert_eq!(result.metadata.skipped_rules.len(), 1);
+    
+    match &result.actions[0] {
+        Action::SetFraudScore { score } => assert_eq!(*score, 0.5),
+        _ => panic!("Expected SetFraudScore"),
+    }
+}
+
+#[test]
+fn test_performance_500_rules() {
+    use std::time::Instant;
+    
+    // Generate 500 simple rules
+    let mut dsl = String::new();
+    for i in 0..500 {
+        dsl.push_str(&format!(
+            r#"
+            rule "rule_{}" {{
+                priority: {},
+                if (txn.amount > {}) {{
+                    profile.rule_{}_executed = true;
+                }}
+            }}
+            "#,
+            i, 1000 - i, i * 10, i
+        ));
+    }
+    
+    let engine = RuleEngine::from_dsl(&dsl).unwrap();
+    
+    let transaction = Transaction::new()
+        .with_field("amount", Value::Float(5000.0));
+    
+    let profile = UserProfile::new();
+    
+    // Warm up
+    for _ in 0..10 {
+        engine.execute(transaction.clone(), profile.clone());
+    }
+    
+    // Measure
+    let start = Instant::now();
+    let iterations = 100;
+    
+    for _ in 0..iterations {
+        engine.execute(transaction.clone(), profile.clone());
+    }
+    
+    let elapsed = start.elapsed();
+    let avg_time = elapsed / iterations;
+    
+    println!("Average execution time for 500 rules: {:?}", avg_time);
+    
+    // Should be under 2ms on most systems
+    assert!(avg_time.as_millis() < 5, "Execution took {:?}, expected < 5ms", avg_time);
+}
+
+#[test]
+fn test_in_operator() {
+    let dsl = r#"
+        rule "blocked_country" {
+            priority: 100,
+            if (txn.country in profile.blocked_countries) {
+                setDecision("BLOCK");
+            }
+        }
+        
+        rule "flagged_mcc" {
+            priority: 90,
+            if (txn.mcc in profile.flagged_mccs) {
+                createCase("MEDIUM", "Flagged MCC");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("blocked_countries", Value::from(vec!["KP", "IR"]))
+        .with_field("flagged_mccs", Value::from(vec![7995, 6051]));
+    
+    // String and int membership both match
+    let txn = Transaction::new()
+        .with_field("country", Value::from("KP"))
+        .with_field("mcc", Value::Int(7995));
+    let result = engine.execute(txn, profile.clone());
+    assert_eq!(result.actions.len(), 2);
+    
+    // Neither matches
+    let txn = Transaction::new()
+        .with_field("country", Value::from("US"))
+        .with_field("mcc", Value::Int(5411));
+    let result = engine.execute(txn, profile);
+    assert!(result.actions.is_empty());
+}
+
+#[test]
+fn test_in_operator_missing_fields() {
+    let dsl = r#"
+        rule "blocked_country" {
+            priority: 100,
+            if (txn.country in profile.blocked_countries) {
+                setDecision("BLOCK");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    // Missing array field resolves to Null, which is not a collection
+    let txn = Transaction::new().with_field("country", Value::from("KP"));
+    let result = engine.execute(txn, UserProfile::new());
+    assert!(result.actions.is_empty());
+    
+    // Missing item field resolves to Null, which is not in the array
+    let profile = UserProfile::new()
+        .with_field("blocked_countries", Value::from(vec!["KP", "IR"]));
+    let result = engine.execute(Transaction::new(), profile);
+    assert!(result.actions.is_empty());
+}
+
+#[test]
+fn test_not_in_operator() {
+    let dsl = r#"
+        rule "unusual_country" {
+            priority: 100,
+            if (txn.country not in profile.allowed_countries) {
+                createCase("MEDIUM", "Country not allowed");
+            }
+        }
+        
+        rule "unusual_mcc" {
+            priority: 90,
+            if (txn.mcc !in profile.usual_mccs) {
+                setFraudScore(0.4);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("allowed_countries", Value::from(vec!["US", "CA"]))
+        .with_field("usual_mccs", Value::from(vec![5411]));
+    
+    // Present in both lists
+    let txn = Transaction::new()
+        .with_field("country", Value::from("US"))
+        .with_field("mcc", Value::Int(5411));
+    let result = engine.execute(txn, profile.clone());
+    assert!(result.actions.is_empty());
+    
+    // Absent from both lists
+    let txn = Transaction::new()
+        .with_field("country", Value::from("FR"))
+        .with_field("mcc", Value::Int(7995));
+    let result = engine.execute(txn, profile);
+    assert_eq!(result.actions.len(), 2);
+    
+    // A missing (non-array) right side contains nothing
+    let txn = Transaction::new().with_field("country", Value::from("US"));
+    let result = engine.execute(txn, UserProfile::new());
+    assert_eq!(result.actions.len(), 2);
+}
+
+#[test]
+fn test_division_by_zero_reported_in_metadata() {
+    let dsl = r#"
+        rule "avg_amount" {
+            priority: 100,
+            if (true) {
+                profile.avg_amount = profile.total_amount / profile.txn_count;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("total_amount", Value::Float(1000.0))
+        .with_field("txn_count", Value::Int(0));
+    
+    let result = engine.execute(Transaction::new(), profile);
+    
+    assert_eq!(result.profile.fields.get("avg_amount"), Some(&Value::Null));
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("division by zero".to_string())]
+    );
+}
+
+#[test]
+fn test_runtime_errors_report_rule_and_line() {
+    let dsl = r#"function ratio(a, b) {
+    let r = a / b;
+}
+
+rule "velocity" {
+    priority: 100,
+    if (true) {
+        profile.seen = true;
+    }
+}
+
+rule "avg_amount" {
+    priority: 50,
+    if (profile.txn_count >= 0) {
+        profile.label = "checked";
+        if (true) {
+            profile.avg = profile.total / profile.txn_count;
+        } else if (false) {
+            profile.avg = 0;
+        }
+        ratio(1, 0);
+    }
+}
+"#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let profile = UserProfile::new()
+        .with_field("total", Value::Int(10))
+        .with_field("txn_count", Value::Int(0));
+    
+    let result = engine.execute(Transaction::new(), profile.clone());
+    
+    assert_eq!(result.metadata.errors.len(), 2);
+    let locations: Vec<_> = result.metadata.error_locations.iter().map(|l| l.to_string()).collect();
+    // The error inside `ratio` is located at the rule's call
+    assert_eq!(locations, ["rule 'avg_amount', line 17", "rule 'avg_amount', line 21"]);
+    
+    // Lines survive a bytecode round trip
+    let reloaded = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap();
+    let result = reloaded.execute(Transaction::new(), profile);
+    assert_eq!(
+        result.metadata.error_locations[0],
+        ErrorLocation { rule_id: "avg_amount".to_string(), line: Some(17) }
+    );
+}
+
+#[test]
+fn test_compound_assignment() {
+    let dsl = r#"
+        rule "accumulate" {
+            priority: 100,
+            if (true) {
+                profile.txn_count += 1;
+                profile.total_amount += txn.amount;
+                profile.balance -= 25;
+                profile.risk *= 1.5;
+                profile.budget /= 4;
+                txn.fee += 0.5;
+                let points = 10;
+                points *= 3;
+                profile.points = points;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new()
+        .with_field("amount", Value::Float(250.5))
+        .with_field("fee", Value::Int(2));
+    
+    let profile = UserProfile::new()
+        .with_field("txn_count", Value::Int(4))
+        .with_field("total_amount", Value::Int(1000))
+        .with_field("balance", Value::Float(100.0))
+        .with_field("risk", Value::Int(2))
+        .with_field("budget", Value::Int(100));
+    
+    let result = engine.execute(transaction, profile);
+    let fields = &result.profile.fields;
+    
+    assert_eq!(fields.get("txn_count"), Some(&Value::Int(5)));
+    assert_eq!(fields.get("total_amount"), Some(&Value::Float(1250.5)));
+    assert_eq!(fields.get("balance"), Some(&Value::Float(75.0)));
+    assert_eq!(fields.get("risk"), Some(&Value::Float(3.0)));
+    assert_eq!(fields.get("budget"), Some(&Value::Int(25)));
+    assert_eq!(fields.get("points"), Some(&Value::Int(30)));
+    assert_eq!(result.transaction.fields.get("fee"), Some(&Value::Float(2.5)));
+}
+
+#[test]
+fn test_logical_short_circuit_skips_right_operand() {
+    let dsl = r#"
+        function markCalled(profile) {
+            profile.called = true;
+        }
+        
+        rule "and_guard" {
+            priority: 100,
+            if (profile.txn_count > 0 && markCalled(profile)) {
+                setFraudScore(0.5);
+            }
+        }
+        
+        rule "or_guard" {
+            priority: 90,
+            if (profile.vip || markCalled(profile)) {
+                setDecision("ALLOW");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("txn_count", Value::Int(0))
+        .with_field("vip", Value::Bool(true));
+    
+    let result = engine.execute(Transaction::new(), profile);
+    
+    // Left side of && is false and left side of || is true: neither call happens
+    assert_eq!(result.profile.fields.get("called"), None);
+    assert_eq!(result.actions, vec![Action::set_decision(Decision::Allow)]);
+}
+
+#[test]
+fn test_logical_operators_produce_booleans() {
+    let dsl = r#"
+        rule "flags" {
+            priority: 100,
+            if (true) {
+                profile.both = profile.a && profile.b;
+                profile.either = profile.a || profile.b;
+                profile.neither = profile.missing || 0;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("a", Value::Int(1))
+        .with_field("b", Value::String(String::new()));
+    
+    let result = engine.execute(Transaction::new(), profile);
+    let fields = &result.profile.fields;
+    
+    assert_eq!(fields.get("both"), Some(&Value::Bool(false)));
+    assert_eq!(fields.get("either"), Some(&Value::Bool(true)));
+    assert_eq!(fields.get("neither"), Some(&Value::Bool(false)));
+}
+
+#[test]
+fn test_math_builtins() {
+    let dsl = r#"
+        rule "score" {
+            priority: 100,
+            if (true) {
+                profile.capped = min(txn.amount, 1000);
+                profile.floor_count = max(profile.count, 1);
+                profile.delta = abs(txn.amount - profile.avg_amount);
+                profile.ratio = round(txn.amount / 3.0, 2);
+                profile.whole = floor(2.9) + ceil(0.1);
+                profile.root = sqrt(profile.count);
+                setFraudScore(min(txn.amount / 10000.0, 1.0));
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new().with_field("amount", Value::Int(2500));
+    let profile = UserProfile::new()
+        .with_field("count", Value::Int(9))
+        .with_field("avg_amount", Value::Int(3000));
+    
+    let result = engine.execute(transaction, profile);
+    let fields = &result.profile.fields;
+    
+    assert_eq!(fields.get("capped"), Some(&Value::Int(1000)));
+    assert_eq!(fields.get("floor_count"), Some(&Value::Int(9)));
+    assert_eq!(fields.get("delta"), Some(&Value::Int(500)));
+    assert_eq!(fields.get("ratio"), Some(&Value::Float(833.33)));
+    assert_eq!(fields.get("whole"), Some(&Value::Float(3.0)));
+    assert_eq!(fields.get("root"), Some(&Value::Float(3.0)));
+    assert_eq!(result.actions, vec![Action::set_fraud_score(0.25)]);
+}
+
+#[test]
+fn test_string_method_chaining() {
+    let dsl = r#"
+        rule "suspicious_email" {
+            priority: 100,
+            if (txn.email.toLowerCase().endsWith("@test.com")) {
+                createComment("Test email domain");
+            }
+        }
+        
+        rule "suspicious_description" {
+            priority: 90,
+            if (txn.description.trim().toLowerCase().contains("fraud")) {
+                createCase("HIGH", "Suspicious description");
+            }
+        }
+        
+        rule "known_device" {
+            priority: 80,
+            if (profile.devices.contains(txn.device)) {
+                setDecision("ALLOW");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new()
+        .with_field("email", Value::from("Someone@TEST.com"))
+        .with_field("description", Value::from("  Possible FRAUD attempt "))
+        .with_field("device", Value::from("d-1"));
+    let profile = UserProfile::new()
+        .with_field("devices", Value::from(vec!["d-1", "d-2"]));
+    
+    let result = engine.execute(transaction, profile);
+    
+    assert_eq!(result.actions.len(), 3);
+    
+    // Non-string receivers evaluate to Null and the rules do not fire
+    let transaction = Transaction::new()
+        .with_field("email", Value::Int(42))
+        .with_field("description", Value::Null);
+    
+    let result = engine.execute(transaction, UserProfile::new());
+    
+    assert!(result.actions.is_empty());
+}
+
+#[test]
+fn test_parse_error_reports_position() {
+    let dsl = r#"rule "broken" {
+    priority: 100,
+    if (txn.amount > 1000 {
+        setFraudScore(0.8);
+    }
+}"#;
+    
+    match RuleEngine::from_dsl(dsl) {
+        Err(CompilationError::ParseError { line, column, .. }) => {
+            assert_eq!((line, column), (3, 27));
+        }
+        other => panic!("Expected parse error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_for_loop_sums_array() {
+    let dsl = r#"
+        rule "recent_velocity" {
+            priority: 100,
+            if (true) {
+                let total = 0;
+                let count = 0;
+                for (amount in profile.recent_amounts) {
+                    total += amount;
+                    count += 1;
+                }
+                profile.recent_total = total;
+                profile.recent_count = count;
+                if (total > 10000) {
+                    createCase("HIGH", "Recent spend velocity");
+                }
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("recent_amounts", Value::from(vec![2500.0, 4000.0, 5000.5]));
+    let result = engine.execute(Transaction::new(), profile);
+    
+    assert_eq!(result.profile.fields.get("recent_total"), Some(&Value::Float(11500.5)));
+    assert_eq!(result.profile.fields.get("recent_count"), Some(&Value::Int(3)));
+    assert_eq!(result.actions.len(), 1);
+    
+    // Empty arrays and non-array values run zero iterations
+    for recent in [Value::Array(vec![]), Value::Int(5), Value::from("abc")] {
+        let profile = UserProfile::new().with_field("recent_amounts", recent);
+        let result = engine.execute(Transaction::new(), profile);
+        
+        assert_eq!(result.profile.fields.get("recent_total"), Some(&Value::Int(0)));
+        assert_eq!(result.profile.fields.get("recent_count"), Some(&Value::Int(0)));
+        assert!(result.actions.is_empty());
+    }
+}
+
+#[test]
+fn test_nested_for_loops() {
+    let dsl = r#"
+        rule "pairs" {
+            priority: 100,
+            if (true) {
+                let matches = 0;
+                for (a in profile.left) {
+                    for (b in profile.right) {
+                        if (a == b) {
+                            matches += 1;
+                        }
+                    }
+                }
+                profile.matches = matches;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("left", Value::from(vec![1, 2, 3]))
+        .with_field("right", Value::from(vec![2, 3, 4]));
+    let result = engine.execute(Transaction::new(), profile);
+    
+    assert_eq!(result.profile.fields.get("matches"), Some(&Value::Int(2)));
+}
+
+#[test]
+fn test_while_loop() {
+    let dsl = r#"
+        rule "halve_until_small" {
+            priority: 100,
+            if (true) {
+                let amount = txn.amount;
+                let steps = 0;
+                while (amount > 100) {
+                    amount /= 2;
+                    steps += 1;
+                }
+                profile.steps = steps;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new().with_field("amount", Value::Int(1000));
+    let result = engine.execute(transaction, UserProfile::new());
+    
+    assert_eq!(result.profile.fields.get("steps"), Some(&Value::Int(4)));
+    assert!(result.metadata.errors.is_empty());
+}
+
+#[test]
+fn test_break_leaves_for_loop_early() {
+    let dsl = r#"
+        rule "first_large" {
+            priority: 100,
+            if (true) {
+                let seen = 0;
+                for (amount in profile.recent_amounts) {
+                    seen += 1;
+                    if (amount > 1000) {
+                        profile.first_large = amount;
+                        break;
+                    }
+                }
+                profile.seen = seen;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("recent_amounts", Value::from(vec![10, 2000, 30, 4000]));
+    let result = engine.execute(Transaction::new(), profile);
+    
+    assert_eq!(result.profile.fields.get("first_large"), Some(&Value::Int(2000)));
+    assert_eq!(result.profile.fields.get("seen"), Some(&Value::Int(2)));
+}
+
+#[test]
+fn test_continue_skips_iterations() {
+    let dsl = r#"
+        rule "sum_positive" {
+            priority: 100,
+            if (true) {
+                let total = 0;
+                for (amount in profile.recent_amounts) {
+                    if (amount < 0) {
+                        continue;
+                    }
+                    total += amount;
+                }
+                profile.total = total;
+                
+                let n = 0;
+                let odd = 0;
+                while (n < 10) {
+                    n += 1;
+                    if (n % 2 == 0) {
+                        continue;
+                    }
+                    if (n > 7) {
+                        break;
+                    }
+                    odd += 1;
+                }
+                profile.odd = odd;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let profile = UserProfile::new()
+        .with_field("recent_amounts", Value::from(vec![100, -50, 200, -1]));
+    let result = engine.execute(Transaction::new(), profile);
+    
+    assert_eq!(result.profile.fields.get("total"), Some(&Value::Int(300)));
+    // 1, 3, 5, 7 are counted; 9 breaks out
+    assert_eq!(result.profile.fields.get("odd"), Some(&Value::Int(4)));
+    assert!(result.metadata.errors.is_empty());
+}
+
+#[test]
+fn test_break_outside_loop_is_rejected() {
+    for keyword in ["break", "continue"] {
+        let dsl = format!(
+            r#"rule "test" {{ priority: 1, if (true) {{ {}; }} }}"#,
+            keyword
+        );
+        
+        let err = RuleEngine::from_dsl(&dsl).err().unwrap();
+        assert!(err.to_string().contains(&format!("'{}' outside of a loop", keyword)), "{}", err);
+    }
+}
+
+#[test]
+fn test_while_loop_iteration_cap() {
+    let dsl = r#"
+        rule "runaway" {
+            priority: 100,
+            if (true) {
+                let n = 0;
+                while (true) {
+                    n += 1;
+                }
+                profile.n = n;
+            }
+        }
+        
+        rule "after" {
+            priority: 90,
+            if (true) {
+                setFraudScore(0.1);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap().with_max_loop_iterations(50);
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    
+    // The loop is stopped at the cap and execution carries on after it
+    assert_eq!(result.profile.fields.get("n"), Some(&Value::Int(50)));
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("loop iteration limit of 50 exceeded".to_string())]
+    );
+    assert_eq!(result.actions.len(), 1);
+}
+
+#[test]
+fn test_instruction_budget_stops_tight_loop() {
+    let dsl = r#"
+        rule "spin" {
+            priority: 100,
+            if (true) {
+                while (true) {
+                    profile.spins += 1;
+                }
+            }
+        }
+        
+        rule "never_reached" {
+            priority: 90,
+            if (true) {
+                setFraudScore(0.5);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl)
+        .unwrap()
+        .with_max_loop_iterations(usize::MAX);
+    
+    let profile = UserProfile::new().with_field("spins", Value::Int(0));
+    let result = engine.execute_with_budget(Transaction::new(), profile, 1_000);
+    
+    assert!(result.metadata.budget_exceeded);
+    assert_eq!(result.metadata.executed_rules, vec!["spin".to_string()]);
+    assert!(result.actions.is_empty());
+    
+    let spins = result.profile.fields.get("spins").unwrap().as_int();
+    assert!(spins > 0 && spins < 1_000);
+}
+
+#[test]
+fn test_instruction_budget_not_exceeded() {
+    let dsl = r#"
+        rule "simple" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.8);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new().with_field("amount", Value::Int(5000));
+    let result = engine.execute_with_budget(transaction, UserProfile::new(), 100);
+    
+    assert!(!result.metadata.budget_exceeded);
+    assert_eq!(result.actions.len(), 1);
+}
+
+#[test]
+fn test_mutual_recursion_hits_depth_limit() {
+    let dsl = r#"
+        function ping(profile) {
+            profile.calls += 1;
+            pong(profile);
+        }
+        
+        function pong(profile) {
+            profile.calls += 1;
+            ping(profile);
+        }
+        
+        rule "recurse" {
+            priority: 100,
+            if (true) {
+                ping(profile);
+                setFraudScore(0.2);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap().with_max_call_depth(10);
+    
+    let profile = UserProfile::new().with_field("calls", Value::Int(0));
+    let result = engine.execute(Transaction::new(), profile);
+    
+    // The engine returns normally and the rest of the rule still runs
+    assert_eq!(result.profile.fields.get("calls"), Some(&Value::Int(10)));
+    assert_eq!(result.actions.len(), 1);
+    assert_eq!(result.metadata.errors.len(), 1);
+    assert!(matches!(
+        &result.metadata.errors[0],
+        ExecutionError::RuntimeError(msg) if msg.contains("maximum call depth of 10")
+    ));
+}
+
+#[test]
+fn test_default_call_depth_limit_prevents_overflow() {
+    let dsl = r#"
+        function forever(profile) {
+            forever(profile);
+        }
+        
+        rule "recurse" {
+            priority: 100,
+            if (true) {
+                forever(profile);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    
+    assert_eq!(result.metadata.errors.len(), 1);
+}
+
+#[test]
+fn test_object_literal_case_metadata() {
+    let dsl = r#"
+        rule "high_amount" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                createCase("HIGH", "Large transaction", {
+                    rule: "v1",
+                    score: 0.9,
+                    "amount": txn.amount,
+                    score: 0.95
+                });
+                createCase("LOW", "No metadata", {});
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let transaction = Transaction::new().with_field("amount", Value::Int(5000));
+    let result = engine.execute(transaction, UserProfile::new());
+    
+    assert_eq!(result.actions.len(), 2);
+    match &result.actions[0] {
+        Action::CreateCase { metadata, .. } => {
+            assert_eq!(metadata.len(), 3);
+            assert_eq!(metadata.get("rule"), Some(&Value::from("v1")));
+            assert_eq!(metadata.get("score"), Some(&Value::Float(0.95)));
+            assert_eq!(metadata.get("amount"), Some(&Value::Int(5000)));
+        }
+        _ => panic!("Expected CreateCase action"),
+    }
+    match &result.actions[1] {
+        Action::CreateCase { metadata, .. } => assert!(metadata.is_empty()),
+        _ => panic!("Expected CreateCase action"),
+    }
+}
+
+#[test]
+fn test_strict_fields_reports_unknown_fields() {
+    let dsl = r#"
+        rule "typo" {
+            priority: 100,
+            if (txn.ammount > 1000 || profile.risk_score > 0.5) {
+                setFraudScore(0.9);
+            }
+        }
+    "#;
+    
+    let transaction = Transaction::new().with_field("amount", Value::Int(5000));
+    let profile = UserProfile::new().with_field("risk_score", Value::Null);
+    
+    // Default mode stays silent
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(transaction.clone(), profile.clone());
+    assert!(result.metadata.errors.is_empty());
+    
+    // Strict mode flags the typo but not a field that is present with a Null value
+    let engine = RuleEngine::from_dsl(dsl).unwrap().with_strict_fields(true);
+    let result = engine.execute(transaction, profile);
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("unknown field: txn.ammount".to_string())]
+    );
+    assert!(result.actions.is_empty());
+}
+
+#[test]
+fn test_execute_batch_matches_execute() {
+    let dsl = r#"
+        rule "scratch_locals" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                let flagged = true;
+            }
+            if (flagged) {
+                createComment("flag leaked");
+            }
+        }
+        
+        rule "velocity" {
+            priority: 90,
+            if (profile.txn_count > 5) {
+                createCase("MEDIUM", "Velocity");
+                return;
+            }
+        }
+        
+        rule "count" {
+            priority: 80,
+            if (true) {
+                profile.txn_count += 1;
+                profile.ratio = txn.amount / profile.divisor;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let inputs = vec![
+        (
+            Transaction::new().with_field("amount", Value::Int(5000)),
+            UserProfile::new().with_field("txn_count", Value::Int(10)),
+        ),
+        (
+            Transaction::new().with_field("amount", Value::Int(500)),
+            UserProfile::new()
+                .with_field("txn_count", Value::Int(1))
+                .with_field("divisor", Value::Int(0)),
+        ),
+        (
+            Transaction::new().with_field("amount", Value::Int(50)),
+            UserProfile::new()
+                .with_field("txn_count", Value::Int(2))
+                .with_field("divisor", Value::Int(5)),
+        ),
+    ];
+    
+    let batch = engine.execute_batch(inputs.clone());
+    
+    assert_eq!(batch.len(), inputs.len());
+    for ((txn, profile), batched) in inputs.into_iter().zip(batch) {
+        let single = engine.execute(txn, profile);
+        
+        assert_eq!(batched.actions, single.actions);
+        assert_eq!(batched.profile.fields, single.profile.fields);
+        assert_eq!(batched.transaction.fields, single.transaction.fields);
+        assert_eq!(batched.metadata.executed_rules, single.metadata.executed_rules);
+        assert_eq!(batched.metadata.short_circuited, single.metadata.short_circuited);
+        assert_eq!(batched.metadata.errors, single.metadata.errors);
+    }
+}
+
+#[test]
+fn test_ternary_expression() {
+    let dsl = r#"
+        function markCalled(profile) {
+            profile.called = true;
+        }
+        
+        rule "tiered" {
+            priority: 100,
+            if (true) {
+                let s = txn.amount > 1000 ? 0.8 : txn.amount > 500 ? 0.5 : 0.2;
+                profile.score = s;
+                profile.label = txn.country == profile.home_country ? "domestic" : "foreign";
+                setFraudScore(profile.vip ? 0.0 : s);
+                profile.guard = false ? markCalled(profile) : 1;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    for (amount, expected) in [(5000, 0.8), (750, 0.5), (100, 0.2)] {
+        let txn = Transaction::new()
+            .with_field("amount", Value::Int(amount))
+            .with_field("country", Value::from("UK"));
+        let profile = UserProfile::new().with_field("home_country", Value::from("US"));
+        
+        let result = engine.execute(txn, profile);
+        let fields = &result.profile.fields;
+        
+        assert_eq!(fields.get("score"), Some(&Value::Float(expected)));
+        assert_eq!(fields.get("label"), Some(&Value::from("foreign")));
+        assert_eq!(fields.get("guard"), Some(&Value::Int(1)));
+        assert_eq!(fields.get("called"), None);
+        assert_eq!(result.actions, vec![Action::set_fraud_score(expected)]);
+    }
+}
+
+#[test]
+fn test_decimal_arithmetic_is_exact() {
+    let dsl = r#"
+        rule "accumulate" {
+            priority: 100,
+            if (true) {
+                profile.sum = decimal("0.1") + decimal("0.2");
+                profile.exact = profile.sum == decimal("0.3");
+                profile.total_amount += txn.amount;
+                profile.doubled = txn.amount * 2;
+                profile.over = txn.amount > 100;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new().with_field("amount", Value::Decimal("100.10".parse().unwrap()));
+    let profile = UserProfile::new().with_field("total_amount", Value::Decimal("0.20".parse().unwrap()));
+    
+    let result = engine.execute(txn, profile);
+    let fields = &result.profile.fields;
+    
+    assert_eq!(fields.get("sum"), Some(&Value::Decimal("0.3".parse().unwrap())));
+    assert_eq!(fields.get("exact"), Some(&Value::Bool(true)));
+    assert_eq!(fields.get("total_amount").map(|v| v.as_string()), Some("100.30".to_string()));
+    assert_eq!(fields.get("doubled").map(|v| v.as_string()), Some("200.20".to_string()));
+    assert_eq!(fields.get("over"), Some(&Value::Bool(true)));
+}
+
+#[test]
+fn test_datetime_time_window() {
+    let dsl = r#"
+        rule "rapid_repeat" {
+            priority: 100,
+            if (hoursBetween(now(), profile.last_txn_time) < 1) {
+                createCase("MEDIUM", "Repeat transaction within an hour");
+            }
+            if (parseDate(txn.booked_at) > parseDate("2024-01-01")) {
+                profile.booked_this_year = true;
+            }
+            profile.bad_date = parseDate("not a date");
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let now_millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    
+    for (minutes_ago, expect_case) in [(10, true), (120, false)] {
+        let txn = Transaction::new().with_field("booked_at", Value::from("2024-03-15T10:00:00Z"));
+        let profile = UserProfile::new()
+            .with_field("last_txn_time", Value::DateTime(now_millis - minutes_ago * 60_000));
+        
+        let result = engine.execute(txn, profile);
+        
+        assert_eq!(!result.actions.is_empty(), expect_case);
+        assert_eq!(result.profile.fields.get("booked_this_year"), Some(&Value::Bool(true)));
+        assert_eq!(result.profile.fields.get("bad_date"), Some(&Value::Null));
+    }
+}
+
+#[test]
+fn test_explain_reports_conditions_and_fields() {
+    let dsl = r#"
+        rule "high_amount" {
+            priority: 100,
+            if (txn.amount > 1000 && profile.verified == false) {
+                createCase("HIGH", "Large unverified transaction");
+            }
+            if (txn.country in profile.blocked_countries) {
+                setDecision("BLOCK");
+            }
+        }
+        
+        rule "new_account" {
+            priority: 50,
+            if (profile.account_age_days < 30) {
+                setFraudScore(0.6);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new()
+        .with_field("amount", Value::Int(5000))
+        .with_field("country", Value::from("US"));
+    let profile = UserProfile::new()
+        .with_field("verified", Value::Bool(false))
+        .with_field("blocked_countries", Value::from(vec!["RU"]))
+        .with_field("account_age_days", Value::Int(400));
+    
+    let explained = engine.explain(txn.clone(), profile.clone());
+    let executed = engine.execute(txn, profile);
+    
+    assert_eq!(explained.result.actions, executed.actions);
+    assert_eq!(explained.rules.len(), 2);
+    
+    let high = &explained.rules[0];
+    assert_eq!(high.rule_id, "high_amount");
+    assert_eq!(high.conditions.len(), 2);
+    assert_eq!(high.conditions[0].condition, "(txn.amount > 1000) && (profile.verified == false)");
+    assert!(high.conditions[0].result);
+    assert_eq!(high.conditions[1].condition, "txn.country in profile.blocked_countries");
+    assert!(!high.conditions[1].result);
+    assert!(high.fields_read.contains(&("txn.amount".to_string(), Value::Int(5000))));
+    assert!(high.fields_read.contains(&("profile.verified".to_string(), Value::Bool(false))));
+    assert_eq!(high.actions.len(), 1);
+    
+    let new_account = &explained.rules[1];
+    assert!(!new_account.conditions[0].result);
+    assert_eq!(
+        new_account.fields_read,
+        vec![("profile.account_age_days".to_string(), Value::Int(400))]
+    );
+    assert!(new_account.actions.is_empty());
+}
+
+#[test]
+fn test_set_rule_enabled_toggles_rule() {
+    let dsl = r#"
+        rule "flag_large" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.9);
+            }
+        }
+        
+        rule "always" {
+            priority: 50,
+            if (true) {
+                profile.seen = true;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let shared = engine.clone();
+    let txn = Transaction::new().with_field("amount", Value::Int(5000));
+    
+    assert!(engine.set_rule_enabled("flag_large", false));
+    assert!(!engine.set_rule_enabled("missing", false));
+    assert_eq!(engine.get_rule("flag_large").map(|r| r.enabled), Some(false));
+    assert!(engine.get_rule("missing").is_none());
+    
+    // Clones share the toggle
+    let result = shared.execute(txn.clone(), UserProfile::new());
+    assert!(result.actions.is_empty());
+    assert_eq!(result.metadata.skipped_rules, vec!["flag_large".to_string()]);
+    assert_eq!(result.metadata.executed_rules, vec!["always".to_string()]);
+    
+    // The toggle survives a bytecode round trip
+    let reloaded = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap();
+    assert_eq!(reloaded.get_rule("flag_large").map(|r| r.enabled), Some(false));
+    
+    assert!(engine.set_rule_enabled("flag_large", true));
+    let result = engine.execute(txn, UserProfile::new());
+    assert_eq!(result.actions, vec![Action::set_fraud_score(0.9)]);
+}
+
+#[test]
+fn test_add_and_remove_rules_at_runtime() {
+    let dsl = r#"
+        rule "low" {
+            priority: 10,
+            if (true) {
+                profile.order = "low";
+            }
+        }
+    "#;
+    
+    let mut engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    engine.add_rule(r#"
+        rule "high" {
+            priority: 100,
+            if (true) {
+                profile.order = "high";
+                return;
+            }
+        }
+    "#).unwrap();
+    
+    let ids: Vec<_> = engine.get_rules_metadata().into_iter().map(|r| r.id).collect();
+    assert_eq!(ids, vec!["high", "low"]);
+    
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(result.profile.fields.get("order"), Some(&Value::from("high")));
+    assert!(result.metadata.short_circuited);
+    
+    // Fragments must hold exactly one rule, with an id not already loaded
+    let two_rules = r#"
+        rule "a" { priority: 1, if (true) {} }
+        rule "b" { priority: 1, if (true) {} }
+    "#;
+    assert!(engine.add_rule(two_rules).is_err());
+    assert!(engine.add_rule("").is_err());
+    assert!(engine.add_rule(r#"rule "low" { priority: 1, if (true) {} }"#).is_err());
+    
+    engine.remove_rule("high").unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(result.profile.fields.get("order"), Some(&Value::from("low")));
+    
+    assert!(matches!(
+        engine.remove_rule("high"),
+        Err(CompilationError::UnknownRule(id)) if id == "high"
+    ));
+}
+
+#[test]
+fn test_json_round_trip_matches_bincode() {
+    let dsl = r#"
+        function bump(profile) {
+            profile.count += 1;
+        }
+        
+        rule "scored" {
+            priority: 100,
+            if (txn.amount > decimal("999.99") && txn.country in profile.blocked) {
+                bump(profile);
+                createCase("HIGH", "Blocked country", { amount: txn.amount, limit: 1e3 });
+            }
+        }
+        
+        rule "disabled" {
+            priority: 50,
+            enabled: false,
+            if (true) {
+                setFraudScore(1.0);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let json = engine.to_json().unwrap();
+    let from_json = RuleEngine::from_json(&json).unwrap();
+    let from_bincode = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap();
+    
+    assert_eq!(from_json.to_json().unwrap(), json);
+    assert_eq!(from_bincode.to_json().unwrap(), json);
+    assert_eq!(from_json.to_bytecode().unwrap(), from_bincode.to_bytecode().unwrap());
+    
+    let txn = Transaction::new()
+        .with_field("amount", Value::Int(2000))
+        .with_field("country", Value::from("RU"));
+    let profile = UserProfile::new()
+        .with_field("blocked", Value::from(vec!["RU"]))
+        .with_field("count", Value::Int(0));
+    
+    let a = from_json.execute(txn.clone(), profile.clone());
+    let b = from_bincode.execute(txn, profile);
+    assert_eq!(a.actions, b.actions);
+    assert_eq!(a.profile.fields, b.profile.fields);
+    assert_eq!(a.metadata.skipped_rules, vec!["disabled".to_string()]);
+    
+    assert!(matches!(RuleEngine::from_json("{"), Err(CompilationError::CompileError(_))));
+}
+
+#[test]
+fn test_corrupt_payload_is_rejected() {
+    let dsl = r#"
+        rule "big" {
+            priority: 100,
+            if (txn.amount > 1000 && txn.country in ["RU", "NG"]) {
+                createCase("HIGH", "big");
+            }
+        }
+    "#;
+    let json = RuleEngine::from_dsl(dsl).unwrap().to_json().unwrap();
+    assert!(json.contains(r#""LoadTxnField": 0"#));
+    
+    // A field id past the symbol table fails to load instead of panicking
+    // on execute
+    let corrupt = json.replacen(r#""LoadTxnField": 0"#, r#""LoadTxnField": 99"#, 1);
+    let err = RuleEngine::from_json(&corrupt).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Compilation error: field symbol index 99 is out of range for 2 entries (in rule 'big')"
+    );
+    
+    let corrupt = json.replacen(r#""LoadConst": 0"#, r#""LoadConst": 7"#, 1);
+    assert_ne!(corrupt, json);
+    assert!(RuleEngine::from_json(&corrupt).is_err());
+}
+
+#[test]
+fn test_set_decision_is_validated() {
+    let dsl = r#"
+        rule "decide" {
+            priority: 100,
+            if (true) {
+                setDecision(txn.decision);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    for (name, expected) in [
+        ("ALLOW", Decision::Allow),
+        ("BLOCK", Decision::Block),
+        ("REVIEW", Decision::Review),
+    ] {
+        let txn = Transaction::new().with_field("decision", Value::from(name));
+        let result = engine.execute(txn, UserProfile::new());
+        
+        assert_eq!(result.actions, vec![Action::set_decision(expected)]);
+        assert!(result.metadata.errors.is_empty());
+    }
+    
+    // A typo falls back to manual review and is reported
+    let txn = Transaction::new().with_field("decision", Value::from("BLOCKK"));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert_eq!(result.actions, vec![Action::set_decision(Decision::Review)]);
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("unknown decision: BLOCKK".to_string())]
+    );
+}
+
+#[test]
+fn test_action_dedup_options() {
+    let dsl = r#"
+        rule "first" {
+            priority: 100,
+            if (true) {
+                setFraudScore(0.9);
+                createCase("HIGH", "Suspicious");
+            }
+        }
+        
+        rule "second" {
+            priority: 50,
+            if (true) {
+                createCase("HIGH", "Suspicious");
+                setFraudScore(0.9);
+                setFraudScore(0.4);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(result.actions.len(), 5);
+    
+    let dedup = engine.clone().with_dedup_actions(true);
+    let result = dedup.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(
+        result.actions,
+        vec![
+            Action::set_fraud_score(0.9),
+            Action::create_case("HIGH", "Suspicious"),
+            Action::set_fraud_score(0.4),
+        ]
+    );
+    
+    let last_wins = engine.with_dedup_actions(true).with_last_fraud_score_wins(true);
+    let result = last_wins.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(
+        result.actions,
+        vec![
+            Action::create_case("HIGH", "Suspicious"),
+            Action::set_fraud_score(0.4),
+        ]
+    );
+}
+
+#[test]
+fn test_fraud_score_policies() {
+    let dsl = r#"
+        rule "velocity" { priority: 30, if (true) { setFraudScore(0.3); } }
+        rule "geo" { priority: 20, if (true) { setFraudScore(0.9); } }
+        rule "amount" { priority: 10, if (true) { setFraudScore(0.5); } }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let cases = [
+        (FraudScorePolicy::Last, 0.5),
+        (FraudScorePolicy::Max, 0.9),
+        (FraudScorePolicy::Sum, 1.0),
+    ];
+    for (policy, expected) in cases {
+        let result = engine
+            .clone()
+            .with_fraud_score_policy(policy)
+            .execute(Transaction::new(), UserProfile::new());
+        assert_eq!(result.actions, vec![Action::set_fraud_score(expected)], "{:?}", policy);
+    }
+    
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    assert_eq!(result.actions.len(), 3);
+}
+
+#[test]
+fn test_execute_debug_traces_instructions() {
+    let dsl = r#"rule "r" { priority: 1, if (txn.amount > 100) { setFraudScore(0.5); } }"#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let debug = engine.execute_debug(
+        Transaction::new().with_field("amount", Value::Int(150)),
+        UserProfile::new(),
+    );
+    
+    let instructions: Vec<String> = debug.steps.iter().map(|step| format!("{:?}", step.instruction)).collect();
+    assert_eq!(
+        instructions,
+        [
+            "MemoCondition(0, 4)",
+            "LoadTxnField(0)",
+            "Push(Int(100))",
+            "Gt",
+            "TraceCondition(0)",
+            "JumpIfUnknown(11)",
+            "JumpIfFalse(11)",
+            "MarkFired",
+            "Push(Float(0.5))",
+            "CallAction(SetFraudScore, 1)",
+            "Jump(11)",
+        ]
+    );
+    assert_eq!(debug.steps[3].stack, vec![Value::Int(150), Value::Int(100)]);
+    assert!(debug.steps.iter().all(|step| step.rule_id == "r"));
+    
+    let result = engine.execute(Transaction::new().with_field("amount", Value::Int(150)), UserProfile::new());
+    assert_eq!(debug.result.actions, result.actions);
+}
+
+#[test]
+fn test_schema_type_mismatch() {
+    let schema = Schema::new()
+        .with_field("txn.amount", ValueType::Float)
+        .with_field("profile.country", ValueType::String);
+    
+    let dsl = r#"
+        rule "big" {
+            priority: 100,
+            if (txn.amount > "1000") { setFraudScore(0.5); }
+        }
+    "#;
+    let err = RuleEngine::from_dsl_with_schema(dsl, schema.clone()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        r#"Type mismatch: expected float (txn.amount), got string ("1000")"#
+    );
+    
+    // Without a schema the same rule compiles, and the comparison is false
+    assert!(RuleEngine::from_dsl(dsl).is_ok());
+    
+    let dsl = r#"
+        rule "big" {
+            priority: 100,
+            if (txn.amount > 1000 && profile.country != "US" && txn.channel == 3) {
+                setFraudScore(0.5);
+            }
+        }
+    "#;
+    let mut engine = RuleEngine::from_dsl_with_schema(dsl, schema).unwrap();
+    let result = engine.execute(
+        Transaction::new()
+            .with_field("amount", Value::Float(5000.0))
+            .with_field("channel", Value::Int(3)),
+        UserProfile::new().with_field("country", Value::from("FR")),
+    );
+    assert_eq!(result.actions, vec![Action::set_fraud_score(0.5)]);
+    
+    let err = engine
+        .add_rule(r#"rule "local" { priority: 1, if (profile.country == 1) {} }"#)
+        .unwrap_err();
+    assert!(matches!(err, CompilationError::TypeMismatch { .. }), "{}", err);
+}
+
+#[test]
+fn test_builder_combines_compile_options() {
+    let schema = Schema::new().with_field("txn.amount", ValueType::Float);
+    let actions = ActionRegistry::new().register_action("setRiskBand", &["band"]);
+    let builder = RuleEngine::builder()
+        .with_schema(schema)
+        .with_limits(CompileLimits::new().with_max_rules(2))
+        .with_actions(actions)
+        .with_default_priority(7);
+    
+    let mut engine = builder
+        .clone()
+        .compile(r#"rule "band" { if (txn.amount > 100) { setRiskBand("HIGH"); } }"#)
+        .unwrap();
+    assert_eq!(engine.get_rule("band").unwrap().priority, 7);
+    let result = engine.execute(Transaction::new().with_field("amount", Value::Float(500.0)), UserProfile::new());
+    assert!(matches!(&result.actions[0], Action::Custom { params, .. } if params.contains_key("band")));
+    
+    // Every setting applies at once, and to rules added later
+    let err = builder.clone().compile(r#"rule "bad" { if (txn.amount > "100") {} }"#).err().unwrap();
+    assert!(matches!(err, CompilationError::TypeMismatch { .. }));
+    let three = r#"rule "a" { if (true) {} } rule "b" { if (true) {} } rule "c" { if (true) {} }"#;
+    assert!(builder.clone().compile(three).is_err());
+    assert!(builder.compile_sources(&[("a.rules", r#"rule "a" { if (true) {} }"#), ("b.rules", three)]).is_err());
+    
+    engine.add_rule(r#"rule "later" { if (true) { setRiskBand("LOW"); } }"#).unwrap();
+    assert_eq!(engine.get_rule("later").unwrap().priority, 7);
+    assert!(engine.add_rule(r#"rule "third" { if (true) {} }"#).is_err());
+}
+
+#[test]
+fn test_require_records_error_and_stops_rule() {
+    let dsl = r#"
+        rule "checked" {
+            priority: 100,
+            require(profile.risk_score >= 0);
+            setFraudScore(0.4);
+        }
+        
+        rule "next" {
+            priority: 50,
+            if (true) { profile.visited = true; }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let result = engine.execute(
+        Transaction::new(),
+        UserProfile::new().with_field("risk_score", Value::Int(5)),
+    );
+    assert_eq!(result.actions, vec![Action::set_fraud_score(0.4)]);
+    assert!(result.metadata.errors.is_empty());
+    
+    let result = engine.execute(
+        Transaction::new(),
+        UserProfile::new().with_field("risk_score", Value::Int(-1)),
+    );
+    assert!(result.actions.is_empty());
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("require failed: profile.risk_score >= 0".to_string())]
+    );
+    assert_eq!(result.metadata.error_locations[0].rule_id, "checked");
+    assert_eq!(result.profile.fields.get("visited"), Some(&Value::Bool(true)));
+}
+
+#[test]
+fn test_fraud_score_is_clamped_and_nan_rejected() {
+    let dsl = r#"
+        rule "score" {
+            priority: 100,
+            if (true) {
+                setFraudScore(txn.score);
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let result = engine.execute(
+        Transaction::new().with_field("score", Value::Float(2.0)),
+        UserProfile::new(),
+    );
+    assert_eq!(result.actions, vec![Action::set_fraud_score(1.0)]);
+    assert!(result.metadata.errors.is_empty());
+    
+    let result = engine.execute(
+        Transaction::new().with_field("score", Value::Float(f64::NAN)),
+        UserProfile::new(),
+    );
+    assert!(result.actions.is_empty());
+    assert_eq!(
+        result.metadata.errors,
+        vec![ExecutionError::RuntimeError("setFraudScore() rejected a NaN score".to_string())]
+    );
+}
+
+#[test]
+fn test_nan_arithmetic_becomes_null() {
+    let dsl = r#"
+        rule "ratio" {
+            priority: 100,
+            if (true) {
+                profile.ratio = txn.amount * 0.0;
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let result = engine.execute(
+        Transaction::new().with_field("amount", Value::Float(f64::INFINITY)),
+        UserProfile::new(),
+    );
+    assert_eq!(result.profile.fields.get("ratio"), Some(&Value::Null));
+    assert_eq!(result.metadata.errors.len(), 1);
+}
+
+#[test]
+fn test_nested_field_access() {
+    let dsl = r#"
+        rule "nested" {
+            priority: 100,
+            if (profile.address.country != txn.country) {
+                profile.mismatch = true;
+            }
+            if (true) {
+                profile.city = profile.address.geo.city;
+                profile.missing = profile.billing.country;
+                let total = 0;
+                for (item in txn.items) {
+                    total += item.amount;
+                }
+                profile.items_total = total;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let mut geo = ahash::HashMap::default();
+    geo.insert("city".to_string(), Value::from("Lyon"));
+    let mut address = ahash::HashMap::default();
+    address.insert("country".to_string(), Value::from("FR"));
+    address.insert("geo".to_string(), Value::Object(geo));
+    
+    let item = |amount: i64| {
+        let mut fields = ahash::HashMap::default();
+        fields.insert("amount".to_string(), Value::Int(amount));
+        Value::Object(fields)
+    };
+    
+    let txn = Transaction::new()
+        .with_field("country", Value::from("US"))
+        .with_field("items", Value::Array(vec![item(10), item(32)]));
+    let profile = UserProfile::new().with_field("address", Value::Object(address));
+    
+    let result = engine.execute(txn, profile);
+    let fields = &result.profile.fields;
+    
+    assert_eq!(fields.get("mismatch"), Some(&Value::Bool(true)));
+    assert_eq!(fields.get("city"), Some(&Value::from("Lyon")));
+    assert_eq!(fields.get("missing"), Some(&Value::Null));
+    assert_eq!(fields.get("items_total"), Some(&Value::Int(42)));
+    
+    // A dotted name that is neither a source nor a local is still rejected
+    let err = RuleEngine::from_dsl(r#"rule "typo" { priority: 1, if (prfile.x > 1) {} }"#);
+    assert!(matches!(err, Err(CompilationError::UnknownField(f)) if f == "prfile.x"));
+}
+
+#[test]
+fn test_substring_membership() {
+    let dsl = r#"
+        rule "refunds" {
+            priority: 100,
+            if ("refund" in txn.description) {
+                profile.refund = true;
+            }
+            if (txn.description.includes("chargeback") || txn.description.contains("dispute")) {
+                profile.dispute = true;
+            }
+            if ("1" in txn.amount) {
+                profile.numeric_haystack = true;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let txn = Transaction::new()
+        .with_field("description", Value::from("Partial refund after dispute"))
+        .with_field("amount", Value::Int(100));
+    let result = engine.execute(txn, UserProfile::new());
+    assert_eq!(result.profile.fields.get("refund"), Some(&Value::Bool(true)));
+    assert_eq!(result.profile.fields.get("dispute"), Some(&Value::Bool(true)));
+    assert_eq!(result.profile.fields.get("numeric_haystack"), None);
+    
+    let txn = Transaction::new().with_field("description", Value::from("Grocery purchase"));
+    let result = engine.execute(txn, UserProfile::new());
+    assert!(result.profile.fields.is_empty());
+}
+
+#[test]
+fn test_constants_are_inlined() {
+    let dsl = r#"
+        const HIGH_AMOUNT = 1_000;
+        const VERY_HIGH_AMOUNT = HIGH_AMOUNT * 10;
+        const HOME = "US";
+        
+        rule "high_abroad" {
+            priority: 100,
+            if (txn.amount > HIGH_AMOUNT && txn.country != HOME) {
+                profile.level = txn.amount > VERY_HIGH_AMOUNT ? "very_high" : "high";
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let run = |amount: i64, country: &str| {
+        let txn = Transaction::new()
+            .with_field("amount", Value::Int(amount))
+            .with_field("country", Value::from(country));
+        engine.execute(txn, UserProfile::new()).profile.fields.get("level").cloned()
+    };
+    
+    assert_eq!(run(50_000, "FR"), Some(Value::from("very_high")));
+    assert_eq!(run(5_000, "FR"), Some(Value::from("high")));
+    assert_eq!(run(5_000, "US"), None);
+    assert_eq!(run(500, "FR"), None);
+    
+    // Constants are pushed as values rather than loaded by name
+    assert!(!engine.to_json().unwrap().contains(r#""LoadLocal": "HIGH_AMOUNT""#));
+}
+
+#[test]
+fn test_invalid_constants_are_rejected() {
+    let cases = [
+        // forward reference
+        "const A = B + 1; const B = 2;",
+        // circular reference
+        "const A = A + 1;",
+        // runtime data
+        "const A = txn.amount;",
+        // duplicate
+        "const A = 1; const A = 2;",
+        // non-deterministic
+        "const A = now();",
+        // reassignment
+        r#"const A = 1; rule "r" { priority: 1, if (true) { A = 2; } }"#,
+    ];
+    
+    for dsl in cases {
+        assert!(
+            matches!(RuleEngine::from_dsl(dsl), Err(CompilationError::CompileError(_))),
+            "{}",
+            dsl
+        );
+    }
+}
+
+#[test]
+fn test_equal_priority_rules_run_in_declaration_order() {
+    let dsl = r#"
+        rule "c" {
+            priority: 50,
+            if (true) {
+                profile.sequence = profile.sequence + "c";
+            }
+        }
+        
+        rule "a" {
+            priority: 50,
+            if (true) {
+                profile.sequence = profile.sequence + "a";
+            }
+        }
+        
+        rule "first" {
+            priority: 100,
+            if (true) {
+                profile.sequence = "first:";
+            }
+        }
+        
+        rule "b" {
+            priority: 50,
+            if (true) {
+                profile.sequence = profile.sequence + "b";
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    
+    assert_eq!(result.profile.fields.get("sequence"), Some(&Value::from("first:cab")));
+    assert_eq!(result.metadata.executed_rules, vec!["first", "c", "a", "b"]);
+}
+
+#[test]
+fn test_unknown_functions_and_actions_are_rejected() {
+    let dsl = r#"
+        function calculateRisk(profile) {
+            profile.risk = 1;
+        }
+        
+        rule "typos" {
+            priority: 100,
+            if (true) {
+                calculateRsik(profile);
+                createcase("HIGH", "Typo in the action name");
+                calculateRisk(profile);
+            }
+        }
+    "#;
+    
+    let err = RuleEngine::from_dsl(dsl).err().expect("unknown names must not compile");
+    let message = err.to_string();
+    
+    assert!(message.contains("calculateRsik (in rule 'typos')"), "{}", message);
+    assert!(message.contains("createcase (in rule 'typos')"), "{}", message);
+    assert!(!message.contains("calculateRisk "), "{}", message);
+    assert!(RuleEngine::validate_dsl(dsl).is_err());
+    
+    // Fragments added later are checked against the loaded functions
+    let mut engine = RuleEngine::from_dsl(
+        "function calculateRisk(profile) { profile.risk = 1; }",
+    ).unwrap();
+    assert!(engine.add_rule(r#"rule "ok" { priority: 1, if (true) { calculateRisk(profile); } }"#).is_ok());
+    assert!(engine.add_rule(r#"rule "bad" { priority: 1, if (true) { missing(); } }"#).is_err());
+}
+
+#[test]
+fn test_function_arity_is_checked() {
+    let function = r#"
+        function addRisk(profile, amount) {
+            profile.risk += amount;
+        }
+    "#;
+    
+    let compile = |call: &str| {
+        RuleEngine::from_dsl(&format!(
+            r#"{} rule "r" {{ priority: 1, if (true) {{ {} }} }}"#,
+            function, call
+        ))
+    };
+    
+    let too_few = compile("addRisk(profile);").err().unwrap().to_string();
+    assert!(too_few.contains("function 'addRisk' expects 2 argument(s), got 1"), "{}", too_few);
+    
+    let too_many = compile("addRisk(profile, 1, 2);").err().unwrap().to_string();
+    assert!(too_many.contains("function 'addRisk' expects 2 argument(s), got 3"), "{}", too_many);
+    
+    assert!(compile("addRisk(profile, 1);").is_ok());
+}
+
+#[test]
+fn test_locals_do_not_leak_between_rules() {
+    let dsl = r#"
+        rule "a" {
+            priority: 100,
+            if (true) {
+                let x = 5;
+                profile.from_a = x;
+            }
+        }
+        
+        rule "b" {
+            priority: 50,
+            if (true) {
+                profile.from_b = x;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new());
+    
+    assert_eq!(result.profile.fields.get("from_a"), Some(&Value::Int(5)));
+    assert_eq!(result.profile.fields.get("from_b"), Some(&Value::Null));
+}
+
+#[test]
+fn test_literal_folding_preserves_results() {
+    let dsl = r#"
+        rule "folded" {
+            priority: 100,
+            if (txn.amount > 100 * 10 && 2 ** 3 == 8) {
+                profile.limit = 100 * 10 + 24;
+                profile.label = "risk_" + "high";
+                profile.negative = -(5 - 2);
+                profile.ratio = decimal("1.50") * 2;
+                profile.broken = 1 / 0;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let result = engine.execute(
+        Transaction::new().with_field("amount", Value::Int(5000)),
+        UserProfile::new(),
+    );
+    assert_eq!(result.profile.fields.get("limit"), Some(&Value::Int(1024)));
+    assert_eq!(result.profile.fields.get("label"), Some(&Value::String("risk_high".to_string())));
+    assert_eq!(result.profile.fields.get("negative"), Some(&Value::Int(-3)));
+    assert_eq!(result.profile.fields.get("ratio").map(Value::as_string), Some("3.00".to_string()));
+    
+    // Division by zero is left for the VM, so it is still reported
+    assert_eq!(result.profile.fields.get("broken"), Some(&Value::Null));
+    assert_eq!(result.metadata.errors.len(), 1);
+    
+    let result = engine.execute(
+        Transaction::new().with_field("amount", Value::Int(500)),
+        UserProfile::new(),
+    );
+    assert!(!result.profile.fields.contains_key("limit"));
+}
+
+#[test]
+fn test_production_config_skips_rule_metadata() {
+    let dsl = r#"
+        rule "flag_large" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setFraudScore(0.8);
+            }
+        }
+        
+        rule "disabled" {
+            priority: 50,
+            enabled: false,
+            if (true) {
+                setFraudScore(0.1);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let production = engine.clone().with_config(EngineConfig::production());
+    assert!(!production.config().collect_metadata);
+    
+    let transaction = Transaction::new().with_field("amount", Value::Int(5000));
+    let full = engine.execute(transaction.clone(), UserProfile::new());
+    let lean = production.execute(transaction, UserProfile::new());
+    
+    assert_eq!(lean.actions, full.actions);
+    assert!(lean.metadata.executed_rules.is_empty());
+    assert!(lean.metadata.skipped_rules.is_empty());
+    assert!(lean.metadata.rule_timings.is_empty());
+    
+    assert_eq!(full.metadata.executed_rules, vec!["flag_large".to_string()]);
+    assert_eq!(full.metadata.skipped_rules, vec!["disabled".to_string()]);
+    assert_eq!(full.metadata.rule_timings.len(), 1);
+}
+
+#[test]
+fn test_registered_action_uses_named_params() {
+    let dsl = r#"
+        rule "gold_band" {
+            priority: 100,
+            if (txn.amount > 1000) {
+                setRiskBand("GOLD", txn.amount);
+            }
+        }
+    "#;
+    
+    let actions = ActionRegistry::new().register_action("setRiskBand", &["band", "amount"]);
+    let engine = RuleEngine::from_dsl_with_actions(dsl, actions).unwrap();
+    
+    let txn = Transaction::new().with_field("amount", Value::Int(5000));
+    let result = engine.execute(txn, UserProfile::new());
+    
+    assert!(result.metadata.errors.is_empty());
+    match &result.actions[..] {
+        [Action::Custom { action_name, params }] => {
+            assert_eq!(action_name, "setRiskBand");
+            assert_eq!(params.get("band"), Some(&Value::from("GOLD")));
+            assert_eq!(params.get("amount"), Some(&Value::Int(5000)));
+            assert!(!params.contains_key("arg0"));
+        }
+        other => panic!("expected one custom action, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_registered_action_checks_arity() {
+    let dsl = r#"
+        rule "gold_band" {
+            priority: 100,
+            if (true) {
+                setRiskBand("GOLD", "extra");
+            }
+        }
+    "#;
+    
+    let actions = ActionRegistry::new().register_action("setRiskBand", &["band"]);
+    let err = RuleEngine::from_dsl_with_actions(dsl, actions).err().unwrap();
+    assert!(err.to_string().contains("expects 1 argument(s), got 2"));
+    
+    // Unregistered, the same call is an unknown function
+    assert!(RuleEngine::from_dsl(dsl).is_err());
+}
+
+fn bin_lookup() -> HostFunctions {
+    let lookup_bin: HostFn = Arc::new(|args: &[Value]| match args {
+        [Value::String(bin)] if bin.starts_with("4") => Value::from("VISA"),
+        [Value::String(bin)] if bin.starts_with("5") => Value::from("MASTERCARD"),
+        _ => Value::Null,
+    });
+    
+    let mut host_functions = HostFunctions::default();
+    host_functions.insert("lookupBin".to_string(), lookup_bin);
+    host_functions
+}
+
+#[test]
+fn test_host_function_lookup() {
+    let dsl = r#"
+        rule "prepaid_network" {
+            priority: 100,
+            if (lookupBin(txn.bin) == "MASTERCARD") {
+                createCase("LOW", "Mastercard BIN");
+            }
+        }
+        
+        rule "unknown_bin" {
+            priority: 90,
+            if (true) {
+                let network = lookupBin(txn.bin);
+                if (network == null) {
+                    setFraudScore(0.5);
+                }
+            }
+        }
+    "#;
+    
+    assert!(RuleEngine::from_dsl(dsl).is_err());
+    let engine = RuleEngine::from_dsl_with_host_functions(dsl, bin_lookup()).unwrap();
+    
+    let txn = Transaction::new().with_field("bin", Value::from("545454"));
+    let result = engine.execute(txn, UserProfile::new());
+    assert_eq!(result.actions.len(), 1);
+    assert!(matches!(result.actions[0], Action::CreateCase { .. }));
+    
+    let txn = Transaction::new().with_field("bin", Value::from("999999"));
+    let result = engine.execute(txn, UserProfile::new());
+    assert_eq!(result.actions, vec![Action::SetFraudScore { score: 0.5 }]);
+}
+
+#[test]
+fn test_host_functions_survive_bytecode_reload() {
+    let dsl = r#"
+        rule "visa" {
+            priority: 100,
+            if (lookupBin(txn.bin) == "VISA") {
+                setFraudScore(0.1);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl_with_host_functions(dsl, bin_lookup()).unwrap();
+    let reloaded = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap())
+        .unwrap()
+        .with_host_functions(bin_lookup());
+    
+    let txn = Transaction::new().with_field("bin", Value::from("411111"));
+    let result = reloaded.execute(txn, UserProfile::new());
+    assert_eq!(result.actions, vec![Action::SetFraudScore { score: 0.1 }]);
+}
+
+#[test]
+fn test_dry_run_reports_profile_changes() {
+    let dsl = r#"
+        rule "velocity" {
+            priority: 100,
+            if (txn.amount > 100) {
+                profile.large_txn_count = profile.large_txn_count + 1;
+                profile.last_large_amount = txn.amount;
+                setFraudScore(0.3);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let txn = Transaction::new().with_field("amount", Value::Int(500));
+    let profile = UserProfile::new().with_field("large_txn_count", Value::Int(2));
+    
+    let executed = engine.execute(txn.clone(), profile.clone());
+    assert!(!executed.metadata.dry_run);
+    assert!(executed.metadata.profile_changes.is_empty());
+    
+    let result = engine.dry_run(txn, profile);
+    assert!(result.metadata.dry_run);
+    assert_eq!(result.actions, executed.actions);
+    assert_eq!(result.profile.fields.get("large_txn_count"), Some(&Value::Int(3)));
+    
+    let mut changes = result.metadata.profile_changes.clone();
+    changes.sort_by(|a, b| a.field.cmp(&b.field));
+    assert_eq!(
+        changes,
+        vec![
+            FieldChange {
+                field: "large_txn_count".to_string(),
+                old: Value::Int(2),
+                new: Value::Int(3),
+            },
+            FieldChange {
+                field: "last_large_amount".to_string(),
+                old: Value::Null,
+                new: Value::Int(500),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_bitwise_flag_checks() {
+    let dsl = r#"
+        const FLAG_VIP = 1 << 2;
+        
+        rule "vip" {
+            priority: 100,
+            if ((profile.flags & FLAG_VIP) != 0) {
+                createComment("VIP customer");
+            }
+        }
+        
+        rule "mark_seen" {
+            priority: 90,
+            if (true) {
+                profile.flags = profile.flags | 1;
+                profile.toggled = profile.flags ^ 3;
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let result = engine.execute(Transaction::new(), UserProfile::new().with_field("flags", Value::Int(4)));
+    assert_eq!(result.actions.len(), 1);
+    assert_eq!(result.profile.fields.get("flags"), Some(&Value::Int(5)));
+    assert_eq!(result.profile.fields.get("toggled"), Some(&Value::Int(6)));
+    
+    let result = engine.execute(Transaction::new(), UserProfile::new().with_field("flags", Value::Int(2)));
+    assert!(result.actions.is_empty());
+    assert_eq!(result.profile.fields.get("flags"), Some(&Value::Int(3)));
+}
+
+#[test]
+fn test_first_match_mode_stops_after_first_firing_rule() {
+    let dsl = r#"
+        rule "blocked_country" {
+            priority: 300,
+            if (txn.country == "XX") {
+                setDecision("BLOCK");
+            }
+        }
+        
+        rule "large_amount" {
+            priority: 200,
+            if (txn.amount > 1000) {
+                setDecision("REVIEW");
+                createComment("large amount");
+            } else {
+                createComment("small amount");
+            }
+        }
+        
+        rule "default" {
+            priority: 100,
+            if (true) {
+                setDecision("ALLOW");
+            }
+        }
+    "#;
+    
+    let run_all = RuleEngine::from_dsl(dsl).unwrap();
+    let first_match = RuleEngine::from_dsl(dsl)
+        .unwrap()
+        .with_execution_mode(ExecutionMode::FirstMatch);
+    
+    let large = Transaction::new()
+        .with_field("country", Value::from("US"))
+        .with_field("amount", Value::Int(5000));
+    
+    // Run-all lets every rule add its decision
+    let result = run_all.execute(large.clone(), UserProfile::new());
+    assert_eq!(result.actions.len(), 3);
+    
+    // First match stops once "large_amount" fires, after its whole body ran
+    let result = first_match.execute(large, UserProfile::new());
+    assert_eq!(
+        result.actions,
+        vec![
+            Action::SetDecision { decision: Decision::Review },
+            Action::CreateComment { case_id: None, comment: "large amount".to_string() },
+        ]
+    );
+    assert_eq!(result.metadata.executed_rules, vec!["blocked_country", "large_amount"]);
+    assert!(!result.metadata.short_circuited);
+    
+    // Taking an else branch is not firing, so evaluation continues
+    let small = Transaction::new()
+        .with_field("country", Value::from("US"))
+        .with_field("amount", Value::Int(10));
+    let result = first_match.execute(small, UserProfile::new());
+    assert_eq!(
+        result.actions,
+        vec![
+            Action::CreateComment { case_id: None, comment: "small amount".to_string() },
+            Action::SetDecision { decision: Decision::Allow },
+        ]
+    );
+}
+
+#[test]
+fn test_array_push_rolling_window() {
+    let dsl = r#"
+        rule "track_amounts" {
+            priority: 100,
+            if (true) {
+                profile.recent_amounts.push(txn.amount, 3);
+                profile.countries.push(txn.country);
+                
+                let seen = null;
+                seen.push(profile.recent_amounts.get(0));
+                profile.oldest = seen.get(0);
+                profile.window_size = profile.recent_amounts.length();
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let profile = UserProfile::new().with_field("recent_amounts", Value::from(vec![10, 20, 30]));
+    let txn = Transaction::new()
+        .with_field("amount", Value::Int(40))
+        .with_field("country", Value::from("US"));
+    
+    let result = engine.execute(txn, profile);
+    
+    assert!(result.metadata.errors.is_empty());
+    // The window keeps the last three amounts; a missing field starts empty
+    assert_eq!(result.profile.fields.get("recent_amounts"), Some(&Value::from(vec![20, 30, 40])));
+    assert_eq!(result.profile.fields.get("countries"), Some(&Value::from(vec!["US"])));
+    assert_eq!(result.profile.fields.get("oldest"), Some(&Value::Int(20)));
+    assert_eq!(result.profile.fields.get("window_size"), Some(&Value::Int(3)));
+}
+
+#[test]
+fn test_array_push_errors() {
+    // Pushing onto a non-array records an error and leaves the field alone
+    let dsl = r#"rule "test" { priority: 1, if (true) { profile.count.push(1); } }"#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let result = engine.execute(Transaction::new(), UserProfile::new().with_field("count", Value::Int(5)));
+    assert_eq!(result.metadata.errors.len(), 1);
+    assert_eq!(result.profile.fields.get("count"), Some(&Value::Int(5)));
+    
+    // push() has no value and needs an assignable target
+    for body in ["let n = profile.items.push(1);", "profile.a.b.push(1);", "profile.items.push();"] {
+        let dsl = format!(r#"rule "test" {{ priority: 1, if (true) {{ {} }} }}"#, body);
+        let err = RuleEngine::from_dsl(&dsl).err().unwrap();
+        assert!(err.to_string().contains("push()"), "{}", err);
+    }
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_device_fingerprint_hash() {
+    let dsl = r#"
+        rule "known_device" {
+            priority: 100,
+            if (sha256(txn.device_id) == profile.device_hash && md5("abc") == "900150983cd24fb0d6963f7d28e17f72") {
+                createComment("known device");
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    let profile = UserProfile::new().with_field(
+        "device_hash",
+        Value::from("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    );
+    
+    let txn = Transaction::new().with_field("device_id", Value::from("abc"));
+    assert_eq!(engine.execute(txn, profile.clone()).actions.len(), 1);
+    
+    let txn = Transaction::new().with_field("device_id", Value::Bytes(b"abd".to_vec()));
+    assert!(engine.execute(txn, profile).actions.is_empty());
+}
+
+#[cfg(not(feature = "crypto"))]
+#[test]
+fn test_hash_builtins_need_crypto_feature() {
+    let dsl = r#"rule "test" { priority: 1, if (sha256(txn.device_id) == "x") { } }"#;
+    let err = RuleEngine::from_dsl(dsl).err().unwrap();
+    assert!(err.to_string().contains("unknown function or action: sha256"), "{}", err);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_matches_builtin() {
+    let dsl = r#"
+        const TEST_EMAIL = "@(test|example)\\.com$";
+        
+        rule "test_email" {
+            priority: 100,
+            if (matches(txn.email, TEST_EMAIL)) {
+                createComment("test account");
+            }
+        }
+        
+        rule "visa_bin" {
+            priority: 90,
+            if (matches(txn.bin, """^4[0-9]{5}$""")) {
+                setFraudScore(0.1);
+            }
+        }
+    "#;
+    
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let txn = Transaction::new()
+        .with_field("email", Value::from("ann@test.com"))
+        .with_field("bin", Value::from("411111"));
+    assert_eq!(engine.execute(txn, UserProfile::new()).actions.len(), 2);
+    
+    let txn = Transaction::new()
+        .with_field("email", Value::from("ann@testXcom"))
+        .with_field("bin", Value::from("511111"));
+    assert!(engine.execute(txn, UserProfile::new()).actions.is_empty());
+    
+    // Compiled patterns survive a bytecode round trip
+    let reloaded = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap();
+    let txn = Transaction::new().with_field("bin", Value::from("422222"));
+    assert_eq!(reloaded.execute(txn, UserProfile::new()).actions.len(), 1);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_matches_rejects_bad_patterns() {
+    let cases = [
+        (r#"matches(txn.email, "(unclosed")"#, "invalid regex pattern '(unclosed'"),
+        ("matches(txn.email, txn.pattern)", "pattern must be a string literal"),
+        ("matches(txn.email)", "takes a value and a pattern"),
+    ];
+    for (condition, expected) in cases {
+        let dsl = format!(r#"rule "test" {{ priority: 1, if ({}) {{ }} }}"#, condition);
+        let err = RuleEngine::from_dsl(&dsl).err().unwrap();
+        assert!(err.to_string().contains(expected), "{}", err);
+    }
+}
+
+#[cfg(not(feature = "regex"))]
+#[test]
+fn test_matches_needs_regex_feature() {
+    let dsl = r#"rule "test" { priority: 1, if (matches(txn.email, "x")) { } }"#;
+    let err = RuleEngine::from_dsl(dsl).err().unwrap();
+    assert!(err.to_string().contains("requires the `regex` feature"), "{}", err);
+}
+
+#[test]
+fn test_transaction_from_nested_json() {
+    let dsl = r#"
+        rule "prepaid_foreign_card" {
+            priority: 10,
+            if (txn.card.bin == "411111" && txn.card.issuer.country != txn.shipping.country) {
+                setFraudScore(0.7);
+            }
+        }
+
+        rule "risky_line_item" {
+            priority: 5,
+            if (txn.items[1].sku == "GIFT-CARD" && txn.card.issuer.bank == null) {
+                setDecision("REVIEW");
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).expect("Failed to compile");
+
+    let transaction = Transaction::from_json(r#"{
+        "amount": 120.5,
+        "card": {"bin": "411111", "issuer": {"country": "GB"}},
+        "shipping": {"country": "US"},
+        "items": [{"sku": "BOOK-1"}, {"sku": "GIFT-CARD", "qty": 2}]
+    }"#)
+    .unwrap();
+    assert_eq!(transaction.fields.get("amount"), Some(&Value::Float(120.5)));
+
+    let result = engine.execute(transaction, UserProfile::new());
+
+    assert!(result.metadata.errors.is_empty(), "{:?}", result.metadata.errors);
+    assert!(result.actions.contains(&Action::SetFraudScore { score: 0.7 }));
+    assert!(result.actions.contains(&Action::SetDecision { decision: Decision::Review }));
+
+    assert!(Transaction::from_json("[1, 2]").is_err());
+}
+
+#[test]
+fn test_return_in_group_only_skips_its_group() {
+    let dsl = r#"
+        group "geo" {
+            priority: 50,
+            rule "country_mismatch" {
+                priority: 2,
+                if (txn.country != profile.home_country) {
+                    createCase("MEDIUM", "Country mismatch");
+                }
+            }
+            rule "high_risk_country" {
+                priority: 1,
+                if (txn.country == "XX") {
+                    setDecision("BLOCK");
+                }
+            }
+        }
+
+        group "velocity" {
+            priority: 100,
+            rule "burst" {
+                priority: 2,
+                if (profile.txn_count_1h > 10) {
+                    setFraudScore(0.9);
+                    return;
+                }
+            }
+            rule "daily_limit" {
+                priority: 1,
+                if (true) {
+                    setFraudScore(0.1);
+                }
+            }
+        }
+
+        rule "audit" {
+            priority: 10,
+            if (true) {
+                createCase("LOW", "Audit");
+            }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).expect("Failed to compile");
+
+    let order: Vec<_> = engine.get_rules_metadata().into_iter().map(|r| r.id).collect();
+    assert_eq!(order, ["burst", "daily_limit", "country_mismatch", "high_risk_country", "audit"]);
+    assert_eq!(engine.get_rule("burst").unwrap().group.as_deref(), Some("velocity"));
+    assert_eq!(engine.get_rule("audit").unwrap().group, None);
+
+    let transaction = Transaction::new().with_field("country", Value::from("XX"));
+    let profile = UserProfile::new()
+        .with_field("txn_count_1h", Value::Int(25))
+        .with_field("home_country", Value::from("GB"));
+    let result = engine.execute(transaction, profile);
+
+    assert!(result.metadata.short_circuited);
+    assert_eq!(
+        result.metadata.executed_rules,
+        ["burst", "country_mismatch", "high_risk_country", "audit"]
+    );
+    assert!(result.actions.contains(&Action::SetFraudScore { score: 0.9 }));
+    assert!(!result.actions.contains(&Action::SetFraudScore { score: 0.1 }));
+    assert!(result.actions.contains(&Action::SetDecision { decision: Decision::Block }));
+}
+
+#[test]
+fn test_group_errors() {
+    let dsl = r#"
+        group "velocity" { rule "a" { priority: 1, if (true) {} } }
+        group "velocity" { rule "b" { priority: 1, if (true) {} } }
+    "#;
+    let err = RuleEngine::from_dsl(dsl).err().unwrap();
+    assert!(err.to_string().contains("duplicate group 'velocity'"), "{}", err);
+
+    let mut engine = RuleEngine::from_dsl(r#"rule "a" { priority: 1, if (true) {} }"#).unwrap();
+    let err = engine
+        .add_rule(r#"group "geo" { rule "b" { priority: 1, if (true) {} } }"#)
+        .unwrap_err();
+    assert!(err.to_string().contains("1 group(s)"), "{}", err);
+}
+
+#[test]
+fn test_execute_tagged_runs_matching_rules() {
+    let dsl = r#"
+        rule "structuring" {
+            priority: 30,
+            tags: ["aml"],
+            if (true) { createComment("structuring"); }
+        }
+        rule "burst" {
+            priority: 20,
+            tags: ["velocity"],
+            if (true) { createComment("burst"); }
+        }
+        rule "foreign_cash" {
+            priority: 10,
+            tags: ["aml", "geo"],
+            if (true) { createComment("foreign_cash"); }
+        }
+        rule "untagged" {
+            priority: 5,
+            if (true) { createComment("untagged"); }
+        }
+    "#;
+    let engine = RuleEngine::from_dsl(dsl).unwrap();
+    
+    let run = |engine: &RuleEngine, tags: &[&str]| {
+        engine
+            .execute_tagged(Transaction::new(), UserProfile::new(), tags)
+            .metadata
+            .executed_rules
+    };
+    
+    assert_eq!(run(&engine, &["aml"]), ["structuring", "foreign_cash"]);
+    assert_eq!(run(&engine, &["velocity", "geo"]), ["burst", "foreign_cash"]);
+    assert!(run(&engine, &["unknown"]).is_empty());
+    assert_eq!(run(&engine, &[]).len(), 4);
+    assert_eq!(engine.get_rule("foreign_cash").unwrap().tags, ["aml", "geo"]);
+    
+    // Tags survive both export formats
+    let from_bytecode = RuleEngine::from_bytecode(&engine.to_bytecode().unwrap()).unwrap();
+    let from_json = RuleEngine::from_json(&engine.to_json().unwrap()).unwrap();
+    for reloaded in [&from_bytecode, &from_json] {
+        assert_eq!(run(reloaded, &["aml"]), ["structuring", "foreign_cash"]);
+        assert_eq!(reloaded.get_rule("untagged").unwrap().tags, Vec::<String>::new());
+    }
 }